@@ -0,0 +1,66 @@
+//! Benchmarks of representative workloads. Every evaluation workload is run with both engines.
+
+use std::hint::black_box;
+use std::io::{BufRead, Cursor};
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use linsl::datatypes::Engine;
+use linsl::interpreter::Interpreter;
+use linsl::parsing::Tokenizer;
+
+const PRELUDE: &str = "
+    (define - (lambda (a b) (+ a (neg b))))
+    (define sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1))))))
+    (define fold (lambda (f acc xs) (if (empty? xs) acc (fold f (f acc (car xs)) (cdr xs)))))
+    (define unless (macro (c a b) `(if ,c ,b ,a)))
+    (define count-down (lambda (n) (unless (= n 0) (count-down (- n 1)) 'done)))
+";
+
+/// Benchmark evaluating `code` with each engine, after evaluating the prelude.
+fn bench_eval(c: &mut Criterion, name: &str, code: &str) {
+    let mut group = c.benchmark_group(name);
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.engine = engine;
+        interpreter.eval_str(PRELUDE).unwrap();
+
+        group.bench_function(BenchmarkId::from_parameter(format!("{:?}", engine)), |b| {
+            b.iter(|| interpreter.eval_str(black_box(code)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn deep_recursion(c: &mut Criterion) {
+    bench_eval(c, "deep_recursion", "(sum 1000)");
+}
+
+fn fold_quoted_list(c: &mut Criterion) {
+    let elems: Vec<String> = (0..10000).map(|i| i.to_string()).collect();
+    bench_eval(c, "fold_quoted_list", &format!("(fold + 0 '({}))", elems.join(" ")));
+}
+
+fn macro_expansion(c: &mut Criterion) {
+    bench_eval(c, "macro_expansion", "(count-down 2000)");
+}
+
+fn tokenize(c: &mut Criterion) {
+    let code: String = (0..5000)
+        .map(|i| format!("(define f{} (lambda (x) `(+ x ,{} 'y))) ; Function number {}\n", i, i, i))
+        .collect();
+    let code: Arc<[u8]> = code.into_bytes().into();
+
+    c.bench_function("tokenize", |b| b.iter(|| {
+        let input: Box<dyn BufRead> = Box::new(Cursor::new(black_box(code.clone())));
+        let mut tokenizer = Tokenizer::new(vec![input].into()).unwrap();
+        let mut tokens = 0;
+        while tokenizer.next_token().unwrap().is_some() {
+            tokens += 1;
+        }
+        tokens
+    }));
+}
+
+criterion_group!(benches, deep_recursion, fold_quoted_list, macro_expansion, tokenize);
+criterion_main!(benches);
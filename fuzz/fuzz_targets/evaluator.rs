@@ -0,0 +1,40 @@
+//! Parsed expressions into the evaluator, on both engines, which must either give a value or fail
+//! with an error. The evaluations are sandboxed to pure computation, and limited in fuel and depth
+//! so that every input finishes quickly and within the stack.
+
+#![no_main]
+
+use std::io::{BufRead, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use linsl::datatypes::Engine;
+use linsl::interpreter::Interpreter;
+use linsl::parsing::{parse, Tokenizer};
+use linsl::sandbox::SandboxPolicy;
+
+const FUEL: usize = 10_000;
+const MAX_DEPTH: usize = 200;
+
+fuzz_target!(|code: &str| {
+    let input: Box<dyn BufRead> = Box::new(Cursor::new(code.to_string()));
+    let Ok(mut tokenizer) = Tokenizer::new(vec![input].into()) else {
+        return;
+    };
+    let mut exprs = Vec::new();
+    while tokenizer.peek().is_some() {
+        match parse(&mut tokenizer) {
+            Ok(expr) => exprs.push(expr),
+            Err(_) => break,
+        }
+    }
+
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let policy = SandboxPolicy { max_depth: Some(MAX_DEPTH), ..SandboxPolicy::pure() };
+        let mut interpreter = Interpreter::with_policy(policy);
+        interpreter.ctx.engine = engine;
+        for expr in &exprs {
+            interpreter.ctx.fuel = Some(FUEL);
+            let _ = interpreter.evaluate(expr);
+        }
+    }
+});
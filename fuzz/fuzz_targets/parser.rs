@@ -0,0 +1,67 @@
+//! Streams of tokens into the parser, which must either parse every expression or fail with an
+//! error. The tokens are chosen from the ones the tokenizer gives, so that the parser is reached
+//! more often than with raw bytes.
+
+#![no_main]
+
+use std::io::{BufRead, Cursor};
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use linsl::parsing::{parse, Tokenizer};
+
+#[derive(Debug, Arbitrary)]
+enum Token {
+    Open,
+    Close,
+    VectorOpen,
+    Quote,
+    Quasiquote,
+    Comma,
+    CommaAt,
+    Dot,
+    Hash(String),
+    Number(f64),
+    Integer(i64),
+    Str(String),
+    Symbol(String),
+    Keyword(String),
+}
+
+impl Token {
+    fn write(&self, s: &mut String) {
+        match self {
+            Token::Open => s.push('('),
+            Token::Close => s.push(')'),
+            Token::VectorOpen => s.push_str("#("),
+            Token::Quote => s.push('\''),
+            Token::Quasiquote => s.push('`'),
+            Token::Comma => s.push(','),
+            Token::CommaAt => s.push_str(",@"),
+            Token::Dot => s.push('.'),
+            Token::Hash(h) => s.push_str(&format!("#{}", h)),
+            Token::Number(n) => s.push_str(&n.to_string()),
+            Token::Integer(n) => s.push_str(&n.to_string()),
+            Token::Str(string) => s.push_str(&format!("{:?}", string)),
+            Token::Symbol(name) => s.push_str(name),
+            Token::Keyword(name) => s.push_str(&format!(":{}", name)),
+        }
+        s.push(' ');
+    }
+}
+
+fuzz_target!(|tokens: Vec<Token>| {
+    let mut code = String::new();
+    for token in &tokens {
+        token.write(&mut code);
+    }
+    let input: Box<dyn BufRead> = Box::new(Cursor::new(code));
+    let Ok(mut tokenizer) = Tokenizer::new(vec![input].into()) else {
+        return;
+    };
+    while tokenizer.peek().is_some() {
+        if parse(&mut tokenizer).is_err() {
+            break;
+        };
+    }
+});
@@ -0,0 +1,18 @@
+//! Raw bytes into the tokenizer, which must either read every token or fail with an error.
+
+#![no_main]
+
+use std::io::{BufRead, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use linsl::parsing::Tokenizer;
+
+fuzz_target!(|data: &[u8]| {
+    let input: Box<dyn BufRead> = Box::new(Cursor::new(data.to_vec()));
+    let Ok(mut tokenizer) = Tokenizer::new(vec![input].into()) else {
+        return;
+    };
+    while let Ok(Some(_)) = tokenizer.next_token() {
+        let _ = tokenizer.get_pos();
+    }
+});
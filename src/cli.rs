@@ -0,0 +1,405 @@
+//! The command line of the Linsl binary: its subcommands and their flags, read into a `Config`.
+//!
+//! The first argument which is not a flag can name a subcommand; otherwise the subcommand is
+//! `run`, and the argument is the first file to run. Flags taking a value accept it either as the
+//! next argument or after a `=`, e.g. `--image img` or `--image=img`, and `--` ends the flags, so
+//! that everything after it is a file.
+
+use linsl::datatypes::{Engine, WarningPolicy};
+
+/// The help printed by `--help`.
+pub const HELP: &str = "\
+Linsl, an interpreter for a small Lisp.
+
+Usage: Linsl [run] [OPTIONS] [FILE]...
+       Linsl repl [OPTIONS]
+       Linsl check [--deny-warnings] FILE...
+       Linsl fmt FILE...
+       Linsl test [OPTIONS] FILE...
+
+Commands:
+  run    Run the files, or start the REPL if there are none (the default)
+  repl   Start the REPL
+  check  Lint the files without running them
+  fmt    Print the files indented by how deeply they are nested
+  test   Run the files, and then the tests they define
+
+Options:
+  -e, --eval <CODE>        Run CODE after the files (run)
+  -i, --interactive        Start the REPL once the files have run (run)
+  -k, --keep-going         Report a form which fails and go on with the next one (run)
+      --vm                 Evaluate with the virtual machine rather than the tree walker
+      --optimize           Simplify every expression before it is evaluated
+      --show-ast           Print every form to stderr as it was parsed, before evaluating it
+      --trace-eval         Log every evaluation and its result to stderr
+      --trace-width <N>    Cut the expressions logged by --trace-eval off after N characters
+      --debug              Step through the evaluation with the debugger
+      --profile            Print a profile of the evaluation once it is done
+      --warnings <POLICY>  What to do with warnings: error, print (default) or silent
+      --image <PATH>       Restore the bindings saved to an image before running anything
+      --color <WHEN>       Color the REPL: auto (default), always or never
+      --print-length <N>   Print at most N elements of a list at the REPL (default 100), or all
+                           of them if N is none
+      --print-depth <N>    Print lists nested at most N levels deep at the REPL (default 100), or
+                           however deep if N is none
+      --deny-warnings      Fail if there are any warnings (check)
+  -h, --help               Print this help
+  -V, --version            Print the version
+";
+
+/// What the binary is asked to do, along with what it is done to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run the files and then the code given with -e, printing the value of every expression, and
+    /// start the REPL if there was nothing to run or if it was asked for.
+    Run { paths: Vec<String>, eval: Vec<String>, interactive: bool },
+    /// Start the REPL.
+    Repl,
+    /// Lint the files rather than running them, failing on warnings if they are denied.
+    Check { paths: Vec<String>, deny_warnings: bool },
+    /// Print the files indented.
+    Fmt { paths: Vec<String> },
+    /// Run the files, and then the tests they define.
+    Test { paths: Vec<String> },
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Run { paths: Vec::new(), eval: Vec::new(), interactive: false }
+    }
+}
+
+/// When to print in color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Color {
+    /// Only when printing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Whether to print in color to a stream, given whether it is a terminal.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            Color::Auto => terminal,
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+/// The configuration given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub command: Command,
+    pub engine: Engine,
+    /// Whether to run expressions through the optimization pass before evaluating them.
+    pub optimize: bool,
+    /// Whether to go on with the next form when one fails, rather than stopping.
+    pub keep_going: bool,
+    /// Whether to print every form as it was parsed before evaluating it.
+    pub show_ast: bool,
+    /// Whether to log every evaluation of the tree walker.
+    pub trace: bool,
+    /// How many characters of the expressions logged to keep, if not the default.
+    pub trace_width: Option<usize>,
+    /// Whether to step through the evaluation with the step debugger.
+    pub debug: bool,
+    /// Whether to profile the evaluation, printing the profile once it is done.
+    pub profile: bool,
+    /// What is done with the warnings given while running.
+    pub warnings: WarningPolicy,
+    /// The image to restore before running anything, if any.
+    pub image: Option<String>,
+    pub color: Color,
+    /// The limit on the elements of a list printed at the REPL, if it is not the default. The
+    /// limit itself is None if everything is printed.
+    pub print_length: Option<Option<usize>>,
+    /// The limit on the nesting of the lists printed at the REPL, if it is not the default.
+    pub print_depth: Option<Option<usize>>,
+}
+
+/// What the command line asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Run(Config),
+    /// Print the help.
+    Help,
+    /// Print the version.
+    Version,
+}
+
+/// The subcommands, by name.
+const COMMANDS: &[&str] = &["run", "repl", "check", "fmt", "test"];
+
+/// The flags which only some subcommands accept, along with those subcommands.
+const RESTRICTED_FLAGS: &[(&str, &[&str])] = &[
+    ("--eval", &["run"]),
+    ("--interactive", &["run"]),
+    ("--keep-going", &["run"]),
+    ("--vm", &["run", "repl", "test"]),
+    ("--optimize", &["run", "repl", "test"]),
+    ("--show-ast", &["run", "repl", "test"]),
+    ("--trace-eval", &["run", "repl", "test"]),
+    ("--trace-width", &["run", "repl", "test"]),
+    ("--debug", &["run", "repl", "test"]),
+    ("--profile", &["run", "repl", "test"]),
+    ("--warnings", &["run", "repl", "test"]),
+    ("--image", &["run", "repl", "test"]),
+    ("--color", &["run", "repl"]),
+    ("--print-length", &["run", "repl"]),
+    ("--print-depth", &["run", "repl"]),
+    ("--deny-warnings", &["check"]),
+];
+
+/// A limit given to a flag, which is either a number or `none` for no limit.
+fn parse_limit(flag: &str, value: &str) -> Result<Option<usize>, String> {
+    match value {
+        "none" => Ok(None),
+        _ => value.parse().map(Some)
+            .map_err(|_| format!("Expected a number or none for {}, found \'{}\'", flag, value)),
+    }
+}
+
+/// Read the command line arguments, without the name of the binary. Returns an error describing
+/// the problem if they are not understood, e.g. an unknown flag or flags which conflict.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Action, String> {
+    let mut config = Config::default();
+    let mut command: Option<String> = None;
+    let mut paths = Vec::new();
+    let mut eval = Vec::new();
+    let (mut interactive, mut deny_warnings) = (false, false);
+    // The flags given, by their long names, to be checked against the subcommand.
+    let mut given: Vec<&'static str> = Vec::new();
+
+    let mut args = args.into_iter();
+    let mut flags_ended = false;
+    while let Some(arg) = args.next() {
+        if flags_ended || !arg.starts_with('-') || arg == "-" {
+            match &command {
+                None if paths.is_empty() && COMMANDS.contains(&arg.as_str()) => {
+                    command = Some(arg);
+                },
+                _ => paths.push(arg),
+            }
+            continue;
+        };
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let flag: &'static str = match flag {
+            "--" => {
+                flags_ended = true;
+                continue;
+            },
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "-e" | "--eval" => "--eval",
+            "-i" | "--interactive" => "--interactive",
+            "-k" | "--keep-going" => "--keep-going",
+            "--vm" => "--vm",
+            "--optimize" => "--optimize",
+            "--show-ast" => "--show-ast",
+            "--trace-eval" => "--trace-eval",
+            "--trace-width" => "--trace-width",
+            "--debug" => "--debug",
+            "--profile" => "--profile",
+            "--warnings" => "--warnings",
+            "--image" => "--image",
+            "--color" => "--color",
+            "--print-length" => "--print-length",
+            "--print-depth" => "--print-depth",
+            "--deny-warnings" => "--deny-warnings",
+            _ => return Err(format!("Unknown option \'{}\'", arg)),
+        };
+        let takes_value = matches!(
+            flag,
+            "--eval" | "--warnings" | "--image" | "--color" | "--trace-width" | "--print-length"
+                | "--print-depth"
+        );
+        let value = match (takes_value, inline_value) {
+            (true, Some(value)) => value,
+            (true, None) => args.next().ok_or_else(|| format!("{} must be given a value", flag))?,
+            (false, Some(_)) => return Err(format!("{} does not take a value", flag)),
+            (false, None) => String::new(),
+        };
+        given.push(flag);
+
+        match flag {
+            "--eval" => eval.push(value),
+            "--interactive" => interactive = true,
+            "--keep-going" => config.keep_going = true,
+            "--vm" => config.engine = Engine::Vm,
+            "--optimize" => config.optimize = true,
+            "--show-ast" => config.show_ast = true,
+            "--trace-eval" => config.trace = true,
+            "--trace-width" => match value.parse() {
+                Ok(width) => config.trace_width = Some(width),
+                Err(_) => {
+                    return Err(format!("Expected a width for --trace-width, found \'{}\'", value));
+                },
+            },
+            "--debug" => config.debug = true,
+            "--profile" => config.profile = true,
+            "--warnings" => config.warnings = match value.as_str() {
+                "error" => WarningPolicy::Error,
+                "print" => WarningPolicy::Print,
+                "silent" => WarningPolicy::Silent,
+                _ => return Err(format!(
+                    "Unknown warning policy \'{}\', expected error, print or silent", value
+                )),
+            },
+            "--image" => config.image = Some(value),
+            "--color" => config.color = match value.as_str() {
+                "auto" => Color::Auto,
+                "always" => Color::Always,
+                "never" => Color::Never,
+                _ => return Err(
+                    format!("Unknown color choice \'{}\', expected auto, always or never", value)
+                ),
+            },
+            "--print-length" => config.print_length = Some(parse_limit(flag, &value)?),
+            "--print-depth" => config.print_depth = Some(parse_limit(flag, &value)?),
+            _ => deny_warnings = true,
+        }
+    }
+
+    let command = command.unwrap_or_else(|| "run".to_string());
+    for flag in &given {
+        if let Some((_, commands)) = RESTRICTED_FLAGS.iter().find(|(f, _)| f == flag)
+            && !commands.contains(&command.as_str())
+        {
+            return Err(format!("{} cannot be used with {}", flag, command));
+        };
+    }
+    if config.debug && config.engine == Engine::Vm {
+        return Err("The debugger cannot be used with the virtual machine".to_string());
+    };
+    if config.profile && config.engine == Engine::Vm {
+        return Err("The profiler cannot be used with the virtual machine".to_string());
+    };
+    if config.trace && config.engine == Engine::Vm {
+        return Err("Evaluations cannot be traced with the virtual machine".to_string());
+    };
+    if config.trace_width.is_some() && !config.trace {
+        return Err("--trace-width can only be used with --trace-eval".to_string());
+    };
+    if paths.is_empty() && matches!(command.as_str(), "check" | "fmt" | "test") {
+        return Err(format!("{} must be given the files to {}", command, command));
+    };
+
+    config.command = match command.as_str() {
+        "repl" if !paths.is_empty() => {
+            return Err("repl does not take any files; use run -i to run files first".to_string());
+        },
+        "repl" => Command::Repl,
+        "check" => Command::Check { paths, deny_warnings },
+        "fmt" => Command::Fmt { paths },
+        "test" => Command::Test { paths },
+        _ => Command::Run { paths, eval, interactive },
+    };
+    Ok(Action::Run(config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Action, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn config(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Action::Run(config)) => config,
+            res => panic!("Expected a configuration for {:?}, got {:?}", args, res),
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn run() {
+        assert_eq!(Config::default(), config(&[]));
+        let run = config(&["--vm", "a.linsl", "run", "-i", "-e", "(+ 1 2)", "--eval=x"]);
+        assert_eq!(
+            Command::Run {
+                paths: paths(&["a.linsl", "run"]),
+                eval: paths(&["(+ 1 2)", "x"]),
+                interactive: true,
+            },
+            run.command
+        );
+        assert_eq!(Engine::Vm, run.engine);
+
+        let run = config(&["run", "--optimize", "--image", "img", "--warnings=silent", "--show-ast",
+                           "--", "-f"]);
+        assert_eq!(
+            Command::Run { paths: paths(&["-f"]), eval: vec![], interactive: false },
+            run.command
+        );
+        assert!(run.optimize && run.show_ast && !run.keep_going);
+        assert!(config(&["-k", "a"]).keep_going);
+        assert_eq!(Some("img".to_string()), run.image);
+        assert_eq!(WarningPolicy::Silent, run.warnings);
+    }
+
+    #[test]
+    fn subcommands() {
+        let repl = config(&["--color", "never", "repl", "--profile"]);
+        assert_eq!((Command::Repl, Color::Never, true), (repl.command, repl.color, repl.profile));
+        let repl = config(&["repl", "--trace-eval", "--trace-width=20"]);
+        assert_eq!((true, Some(20)), (repl.trace, repl.trace_width));
+        let repl = config(&["repl", "--print-length", "none", "--print-depth=3"]);
+        assert_eq!((Some(None), Some(Some(3))), (repl.print_length, repl.print_depth));
+        assert_eq!(
+            Command::Check { paths: paths(&["a", "b"]), deny_warnings: true },
+            config(&["check", "a", "--deny-warnings", "b"]).command
+        );
+        assert_eq!(Command::Fmt { paths: paths(&["a"]) }, config(&["fmt", "a"]).command);
+        let test = config(&["test", "--vm", "a"]);
+        assert_eq!(Command::Test { paths: paths(&["a"]) }, test.command);
+        assert_eq!(Engine::Vm, test.engine);
+
+        assert_eq!(Ok(Action::Help), parse(&["check", "-h"]));
+        assert_eq!(Ok(Action::Version), parse(&["--version", "--unknown"]));
+    }
+
+    #[test]
+    fn errors() {
+        for (args, err) in [
+            (&["--unknown", "a"][..], "Unknown option '--unknown'"),
+            (&["-x"], "Unknown option '-x'"),
+            (&["--image"], "--image must be given a value"),
+            (&["--vm=yes"], "--vm does not take a value"),
+            (&["--warnings=loud"], "Unknown warning policy 'loud', expected error, print or \
+                                    silent"),
+            (&["--color", "blue"], "Unknown color choice 'blue', expected auto, always or never"),
+            (&["check", "--vm", "a"], "--vm cannot be used with check"),
+            (&["fmt", "--show-ast", "a"], "--show-ast cannot be used with fmt"),
+            (&["fmt", "-e", "1", "a"], "--eval cannot be used with fmt"),
+            (&["test", "-i", "a"], "--interactive cannot be used with test"),
+            (&["repl", "--keep-going"], "--keep-going cannot be used with repl"),
+            (&["--deny-warnings", "a"], "--deny-warnings cannot be used with run"),
+            (&["--debug", "--vm"], "The debugger cannot be used with the virtual machine"),
+            (&["repl", "--vm", "--profile"], "The profiler cannot be used with the virtual \
+                                               machine"),
+            (&["--trace-eval", "--vm"], "Evaluations cannot be traced with the virtual machine"),
+            (&["--trace-width", "20"], "--trace-width can only be used with --trace-eval"),
+            (&["--trace-eval", "--trace-width", "x"], "Expected a width for --trace-width, \
+                                                        found 'x'"),
+            (&["--print-depth", "-1"], "Expected a number or none for --print-depth, found '-1'"),
+            (&["test", "--print-length=1", "a"], "--print-length cannot be used with test"),
+            (&["check"], "check must be given the files to check"),
+            (&["test", "--"], "test must be given the files to test"),
+            (&["repl", "a"], "repl does not take any files; use run -i to run files first"),
+        ] {
+            assert_eq!(Err(err.to_string()), parse(args), "{:?}", args);
+        }
+    }
+}
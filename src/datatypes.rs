@@ -1,7 +1,33 @@
 //! The datatypes used throughout the code base.
-use std::{collections::HashMap, fmt};
+use std::{
+    any::{type_name, Any},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    ops::Deref,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering},
+        Arc, LazyLock, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard
+    },
+};
 
-use crate::primitives::{add, append, car, cdr, eq, eq_types, gr, inv, is_nil, list, mul, neg};
+use crate::primitives::{
+    control, dicts, functions, introspection, io, iteration, keywords, lists, math, net, paths,
+    patterns, predicates, random, strings, testing, time, vectors, Help, PrimitiveEntry
+};
+use crate::debugger::DebugHook;
+use crate::modules::{default_library_path, Modules};
+use crate::parsing::Tokenizer;
+use crate::printing::{write_expr, Limited, PrintLimits};
+use crate::profiler::Profile;
+use crate::random::Rng;
+use crate::sandbox::{Capability, SandboxPolicy};
+use crate::testing::{ReportOutput, Test};
+use crate::trace::Trace;
+use crate::vm::CodeCache;
 
 pub type Num = f64;
 pub type PosNum = usize;
@@ -11,43 +37,800 @@ pub type Pos = (PosNum, PosNum);
 
 pub type LinslRes = Result<LinslExpr, LinslErr>;
 
+/// The default for how deeply nested an evaluation may get before it is aborted. It is reached
+/// before a stack of DEFAULT_STACK_SIZE bytes overflows, even in debug builds.
+pub const DEFAULT_MAX_DEPTH: PosNum = 2000;
+/// The stack size needed by a thread evaluating Linsl code with the default limits.
+pub const DEFAULT_STACK_SIZE: usize = 64 << 20;
+/// The default for how deeply nested lists may be when parsing. Like DEFAULT_MAX_DEPTH, it is
+/// reached before a stack of DEFAULT_STACK_SIZE bytes overflows.
+pub const DEFAULT_MAX_NESTING: PosNum = 2000;
+
 /// The basic unit of code in the language. Any valid piece of Linsl code is an expression.
+///
+/// Every expression nested in another one is shared rather than owned, so cloning an expression is
+/// cheap no matter how large it is. The sharing is thread safe, so expressions are Send and Sync.
 #[derive(Debug, Clone)]
 pub enum LinslExpr {
     /// One of '#t' or '#f'.
     Bool(bool),
-    /// A lambda function, in the spirit of lambda calculus.
-    Closure(Box<LinslExpr>, Box<LinslExpr>),
-    List(Vec<LinslExpr>),
+    /// A lambda function, in the spirit of lambda calculus. Along with the parameters and the body
+    /// it holds the environment it was created in, which is where its free symbols are looked up,
+    /// the name it was first defined under, if it has been defined, and its docstring, if any.
+    Closure(Arc<LinslExpr>, Arc<LinslExpr>, LinslEnv, Option<Symbol>, Option<Arc<str>>),
+    /// A function made by `case-lambda`, holding a closure for each of its clauses. It is applied
+    /// as the first of them accepting as many arguments as it is given.
+    CaseLambda(Arc<[LinslExpr]>),
+    /// A complex number, as its real and imaginary parts, written e.g. `3+4i` or `-2i`. The
+    /// imaginary part is never zero; such numbers are real, and so `Number`s. See `complex`.
+    Complex(Num, Num),
+    /// A mutable mapping from keys to values.
+    Dict(LinslDict),
+    /// The function `call/ec` passes to its argument, which makes the `call/ec` return the value
+    /// it is applied to.
+    Escape(LinslEscape),
+    /// An opaque value owned by the host application, along with the name of its type. Linsl code
+    /// can pass it around, but only primitives registered by the host can look inside it.
+    Foreign(&'static str, Arc<dyn Any + Send + Sync>),
+    /// The state of iteration over a list, vector or dict, created with `iterate`.
+    Iterator(Arc<LinslIter>),
+    /// A name which evaluates to itself, written `:name` (or `#:name`).
+    Keyword(Symbol),
+    List(LinslList),
     Number(Num),
-    /// A macro, which is similar to a closure but does not evaluate its parameters.
-    Macro(Box<LinslExpr>, Box<LinslExpr>),
+    /// A parameter created with `make-parameter`, which gives its value when applied to no
+    /// arguments. The value can be overridden for the duration of a `parameterize` form.
+    Parameter(LinslParameter),
+    /// A macro, which is similar to a closure but does not evaluate its parameters. Like a closure
+    /// it may have a docstring.
+    Macro(Arc<LinslExpr>, Arc<LinslExpr>, Option<Arc<str>>),
+    /// A value which is computed the first time it is forced, created with `delay`.
+    Promise(LinslPromise),
     /// A built in transformation of expressions. These have deliberately been kept as few as
     /// possible; there are just enough of them to allow other functions that are desirable to be
-    /// defined in Linsl.
-    Primitive(fn(&[LinslExpr]) -> LinslRes),
-    Symbol(String),
+    /// defined in Linsl. The name is the one it is registered under, and is printed along with it.
+    Primitive(&'static str, fn(&[LinslExpr]) -> LinslRes),
+    /// A built in function which is also given the context, which allows it to apply the functions
+    /// it is given, like `map` does. Otherwise it is just like a primitive.
+    CtxPrimitive(&'static str, fn(&[LinslExpr], &mut LinslCtx) -> LinslRes),
+    /// An instance of a record type created with `define-record`.
+    Record(LinslRecord),
+    /// One of the functions `define-record` defines for a record type. They are applied just like
+    /// primitives.
+    RecordFn(Arc<RecordType>, RecordOp),
+    /// An immutable piece of text, written between double quotes.
+    String(Arc<str>),
+    Symbol(Symbol),
+    /// Several values returned at once by `values`, to be taken apart by `call-with-values`.
+    Values(Arc<[LinslExpr]>),
+    /// A mutable, fixed length sequence of expressions, which can be indexed in constant time.
+    Vector(LinslVector),
 }
 
+/// Expressions are printed as Linsl code, which parses back into the same expression. Macros print
+/// as the `macro` form that creates them (without their environment), and record functions as the
+/// name they are bound to. Only closures, case-lambdas, primitives, foreign values, dicts, escapes,
+/// iterators, parameters, promises, records and multiple values cannot be read back.
 impl fmt::Display for LinslExpr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let str = match self {
-            LinslExpr::Bool(b)          => if *b {"#t".to_string()} else {"#f".to_string()}
-            LinslExpr::Closure(ps, bd)  => format!("(lambda {}, {})", ps, bd),
-            LinslExpr::Primitive(_)     => "Primitive operator".to_string(),
-            LinslExpr::List(xs)         => {
-                let strs : Vec<String> = xs
-                    .iter()
-                    .map(|x| x.to_string())
-                    .collect();
-                format!("({})", strs.join(" "))
+        write_expr(f, self, PrintLimits::EXACT)
+    }
+}
+
+impl LinslExpr {
+    /// Display the expression within the limits given, e.g. `expr.limited(PrintLimits::REPL)`.
+    pub fn limited(&self, limits: PrintLimits) -> Limited<'_> {
+        Limited(self, limits)
+    }
+
+    /// Give a closure the name it is defined under. Closures keep the first name they are defined
+    /// under, so defining one under another name does not rename it, and other values are
+    /// returned as they are.
+    pub(crate) fn named(self, name: &Symbol) -> LinslExpr {
+        match self {
+            LinslExpr::Closure(ps, bd, env, None, doc) => {
+                LinslExpr::Closure(ps, bd, env, Some(name.clone()), doc)
+            },
+            LinslExpr::CaseLambda(clauses) => {
+                LinslExpr::CaseLambda(clauses.iter().map(|c| c.clone().named(name)).collect())
+            },
+            _ => self,
+        }
+    }
+
+    /// The complex number with the parts given, or the real number `re` if `im` is zero.
+    pub fn complex(re: Num, im: Num) -> LinslExpr {
+        if im == 0 as Num {
+            LinslExpr::Number(re)
+        } else {
+            LinslExpr::Complex(re, im)
+        }
+    }
+
+    /// The position of the opening parenthesis the expression was parsed from, if it is a list
+    /// which was parsed rather than built while running.
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            LinslExpr::List(l) => l.pos(),
+            _ => None,
+        }
+    }
+
+    /// Wrap a host value so that it can be passed through Linsl code. It is printed using the
+    /// name of its type, without the module path.
+    pub fn foreign<T: Any + Send + Sync>(value: T) -> LinslExpr {
+        let full_name = type_name::<T>();
+        let name = full_name.rsplit("::").next().unwrap_or(full_name);
+        LinslExpr::Foreign(name, Arc::new(value))
+    }
+
+    /// Retrieve the host value wrapped by `foreign`. Fails with a type error if the expression is
+    /// not a foreign value, or if it wraps a value of some other type.
+    pub fn downcast_foreign<T: Any + Send + Sync>(&self) -> Result<Arc<T>, LinslErr> {
+        match self {
+            LinslExpr::Foreign(n, v) => v.clone().downcast::<T>().map_err(|_|
+                LinslErr::TypeError(
+                    // TODO: Fix pos
+                    format!("Expected foreign value of type {}, found one of type {}",
+                        type_name::<T>(),
+                        n),
+                    (0, 0)
+                )
+            ),
+            _ => Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos
+                    format!("Expected foreign value of type {}, found \'{}\'",
+                        type_name::<T>(),
+                        self),
+                    (0, 0)
+                )
+            ),
+        }
+    }
+}
+
+/// A string as it is written in Linsl code, i.e. between double quotes, with backslashes, double
+/// quotes, newlines and tabs escaped.
+pub(crate) fn quote_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The bits a number is compared and hashed by. All NaNs are considered the same number, so that
+/// every number is equal to itself, and so are 0 and -0, since `=` considers them equal.
+fn number_bits(v: Num) -> u64 {
+    if v.is_nan() {
+        Num::NAN.to_bits()
+    } else if v == 0.0 {
+        0.0f64.to_bits()
+    } else {
+        v.to_bits()
+    }
+}
+
+/// Expressions are equal if they are the same value: bools, keywords, numbers and symbols are
+/// compared by value and lists and multiple values element by element, while everything else
+/// (closures, dicts, escapes, iterators, macros, parameters, primitives, promises, foreign values,
+/// records and vectors) is only equal to itself (or copies of itself).
+/// This makes expressions usable as keys in e.g. a `HashMap`, unlike `=`, which fails for most
+/// types.
+impl PartialEq for LinslExpr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LinslExpr::Bool(a), LinslExpr::Bool(b)) => a == b,
+            (LinslExpr::Closure(p1, b1, e1, _, _), LinslExpr::Closure(p2, b2, e2, _, _)) =>
+                Arc::ptr_eq(p1, p2) && Arc::ptr_eq(b1, b2) && Arc::ptr_eq(&e1.0, &e2.0),
+            (LinslExpr::CaseLambda(xs), LinslExpr::CaseLambda(ys)) => xs == ys,
+            (LinslExpr::Complex(a, b), LinslExpr::Complex(c, d)) =>
+                number_bits(*a) == number_bits(*c) && number_bits(*b) == number_bits(*d),
+            (LinslExpr::Dict(a), LinslExpr::Dict(b)) => Arc::ptr_eq(&a.0, &b.0),
+            (LinslExpr::Escape(a), LinslExpr::Escape(b)) => a.same(b),
+            (LinslExpr::Foreign(_, v1), LinslExpr::Foreign(_, v2)) =>
+                Arc::as_ptr(v1) as *const () == Arc::as_ptr(v2) as *const (),
+            (LinslExpr::Iterator(a), LinslExpr::Iterator(b)) => Arc::ptr_eq(a, b),
+            (LinslExpr::Keyword(a), LinslExpr::Keyword(b)) => a == b,
+            (LinslExpr::List(xs), LinslExpr::List(ys)) => **xs == **ys,
+            (LinslExpr::Number(a), LinslExpr::Number(b)) => number_bits(*a) == number_bits(*b),
+            (LinslExpr::Parameter(a), LinslExpr::Parameter(b)) => a.same(b),
+            (LinslExpr::Macro(p1, b1, _), LinslExpr::Macro(p2, b2, _)) =>
+                Arc::ptr_eq(p1, p2) && Arc::ptr_eq(b1, b2),
+            (LinslExpr::Primitive(n1, f1), LinslExpr::Primitive(n2, f2)) =>
+                n1 == n2 && std::ptr::fn_addr_eq(*f1, *f2),
+            (LinslExpr::CtxPrimitive(n1, f1), LinslExpr::CtxPrimitive(n2, f2)) =>
+                n1 == n2 && std::ptr::fn_addr_eq(*f1, *f2),
+            (LinslExpr::Promise(a), LinslExpr::Promise(b)) => Arc::ptr_eq(&a.0, &b.0),
+            (LinslExpr::Record(a), LinslExpr::Record(b)) => Arc::ptr_eq(&a.fields.0, &b.fields.0),
+            (LinslExpr::RecordFn(k1, op1), LinslExpr::RecordFn(k2, op2)) =>
+                Arc::ptr_eq(k1, k2) && op1 == op2,
+            (LinslExpr::String(a), LinslExpr::String(b)) => a == b,
+            (LinslExpr::Symbol(a), LinslExpr::Symbol(b)) => a == b,
+            (LinslExpr::Values(xs), LinslExpr::Values(ys)) => xs == ys,
+            (LinslExpr::Vector(a), LinslExpr::Vector(b)) => Arc::ptr_eq(&a.0, &b.0),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LinslExpr {}
+
+/// Consistent with `PartialEq`: whatever equality is based on is what is hashed. In particular only
+/// the addresses of a closure's environment and of dicts, escapes, iterators, parameters, promises,
+/// records and vectors are hashed, not their contents, which may change.
+impl Hash for LinslExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LinslExpr::Bool(b) => b.hash(state),
+            LinslExpr::Closure(ps, bd, env, _, _) => {
+                Arc::as_ptr(ps).hash(state);
+                Arc::as_ptr(bd).hash(state);
+                Arc::as_ptr(&env.0).hash(state);
+            },
+            LinslExpr::CaseLambda(clauses) => clauses.hash(state),
+            LinslExpr::Complex(re, im) => {
+                number_bits(*re).hash(state);
+                number_bits(*im).hash(state);
+            },
+            LinslExpr::Dict(d) => Arc::as_ptr(&d.0).hash(state),
+            LinslExpr::Escape(e) => Arc::as_ptr(&e.0).hash(state),
+            LinslExpr::Foreign(_, v) => (Arc::as_ptr(v) as *const ()).hash(state),
+            LinslExpr::Iterator(it) => Arc::as_ptr(it).hash(state),
+            LinslExpr::Keyword(k) => k.hash(state),
+            LinslExpr::List(xs) => (**xs).hash(state),
+            LinslExpr::Number(v) => number_bits(*v).hash(state),
+            LinslExpr::Parameter(p) => Arc::as_ptr(&p.0).hash(state),
+            LinslExpr::Macro(ps, bd, _) => {
+                Arc::as_ptr(ps).hash(state);
+                Arc::as_ptr(bd).hash(state);
+            },
+            LinslExpr::Primitive(n, _) | LinslExpr::CtxPrimitive(n, _) => n.hash(state),
+            LinslExpr::Promise(p) => Arc::as_ptr(&p.0).hash(state),
+            LinslExpr::Record(r) => Arc::as_ptr(&r.fields.0).hash(state),
+            LinslExpr::RecordFn(kind, op) => {
+                Arc::as_ptr(kind).hash(state);
+                op.hash(state);
+            },
+            LinslExpr::String(s) => s.hash(state),
+            LinslExpr::Symbol(s) => s.hash(state),
+            LinslExpr::Values(xs) => xs.hash(state),
+            LinslExpr::Vector(v) => Arc::as_ptr(&v.0).hash(state),
+        }
+    }
+}
+
+/// Print a number so that it reads back in as the same number. Infinities and NaN are written
+/// +inf.0, -inf.0 and +nan.0, as in Scheme.
+pub fn format_num(v: Num) -> String {
+    if v.is_nan() {
+        "+nan.0".to_string()
+    } else if v.is_infinite() {
+        if v > 0 as Num { "+inf.0" } else { "-inf.0" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// Print a complex number so that it reads back in as the same number, e.g. 3+4i, or +2i if the
+/// real part is zero.
+pub fn format_complex(re: Num, im: Num) -> String {
+    let sign = if im.is_sign_negative() && !im.is_nan() { '-' } else { '+' };
+    let imag = format_num(im.abs());
+    let imag = imag.trim_start_matches('+');
+    if re == 0 as Num {
+        format!("{}{}i", sign, imag)
+    } else {
+        format!("{}{}{}i", format_num(re), sign, imag)
+    }
+}
+
+/// A list of expressions. The elements are shared both between clones of a list and between a list
+/// and its tail, so neither cloning a list nor taking its tail copies any elements.
+#[derive(Clone, Default)]
+pub struct LinslList {
+    elems: Arc<[LinslExpr]>,
+    /// The index of the first element in `elems` which is part of the list.
+    start: usize,
+    /// The position of the opening parenthesis the list was parsed from, if any.
+    pos: Option<Pos>,
+}
+
+impl LinslList {
+    /// Whether the two lists are the very same list, rather than just having equal elements. All
+    /// empty lists are the same list.
+    pub fn same(&self, other: &LinslList) -> bool {
+        (self.is_empty() && other.is_empty())
+            || (Arc::ptr_eq(&self.elems, &other.elems) && self.start == other.start)
+    }
+
+    /// The list without its first element, or the empty list if it has none.
+    pub fn tail(&self) -> LinslList {
+        LinslList {
+            elems: self.elems.clone(),
+            start: (self.start + 1).min(self.elems.len()),
+            pos: None,
+        }
+    }
+
+    /// The position of the opening parenthesis the list was parsed from, or None if it was built
+    /// while running, e.g. by a macro.
+    pub fn pos(&self) -> Option<Pos> {
+        self.pos
+    }
+
+    /// The list, parsed from an opening parenthesis at the position given.
+    pub fn with_pos(self, pos: Pos) -> LinslList {
+        LinslList { pos: Some(pos), ..self }
+    }
+}
+
+impl Deref for LinslList {
+    type Target = [LinslExpr];
+
+    fn deref(&self) -> &[LinslExpr] {
+        &self.elems[self.start..]
+    }
+}
+
+impl From<Vec<LinslExpr>> for LinslList {
+    fn from(elems: Vec<LinslExpr>) -> Self {
+        LISTS.fetch_add(1, AtomicOrdering::Relaxed);
+        LinslList { elems: elems.into(), start: 0, pos: None }
+    }
+}
+
+impl FromIterator<LinslExpr> for LinslList {
+    fn from_iter<I: IntoIterator<Item = LinslExpr>>(iter: I) -> Self {
+        LISTS.fetch_add(1, AtomicOrdering::Relaxed);
+        LinslList { elems: iter.into_iter().collect(), start: 0, pos: None }
+    }
+}
+
+impl fmt::Debug for LinslList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// The elements of a vector. Unlike a list, a vector is mutable, and every clone of it refers to
+/// the very same elements; setting an element of one is seen by all of them.
+#[derive(Clone, Default)]
+pub struct LinslVector(pub Arc<RwLock<Vec<LinslExpr>>>);
+
+impl LinslVector {
+    /// The elements, locked for reading.
+    pub fn elems(&self) -> RwLockReadGuard<'_, Vec<LinslExpr>> {
+        // Elements are only ever replaced one at a time, so a vector is never left half updated.
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The elements, locked for writing.
+    pub fn elems_mut(&self) -> RwLockWriteGuard<'_, Vec<LinslExpr>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl From<Vec<LinslExpr>> for LinslVector {
+    fn from(elems: Vec<LinslExpr>) -> Self {
+        LinslVector(Arc::new(RwLock::new(elems)))
+    }
+}
+
+impl fmt::Debug for LinslVector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.elems().iter()).finish()
+    }
+}
+
+/// A key of a dict. Only bools, real numbers, strings, symbols and keywords can be keys, since they
+/// can never change. They are compared the same way as by `=`, and ordered with bools first, then
+/// numbers, then strings, then symbols and then keywords (the last three by their text).
+#[derive(Debug, Clone)]
+pub enum DictKey {
+    Bool(bool),
+    Number(Num),
+    String(Arc<str>),
+    Symbol(Symbol),
+    Keyword(Symbol),
+}
+
+impl TryFrom<&LinslExpr> for DictKey {
+    type Error = LinslErr;
+
+    fn try_from(expr: &LinslExpr) -> Result<Self, Self::Error> {
+        match expr {
+            LinslExpr::Bool(b) => Ok(DictKey::Bool(*b)),
+            LinslExpr::Number(v) => Ok(DictKey::Number(*v)),
+            LinslExpr::String(s) => Ok(DictKey::String(s.clone())),
+            LinslExpr::Symbol(s) => Ok(DictKey::Symbol(s.clone())),
+            LinslExpr::Keyword(k) => Ok(DictKey::Keyword(k.clone())),
+            _ => Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos
+                    format!(
+                        "Only bools, real numbers, strings, symbols and keywords can be dict \
+                         keys, found \'{}\'",
+                        expr
+                    ),
+                    (0, 0)
+                )
+            ),
+        }
+    }
+}
+
+impl From<&DictKey> for LinslExpr {
+    fn from(key: &DictKey) -> Self {
+        match key {
+            DictKey::Bool(b) => LinslExpr::Bool(*b),
+            DictKey::Number(v) => LinslExpr::Number(*v),
+            DictKey::String(s) => LinslExpr::String(s.clone()),
+            DictKey::Symbol(s) => LinslExpr::Symbol(s.clone()),
+            DictKey::Keyword(k) => LinslExpr::Keyword(k.clone()),
+        }
+    }
+}
+
+impl PartialEq for DictKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DictKey {}
+
+impl Hash for DictKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            DictKey::Bool(b) => b.hash(state),
+            DictKey::Number(v) => number_bits(*v).hash(state),
+            DictKey::String(s) => s.hash(state),
+            DictKey::Symbol(s) | DictKey::Keyword(s) => s.hash(state),
+        }
+    }
+}
+
+impl PartialOrd for DictKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DictKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (DictKey::Bool(a), DictKey::Bool(b)) => a.cmp(b),
+            (DictKey::Number(a), DictKey::Number(b)) =>
+                Num::from_bits(number_bits(*a)).total_cmp(&Num::from_bits(number_bits(*b))),
+            (DictKey::String(a), DictKey::String(b)) => a.cmp(b),
+            (DictKey::Symbol(a), DictKey::Symbol(b)) | (DictKey::Keyword(a), DictKey::Keyword(b)) =>
+                if a == b { Ordering::Equal } else { a.as_str().cmp(b.as_str()) },
+            _ => key_rank(self).cmp(&key_rank(other)),
+        }
+    }
+}
+
+/// The position of the kind of a key in the order of dict keys.
+fn key_rank(key: &DictKey) -> u8 {
+    match key {
+        DictKey::Bool(_) => 0,
+        DictKey::Number(_) => 1,
+        DictKey::String(_) => 2,
+        DictKey::Symbol(_) => 3,
+        DictKey::Keyword(_) => 4,
+    }
+}
+
+impl fmt::Display for DictKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", LinslExpr::from(self))
+    }
+}
+
+/// The entries of a dict. Like a vector, a dict is mutable and shared between all its clones.
+#[derive(Clone, Default)]
+pub struct LinslDict(pub Arc<RwLock<HashMap<DictKey, LinslExpr>>>);
+
+impl LinslDict {
+    /// The entries, locked for reading.
+    pub fn entries(&self) -> RwLockReadGuard<'_, HashMap<DictKey, LinslExpr>> {
+        // Entries are only ever inserted or removed one at a time, so a dict is never left half
+        // updated.
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The entries, locked for writing.
+    pub fn entries_mut(&self) -> RwLockWriteGuard<'_, HashMap<DictKey, LinslExpr>> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A copy of the entries, sorted by key.
+    pub fn sorted(&self) -> Vec<(DictKey, LinslExpr)> {
+        let mut entries: Vec<(DictKey, LinslExpr)> = self.entries()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        entries
+    }
+}
+
+impl fmt::Debug for LinslDict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.sorted()).finish()
+    }
+}
+
+/// Where iteration over a collection has got to. Iterators are immutable: `iter-next` gives a new
+/// iterator for the rest of the collection rather than advancing the one it is given.
+#[derive(Debug)]
+pub enum LinslIter {
+    /// The elements of a list which are left.
+    List(LinslList),
+    /// A vector, and the index of the next element. The vector is read as it is iterated, so an
+    /// element set before it is reached is seen with its new value.
+    Vector(LinslVector, PosNum),
+    /// The entries of a dict as (key value) lists, sorted by key when iteration started, and the
+    /// index of the next one.
+    Dict(Arc<[LinslExpr]>, PosNum),
+    /// A string, and the byte offset of the next character. Each character is given as a string of
+    /// its own.
+    String(Arc<str>, PosNum),
+}
+
+impl LinslIter {
+    /// The next element, along with an iterator for the rest, or None if there are no more.
+    pub fn next(&self) -> Option<(LinslExpr, LinslIter)> {
+        match self {
+            LinslIter::List(xs) => xs.first().map(|x| (x.clone(), LinslIter::List(xs.tail()))),
+            LinslIter::Vector(v, i) => {
+                v.elems().get(*i).map(|x| (x.clone(), LinslIter::Vector(v.clone(), i + 1)))
+            },
+            LinslIter::Dict(entries, i) => {
+                entries.get(*i).map(|x| (x.clone(), LinslIter::Dict(entries.clone(), i + 1)))
+            },
+            LinslIter::String(s, i) => s[*i..].chars().next().map(|c| {
+                let rest = LinslIter::String(s.clone(), i + c.len_utf8());
+                (LinslExpr::String(c.to_string().into()), rest)
+            }),
+        }
+    }
+}
+
+/// An escape created by `call/ec`. It can only be used while the `call/ec` which created it is
+/// being evaluated, i.e. while it is active; clones share whether it is.
+#[derive(Debug, Clone)]
+pub struct LinslEscape(pub Arc<AtomicBool>);
+
+impl LinslEscape {
+    /// Create an active escape.
+    pub fn new() -> LinslEscape {
+        LinslEscape(Arc::new(AtomicBool::new(true)))
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.0.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Make the escape unusable, once its `call/ec` has returned.
+    pub fn deactivate(&self) {
+        self.0.store(false, AtomicOrdering::SeqCst)
+    }
+
+    /// Whether the two are the same escape, i.e. escape to the same `call/ec`.
+    pub fn same(&self, other: &LinslEscape) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Default for LinslEscape {
+    fn default() -> Self {
+        LinslEscape::new()
+    }
+}
+
+/// A parameter, holding the value it has outside of any `parameterize` form. Parameters are told
+/// apart by identity, so clones are the same parameter.
+#[derive(Debug, Clone)]
+pub struct LinslParameter(pub Arc<LinslExpr>);
+
+impl LinslParameter {
+    pub fn new(initial: LinslExpr) -> LinslParameter {
+        LinslParameter(Arc::new(initial))
+    }
+
+    /// Whether the two are the same parameter.
+    pub fn same(&self, other: &LinslParameter) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The state of a promise.
+#[derive(Debug)]
+pub enum Promise {
+    /// Not yet forced: the expression to evaluate, and the environment to evaluate it in.
+    Delayed(LinslExpr, LinslEnv),
+    /// Forced, with the value the expression evaluated to.
+    Forced(LinslExpr),
+}
+
+/// A promise, shared between all its clones so that it is only ever forced once.
+#[derive(Debug, Clone)]
+pub struct LinslPromise(pub Arc<RwLock<Promise>>);
+
+impl LinslPromise {
+    /// Create a promise to evaluate `expr` in `env`.
+    pub fn new(expr: LinslExpr, env: LinslEnv) -> LinslPromise {
+        LinslPromise(Arc::new(RwLock::new(Promise::Delayed(expr, env))))
+    }
+
+    /// The state, locked for reading.
+    pub fn state(&self) -> RwLockReadGuard<'_, Promise> {
+        // The state is only ever replaced as a whole, so it is never left half updated.
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The state, locked for writing.
+    pub fn state_mut(&self) -> RwLockWriteGuard<'_, Promise> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A record type, as defined by `define-record`.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+}
+
+/// An instance of a record type. The fields are mutable, and shared between all clones of the
+/// record, just like the elements of a vector.
+#[derive(Debug, Clone)]
+pub struct LinslRecord {
+    pub kind: Arc<RecordType>,
+    /// The values of the fields, in the order they are listed in the record type.
+    pub fields: LinslVector,
+}
+
+/// The functions `define-record` defines for a record type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordOp {
+    /// Create an instance from the values of the fields, i.e. `make-name`.
+    Make,
+    /// Check if a value is an instance, i.e. `name?`.
+    Is,
+    /// Get the field with the given index, i.e. `name-field`.
+    Get(usize),
+    /// Set the field with the given index, i.e. `set-name-field!`.
+    Set(usize),
+}
+
+impl RecordOp {
+    /// The name the function is bound to, for a record type `kind`.
+    pub fn name(&self, kind: &RecordType) -> String {
+        match self {
+            RecordOp::Make => format!("make-{}", kind.name),
+            RecordOp::Is => format!("{}?", kind.name),
+            RecordOp::Get(i) => format!("{}-{}", kind.name, kind.fields[*i]),
+            RecordOp::Set(i) => format!("set-{}-{}!", kind.name, kind.fields[*i]),
+        }
+    }
+
+    /// How many arguments the function accepts, for a record type `kind`.
+    pub fn arity(&self, kind: &RecordType) -> Arity {
+        match self {
+            RecordOp::Make => Arity::Exactly(kind.fields.len()),
+            RecordOp::Is | RecordOp::Get(_) => Arity::Exactly(1),
+            RecordOp::Set(_) => Arity::Exactly(2),
+        }
+    }
+}
+
+/// Every symbol name seen so far, by any thread. Each name is stored once, and all symbols with that
+/// name share its allocation.
+static SYMBOLS: LazyLock<Mutex<HashSet<Arc<str>>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// An interned symbol name. Since there is only ever one allocation per name, symbols are compared
+/// and hashed by address, and cloning one only bumps a reference count.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    /// Look up the symbol called `name`, interning the name if it has not been seen before.
+    pub fn new(name: &str) -> Symbol {
+        let mut symbols = SYMBOLS.lock().unwrap_or_else(PoisonError::into_inner);
+        match symbols.get(name) {
+            Some(s) => Symbol(s.clone()),
+            None => {
+                let s: Arc<str> = Arc::from(name);
+                symbols.insert(s.clone());
+                Symbol(s)
             }
-            LinslExpr::Number(v)        => v.to_string(),
-            LinslExpr::Symbol(s)        => s.clone(),
-            LinslExpr::Macro(ps, bd)    => format!("(macro {}, {})", ps, bd),
-        };
+        }
+    }
 
-        write!(f, "{}", str)
+    /// The name of the symbol.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The number of distinct symbol names interned so far.
+    pub fn interned() -> usize {
+        SYMBOLS.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
     }
 }
 
@@ -58,9 +841,70 @@ pub enum LinslErr {
     /// occur.
     InternalError(String),
     SyntaxError(String, Pos),
-    /// Created if the number of opening parentheses is not the same as closing parentheses.
-    /// Returns (number of '(', number of ')')
+    /// Created when evaluation or parsing is nested more deeply than allowed, e.g. due to infinite
+    /// recursion. Returns the depth reached.
+    RecursionLimit(PosNum, Pos),
+    /// Created when an expression of the wrong type is supplied, e.g. when a host primitive
+    /// receives a foreign value wrapping some other type than the one it expects.
+    TypeError(String, Pos),
+    /// Created when indexing a vector outside of its bounds. Returns (index, length).
+    IndexError(PosNum, PosNum, Pos),
+    /// Created when a range of indices, e.g. of elements to copy, is not within the bounds of a
+    /// vector. Returns (start, end, length).
+    RangeError(PosNum, PosNum, PosNum, Pos),
+    /// Created when looking up a key which is not in a dict. Returns the key.
+    KeyError(String, Pos),
+    /// Created when the input ends before a parenthesis is closed. Returns the (line, column) of
+    /// the parenthesis.
     UnbalancedParens(PosNum, PosNum),
+    /// Created when a function is applied to the wrong number of arguments. Returns the name of
+    /// the function, how many arguments it accepts and how many it was given. The name is left
+    /// empty by the function itself, and filled in where it is applied.
+    ArityError(String, Arity, PosNum, Pos),
+    /// Like ArityError, but for a function accepting several numbers of arguments, such as a
+    /// case-lambda. Returns the name of the function, each number it accepts, and how many
+    /// arguments it was given.
+    ClauseArityError(String, Vec<Arity>, PosNum, Pos),
+    /// Created when an escape is applied, to unwind the evaluation up to the `call/ec` which
+    /// created it. Returns the escape, and the value that `call/ec` is to return.
+    Escape(LinslEscape, LinslExpr),
+    /// Created when a debugger aborts the evaluation.
+    Aborted(Pos),
+    /// Created when the evaluation has taken as many steps as its fuel allowed.
+    FuelExhausted(Pos),
+    /// Created when code cannot be read, e.g. because the file does not exist. Returns the path of
+    /// the file, if the code is read from one, and why it could not be read.
+    ReadError(Option<String>, String),
+    /// Created when something cannot be done to a file or directory, e.g. deleting a file which
+    /// does not exist. Returns what was being done, the path, and why it could not be done.
+    FileError(String, String, String),
+    /// Created when something cannot be done over the network, e.g. connecting to a port nothing
+    /// listens on, or receiving before a timeout. Returns the address, and why.
+    NetError(String, String),
+    /// Created when a regular expression cannot be compiled. Returns the message of the regex
+    /// crate, which shows where in the pattern the problem is.
+    PatternError(String, Pos),
+    /// Created when `require` finds no file for a name. Returns the name, and the paths searched.
+    ModuleNotFound(String, Vec<String>),
+    /// Created when binding a name again in the frame where it was defined as a constant. Returns
+    /// the name, and where it was defined.
+    ConstantError(String, Pos, Pos),
+    /// Created when an image cannot be saved or restored, e.g. because it holds a foreign value or
+    /// was saved by an incompatible version of Linsl. Returns why.
+    ImageError(String),
+    /// Created when applying a primitive or special form needing a capability which the sandbox
+    /// does not allow. Returns the name of the primitive or special form.
+    CapabilityDenied(String, Pos),
+    /// Created when a warning is given while the warning policy is `WarningPolicy::Error`.
+    /// Returns the warning.
+    DeniedWarning(LinslWarning),
+    /// Created when an assertion, e.g. `assert-equal`, does not hold. Returns what was found.
+    AssertionFailed(String, Pos),
+    /// Created when a string cannot be decoded, e.g. as base64 or as a time. Returns why, and the
+    /// index of the character of the string where decoding failed.
+    DecodeError(String, PosNum, Pos),
+    /// Created when no clause of a match form matches the value. Returns the value, as printed.
+    MatchError(String, Pos),
 }
 
 impl fmt::Display for LinslErr {
@@ -70,50 +914,898 @@ impl fmt::Display for LinslErr {
             LinslErr::SyntaxError(s, p) => {
                 format!("Syntax error at ({}, {}): {}", p.0, p.1, s)
             },
-            LinslErr::UnbalancedParens(v1, v2) => format!("Unbalanced Parenthesis ({}, {})", v1, v2),
+            LinslErr::RecursionLimit(d, p) => {
+                format!("Recursion limit reached at ({}, {}): nested {} levels deep", p.0, p.1, d)
+            },
+            LinslErr::TypeError(s, p) => {
+                format!("Type error at ({}, {}): {}", p.0, p.1, s)
+            },
+            LinslErr::IndexError(i, l, p) => {
+                format!("Index error at ({}, {}): index {} is out of range for length {}",
+                    p.0, p.1, i, l)
+            },
+            LinslErr::RangeError(s, e, l, p) => {
+                format!("Range error at ({}, {}): the range from {} to {} is not within length {}",
+                    p.0, p.1, s, e, l)
+            },
+            LinslErr::KeyError(k, p) => {
+                format!("Key error at ({}, {}): no entry for \'{}\'", p.0, p.1, k)
+            },
+            LinslErr::UnbalancedParens(l, c) => {
+                format!("Unbalanced Parenthesis at ({}, {}): it is never closed", l, c)
+            },
+            LinslErr::ArityError(name, arity, n, p) => {
+                format!("Arity error at ({}, {}): {} expects {}, but was given {}",
+                    p.0, p.1, if name.is_empty() { "function" } else { name }, arity, n)
+            },
+            LinslErr::ClauseArityError(name, arities, n, p) => {
+                let mut accepted = String::new();
+                for (i, arity) in arities.iter().enumerate() {
+                    let sep = match i {
+                        0 => "",
+                        _ if i + 1 == arities.len() => " or ",
+                        _ => ", ",
+                    };
+                    accepted.push_str(&format!("{}{}", sep, arity));
+                }
+                format!("Arity error at ({}, {}): {} expects {}, but was given {}",
+                    p.0, p.1, if name.is_empty() { "function" } else { name }, accepted, n)
+            },
+            // An escape is always caught by its call/ec, so this is only seen if e.g. an escape is
+            // used by another thread.
+            LinslErr::Escape(_, v) => format!("Escaped with '{}' from outside of its call/ec", v),
+            LinslErr::Aborted(p) => format!("Evaluation aborted at ({}, {})", p.0, p.1),
+            LinslErr::FuelExhausted(p) => format!("Ran out of fuel at ({}, {})", p.0, p.1),
+            LinslErr::ReadError(Some(path), cause) => {
+                format!("Could not read \'{}\': {}", path, cause)
+            },
+            LinslErr::ReadError(None, cause) => format!("Could not read the input: {}", cause),
+            LinslErr::FileError(action, path, cause) => {
+                format!("Could not {} \'{}\': {}", action, path, cause)
+            },
+            LinslErr::NetError(addr, cause) => format!("Network error with {}: {}", addr, cause),
+            LinslErr::PatternError(s, p) => {
+                format!("Invalid regular expression at ({}, {}): {}", p.0, p.1, s)
+            },
+            LinslErr::ModuleNotFound(name, searched) => {
+                format!("Could not find '{}', searched {}", name, searched.join(", "))
+            },
+            LinslErr::ConstantError(name, def, p) => {
+                format!("Constant error at ({}, {}): \'{}\' is a constant, defined at ({}, {})",
+                    p.0, p.1, name, def.0, def.1)
+            },
+            LinslErr::ImageError(s) => format!("Image error: {}", s),
+            LinslErr::CapabilityDenied(name, p) => {
+                format!("Capability error at ({}, {}): \'{}\' is not allowed by the sandbox",
+                    p.0, p.1, name)
+            },
+            LinslErr::DeniedWarning(w) => {
+                format!("Denied warning at ({}, {}): {}", w.pos.0, w.pos.1, w.message)
+            },
+            LinslErr::AssertionFailed(s, p) => {
+                format!("Assertion failed at ({}, {}): {}", p.0, p.1, s)
+            },
+            LinslErr::DecodeError(s, i, p) => {
+                format!("Decode error at ({}, {}): {} at index {}", p.0, p.1, s, i)
+            },
+            LinslErr::MatchError(val, p) => {
+                format!("Match error at ({}, {}): no clause matches \'{}\'", p.0, p.1, val)
+            },
         };
 
         write!(f, "{}", str)
     }
 }
 
-/// The bindings between symbol names and code. The inner scope is the local scope, enabling scoped variables. This enables closures.
+/// Something the evaluation or parsing tells the user about without aborting, e.g. that a
+/// primitive has been redefined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinslWarning {
+    pub category: WarningCategory,
+    pub message: String,
+    pub pos: Pos,
+}
+
+/// What a warning is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningCategory {
+    /// A binding of the same frame was replaced.
+    Redefinition,
+    /// A primitive was hidden by a binding of the same name.
+    Shadowing,
+    /// Code which reads as something else than it looks like, e.g. `inf`, which is a symbol.
+    Syntax,
+    /// Found by the linter, without evaluating the code.
+    Lint,
+}
+
+/// What is done with the warnings given during an evaluation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarningPolicy {
+    /// Abort the evaluation with a DeniedWarning error.
+    Error,
+    /// Keep the warnings until they are taken, e.g. to be printed.
+    #[default]
+    Print,
+    /// Drop the warnings.
+    Silent,
+}
+
+impl fmt::Display for LinslWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Warning at ({}, {}): {}", self.pos.0, self.pos.1, self.message)
+    }
+}
+
+impl LinslErr {
+    /// Name the function an arity error is about, and report it at the call at `pos`, unless it
+    /// has already been named, e.g. by a function applied by the one failing.
+    pub fn with_callee(self, name: &str, pos: Pos) -> LinslErr {
+        match self {
+            LinslErr::ArityError(callee, arity, n, _) if callee.is_empty() => {
+                LinslErr::ArityError(name.to_string(), arity, n, pos)
+            },
+            LinslErr::ClauseArityError(callee, arities, n, _) if callee.is_empty() => {
+                LinslErr::ClauseArityError(name.to_string(), arities, n, pos)
+            },
+            err => err,
+        }
+    }
+}
+
+/// How many arguments a function accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(PosNum),
+    AtLeast(PosNum),
+    /// Any number from the first to the second, inclusive.
+    Between(PosNum, PosNum),
+}
+
+impl Arity {
+    pub fn accepts(self, n: PosNum) -> bool {
+        match self {
+            Arity::Exactly(m) => n == m,
+            Arity::AtLeast(m) => n >= m,
+            Arity::Between(min, max) => min <= n && n <= max,
+        }
+    }
+}
+
+/// Printed as it reads in an error message, e.g. "at least 1 argument".
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (count, last) = match self {
+            Arity::Exactly(n) => (n.to_string(), *n),
+            Arity::AtLeast(n) => (format!("at least {}", n), *n),
+            Arity::Between(min, max) => (format!("{} to {}", min, max), *max),
+        };
+        write!(f, "{} argument{}", count, if last == 1 { "" } else { "s" })
+    }
+}
+
+/// The ways in which expressions can be evaluated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Walk the expressions directly. This is the reference implementation.
+    #[default]
+    TreeWalker,
+    /// Compile the expressions to bytecode, and run it on a virtual machine.
+    Vm,
+}
+
+/// State of the evaluation which does not belong to any particular scope, passed along to every
+/// evaluation.
 #[derive(Debug, Clone)]
-pub struct LinslEnv<'a> {
-    /// The current local scope.
-    pub inner: HashMap<String, LinslExpr>,
-    /// The immediate outer scope. Every scope except the global one has an outer scope.
-    pub outer: Option<&'a LinslEnv<'a>>,
-}
-
-impl LinslEnv<'_> {
-    /// The environment when starting the interpreter, i.e. holding only the primitives.
-    pub fn default<'a>() -> LinslEnv<'a> {
-        let mut env = HashMap::new();
-
-        env.insert("+".to_string(), LinslExpr::Primitive(add));
-        env.insert("neg".to_string(), LinslExpr::Primitive(neg));
-        env.insert("*".to_string(), LinslExpr::Primitive(mul));
-        env.insert("inv".to_string(), LinslExpr::Primitive(inv));
-        env.insert("=".to_string(), LinslExpr::Primitive(eq));
-        env.insert(">".to_string(), LinslExpr::Primitive(gr));
-        env.insert("car".to_string(), LinslExpr::Primitive(car));
-        env.insert("cdr".to_string(), LinslExpr::Primitive(cdr));
-        env.insert("empty?".to_string(), LinslExpr::Primitive(is_nil));
-        env.insert("eqt?".to_string(), LinslExpr::Primitive(eq_types));
-        env.insert("list".to_string(), LinslExpr::Primitive(list));
-        env.insert("append".to_string(), LinslExpr::Primitive(append));
-
-        LinslEnv { 
-            inner: env,
-            outer: None,
-        }
-    }
-    
-    pub fn new<'a>(outer: &'a LinslEnv) -> LinslEnv<'a> {
-        LinslEnv { 
+pub struct LinslCtx {
+    /// How many evaluations are currently in progress, i.e. how deeply nested the evaluation is.
+    /// When using the virtual machine, the number of calls in progress is limited instead.
+    pub depth: PosNum,
+    /// How deeply nested the evaluation may get before it is aborted with a RecursionLimit error.
+    pub max_depth: PosNum,
+    /// The position of the innermost form being evaluated which has one. Errors concerning an
+    /// expression without a position of its own, such as a symbol, are reported there.
+    pub pos: Pos,
+    /// How to evaluate expressions.
+    pub engine: Engine,
+    /// Code compiled by the virtual machine, which is reused by later evaluations.
+    pub code: CodeCache,
+    /// The values given to parameters by the `parameterize` forms being evaluated, innermost last.
+    /// Unlike the bindings of the environment these are dynamically scoped: they are seen by
+    /// everything evaluated during the form, wherever it is defined.
+    pub parameters: Vec<(LinslParameter, LinslExpr)>,
+    /// The debugger the tree walker calls before every application, if any.
+    pub debugger: Option<DebugHook>,
+    /// The profile the tree walker records the applications of functions in, if any.
+    pub profile: Option<Profile>,
+    /// The trace the tree walker logs every evaluation to, if any.
+    pub trace: Option<Trace>,
+    /// Counters describing the work done so far.
+    pub stats: Stats,
+    /// How many more steps the evaluation may take before it is aborted with a FuelExhausted
+    /// error, or None if there is no limit. The tree walker takes a step for every expression it
+    /// evaluates, including the ones in tail position, and the virtual machine for every call and
+    /// jump, so loops and tail calls use up fuel as well.
+    pub fuel: Option<usize>,
+    /// The warnings given by the evaluation which have not been taken yet, oldest first.
+    pub warnings: Vec<LinslWarning>,
+    /// What is done with the warnings given.
+    pub warning_policy: WarningPolicy,
+    /// The limits the REPL prints its results within, set by `set-print-length!` and
+    /// `set-print-depth!`.
+    pub print_limits: PrintLimits,
+    /// The modules defined so far, which can be imported by name.
+    pub modules: Modules,
+    /// The names `require` has loaded files for, which are not loaded again.
+    pub required: HashSet<Symbol>,
+    /// The file being run, if any, whose directory `require` searches first. It is kept as an
+    /// absolute path, so that changing the working directory does not change which one it is.
+    pub current_file: Option<PathBuf>,
+    /// The directories `require` searches after the one of the current file, in order. By default
+    /// these are the directories in the `LINSL_PATH` environment variable, followed by
+    /// `modules::DEFAULT_LIBRARY_DIR`.
+    pub library_path: Vec<PathBuf>,
+    /// What the evaluation may do beyond computing values, e.g. reading files.
+    pub policy: SandboxPolicy,
+    /// The tests defined so far, in the order `run-tests` runs them.
+    pub tests: Vec<Test>,
+    /// Where `run-tests` writes its report, if anywhere.
+    pub test_report: Option<ReportOutput>,
+    /// The generator `random` and `uuid` draw from, seeded from the entropy of the operating
+    /// system unless `set-random-seed!` is given a seed.
+    pub rng: Rng,
+}
+
+impl LinslCtx {
+    /// The current value of a parameter, i.e. the one given by the innermost parameterize for it,
+    /// or its initial value if there is none.
+    pub fn parameter_value(&self, param: &LinslParameter) -> LinslExpr {
+        self.parameters
+            .iter()
+            .rev()
+            .find(|(p, _)| p.same(param))
+            .map(|(_, val)| val.clone())
+            .unwrap_or_else(|| param.0.as_ref().clone())
+    }
+
+    /// Give a warning, which the warning policy either keeps until it is taken, drops, or turns
+    /// into an error.
+    pub fn warn(&mut self, warning: LinslWarning) -> Result<(), LinslErr> {
+        match self.warning_policy {
+            WarningPolicy::Error => return Err(LinslErr::DeniedWarning(warning)),
+            WarningPolicy::Print => self.warnings.push(warning),
+            WarningPolicy::Silent => (),
+        };
+        Ok(())
+    }
+
+    /// Give the warnings a tokenizer has given while parsing, taking them from it.
+    pub fn take_parse_warnings(&mut self, tokenizer: &mut Tokenizer) -> Result<(), LinslErr> {
+        for warning in mem::take(&mut tokenizer.warnings) {
+            self.warn(warning)?;
+        }
+        Ok(())
+    }
+
+    /// Use up a step of fuel for the form at `pos`, failing there if there is none left.
+    pub(crate) fn burn_fuel(&mut self, pos: Pos) -> Result<(), LinslErr> {
+        match &mut self.fuel {
+            None => Ok(()),
+            Some(0) => Err(LinslErr::FuelExhausted(pos)),
+            Some(fuel) => {
+                *fuel -= 1;
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Default for LinslCtx {
+    fn default() -> Self {
+        LinslCtx {
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            pos: (0, 0),
+            engine: Engine::default(),
+            code: CodeCache::default(),
+            parameters: Vec::new(),
+            debugger: None,
+            profile: None,
+            trace: None,
+            stats: Stats::new(),
+            fuel: None,
+            warnings: Vec::new(),
+            warning_policy: WarningPolicy::default(),
+            print_limits: PrintLimits::REPL,
+            modules: Modules::new(),
+            required: HashSet::new(),
+            current_file: None,
+            library_path: default_library_path(),
+            policy: SandboxPolicy::default(),
+            tests: Vec::new(),
+            test_report: None,
+            rng: Rng::default(),
+        }
+    }
+}
+
+/// The number of frames and lists allocated so far, by any thread. They are counted globally,
+/// since creating them does not involve a context.
+static FRAMES: AtomicUsize = AtomicUsize::new(0);
+static LISTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters describing the work done by the evaluation, as returned by `(stats)`.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    /// The number of expressions evaluated, i.e. of calls to `evaluate`.
+    pub evaluations: usize,
+    /// How deeply nested the evaluation has been at most. When using the virtual machine, the
+    /// number of calls in progress is counted instead.
+    pub max_depth: PosNum,
+    /// The number of frames and lists allocated before the counters were reset.
+    frames_before: usize,
+    lists_before: usize,
+}
+
+impl Stats {
+    /// Counters starting from zero.
+    pub fn new() -> Stats {
+        Stats {
+            evaluations: 0,
+            max_depth: 0,
+            frames_before: FRAMES.load(AtomicOrdering::Relaxed),
+            lists_before: LISTS.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    /// The number of frames allocated since the counters were reset. Frames allocated by other
+    /// threads in the meantime are included.
+    pub fn frames(&self) -> usize {
+        FRAMES.load(AtomicOrdering::Relaxed).saturating_sub(self.frames_before)
+    }
+
+    /// The number of lists allocated since the counters were reset, including the ones allocated
+    /// by other threads.
+    pub fn lists(&self) -> usize {
+        LISTS.load(AtomicOrdering::Relaxed).saturating_sub(self.lists_before)
+    }
+
+    /// The counters as they are now, along with the number of symbols.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            evaluations: self.evaluations,
+            max_depth: self.max_depth,
+            frames: self.frames(),
+            symbols: Symbol::interned(),
+            lists: self.lists(),
+        }
+    }
+
+    /// Start measuring the work done from now on, e.g. by a single evaluation, until the
+    /// measurement is finished.
+    pub fn measure(&mut self) -> Measurement {
+        // The depth is counted from zero, so that the measurement gets the peak of its own.
+        let max_depth = mem::take(&mut self.max_depth);
+        Measurement { before: self.snapshot(), max_depth }
+    }
+}
+
+/// The counters of `Stats` at some point, or the difference between two points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub evaluations: usize,
+    pub max_depth: PosNum,
+    pub frames: usize,
+    pub symbols: usize,
+    pub lists: usize,
+}
+
+impl fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "evaluations  {}", self.evaluations)?;
+        writeln!(f, "max-depth    {}", self.max_depth)?;
+        writeln!(f, "frames       {}", self.frames)?;
+        writeln!(f, "symbols      {}", self.symbols)?;
+        write!(f, "lists        {}", self.lists)
+    }
+}
+
+/// A measurement of the work done, started by `Stats::measure`.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    before: StatsSnapshot,
+    /// The deepest the evaluation was nested before the measurement started.
+    max_depth: PosNum,
+}
+
+impl Measurement {
+    /// The work done since the measurement started: the counters which count up are the
+    /// differences, and the depth is the deepest the evaluation was nested in the meantime.
+    pub fn finish(self, stats: &mut Stats) -> StatsSnapshot {
+        let after = stats.snapshot();
+        stats.max_depth = stats.max_depth.max(self.max_depth);
+        StatsSnapshot {
+            evaluations: after.evaluations.saturating_sub(self.before.evaluations),
+            max_depth: after.max_depth,
+            frames: after.frames.saturating_sub(self.before.frames),
+            symbols: after.symbols.saturating_sub(self.before.symbols),
+            lists: after.lists.saturating_sub(self.before.lists),
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats::new()
+    }
+}
+
+/// The value a name is bound to in a frame.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub val: LinslExpr,
+    /// Where the binding was made, if it was made by defconst. A constant cannot be bound again in
+    /// the same frame, though inner frames may still shadow it.
+    pub constant: Option<Pos>,
+}
+
+impl Binding {
+    /// A binding which can be replaced.
+    pub fn new(val: LinslExpr) -> Binding {
+        Binding { val, constant: None }
+    }
+}
+
+/// A single frame of bindings between symbol names and code, along with the frame it is nested
+/// in.
+#[derive(Default)]
+pub struct Scope {
+    /// The bindings local to this frame.
+    pub inner: HashMap<Symbol, Binding>,
+    /// The documentation of the primitives the host has registered in this frame, for `help`.
+    pub help: HashMap<Symbol, Help>,
+    /// The primitives the frame was built with by a `LinslEnvBuilder`, which `restore-primitive`
+    /// binds again. Frames made any other way have none.
+    pub primitives: HashMap<Symbol, LinslExpr>,
+    /// The immediate outer frame. Every frame except the global one has an outer frame.
+    pub outer: Option<LinslEnv>,
+}
+
+/// The bindings between symbol names and code, as a chain of frames with the local one first. This
+/// enables scoped variables and closures.
+///
+/// Frames are shared rather than copied: cloning an environment only clones a pointer to its
+/// innermost frame. A closure can thereby keep the frame it was created in alive, and still see
+/// bindings added to it later on. Frames may be shared between threads, e.g. a prelude which several
+/// interpreters are created on top of.
+#[derive(Clone)]
+pub struct LinslEnv(pub Arc<RwLock<Scope>>);
+
+impl fmt::Debug for LinslEnv {
+    /// Only the names are printed, since a frame may (indirectly) contain closures holding the frame
+    /// itself.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = self.scope();
+        let mut names: Vec<&str> = scope.inner.keys().map(Symbol::as_str).collect();
+        names.sort();
+        f.debug_struct("LinslEnv")
+            .field("inner", &names)
+            .field("outer", &scope.outer)
+            .finish()
+    }
+}
+
+impl Default for LinslEnv {
+    /// The environment when starting the interpreter, i.e. holding only the primitives, all of
+    /// them.
+    fn default() -> Self {
+        LinslEnvBuilder::new().with_all().build()
+    }
+}
+
+/// The groups of primitives a `LinslEnvBuilder` can register, which are also the categories `help`
+/// lists them in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveGroup {
+    /// Arithmetic, comparison, random numbers and time.
+    Math,
+    Lists,
+    Vectors,
+    Dicts,
+    Strings,
+    Keywords,
+    Iteration,
+    /// Escapes, parameters, multiple values, foreign values, and partial application, composition
+    /// and currying.
+    Control,
+    Introspection,
+    /// Reading and hashing files.
+    Io,
+    /// TCP connections and listeners, and HTTP requests if built with the `http` feature.
+    Net,
+    /// Running the tests defined with define-test.
+    Testing,
+}
+
+impl PrimitiveGroup {
+    /// Every group, in the order `LinslEnv::default()` registers them.
+    pub const ALL: [PrimitiveGroup; 12] = [
+        PrimitiveGroup::Math,
+        PrimitiveGroup::Lists,
+        PrimitiveGroup::Vectors,
+        PrimitiveGroup::Dicts,
+        PrimitiveGroup::Strings,
+        PrimitiveGroup::Keywords,
+        PrimitiveGroup::Iteration,
+        PrimitiveGroup::Control,
+        PrimitiveGroup::Introspection,
+        PrimitiveGroup::Io,
+        PrimitiveGroup::Net,
+        PrimitiveGroup::Testing,
+    ];
+
+    /// The capability the primitives of the group need, as listed in `sandbox::CAPABILITIES`.
+    pub fn capability(self) -> Capability {
+        match self {
+            PrimitiveGroup::Io => Capability::Fs,
+            PrimitiveGroup::Net => Capability::Net,
+            PrimitiveGroup::Testing => Capability::Io,
+            _ => Capability::Pure,
+        }
+    }
+
+    /// The tables of the primitives of the group.
+    pub fn tables(self) -> &'static [&'static [PrimitiveEntry]] {
+        match self {
+            PrimitiveGroup::Math => {
+                &[math::MATH, predicates::PREDICATES, random::RANDOM, time::TIME]
+            },
+            PrimitiveGroup::Lists => &[lists::LISTS],
+            PrimitiveGroup::Vectors => &[vectors::VECTORS],
+            PrimitiveGroup::Dicts => &[dicts::DICTS],
+            PrimitiveGroup::Strings => &[strings::STRINGS, paths::PATHS, patterns::PATTERNS],
+            PrimitiveGroup::Keywords => &[keywords::KEYWORDS],
+            PrimitiveGroup::Iteration => &[iteration::ITERATION],
+            PrimitiveGroup::Control => &[control::CONTROL, functions::FUNCTIONS],
+            PrimitiveGroup::Introspection => &[introspection::INTROSPECTION],
+            PrimitiveGroup::Io => &[io::IO],
+            #[cfg(not(feature = "http"))]
+            PrimitiveGroup::Net => &[net::NET],
+            #[cfg(feature = "http")]
+            PrimitiveGroup::Net => &[net::NET, crate::primitives::http::HTTP],
+            PrimitiveGroup::Testing => &[testing::TESTING],
+        }
+    }
+
+    /// Bind the primitives of the group in the innermost frame of `env`.
+    fn register(self, env: &LinslEnv) {
+        for entry in self.tables().iter().flat_map(|table| table.iter()) {
+            env.insert_primitive(entry.name, entry.value());
+        }
+    }
+}
+
+/// Creates an environment holding only the primitives asked for, along with any of the host's own,
+/// e.g. `LinslEnvBuilder::new().with_math().with_lists().with("my-fn", my_fn).build()`.
+#[derive(Debug, Clone, Default)]
+pub struct LinslEnvBuilder {
+    groups: Vec<PrimitiveGroup>,
+    primitives: Vec<(&'static str, LinslExpr)>,
+}
+
+impl LinslEnvBuilder {
+    /// A builder of an environment without any primitives.
+    pub fn new() -> LinslEnvBuilder {
+        LinslEnvBuilder::default()
+    }
+
+    /// Register the primitives of a group.
+    pub fn with_group(mut self, group: PrimitiveGroup) -> LinslEnvBuilder {
+        if !self.groups.contains(&group) {
+            self.groups.push(group);
+        };
+        self
+    }
+
+    /// Register every group of primitives.
+    pub fn with_all(self) -> LinslEnvBuilder {
+        PrimitiveGroup::ALL.into_iter().fold(self, LinslEnvBuilder::with_group)
+    }
+
+    /// Register the groups whose capability the policy allows.
+    pub fn with_policy(self, policy: &SandboxPolicy) -> LinslEnvBuilder {
+        PrimitiveGroup::ALL.into_iter()
+            .filter(|group| policy.allows(group.capability()))
+            .fold(self, LinslEnvBuilder::with_group)
+    }
+
+    pub fn with_math(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Math)
+    }
+
+    pub fn with_lists(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Lists)
+    }
+
+    pub fn with_vectors(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Vectors)
+    }
+
+    pub fn with_dicts(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Dicts)
+    }
+
+    pub fn with_strings(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Strings)
+    }
+
+    pub fn with_keywords(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Keywords)
+    }
+
+    pub fn with_iteration(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Iteration)
+    }
+
+    pub fn with_control(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Control)
+    }
+
+    pub fn with_introspection(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Introspection)
+    }
+
+    pub fn with_io(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Io)
+    }
+
+    pub fn with_net(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Net)
+    }
+
+    pub fn with_testing(self) -> LinslEnvBuilder {
+        self.with_group(PrimitiveGroup::Testing)
+    }
+
+    /// Register a primitive of the host's own, which is bound after the groups and so replaces a
+    /// primitive of the same name.
+    pub fn with(
+        mut self,
+        name: &'static str,
+        primitive: fn(&[LinslExpr]) -> LinslRes
+    ) -> LinslEnvBuilder {
+        self.primitives.push((name, LinslExpr::Primitive(name, primitive)));
+        self
+    }
+
+    /// Create the environment, as a single frame.
+    pub fn build(self) -> LinslEnv {
+        let env = LinslEnv::empty();
+        for group in self.groups {
+            group.register(&env);
+        }
+        for (name, primitive) in self.primitives {
+            env.insert_primitive(name, primitive);
+        }
+        env
+    }
+}
+
+impl LinslEnv {
+    /// An environment of a single frame without any bindings, not even the primitives.
+    pub fn empty() -> LinslEnv {
+        LinslEnv(Arc::new(RwLock::new(Scope::default())))
+    }
+
+    /// Create a new, empty frame nested in `outer`.
+    pub fn new(outer: &LinslEnv) -> LinslEnv {
+        FRAMES.fetch_add(1, AtomicOrdering::Relaxed);
+        LinslEnv(Arc::new(RwLock::new(Scope {
             inner: HashMap::new(),
-            outer: Some(outer)
+            help: HashMap::new(),
+            primitives: HashMap::new(),
+            outer: Some(outer.clone()),
+        })))
+    }
+
+    /// The innermost frame, locked for reading.
+    pub fn scope(&self) -> RwLockReadGuard<'_, Scope> {
+        // A frame is never left half updated, so it is fine to use even if a thread panicked while
+        // holding the lock.
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The innermost frame, locked for writing.
+    pub fn scope_mut(&self) -> RwLockWriteGuard<'_, Scope> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Bind `name` to `val` in the innermost frame, replacing any previous binding there.
+    pub fn insert(&self, name: impl Into<Symbol>, val: LinslExpr) {
+        self.scope_mut().inner.insert(name.into(), Binding::new(val));
+    }
+
+    /// Bind a primitive in the innermost frame, keeping it so that it can be restored if it is
+    /// undefined or redefined.
+    fn insert_primitive(&self, name: &'static str, val: LinslExpr) {
+        let mut scope = self.scope_mut();
+        scope.primitives.insert(name.into(), val.clone());
+        scope.inner.insert(name.into(), Binding::new(val));
+    }
+
+    /// Like `insert`, but also documents the binding, so that `help` can describe it. This is meant
+    /// for primitives registered by the host.
+    pub fn insert_with_help(&self, name: impl Into<Symbol>, val: LinslExpr, help: Help) {
+        let name = name.into();
+        let mut scope = self.scope_mut();
+        scope.help.insert(name.clone(), help);
+        scope.inner.insert(name, Binding::new(val));
+    }
+
+    /// The frame this one is nested in, if any.
+    pub fn outer(&self) -> Option<LinslEnv> {
+        self.scope().outer.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::primitives::{add, neg};
+    use crate::sandbox::capability;
+
+    fn hash(expr: &LinslExpr) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        expr.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn list(elems: Vec<LinslExpr>) -> LinslExpr {
+        LinslExpr::List(elems.into())
+    }
+
+    #[test]
+    // Closures hold their (mutable) environment, but only its address is hashed.
+    #[allow(clippy::mutable_key_type)]
+    fn expressions_as_map_keys() {
+        let env = LinslEnv::default();
+        let params = Arc::new(list(vec![LinslExpr::Symbol("x".into())]));
+        let body = Arc::new(LinslExpr::Symbol("x".into()));
+        let closure = LinslExpr::Closure(params.clone(), body.clone(), env.clone(), None, None);
+        let keys = vec![
+            LinslExpr::Bool(true),
+            LinslExpr::Bool(false),
+            LinslExpr::Number(1.0),
+            LinslExpr::Number(-1.5),
+            LinslExpr::Number(Num::NAN),
+            LinslExpr::Number(Num::INFINITY),
+            LinslExpr::Symbol("x".into()),
+            LinslExpr::Symbol("y".into()),
+            list(vec![]),
+            list(vec![LinslExpr::Number(1.0), list(vec![LinslExpr::Symbol("x".into())])]),
+            closure.clone(),
+            LinslExpr::Closure(params.clone(), body.clone(), LinslEnv::new(&env), None, None),
+            LinslExpr::Macro(params, body, None),
+            LinslExpr::Primitive("+", add),
+            LinslExpr::Primitive("neg", neg),
+            LinslExpr::foreign(1),
+            LinslExpr::Vector(vec![LinslExpr::Number(1.0)].into()),
+        ];
+
+        let map: HashMap<LinslExpr, usize> = keys.iter().cloned().zip(0..).collect();
+        assert_eq!(keys.len(), map.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(&i), map.get(key));
+            assert_eq!(Some(&i), map.get(&key.clone()));
+        }
+
+        // Keys built separately from the inserted ones are found if they are equal.
+        let equal_keys = [
+            (LinslExpr::Number(Num::NAN.copysign(-1.0)), 4),
+            (LinslExpr::Symbol(Symbol::new("y")), 7),
+            (list(vec![]), 8),
+            (list(vec![LinslExpr::Number(1.0), list(vec![LinslExpr::Symbol("x".into())])]), 9),
+            (env.scope().inner[&Symbol::new("+")].val.clone(), 13),
+        ];
+        for (key, i) in equal_keys {
+            assert_eq!(Some(&i), map.get(&key));
+        }
+        assert_eq!(None, map.get(&LinslExpr::foreign(1)));
+        assert_eq!(None, map.get(&LinslExpr::Vector(vec![LinslExpr::Number(1.0)].into())));
+        assert_eq!(None, map.get(&LinslExpr::Closure(
+            Arc::new(list(vec![LinslExpr::Symbol("x".into())])),
+            Arc::new(LinslExpr::Symbol("x".into())),
+            env,
+            None,
+            None,
+        )));
+    }
+
+    #[test]
+    fn dict_keys_are_ordered() {
+        let mut keys = [
+            DictKey::Symbol("b".into()),
+            DictKey::Number(2.0),
+            DictKey::Symbol("a".into()),
+            DictKey::Bool(true),
+            DictKey::Number(-1.0),
+            DictKey::Bool(false),
+        ];
+        keys.sort();
+        let printed: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        assert_eq!(vec!["#f", "#t", "-1", "2", "a", "b"], printed);
+
+        assert_eq!(DictKey::Number(0.0), DictKey::Number(-0.0));
+        assert_eq!(DictKey::Number(Num::NAN), DictKey::Number(Num::NAN));
+        assert!(DictKey::try_from(&list(vec![])).is_err());
+    }
+
+    #[test]
+    fn equal_expressions_hash_equally() {
+        let pairs = [
+            (LinslExpr::Number(0.0), LinslExpr::Number(-0.0)),
+            (LinslExpr::Number(Num::NAN), LinslExpr::Number(-Num::NAN)),
+            (list(vec![LinslExpr::Number(0.0)]), list(vec![LinslExpr::Number(-0.0)])),
+            (LinslExpr::Symbol("a".into()), LinslExpr::Symbol(Symbol::new("a"))),
+        ];
+        for (a, b) in pairs {
+            assert_eq!(a, b);
+            assert_eq!(hash(&a), hash(&b));
+        }
+
+        // A list's tail is equal to a list built with the same elements.
+        let xs: LinslList = (0..4).map(|i| LinslExpr::Number(i as Num)).collect();
+        let tail = LinslExpr::List(xs.tail());
+        let ys = list((1..4).map(|i| LinslExpr::Number(i as Num)).collect());
+        assert_eq!(tail, ys);
+        assert_eq!(hash(&tail), hash(&ys));
+
+        assert_ne!(LinslExpr::Number(1.0), LinslExpr::Bool(true));
+        assert_ne!(list(vec![]), list(vec![list(vec![])]));
+    }
+
+    #[test]
+    fn env_builder() {
+        let math = LinslEnvBuilder::new().with_math().build();
+        let mut interpreter = Interpreter { env: math, ..Interpreter::default() };
+        assert_eq!("3", interpreter.eval_str("(+ 1 2)").unwrap().to_string());
+        match interpreter.eval_str("(car '(1))") {
+            Err(LinslErr::SyntaxError(msg, _)) => assert_eq!("Undefined symbol 'car'", msg),
+            res => panic!("Expected car to be undefined, found {:?}", res),
+        };
+
+        fn double(exprs: &[LinslExpr]) -> LinslRes {
+            match exprs {
+                [LinslExpr::Number(n)] => Ok(LinslExpr::Number(2.0 * n)),
+                _ => Err(LinslErr::TypeError("Expected a number".to_string(), (0, 0))),
+            }
+        }
+        let env = LinslEnvBuilder::new().with_lists().with("double", double).build();
+        let mut interpreter = Interpreter { env, ..Interpreter::default() };
+        assert_eq!("(4)", interpreter.eval_str("(list (double 2))").unwrap().to_string());
+        assert!(interpreter.eval_str("(+ 1 2)").is_err());
+        assert!(LinslEnv::empty().scope().inner.is_empty());
+
+        // The groups make up the default environment, and need the capabilities the sandbox
+        // expects of their primitives.
+        let names = |env: &LinslEnv| {
+            let mut names: Vec<String> = env.scope().inner.keys().map(Symbol::to_string).collect();
+            names.sort();
+            names
+        };
+        let all = LinslEnvBuilder::new().with_all().build();
+        assert_eq!(names(&LinslEnv::default()), names(&all));
+        for group in PrimitiveGroup::ALL {
+            for name in names(&LinslEnvBuilder::new().with_group(group).build()) {
+                assert_eq!(group.capability(), capability(&name), "{}", name);
+            }
         }
+        let pure = LinslEnvBuilder::new().with_policy(&SandboxPolicy::pure()).build();
+        let denied = if cfg!(feature = "http") { 22 } else { 20 };
+        assert_eq!(names(&all).len() - denied, names(&pure).len());
+        assert!(!pure.scope().inner.contains_key(&Symbol::new("read-file")));
     }
 }
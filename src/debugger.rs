@@ -0,0 +1,246 @@
+//! Single stepping through evaluation, e.g. for following along with what a program does while
+//! learning the language.
+//!
+//! The tree walker calls the debugger set in the context before every application of a function
+//! or macro, with the application, the environment it is evaluated in and how deeply nested the
+//! evaluation is. The debugger can then inspect them, and decide whether to go on or to abort the
+//! evaluation. The virtual machine does not call the debugger.
+
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::datatypes::{LinslEnv, LinslErr, LinslExpr, Pos, PosNum, Symbol};
+use crate::evaluation::env_get;
+
+/// What the evaluation should do once the debugger returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    Continue,
+    /// Abort the evaluation with an Aborted error.
+    Abort,
+}
+
+/// A hook called during evaluation.
+pub trait Debugger: Send {
+    /// Called when the evaluation of a top level expression begins.
+    fn begin(&mut self, _expr: &LinslExpr) {}
+
+    /// Called before `expr`, an application, is evaluated in `env`, at the given depth of nesting.
+    fn before_apply(
+        &mut self,
+        expr: &LinslExpr,
+        pos: Pos,
+        env: &LinslEnv,
+        depth: PosNum
+    ) -> DebugAction;
+}
+
+/// A debugger shared by the clones of a context.
+#[derive(Clone)]
+pub struct DebugHook(pub Arc<Mutex<dyn Debugger>>);
+
+impl DebugHook {
+    pub fn new(debugger: impl Debugger + 'static) -> DebugHook {
+        DebugHook(Arc::new(Mutex::new(debugger)))
+    }
+
+    pub(crate) fn begin(&self, expr: &LinslExpr) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).begin(expr);
+    }
+
+    /// Call the debugger before an application, failing if it asks for the evaluation to be
+    /// aborted.
+    pub(crate) fn before_apply(
+        &self,
+        expr: &LinslExpr,
+        pos: Pos,
+        env: &LinslEnv,
+        depth: PosNum
+    ) -> Result<(), LinslErr> {
+        let mut debugger = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match debugger.before_apply(expr, pos, env, depth) {
+            DebugAction::Continue => Ok(()),
+            DebugAction::Abort => Err(LinslErr::Aborted(pos)),
+        }
+    }
+}
+
+impl fmt::Debug for DebugHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DebugHook")
+    }
+}
+
+/// When the step debugger is to pause next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Before the next application.
+    Step,
+    /// Before the next application nested at most this deeply, i.e. once the current one is done.
+    Over(PosNum),
+    /// Not until the next top level expression.
+    Continue,
+}
+
+/// Reads a command for the step debugger, see below.
+type CommandReader = Box<dyn FnMut(&str) -> Option<String> + Send>;
+
+/// A debugger which pauses before applications, and reads commands telling it what to do. The
+/// commands are
+/// - `p` (or `print`), which prints the application,
+/// - `v name` (or `inspect name`), which prints the value `name` is bound to,
+/// - `s` (or `step`), which goes on to the next application, which may be nested in this one,
+/// - `n` (or `next`), which goes on to the next application which is not nested in this one,
+/// - `c` (or `continue`), which goes on without pausing until the next top level expression,
+/// - `a` (or `abort`), which aborts the evaluation, and
+/// - `h` (or `help`), which lists the commands.
+pub struct StepDebugger {
+    /// Reads a command, after showing the prompt given. Gives None once there are no more
+    /// commands, which continues the evaluation.
+    input: CommandReader,
+    /// Where the debugger prints what it is asked to.
+    output: Box<dyn Write + Send>,
+    mode: Mode,
+}
+
+impl StepDebugger {
+    pub fn new(
+        input: impl FnMut(&str) -> Option<String> + Send + 'static,
+        output: impl Write + Send + 'static
+    ) -> StepDebugger {
+        StepDebugger { input: Box::new(input), output: Box::new(output), mode: Mode::Step }
+    }
+
+    /// Print a line, ignoring errors, since there is nowhere to report them.
+    fn print(&mut self, line: &str) {
+        let _ = writeln!(self.output, "{}", line);
+    }
+}
+
+impl Debugger for StepDebugger {
+    fn begin(&mut self, _expr: &LinslExpr) {
+        self.mode = Mode::Step;
+    }
+
+    fn before_apply(
+        &mut self,
+        expr: &LinslExpr,
+        _pos: Pos,
+        env: &LinslEnv,
+        depth: PosNum
+    ) -> DebugAction {
+        match self.mode {
+            Mode::Over(d) if depth > d => return DebugAction::Continue,
+            Mode::Continue => return DebugAction::Continue,
+            _ => (),
+        };
+
+        self.print(&format!("[{}] {}", depth, expr));
+        loop {
+            let Some(line) = (self.input)("debug> ") else {
+                self.mode = Mode::Continue;
+                return DebugAction::Continue;
+            };
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("p" | "print"), None) => self.print(&expr.to_string()),
+                (Some("v" | "inspect"), Some(name)) => {
+                    let line = match env_get(&Symbol::new(name), env) {
+                        Some(val) => format!("{} = {}", name, val),
+                        None => format!("{} is not bound", name),
+                    };
+                    self.print(&line);
+                },
+                (Some("s" | "step"), None) => {
+                    self.mode = Mode::Step;
+                    return DebugAction::Continue;
+                },
+                (Some("n" | "next"), None) => {
+                    self.mode = Mode::Over(depth);
+                    return DebugAction::Continue;
+                },
+                (Some("c" | "continue"), None) => {
+                    self.mode = Mode::Continue;
+                    return DebugAction::Continue;
+                },
+                (Some("a" | "abort"), None) => return DebugAction::Abort,
+                (None, _) => (),
+                _ => self.print(
+                    "Commands: p(rint), v(inspect) name, s(tep), n(ext), c(ontinue), a(bort)"
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::datatypes::LinslRes;
+    use crate::interpreter::Interpreter;
+
+    /// Output which can be read while the debugger holds on to it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Evaluate `code` with a step debugger reading the commands given, and return the result and
+    /// everything the debugger printed.
+    fn debug(interpreter: &mut Interpreter, code: &str, commands: &[&str]) -> (LinslRes, String) {
+        let mut commands: VecDeque<String> = commands.iter().map(|c| c.to_string()).collect();
+        let output = Shared::default();
+        let debugger = StepDebugger::new(move |_| commands.pop_front(), output.clone());
+        interpreter.ctx.debugger = Some(DebugHook::new(debugger));
+
+        let res = interpreter.eval_str(code);
+        interpreter.ctx.debugger = None;
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        (res, output)
+    }
+
+    #[test]
+    fn stepping() {
+        let mut interpreter = Interpreter::default();
+        interpreter.eval_str("(define sq (lambda (x) (* x x)))").unwrap();
+
+        // Stepping goes into the application of sq, where x can be inspected.
+        let (res, output) = debug(
+            &mut interpreter,
+            "(+ (sq 2) 1)",
+            &["p", "s", "s", "v x", "v y", "c"],
+        );
+        assert_eq!("5", res.unwrap().to_string());
+        assert_eq!(
+            "[1] (+ (sq 2) 1)\n(+ (sq 2) 1)\n[2] (sq 2)\n[2] (* x x)\nx = 2\ny is not bound\n",
+            output
+        );
+
+        // Stepping over the first application skips everything nested in it.
+        let (res, output) = debug(&mut interpreter, "(+ (sq 2) (sq 3))", &["n"]);
+        assert_eq!("13", res.unwrap().to_string());
+        assert_eq!("[1] (+ (sq 2) (sq 3))\n", output);
+
+        // Continuing only lasts until the next top level expression.
+        let (res, output) = debug(&mut interpreter, "(sq 1) (sq 2)", &["c", "huh", "c"]);
+        assert_eq!("4", res.unwrap().to_string());
+        assert!(output.starts_with("[1] (sq 1)\n[1] (sq 2)\nCommands: "));
+
+        let (res, _) = debug(&mut interpreter, "(+ (sq 2) 1)", &["s", "a"]);
+        assert!(matches!(res, Err(LinslErr::Aborted(_))));
+        // The evaluation is over, so the context is ready for the next one.
+        assert_eq!(0, interpreter.ctx.depth);
+        assert_eq!("9", interpreter.eval_str("(sq 3)").unwrap().to_string());
+    }
+}
@@ -0,0 +1,188 @@
+//! Base64, as specified in RFC 4648, in both its standard and its URL-safe alphabet.
+//!
+//! Encoding always pads the output with `=` to a multiple of four characters. Decoding accepts
+//! input either padded that way or not padded at all, and nothing else: no whitespace, and no
+//! characters of the other alphabet.
+
+/// Which characters stand for the values 62 and 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    /// `+` and `/`.
+    Standard,
+    /// `-` and `_`, which need no escaping in URLs and file names.
+    UrlSafe,
+}
+
+impl Alphabet {
+    fn chars(self) -> &'static [u8; 64] {
+        match self {
+            Alphabet::Standard => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+            },
+            Alphabet::UrlSafe => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_"
+            },
+        }
+    }
+
+    /// The value a character stands for, if it is in the alphabet.
+    fn value(self, c: char) -> Option<u32> {
+        self.chars().iter().position(|&b| b as char == c).map(|v| v as u32)
+    }
+}
+
+/// Why some input could not be decoded, and the index of the character where decoding failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub message: String,
+    pub index: usize,
+}
+
+impl DecodeError {
+    fn new(message: impl Into<String>, index: usize) -> DecodeError {
+        DecodeError { message: message.into(), index }
+    }
+}
+
+/// The bytes encoded as base64.
+pub fn encode(bytes: &[u8], alphabet: Alphabet) -> String {
+    let chars = alphabet.chars();
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &byte)| group | ((byte as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(chars[((group >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode base64 into the bytes it encodes. Fails at the first character which is not in the
+/// alphabet or is out of place, e.g. padding in the middle of the input.
+pub fn decode(s: &str, alphabet: Alphabet) -> Result<Vec<u8>, DecodeError> {
+    let mut bytes = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut group, mut len) = (0u32, 0usize);
+    // Where the padding starts, and how long it is, once it has been found.
+    let mut padding: Option<(usize, usize)> = None;
+
+    for (index, c) in s.chars().enumerate() {
+        if c == '=' {
+            let (_, pad) = padding.get_or_insert((index, 0));
+            *pad += 1;
+            continue;
+        };
+        if padding.is_some() {
+            return Err(DecodeError::new(format!("Found \'{}\' after the padding", c), index));
+        };
+        let Some(value) = alphabet.value(c) else {
+            let other = match alphabet {
+                Alphabet::Standard => Alphabet::UrlSafe,
+                Alphabet::UrlSafe => Alphabet::Standard,
+            };
+            let hint = match other.value(c) {
+                Some(_) if other == Alphabet::UrlSafe => " (it is in the URL-safe alphabet)",
+                Some(_) => " (it is in the standard alphabet)",
+                None => "",
+            };
+            return Err(DecodeError::new(format!("Invalid character \'{}\'{}", c, hint), index));
+        };
+        group = (group << 6) | value;
+        len += 1;
+        if len % 4 == 0 {
+            bytes.extend_from_slice(&group.to_be_bytes()[1..]);
+            group = 0;
+        };
+    }
+
+    let count = s.chars().count();
+    let missing = (4 - len % 4) % 4;
+    if missing == 3 {
+        return Err(DecodeError::new("The input ends in the middle of a byte", count));
+    };
+    match padding {
+        Some((start, pad)) if pad != missing => {
+            return Err(DecodeError::new(
+                format!("Expected {} padding character(s), found {}", missing, pad),
+                start
+            ));
+        },
+        _ => (),
+    };
+    // The characters of the last, partial group hold `len % 4 * 6` bits, of which the whole bytes
+    // are kept.
+    let rest = len % 4;
+    if rest > 0 {
+        let group = group << (6 * (4 - rest));
+        bytes.extend_from_slice(&group.to_be_bytes()[1..rest]);
+    };
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        // The test vectors of RFC 4648.
+        for (plain, encoded) in [
+            ("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encoded, encode(plain.as_bytes(), Alphabet::Standard));
+            assert_eq!(Ok(plain.as_bytes().to_vec()), decode(encoded, Alphabet::Standard));
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(Ok(plain.as_bytes().to_vec()), decode(unpadded, Alphabet::Standard));
+        }
+
+        let bytes = [0xfb, 0xff, 0xbf];
+        assert_eq!("+/+/", encode(&bytes, Alphabet::Standard));
+        assert_eq!("-_-_", encode(&bytes, Alphabet::UrlSafe));
+        assert_eq!(Ok(bytes.to_vec()), decode("-_-_", Alphabet::UrlSafe));
+
+        let all: Vec<u8> = (0..=255).collect();
+        for alphabet in [Alphabet::Standard, Alphabet::UrlSafe] {
+            assert_eq!(Ok(all.clone()), decode(&encode(&all, alphabet), alphabet));
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let err = |s, alphabet| decode(s, alphabet).unwrap_err();
+        assert_eq!(DecodeError::new("Invalid character '*'", 2), err("Zm*v", Alphabet::Standard));
+        assert_eq!(
+            DecodeError::new("Invalid character 'é'", 1),
+            err("Zé9v", Alphabet::Standard)
+        );
+        assert_eq!(
+            DecodeError::new("Invalid character '-' (it is in the URL-safe alphabet)", 0),
+            err("-_-_", Alphabet::Standard)
+        );
+        assert_eq!(
+            DecodeError::new("Invalid character '/' (it is in the standard alphabet)", 1),
+            err("_/-_", Alphabet::UrlSafe)
+        );
+        assert_eq!(
+            DecodeError::new("Found 'm' after the padding", 3),
+            err("Zg=m9v", Alphabet::Standard)
+        );
+        assert_eq!(
+            DecodeError::new("Expected 2 padding character(s), found 1", 2),
+            err("Zg=", Alphabet::Standard)
+        );
+        assert_eq!(
+            DecodeError::new("Expected 0 padding character(s), found 1", 4),
+            err("Zm9v=", Alphabet::Standard)
+        );
+        assert_eq!(
+            DecodeError::new("The input ends in the middle of a byte", 5),
+            err("Zm9vY", Alphabet::Standard)
+        );
+    }
+}
@@ -1,145 +1,491 @@
 //! Code for evaluating Linsl expressions.
 
-use crate::datatypes::{LinslEnv, LinslErr, LinslExpr, LinslRes};
-use crate::parsing::parse_list_of_symbols;
+use std::borrow::Cow;
+use std::sync::Arc;
 
-/// Creates new bindings within the environment specified. For example, given the list of symbols
-/// (a b c) and the list of values (1 2 3) it will bind a to 1, b to 2 and c to 3.
+use crate::datatypes::{
+    Arity, Binding, Engine, LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslList, LinslPromise,
+    LinslRes, LinslWarning, Pos, Promise, RecordOp, RecordType, Symbol, WarningCategory
+};
+use crate::image::evaluate_save_image;
+use crate::matching::{choose_clause, match_forms};
+use crate::modules::{evaluate_import, evaluate_module, evaluate_require, undefined_symbol};
+use crate::testing::evaluate_define_test;
+use crate::parsing::{parse_list_of_symbols, parse_next, Tokenizer};
+use crate::primitives::{
+    apply_escape, apply_parameter, apply_record_fn, primitive_help, Help, SPECIAL_FORM_HELP
+};
+use crate::profiler::profile_name;
+use crate::streams::{evaluate_stream_form, StreamForm};
+use crate::vm;
+
+/// Creates a new frame on top of the environment specified, holding the new bindings. For example,
+/// given the list of symbols (a b c) and the list of values (1 2 3) it will bind a to 1, b to 2 and
+/// c to 3.
+///
+/// If the last symbol is preceded by a `.`, it is a rest parameter, which is bound to the list of
+/// the values left over once the other symbols are bound. For example, given the list of symbols
+/// (a . rest) and the list of values (1 2 3), it will bind a to 1 and rest to (2 3), and given the
+/// values (1) it will bind rest to ().
 ///
-/// If the list of values is longer than the list of symbols, the last symbol will be bound to the
-/// list of remaining values. For example, given the list of symbols (a b c) and the list of values
-/// (1 2 3 4), it will bind a to 1, b to 2 and c to (3 4).
+/// A parameter may also be a pattern, i.e. a list of parameters, which takes its value apart, as
+/// described in `bind_pattern`. A parameter named `_` binds nothing.
 ///
-/// If the list of values is shorter than the list of symbols, will generate an error.
-fn bind<'a>(
+/// If the number of values does not match the symbols, will generate an arity error, which is left
+/// for the caller to name the function in.
+pub(crate) fn bind(
     symbs: &LinslExpr,
-    vals: &LinslExpr,
-    env: &'a mut LinslEnv
-) -> Result<LinslEnv<'a>, LinslErr> {
-    // First, get the symbols to be bound,
-    let symbs_vec: Vec<String> = parse_list_of_symbols(symbs)?;
-    // then get the values to bind them to.
-    let vals_vec: Vec<LinslExpr> = match vals {
-        LinslExpr::List(v) => Ok(v.clone()),
+    vals_vec: &[LinslExpr],
+    env: &LinslEnv
+) -> Result<LinslEnv, LinslErr> {
+    // First, get the symbols to be bound, then bind them. Parameters which are not all symbols
+    // are taken to be patterns.
+    match (parse_list_of_symbols(symbs), symbs) {
+        (Ok(symbs_vec), _) => bind_symbols(&symbs_vec, vals_vec, env),
+        (Err(_), LinslExpr::List(params)) => {
+            let (fixed, rest) = split_at_dot(params, is_dot)?;
+            check_arity(fixed.len(), rest.is_some(), vals_vec.len())?;
+            let new_env = LinslEnv::new(env);
+            for (param, val) in fixed.iter().zip(vals_vec.iter()) {
+                bind_pattern(param, val, &new_env)?;
+            }
+            if let Some(rest) = rest {
+                let rest_vals = LinslExpr::List(vals_vec[fixed.len()..].to_vec().into());
+                bind_pattern(rest, &rest_vals, &new_env)?;
+            };
+            Ok(new_env)
+        },
+        (Err(e), _) => Err(e),
+    }
+}
+
+/// Bind the symbols of a pattern to the parts of a value in a frame. A symbol is bound to the
+/// whole value, except for `_`, which binds nothing, and a list of patterns takes apart a list
+/// with as many elements, each pattern binding the element at its place, so the pattern
+/// ((a b) c) binds a, b and c given ((1 2) 3). As in a list of parameters, a last pattern preceded
+/// by a `.` binds the list of the elements left over. A value of another shape is a TypeError.
+fn bind_pattern(pattern: &LinslExpr, val: &LinslExpr, frame: &LinslEnv) -> Result<(), LinslErr> {
+    match pattern {
+        LinslExpr::Symbol(s) if s.as_str() == "_" => Ok(()),
+        LinslExpr::Symbol(s) => {
+            // TODO: Fix pos
+            check_bindable(s).map_err(|msg| LinslErr::SyntaxError(msg, (0, 0)))?;
+            frame.insert(s.clone(), val.clone());
+            Ok(())
+        },
+        LinslExpr::List(patterns) => {
+            let (fixed, rest) = split_at_dot(patterns, is_dot)?;
+            let vals = match val {
+                LinslExpr::List(vals) if check_arity(fixed.len(), rest.is_some(), vals.len())
+                    .is_ok() => vals,
+                _ => {
+                    let shape = if rest.is_some() { "at least " } else { "" };
+                    let found = match val {
+                        LinslExpr::List(vals) => format!("a list of {}", vals.len()),
+                        _ => format!("\'{}\'", val),
+                    };
+                    return Err(
+                        LinslErr::TypeError(
+                            format!(
+                                "The pattern \'{}\' expected a list of {}{}, found {}",
+                                pattern, shape, fixed.len(), found
+                            ),
+                            // TODO: Fix pos
+                            (0, 0)
+                        )
+                    );
+                },
+            };
+            for (pattern, val) in fixed.iter().zip(vals.iter()) {
+                bind_pattern(pattern, val, frame)?;
+            }
+            if let Some(rest) = rest {
+                bind_pattern(rest, &LinslExpr::List(vals[fixed.len()..].to_vec().into()), frame)?;
+            };
+            Ok(())
+        },
         _ => Err(
             LinslErr::SyntaxError(
+                format!("Expected a symbol or a list of parameters, found \'{}\'", pattern),
                 // TODO: Fix pos
-                "Expected list of values.".to_string(),
                 (0, 0)
             )
         ),
-    }?;
+    }
+}
 
-    // If there are more symbols than values, the binding cannot be performed.
-    if symbs_vec.len() > vals_vec.len() {
+fn is_dot(expr: &LinslExpr) -> bool {
+    matches!(expr, LinslExpr::Symbol(s) if s.as_str() == ".")
+}
+
+/// How many arguments a closure with the parameters accepts, patterns counting as one each.
+pub(crate) fn params_arity(params: &LinslExpr) -> Result<Arity, LinslErr> {
+    let LinslExpr::List(params) = params else {
         return Err(
             LinslErr::SyntaxError(
-                // TODO: Fox pos
-                format!("Got {} symbols and {} values; cannot have more symbols than values.",
-                    symbs_vec.len(),
-                    vals_vec.len()),
+                format!("Expected list of parameters, found \'{}\'", params),
+                // TODO: Fix pos
                 (0, 0)
             )
         );
     };
+    match split_at_dot(params, is_dot)? {
+        (fixed, Some(_)) => Ok(Arity::AtLeast(fixed.len())),
+        (fixed, None) => Ok(Arity::Exactly(fixed.len())),
+    }
+}
 
-    for (k, v) in symbs_vec.iter().zip(vals_vec.iter()) {
-            env.inner.insert(k.clone(), v.clone());
-    };
-    if symbs_vec.len() < vals_vec.len() {
-        let i = symbs_vec.len();
-        let (_, vals_rest) = vals_vec.split_at(i - 1);
-        env.inner.insert(symbs_vec[i-1].clone(), LinslExpr::List(vals_rest.to_vec()));
+/// Check that as many values as given can be bound to the fixed parameters and the rest parameter,
+/// if there is one.
+fn check_arity(fixed: usize, rest: bool, given: usize) -> Result<(), LinslErr> {
+    let arity = if rest { Arity::AtLeast(fixed) } else { Arity::Exactly(fixed) };
+    if !arity.accepts(given) {
+        // TODO: Fix pos
+        return Err(LinslErr::ArityError(String::new(), arity, given, (0, 0)));
     };
-    Ok(env.clone())
+    Ok(())
 }
 
-/// Recursive function for finding the value for a symbol. Begins looking in the innermost scope,
-/// and looks in the outer scopes only if no match is found. If no match is found anywhere, returns
-/// None.
-fn env_get(s: &str, env: &LinslEnv) -> Option<LinslExpr> {
-    match env.inner.get(s) {
-        Some(expr) => Some(expr.clone()),
-        None => {
-            match &env.outer {
-                Some(outer_env) => env_get(s, outer_env),
-                None => None,
-            }
+/// The symbols a list of parameters binds, including those within patterns, other than `.` and
+/// `_`. Anything malformed is left out, since it is reported once the parameters are bound.
+pub(crate) fn param_symbols(params: &LinslExpr) -> Vec<Symbol> {
+    fn collect(expr: &LinslExpr, symbols: &mut Vec<Symbol>) {
+        match expr {
+            LinslExpr::Symbol(s) if !matches!(s.as_str(), "." | "_") => symbols.push(s.clone()),
+            LinslExpr::List(l) => l.iter().for_each(|e| collect(e, symbols)),
+            _ => (),
         }
     }
+    let mut symbols = Vec::new();
+    collect(params, &mut symbols);
+    symbols
 }
 
-/// The entry point for evaluating a Linsl program (since every program is an expression).
-pub fn evaluate(
-    expr: &LinslExpr, 
-    env: &mut LinslEnv
-) -> LinslRes {
-    match expr {
-        LinslExpr::Bool(_) => Ok(expr.clone()),
-        LinslExpr::List(exprs) => evaluate_list(exprs, env),
-        LinslExpr::Number(_) => Ok(expr.clone()),
-        LinslExpr::Symbol(s) => 
-            env_get(s, env)
-            .ok_or(
-                LinslErr::SyntaxError(
-                    // TODO: Fix pos
-                    format!("Undefined symbol \'{}\'", s),
-                    (0, 0)
-                )
-            )
-        ,
-        // None of the other types of expressions are valid as the top level element, which is why
-        // they cause an error.
+/// Like `bind`, but with the symbols already read from the list of symbols.
+pub(crate) fn bind_symbols(
+    symbs_vec: &[Symbol],
+    vals_vec: &[LinslExpr],
+    env: &LinslEnv
+) -> Result<LinslEnv, LinslErr> {
+    let (fixed, rest) = split_rest(symbs_vec)?;
+    check_arity(fixed.len(), rest.is_some(), vals_vec.len())?;
+
+    // The bindings go in a new frame, which refers to (rather than copies) the outer ones.
+    let new_env = LinslEnv::new(env);
+    for (k, v) in fixed.iter().zip(vals_vec.iter()) {
+        if k.as_str() != "_" {
+            new_env.insert(k.clone(), v.clone());
+        };
+    };
+    if let Some(rest) = rest.filter(|rest| rest.as_str() != "_") {
+        new_env.insert(rest.clone(), LinslExpr::List(vals_vec[fixed.len()..].to_vec().into()));
+    };
+    Ok(new_env)
+}
+
+/// Split a list of parameters into the fixed ones and the rest parameter, if there is one, i.e. if
+/// the last parameter is preceded by a `.`.
+pub(crate) fn split_rest(symbs_vec: &[Symbol]) -> Result<(&[Symbol], Option<&Symbol>), LinslErr> {
+    split_at_dot(symbs_vec, |s| s.as_str() == ".")
+}
+
+/// Like `split_rest`, but for parameters of any kind, given how to tell the `.`.
+fn split_at_dot<T>(params: &[T], is_dot: fn(&T) -> bool) -> Result<(&[T], Option<&T>), LinslErr> {
+    let dot = params.iter().position(is_dot);
+    match (dot, params) {
+        (None, _) => Ok((params, None)),
+        (Some(i), [fixed @ .., _, rest]) if i == fixed.len() && !is_dot(rest) => {
+            Ok((fixed, Some(rest)))
+        },
         _ => Err(
             LinslErr::SyntaxError(
                 // TODO: Fix pos
-                format!("Expected list or atom, found \'{}\'", expr), 
+                "A '.' in a list of parameters must be followed by exactly one symbol".to_string(),
                 (0, 0)
             )
         ),
     }
 }
 
+/// Name the function an arity error is about, and report it at the call at `pos`. A closure is
+/// named by the name it was defined under, and other functions by the symbol they were looked up
+/// by if there is one, or else by their own name. Other errors are returned as they are.
+pub(crate) fn name_callee(
+    err: LinslErr,
+    head: Option<&Symbol>,
+    f: &LinslExpr,
+    pos: Pos
+) -> LinslErr {
+    if !matches!(err, LinslErr::ArityError(..) | LinslErr::ClauseArityError(..)) {
+        return err;
+    };
+    // A case-lambda is named like its clauses are.
+    let f = match f {
+        LinslExpr::CaseLambda(clauses) => clauses.first().unwrap_or(f),
+        _ => f,
+    };
+    let name = match (head, f) {
+        (_, LinslExpr::Closure(_, _, _, Some(n), _)) => n.to_string(),
+        (Some(s), _) => s.to_string(),
+        (None, LinslExpr::Primitive(n, _) | LinslExpr::CtxPrimitive(n, _)) => n.to_string(),
+        (None, LinslExpr::RecordFn(kind, op)) => op.name(kind),
+        (None, _) => "anonymous function".to_string(),
+    };
+    err.with_callee(&name, pos)
+}
+
+/// Finds the value for a symbol by walking the chain of frames. Begins looking in the innermost
+/// frame, and looks in the outer frames only if no match is found. If no match is found anywhere,
+/// returns None.
+pub(crate) fn env_get(s: &Symbol, env: &LinslEnv) -> Option<LinslExpr> {
+    let mut current = env.clone();
+    loop {
+        let outer = {
+            let scope = current.scope();
+            if let Some(binding) = scope.inner.get(s) {
+                return Some(binding.val.clone());
+            };
+            scope.outer.clone()
+        };
+        current = outer?;
+    }
+}
+
+/// The result of evaluating a form whose value is given by an expression in tail position, such
+/// as a closure application or an if. Rather than evaluating that expression recursively, it is
+/// handed back to `evaluate`, which evaluates it in place of the original form. This way loops
+/// written as (tail) recursion run in constant stack space.
+enum Tail {
+    /// The form has been fully evaluated.
+    Done(LinslExpr),
+    /// The value of the form is the value of this expression, evaluated in this environment.
+    Eval(LinslExpr, LinslEnv),
+}
+
+/// The entry point for evaluating a Linsl program (since every program is an expression).
+///
+/// Keeps track of how deeply nested the evaluation is, and aborts it with a RecursionLimit error
+/// once the limit set in the context is exceeded, rather than overflowing the stack. The error is
+/// reported at the expression, or at the innermost form around it if it has no position.
+///
+/// If the context asks for the virtual machine, the expression is compiled and run there instead.
+pub fn evaluate(
+    expr: &LinslExpr, 
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    ctx.stats.evaluations += 1;
+    if ctx.engine == Engine::Vm {
+        return vm::evaluate(expr, env, ctx);
+    };
+
+    if let (0, Some(debugger)) = (ctx.depth, &ctx.debugger) {
+        debugger.begin(expr);
+    };
+
+    let outer_pos = ctx.pos;
+    let res = nested(ctx, expr.pos().unwrap_or(ctx.pos), |ctx| {
+        ctx.stats.max_depth = ctx.stats.max_depth.max(ctx.depth);
+        if let Some(trace) = &ctx.trace {
+            trace.enter(expr, ctx.depth);
+        };
+        let res = evaluate_expr(expr, env, ctx);
+        if let Some(trace) = &ctx.trace {
+            trace.exit(&res, ctx.depth);
+        };
+        if let Some(profile) = &mut ctx.profile {
+            profile.exit_depth(ctx.depth);
+        };
+        res
+    });
+    ctx.pos = outer_pos;
+    res
+}
+
+/// Run `f` a level deeper in the evaluation, failing with a RecursionLimit error at `pos` instead
+/// if that is deeper than the context allows.
+///
+/// Besides every evaluation, forcing a promise, calling a closure from a primitive, running the
+/// body of a parameterize, running the guard of a match clause and running the parts of a round of
+/// a do loop are counted as levels of nesting, since the virtual machine runs each of them using a
+/// new run of its own, which uses the stack just as an evaluation does. Such a run uses more of it
+/// than an evaluation, and so counts as vm::RUN_DEPTH levels.
+pub(crate) fn nested<T>(
+    ctx: &mut LinslCtx,
+    pos: Pos,
+    f: impl FnOnce(&mut LinslCtx) -> Result<T, LinslErr>
+) -> Result<T, LinslErr> {
+    let levels = match ctx.engine {
+        Engine::Vm => vm::RUN_DEPTH,
+        Engine::TreeWalker => 1,
+    };
+    if ctx.depth + levels > ctx.max_depth {
+        return Err(LinslErr::RecursionLimit(ctx.depth, pos));
+    };
+    ctx.depth += levels;
+    let res = f(ctx);
+    ctx.depth -= levels;
+    res
+}
+
+/// Parse and evaluate every form the tokenizer reads, in order, until the input is exhausted, and
+/// return their values. Stops at the first error, which is returned; input which ends in the
+/// middle of a form gives an UnbalancedParens error.
+pub fn run_program(
+    tokenizer: &mut Tokenizer,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Result<Vec<LinslExpr>, LinslErr> {
+    let mut vals = Vec::new();
+    while let Some(expr) = parse_next(tokenizer)? {
+        ctx.take_parse_warnings(tokenizer)?;
+        vals.push(evaluate(&expr, env, ctx)?);
+    }
+    Ok(vals)
+}
+
+/// Evaluates an expression, including whatever expressions are left in tail position.
+fn evaluate_expr(
+    expr: &LinslExpr, 
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let mut expr = Cow::Borrowed(expr);
+    let mut env = env.clone();
+
+    // Expressions in tail position are evaluated by going around the loop again, with the
+    // expression and environment replaced.
+    loop {
+        if let Some(pos) = expr.pos() {
+            ctx.pos = pos;
+        };
+        ctx.burn_fuel(ctx.pos)?;
+        let tail = match expr.as_ref() {
+            LinslExpr::Bool(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Foreign(_, _) => Tail::Done(expr.into_owned()),
+            LinslExpr::Keyword(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::List(exprs) => evaluate_list(exprs, &env, ctx)?,
+            LinslExpr::Number(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Complex(..) => Tail::Done(expr.into_owned()),
+            // Vector literals are self-evaluating, with elements which are not evaluated.
+            LinslExpr::Vector(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Dict(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Record(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Promise(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Iterator(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Parameter(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Values(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::String(_) => Tail::Done(expr.into_owned()),
+            LinslExpr::Symbol(s) => Tail::Done(
+                env_get(s, &env).ok_or_else(|| undefined_symbol(s, ctx))?
+            ),
+            // None of the other types of expressions are valid as the top level element, which is
+            // why they cause an error.
+            _ => return Err(
+                LinslErr::SyntaxError(
+                    format!("Expected list or atom, found \'{}\'", expr), 
+                    ctx.pos
+                )
+            ),
+        };
+
+        match tail {
+            Tail::Done(res) => return Ok(res),
+            Tail::Eval(next_expr, next_env) => {
+                expr = Cow::Owned(next_expr);
+                env = next_env;
+            },
+        }
+    }
+}
+
+/// The message to fail with when evaluating `()`, which has nothing to apply.
+pub(crate) const EMPTY_APPLICATION: &str =
+    "Cannot call the empty list; the empty list itself is written '() or nil";
+
 /// Evaluates any of the special forms, according to their respective rules.
 fn evaluate_built_in_form(
     expr: &LinslExpr, 
     param_forms: &[LinslExpr], 
-    env: &mut LinslEnv
-) -> Option<LinslRes> {
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Option<Result<Tail, LinslErr>> {
     match expr {
         LinslExpr::Symbol(s) =>
-            match s.as_ref() {
-                "define" => Some(evaluate_define(param_forms, env)),
-                "if" => Some(evaluate_if(param_forms, env)),
-                "lambda" => Some(evaluate_lambda(param_forms)),
-                "macro" => Some(evaluate_macro(param_forms)),
+            match s.as_str() {
+                "bound?" => Some(evaluate_bound(param_forms, env, ctx).map(Tail::Done)),
+                "case-lambda" => Some(
+                    evaluate_case_lambda(param_forms, env, ctx.pos).map(Tail::Done)
+                ),
+                form @ ("define" | "defconst") => {
+                    Some(evaluate_define(form, param_forms, env, ctx).map(Tail::Done))
+                },
+                "define-record" => Some(evaluate_define_record(param_forms, env).map(Tail::Done)),
+                "define-values" => Some(
+                    evaluate_define_values(param_forms, env, ctx).map(Tail::Done)
+                ),
+                "define-test" => Some(
+                    evaluate_define_test(param_forms, env, ctx).map(Tail::Done)
+                ),
+                "delay" => Some(evaluate_delay(param_forms, env, ctx.pos).map(Tail::Done)),
+                "doc" => Some(evaluate_doc(param_forms, env, ctx).map(Tail::Done)),
+                "env-symbols" => Some(evaluate_env_symbols(param_forms, env, ctx).map(Tail::Done)),
+                "help" => Some(evaluate_help(param_forms, env, ctx).map(Tail::Done)),
+                "force" => Some(evaluate_force(param_forms, env, ctx).map(Tail::Done)),
+                "if" => Some(evaluate_if(param_forms, env, ctx)),
+                "import" => Some(evaluate_import(param_forms, env, ctx).map(Tail::Done)),
+                "save-image" => Some(evaluate_save_image(param_forms, env, ctx).map(Tail::Done)),
+                "lambda" => Some(evaluate_lambda(param_forms, env, ctx.pos).map(Tail::Done)),
+                "let" => Some(
+                    let_to_lambda(param_forms)
+                        .map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))
+                        .map(|application| Tail::Eval(application, env.clone()))
+                ),
+                "let-values" => Some(evaluate_let_values(param_forms, env, ctx)),
+                "macro" => Some(evaluate_macro(param_forms, ctx.pos).map(Tail::Done)),
+                "match" => Some(evaluate_match(param_forms, env, ctx)),
+                "module" => Some(evaluate_module(param_forms, env, ctx).map(Tail::Done)),
+                "parameterize" => Some(
+                    evaluate_parameterize(param_forms, env, ctx).map(Tail::Done)
+                ),
+                form @ ("undefine" | "restore-primitive") => Some(
+                    evaluate_rebinding(form, param_forms, env, ctx).map(Tail::Done)
+                ),
+                "require" => Some(evaluate_require(param_forms, env, ctx).map(Tail::Done)),
+                "while" => Some(evaluate_while(param_forms, env, ctx).map(Tail::Done)),
+                "do" => Some(evaluate_do(param_forms, env, ctx)),
                 "quote" => match param_forms.first() {
-                    Some(e) => Some(Ok(e.clone())),
+                    Some(e) => Some(Ok(Tail::Done(e.clone()))),
                     None => Some(
                         Err(LinslErr::SyntaxError(
-                            // TODO: Fix pos
-                            "Found no expression to quote.".to_string(), 
-                            (0, 0))
+                            "Found no expression to quote.".to_string(),
+                            ctx.pos)
                         )
                     ),
                 }
-                _ => None
+                name => StreamForm::from_name(name).map(|form| {
+                    evaluate_stream_form(form, param_forms, env, ctx).map(Tail::Done)
+                }),
             },
         _ => None,
     }
 }
 
-/// Evaluation for the special form "define". It adds a new binding to the inner scope, by
-/// evaluating the second expression, and associating the first (which mus tbe a symbol) with the
-/// returned value.
-fn evaluate_define(exprs: &[LinslExpr], env: &mut LinslEnv) -> LinslRes {
+/// Evaluation for the special forms "define" and "defconst". It adds a new binding to the inner
+/// scope, by evaluating the second expression, and associating the first (which mus tbe a symbol)
+/// with the returned value. A binding made by "defconst" is a constant.
+fn evaluate_define(
+    form: &str,
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
     // Since "define" needs a symbol and a value, we check that two expressions are supplied.
     if exprs.len() != 2 {
         return Err(
             LinslErr::SyntaxError(
-                // TODO: Fix pos
-                format!("define must have two forms, found \'{}\'", exprs.len()),
-                (0, 0)
+                format!("{} must have two forms, found \'{}\'", form, exprs.len()),
+                ctx.pos
             )
         );
     };
@@ -149,171 +495,3622 @@ fn evaluate_define(exprs: &[LinslExpr], env: &mut LinslEnv) -> LinslRes {
         .ok_or(LinslErr::InternalError("Could not read define name.".to_string()))?;
 
     // ensure that the first expression is a symbol,
-    let name: String = match name_form {
+    let name: Symbol = match name_form {
         LinslExpr::Symbol(s) => Ok(s.clone()),
         _ => Err(
             LinslErr::SyntaxError(
-                // TODO: Fix pos
-                format!("First define form must be a symbol, found \'{}\'", name_form),
-                (0, 0)
+                format!("First {} form must be a symbol, found \'{}\'", form, name_form),
+                ctx.pos
             )
         ),
     }?;
+    check_bindable(&name).map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
     // and evaluate the second expression to find the value to bind.
-    let val = evaluate(&val_form[0], env)?;
+    let val = evaluate(&val_form[0], env, ctx)?;
+
+    // We then add the binding to the current environment, and return the value.
+    match form {
+        "defconst" => defconst(&name, val, env, ctx),
+        _ => define(&name, val, env, ctx),
+    }
+}
+
+/// Bind `name` to `val` in the innermost frame of `env`, and return the value as it was bound.
+/// Replacing a binding of the same frame, or hiding a primitive, gives a warning, since it is
+/// likely to be a mistake. Replacing a constant is an error. Both are reported at the position kept
+/// in the context, i.e. that of the form making the binding.
+///
+/// If the value is a closure it has captured this very frame (not a copy of it), so once the
+/// binding is added the closure can refer to itself, or to functions defined after it; this is
+/// what makes recursion work.
+pub(crate) fn define(
+    name: &Symbol,
+    val: LinslExpr,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    add_binding(name, val, None, env, ctx)
+}
+
+/// Like `define`, but the binding is a constant, which cannot be replaced. The position of the form
+/// making it is kept with it, for the error given by replacing it.
+pub(crate) fn defconst(
+    name: &Symbol,
+    val: LinslExpr,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    add_binding(name, val, Some(ctx.pos), env, ctx)
+}
+
+fn add_binding(
+    name: &Symbol,
+    val: LinslExpr,
+    constant: Option<Pos>,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let replaces = match env.scope().inner.get(name) {
+        Some(Binding { constant: Some(def), .. }) => {
+            return Err(LinslErr::ConstantError(name.to_string(), *def, ctx.pos));
+        },
+        old => old.is_some(),
+    };
+    let warning = match env_get(name, env) {
+        Some(LinslExpr::Primitive(_, _) | LinslExpr::CtxPrimitive(_, _)) => Some((
+            WarningCategory::Shadowing,
+            format!("Redefining the primitive \'{}\'", name)
+        )),
+        Some(_) if replaces => {
+            Some((WarningCategory::Redefinition, format!("Redefining \'{}\'", name)))
+        },
+        _ => None,
+    };
+    if let Some((category, message)) = warning {
+        ctx.warn(LinslWarning { category, message, pos: ctx.pos })?;
+    };
+
+    let val = val.named(name);
+    env.scope_mut().inner.insert(name.clone(), Binding { val: val.clone(), constant });
+    Ok(val)
+}
+
+/// Evaluation for the special form "define-values". `(define-values (name ...) x)` evaluates x,
+/// and binds each name to one of the values it returns, in the current frame. Returns the values.
+fn evaluate_define_values(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let (names, val_form) = define_values_forms(exprs)
+        .map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
+    let val = evaluate(val_form, env, ctx)?;
+    define_values(names, val, env, ctx)
+}
+
+/// Split the forms of a define-values into the list of names and the form giving the values, or
+/// give the message to fail with if they are malformed.
+pub(crate) fn define_values_forms(
+    exprs: &[LinslExpr]
+) -> Result<(&LinslExpr, &LinslExpr), String> {
+    match exprs {
+        [names @ LinslExpr::List(_), val_form] => {
+            for name in param_symbols(names) {
+                check_bindable(&name)?;
+            }
+            Ok((names, val_form))
+        },
+        _ => Err(
+            format!("define-values must be given a list of names and a form, found {} forms",
+                exprs.len())
+        ),
+    }
+}
 
-    // We then add the binding to the current environment
-    env.inner.insert(name, val);
+/// Bind the names of a define-values to the values of `val` in the innermost frame of `env`, as
+/// define does. The names are a list of parameters, so the last one may be a rest parameter, and
+/// giving more or fewer values than there are names is an arity error, reported at `ctx.pos`.
+pub(crate) fn define_values(
+    names: &LinslExpr,
+    val: LinslExpr,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let bound = bind_values("define-values", names, &val, env, ctx.pos)?;
+    for name in param_symbols(names) {
+        if let Some(name_val) = bound.scope().inner.get(&name).map(|b| b.val.clone()) {
+            define(&name, name_val, env, ctx)?;
+        };
+    }
+    Ok(val)
+}
 
-    // and return the newly bound name.
-    Ok(name_form.clone())
+/// Bind a list of parameters to the values of `val` in a new frame on top of `env`, for the
+/// special form `form` at `pos`. Multiple values give one value each, and anything else just
+/// itself, as for call-with-values.
+pub(crate) fn bind_values(
+    form: &str,
+    params: &LinslExpr,
+    val: &LinslExpr,
+    env: &LinslEnv,
+    pos: Pos
+) -> Result<LinslEnv, LinslErr> {
+    let vals = match val {
+        LinslExpr::Values(vals) => vals,
+        _ => std::slice::from_ref(val),
+    };
+    bind(params, vals, env).map_err(|err| err.with_callee(form, pos))
 }
 
-fn evaluate_forms(forms: &[LinslExpr], env: &mut LinslEnv) -> Result<Vec<LinslExpr>, LinslErr> {
-    forms
-        .iter()
-        .map(|x| evaluate(x, env))
-        .collect()
+/// Evaluation for the special form "let-values". `(let-values (((p ...) x) ...) body)` evaluates
+/// every x, and then body with the parameters p bound to the values of their x, as for
+/// define-values. The body is in tail position.
+fn evaluate_let_values(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Result<Tail, LinslErr> {
+    let pos = ctx.pos;
+    let (bindings, body) = let_values_forms(exprs)
+        .map_err(|msg| LinslErr::SyntaxError(msg, pos))?;
+    let mut vals = Vec::with_capacity(bindings.len());
+    for (_, val_form) in &bindings {
+        vals.push(evaluate(val_form, env, ctx)?);
+    }
+    let scope = bind_all_values(bindings.iter().map(|(params, _)| *params), &vals, env, pos)?;
+    Ok(Tail::Eval(body.clone(), scope))
 }
 
-/// Evaluation of the special form "if". It evaluates the first expression passed expecting a boolean
-/// b.
-/// Then: 
-/// - if b it evaluates the first expression after the test expression.
-/// - if !b it evaluates the second expression after the test expression.
-fn evaluate_if(exprs: &[LinslExpr], env: &mut LinslEnv) -> LinslRes {
-    if exprs.len() != 3 {
+/// The (parameters, value) forms of the bindings of a let-values, and its body.
+pub(crate) type LetValuesForms<'a> = (Vec<(&'a LinslExpr, &'a LinslExpr)>, &'a LinslExpr);
+
+/// Split the forms of a let-values into its bindings and its body, or give the message to fail
+/// with if they are malformed.
+pub(crate) fn let_values_forms(exprs: &[LinslExpr]) -> Result<LetValuesForms<'_>, String> {
+    let [LinslExpr::List(bindings_form), body] = exprs else {
         return Err(
+            format!("let-values must be given a list of bindings and a body, found {}",
+                exprs.len())
+        );
+    };
+    let mut bindings = Vec::with_capacity(bindings_form.len());
+    for binding in bindings_form.iter() {
+        match binding {
+            LinslExpr::List(pair) if pair.len() == 2 && matches!(pair[0], LinslExpr::List(_)) => {
+                bindings.push((&pair[0], &pair[1]))
+            },
+            _ => return Err(
+                format!("Expected a binding ((param ...) x), found \'{}\'", binding)
+            ),
+        }
+    }
+    Ok((bindings, body))
+}
+
+/// The frame in which the body of a let-values is evaluated, binding the parameters of every
+/// binding to the value given for it, on top of `env`. Arity errors are reported at `pos`, the
+/// position of the let-values.
+pub(crate) fn bind_all_values<'a>(
+    params: impl IntoIterator<Item = &'a LinslExpr>,
+    vals: &[LinslExpr],
+    env: &LinslEnv,
+    pos: Pos
+) -> Result<LinslEnv, LinslErr> {
+    // Even without bindings the body gets a frame of its own, as it does in a let.
+    let mut scope = LinslEnv::new(env);
+    for (params, val) in params.into_iter().zip(vals) {
+        scope = bind_values("let-values", params, val, &scope, pos)?;
+    }
+    Ok(scope)
+}
+
+/// Evaluation for the special form "define-record". `(define-record name (field ...))` creates a
+/// new record type, and binds the functions for it in the inner scope: `make-name`, `name?`, and
+/// `name-field` and `set-name-field!` for every field. Returns the name of the record type.
+pub(crate) fn evaluate_define_record(exprs: &[LinslExpr], env: &LinslEnv) -> LinslRes {
+    let (name, fields) = match exprs {
+        [LinslExpr::Symbol(name), fields_form] => (name, parse_list_of_symbols(fields_form)?),
+        [name_form, _] => return Err(
             LinslErr::SyntaxError(
                 // TODO: Fix pos
-                format!("Expected 3 arguments to if, found {}", exprs.len()),
+                format!("The name of a record type must be a symbol, found \'{}\'", name_form),
                 (0, 0)
             )
-        );
+        ),
+        _ => return Err(
+            LinslErr::SyntaxError(
+                // TODO: Fix pos
+                format!("define-record must have two forms, found \'{}\'", exprs.len()),
+                (0, 0)
+            )
+        ),
     };
-    
-    let (test_form, body) = exprs.split_first()
-        .ok_or(
-            LinslErr::InternalError("Could not read if test".to_string())
-        )?;
-    let test = evaluate(test_form, env)?;
-    match test {
-        LinslExpr::Bool(b) => {
-            if b {
-                evaluate(&body[0], env)
-            } else {
-                evaluate(&body[1], env)
-            }
+
+    // Every field needs accessors of its own, so no field may be listed twice.
+    for (i, field) in fields.iter().enumerate() {
+        if fields[..i].contains(field) {
+            return Err(
+                LinslErr::SyntaxError(
+                    // TODO: Fix pos
+                    format!("Field \'{}\' of record type \'{}\' is listed twice", field, name),
+                    (0, 0)
+                )
+            );
+        };
+    }
+
+    let kind = Arc::new(RecordType { name: name.clone(), fields });
+    let ops = [RecordOp::Make, RecordOp::Is].into_iter()
+        .chain((0..kind.fields.len()).flat_map(|i| [RecordOp::Get(i), RecordOp::Set(i)]));
+    for op in ops {
+        env.insert(&op.name(&kind), LinslExpr::RecordFn(kind.clone(), op));
+    }
+
+    Ok(LinslExpr::Symbol(name.clone()))
+}
+
+/// Evaluation of the special form "delay". It creates a promise to evaluate its expression, in the
+/// current environment, once the promise is forced.
+fn evaluate_delay(exprs: &[LinslExpr], env: &LinslEnv, pos: Pos) -> LinslRes {
+    match exprs {
+        [expr] => Ok(LinslExpr::Promise(LinslPromise::new(expr.clone(), env.clone()))),
+        _ => Err(
+            LinslErr::SyntaxError(
+                format!("delay must have one form, found {}", exprs.len()),
+                pos
+            )
+        ),
+    }
+}
+
+/// Evaluation of the special form "force". It evaluates its expression, and if that gives a
+/// promise forces it.
+fn evaluate_force(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    match exprs {
+        [expr] => {
+            let val = evaluate(expr, env, ctx)?;
+            force(val, ctx)
         },
         _ => Err(
             LinslErr::SyntaxError(
-                // TODO: Fix pos
-                format!("Test form must evaluate to bool, but evaluated to \'{}\'", test),
-                (0, 0)
+                format!("force must have one form, found {}", exprs.len()),
+                ctx.pos
             )
         ),
     }
 }
 
-/// Evaluation of the special form "lambda" used to create a closure.
-fn evaluate_lambda(expr: &[LinslExpr]) -> LinslRes {
-    let (params_form, body_form) = get_params_and_body(expr)?;
-    Ok(
-        LinslExpr::Closure(
-            Box::new(params_form),
-            Box::new(body_form),
-        )
-    )
+/// Force a value. The first time a promise is forced its expression is evaluated, and the value is
+/// kept and returned by every later force. Anything but a promise is simply returned.
+pub(crate) fn force(val: LinslExpr, ctx: &mut LinslCtx) -> LinslRes {
+    let promise = match val {
+        LinslExpr::Promise(p) => p,
+        _ => return Ok(val),
+    };
+
+    let (expr, env) = match &*promise.state() {
+        Promise::Delayed(expr, env) => (expr.clone(), env.clone()),
+        Promise::Forced(val) => return Ok(val.clone()),
+    };
+
+    // The promise is not locked while its expression is evaluated, since the expression may well
+    // force the promise itself.
+    let res = nested(ctx, expr.pos().unwrap_or(ctx.pos), |ctx| evaluate(&expr, &env, ctx))?;
+
+    // If the promise was forced while its expression was evaluated, that first value is the one
+    // which is kept.
+    let mut state = promise.state_mut();
+    match &*state {
+        Promise::Forced(val) => Ok(val.clone()),
+        Promise::Delayed(_, _) => {
+            *state = Promise::Forced(res.clone());
+            Ok(res)
+        },
+    }
 }
 
+/// Apply a function to arguments which have already been evaluated. This is how built in functions
+/// such as `map` apply the functions they are given.
+pub(crate) fn apply(f: &LinslExpr, args: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    if let Some(profile) = &mut ctx.profile {
+        profile.enter(profile_name(None, f), ctx.depth);
+    };
+    let pos = ctx.pos;
+    let res = apply_unnamed(f, args, ctx).map_err(|err| name_callee(err, None, f, pos));
+    if let Some(profile) = &mut ctx.profile {
+        profile.exit();
+    };
+    res
+}
 
-fn evaluate_list(exprs: &[LinslExpr], env: &mut LinslEnv) -> LinslRes {
-    let head = exprs
-        .first()
-        .ok_or(
-            LinslErr::SyntaxError(
+fn apply_unnamed(f: &LinslExpr, args: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    match f {
+        LinslExpr::Primitive(_, f) => f(args),
+        LinslExpr::CtxPrimitive(_, f) => f(args, ctx),
+        LinslExpr::RecordFn(kind, op) => apply_record_fn(kind, *op, args),
+        LinslExpr::Escape(escape) => apply_escape(escape, args),
+        LinslExpr::Parameter(param) => apply_parameter(param, args, ctx),
+        LinslExpr::CaseLambda(clauses) => {
+            apply_unnamed(select_clause(clauses, args.len())?, args, ctx)
+        },
+        LinslExpr::Closure(params, body, closure_env, _, _) => {
+            nested(ctx, body.pos().unwrap_or(ctx.pos), |ctx| match ctx.engine {
+                Engine::Vm => vm::apply_closure(params, body, closure_env, args, ctx),
+                Engine::TreeWalker => bind(params, args, closure_env)
+                    .and_then(|lambda_env| evaluate(body, &lambda_env, ctx)),
+            })
+        },
+        _ => Err(
+            LinslErr::TypeError(
                 // TODO: Fix pos
-                "Expected non-empty list".to_string(),
+                format!("Expected a function, found '{}'", f),
                 (0, 0)
-            ))?;
-    let param_forms = &exprs[1..];
+            )
+        ),
+    }
+}
 
-    match evaluate_built_in_form(head, param_forms, env) {
-        Some(res) => res,
-        None => {
-            let primitive = evaluate(head, env)?;
-            match primitive {
-                LinslExpr::Closure(param, body) => {
-                    let evals = LinslExpr::List(evaluate_forms(
-                                param_forms,
-                                env)?);
-                    let mut new_env = LinslEnv::new(env);
-                    let mut lambda_env = 
-                        bind(
-                            &param, 
-                            &evals,
-                            &mut new_env
-                            )?;
-                    evaluate(&body, &mut lambda_env)
-                },
-                LinslExpr::Primitive(f) => {
-                    let params_eval = param_forms
-                        .iter()
-                        .map(|e| evaluate(e, env))
-                        .collect::<Result<Vec<LinslExpr>, LinslErr>>();
-                    f(&params_eval?)
-                },
-                LinslExpr::Macro(param, body) => {
-                    let e = env.clone();
-                    let mut new_env = LinslEnv::new(&e);
-                    let mut macro_env =
-                        bind(
-                            &param,
-                            &LinslExpr::List(param_forms.to_vec()),
-                            &mut new_env
-                        )?;
-                    evaluate(&evaluate(&body, &mut macro_env)?, env)
-                },
-                _ => Err(
-                    LinslErr::SyntaxError(
-                        // TODO: Fix pos
-                        format!("Expected the head of list to be a primitive, found \'{}\'", primitive),
-                        (0, 0)
-                    )
+/// Evaluation of the special form "parameterize". `(parameterize ((p v) ...) body ...)` evaluates
+/// every p, which must be a parameter, and v, and then the body forms with every p giving the
+/// value of its v. Returns the value of the last body form.
+fn evaluate_parameterize(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let (bindings, body) = parameterize_forms(exprs)
+        .map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
+
+    let mut vals = Vec::with_capacity(2 * bindings.len());
+    for (param_form, val_form) in bindings {
+        vals.push(evaluate(param_form, env, ctx)?);
+        vals.push(evaluate(val_form, env, ctx)?);
+    }
+    parameterize(vals, ctx, |ctx| {
+        let mut res = LinslExpr::List(vec![].into());
+        for form in body {
+            res = evaluate(form, env, ctx)?;
+        }
+        Ok(res)
+    })
+}
+
+/// The (parameter, value) forms of the bindings of a parameterize, and its body.
+pub(crate) type ParameterizeForms<'a> = (Vec<(&'a LinslExpr, &'a LinslExpr)>, &'a [LinslExpr]);
+
+/// Split the forms of a parameterize into its bindings and its body, or give the message to fail
+/// with if they are malformed.
+pub(crate) fn parameterize_forms(exprs: &[LinslExpr]) -> Result<ParameterizeForms<'_>, String> {
+    let (bindings_form, body) = match exprs {
+        [LinslExpr::List(bindings), body @ ..] if !body.is_empty() => (bindings, body),
+        _ => return Err(
+            "parameterize must have a list of bindings followed by at least one form".to_string()
+        ),
+    };
+
+    let mut bindings = Vec::with_capacity(bindings_form.len());
+    for binding in bindings_form.iter() {
+        match binding {
+            LinslExpr::List(pair) if pair.len() == 2 => bindings.push((&pair[0], &pair[1])),
+            _ => return Err(
+                format!("A parameterize binding must be a (parameter value) list, found '{}'",
+                    binding)
+            ),
+        }
+    }
+    Ok((bindings, body))
+}
+
+/// Run `body` with the parameters given new values. `vals` holds every parameter followed by its
+/// new value. The parameters get their previous values back however `body` finishes, whether it
+/// returns, fails or is escaped from.
+pub(crate) fn parameterize(
+    vals: Vec<LinslExpr>,
+    ctx: &mut LinslCtx,
+    body: impl FnOnce(&mut LinslCtx) -> LinslRes
+) -> LinslRes {
+    let mut bindings = Vec::with_capacity(vals.len() / 2);
+    let mut vals = vals.into_iter();
+    while let (Some(param), Some(val)) = (vals.next(), vals.next()) {
+        match param {
+            LinslExpr::Parameter(param) => bindings.push((param, val)),
+            _ => return Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos
+                    format!("Expected a parameter, found '{}'", param),
+                    (0, 0)
                 )
-            }
-        },
+            ),
+        }
     }
+
+    nested(ctx, ctx.pos, |ctx| {
+        let outer = ctx.parameters.len();
+        ctx.parameters.extend(bindings);
+        let res = body(ctx);
+        ctx.parameters.truncate(outer);
+        res
+    })
 }
 
-fn evaluate_macro(exprs: &[LinslExpr]) -> LinslRes {
-    let (params_form, body_form) = get_params_and_body(exprs)?;
-    Ok(
-        LinslExpr::Macro(
-            Box::new(params_form),
-            Box::new(body_form)
-        )
-    )
+fn evaluate_forms(
+    forms: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Result<Vec<LinslExpr>, LinslErr> {
+    // A plain loop rather than collecting an iterator, since every iterator adapter adds a stack
+    // frame per level of (non-tail) recursion.
+    let mut vals = Vec::with_capacity(forms.len());
+    for form in forms {
+        vals.push(evaluate(form, env, ctx)?);
+    };
+    Ok(vals)
 }
 
-fn get_params_and_body(exprs: &[LinslExpr]) -> Result<(LinslExpr, LinslExpr), LinslErr> {
-    if exprs.len() != 2 {
+/// Evaluation of the special form "if". It evaluates the first expression passed, the test.
+/// Then:
+/// - if the test is true, i.e. anything but #f, it evaluates the first expression after it.
+/// - otherwise it evaluates the second expression after the test expression, or returns the empty
+///   list if there is none.
+///
+/// The chosen expression is in tail position, and so is handed back to `evaluate` unevaluated.
+fn evaluate_if(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> Result<Tail, LinslErr> {
+    let (test_form, then_form, else_form) = match exprs {
+        [test_form, then_form] => (test_form, then_form, None),
+        [test_form, then_form, else_form] => (test_form, then_form, Some(else_form)),
+        _ => return Err(LinslErr::SyntaxError(if_arity_message(exprs.len()), ctx.pos)),
+    };
+
+    match (is_truthy(&evaluate(test_form, env, ctx)?), else_form) {
+        (true, _) => Ok(Tail::Eval(then_form.clone(), env.clone())),
+        (false, Some(else_form)) => Ok(Tail::Eval(else_form.clone(), env.clone())),
+        (false, None) => Ok(Tail::Done(LinslExpr::List(LinslList::default()))),
+    }
+}
+
+/// Evaluation of the special form "match", as described in `matching`. `(match x clause ...)`
+/// evaluates x, chooses the clause for its value, and evaluates the body of the clause with the
+/// symbols of its pattern bound. The last form of the body is in tail position.
+fn evaluate_match(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Result<Tail, LinslErr> {
+    let pos = ctx.pos;
+    let (expr, clauses) = match_forms(exprs).map_err(|msg| LinslErr::SyntaxError(msg, pos))?;
+    let val = evaluate(expr, env, ctx)?;
+    let patterns = clauses.iter().map(|clause| &clause.pattern);
+    let (i, scope) = choose_clause(patterns, &val, env, pos, |i, scope| match &clauses[i].guard {
+        Some(guard) => Ok(is_truthy(&evaluate(guard, scope, ctx)?)),
+        None => Ok(true),
+    })?;
+
+    let (last, forms) = clauses[i].body.split_last()
+        .ok_or(LinslErr::InternalError("A match clause has no body.".to_string()))?;
+    for form in forms {
+        evaluate(form, &scope, ctx)?;
+    }
+    Ok(Tail::Eval(last.clone(), scope))
+}
+
+/// The message to fail with when an if is given the wrong number of forms.
+pub(crate) fn if_arity_message(forms: usize) -> String {
+    format!("if must be either (if test then) or (if test then else), found {} forms", forms)
+}
+
+/// Whether a value counts as true where a condition is expected, e.g. in the test of an if. Every
+/// value except #f does, including () and 0, so that a function can return either something it
+/// found or #f. Every conditional form decides this here, so that they all agree.
+pub fn is_truthy(expr: &LinslExpr) -> bool {
+    !matches!(expr, LinslExpr::Bool(false))
+}
+
+/// Evaluation of the special form "doc". `(doc f)` evaluates f, and if it is a symbol looks it up,
+/// so that both `(doc 'area)` and `(doc area)` give the docstring of area.
+fn evaluate_doc(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let [expr] = exprs else {
         return Err(
+            LinslErr::SyntaxError(
+                format!("doc must have one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        );
+    };
+    doc(evaluate(expr, env, ctx)?, env)
+}
+
+/// The docstring of a closure or macro, or of the value a symbol is bound to, or #f if it has
+/// none.
+pub(crate) fn doc(val: LinslExpr, env: &LinslEnv) -> LinslRes {
+    let val = match val {
+        LinslExpr::Symbol(s) => env_get(&s, env).ok_or(
             LinslErr::SyntaxError(
                 // TODO: Fix pos
-                format!("Lambda must be given two expressions, found {}", exprs.len()),
+                format!("Undefined symbol \'{}\'", s),
                 (0, 0)
             )
-        );
+        )?,
+        val => val,
     };
+    Ok(match val {
+        LinslExpr::Closure(_, _, _, _, Some(doc)) | LinslExpr::Macro(_, _, Some(doc)) => {
+            LinslExpr::String(doc)
+        },
+        _ => LinslExpr::Bool(false),
+    })
+}
 
-    let params_form = exprs.first().ok_or(
-        LinslErr::InternalError("Could not read parameters.".to_string())
-    )?;
+/// Evaluation of the special form "help", which like "doc" evaluates its form, if it has one.
+fn evaluate_help(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    match exprs {
+        [] => help(None, env),
+        [expr] => help(Some(evaluate(expr, env, ctx)?), env),
+        _ => Err(
+            LinslErr::SyntaxError(
+                format!("help must have at most one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        ),
+    }
+}
 
-    let body_form = exprs.get(1).ok_or(
-        LinslErr::InternalError("Could not read lambda body.".to_string())
-    )?;
+/// The documentation given by `help`. Without an argument this is a list of every special form,
+/// and every documented primitive which is bound, as lists of a category followed by the names in
+/// it. Given a symbol or a primitive it is a string of its usage and description (or the docstring
+/// of the closure or macro the symbol is bound to), or #f if there is none.
+pub(crate) fn help(arg: Option<LinslExpr>, env: &LinslEnv) -> LinslRes {
+    let Some(arg) = arg else {
+        return Ok(help_index(env));
+    };
+    let found = match &arg {
+        LinslExpr::Symbol(s) => find_help(s, env),
+        LinslExpr::Primitive(n, _) | LinslExpr::CtxPrimitive(n, _) => {
+            find_help(&Symbol::new(n), env)
+        },
+        _ => return doc(arg, env),
+    };
+    match (found, &arg) {
+        (Some(h), _) => Ok(LinslExpr::String(format!("{}: {}", h.usage, h.description).into())),
+        // A symbol without documentation of its own may be bound to something which has some.
+        (None, LinslExpr::Symbol(s)) => match env_get(s, env) {
+            Some(val @ (LinslExpr::Primitive(..) | LinslExpr::CtxPrimitive(..))) => {
+                help(Some(val), env)
+            },
+            Some(val) => doc(val, env),
+            None => Ok(LinslExpr::Bool(false)),
+        },
+        (None, _) => Ok(LinslExpr::Bool(false)),
+    }
+}
+
+/// The documentation of a name, looking first at what the host has registered, innermost frame
+/// first, and then at the built ins.
+fn find_help(name: &Symbol, env: &LinslEnv) -> Option<Help> {
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        let scope = frame.scope();
+        if let Some(help) = scope.help.get(name) {
+            return Some(*help);
+        };
+        current = scope.outer.clone();
+    }
+    primitive_help()
+        .chain(SPECIAL_FORM_HELP.iter().copied())
+        .find(|(n, _)| name == *n)
+        .map(|(_, help)| help)
+}
+
+/// The names `(help)` lists, grouped by category in the order the categories are first seen.
+fn help_index(env: &LinslEnv) -> LinslExpr {
+    let mut groups: Vec<(&'static str, Vec<LinslExpr>)> = Vec::new();
+    let mut add = |category: &'static str, name: Symbol| {
+        let name = LinslExpr::Symbol(name);
+        match groups.iter_mut().find(|(c, _)| *c == category) {
+            Some((_, names)) if !names.contains(&name) => names.push(name),
+            Some(_) => (),
+            None => groups.push((category, vec![name])),
+        }
+    };
+
+    for (name, help) in primitive_help() {
+        if env_get(&Symbol::new(name), env).is_some() {
+            add(help.category, Symbol::new(name));
+        };
+    }
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        let scope = frame.scope();
+        let mut registered: Vec<(&Symbol, &Help)> = scope.help.iter().collect();
+        registered.sort_by_key(|(name, _)| name.as_str());
+        for (name, help) in registered {
+            add(help.category, name.clone());
+        }
+        current = scope.outer.clone();
+    }
+    for (name, help) in SPECIAL_FORM_HELP {
+        add(help.category, Symbol::new(name));
+    }
+
+    let groups = groups.into_iter().map(|(category, names)| {
+        let mut group = vec![LinslExpr::Symbol(Symbol::new(category))];
+        group.extend(names);
+        LinslExpr::List(group.into())
+    });
+    LinslExpr::List(groups.collect())
+}
+
+/// Evaluation of the special form "bound?", which evaluates its form, giving the name to look up.
+fn evaluate_bound(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let [expr] = exprs else {
+        return Err(
+            LinslErr::SyntaxError(
+                format!("bound? must have one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        );
+    };
+    is_bound(evaluate(expr, env, ctx)?, env)
+}
+
+/// Whether a symbol is bound in the environment, in this frame or any frame it is nested in.
+/// Special forms are not bound, since they are not looked up in the environment.
+pub(crate) fn is_bound(name: LinslExpr, env: &LinslEnv) -> LinslRes {
+    match name {
+        LinslExpr::Symbol(s) => Ok(LinslExpr::Bool(env_get(&s, env).is_some())),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos
+                format!("bound? expected a symbol, found \'{}\'", name),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Evaluation of the special forms "undefine" and "restore-primitive", which evaluate their form,
+/// giving the name to remove or restore.
+fn evaluate_rebinding(
+    form: &str,
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let [expr] = exprs else {
+        return Err(
+            LinslErr::SyntaxError(
+                format!("{} must have one form, found {}", form, exprs.len()),
+                ctx.pos
+            )
+        );
+    };
+    let name = evaluate(expr, env, ctx)?;
+    match form {
+        "undefine" => undefine(name, env),
+        _ => restore_primitive(name, env),
+    }
+}
+
+/// The symbol given to `form`, or a type error if it is something else.
+fn expect_symbol(form: &str, name: LinslExpr) -> Result<Symbol, LinslErr> {
+    match name {
+        LinslExpr::Symbol(s) => Ok(s),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos
+                format!("{} expected a symbol, found \'{}\'", form, name),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Remove the binding of a symbol from the innermost frame binding it, uncovering any binding in
+/// the frames around that one. Returns whether there was a binding to remove. Special forms have no
+/// bindings, and cannot be removed.
+pub(crate) fn undefine(name: LinslExpr, env: &LinslEnv) -> LinslRes {
+    let name = expect_symbol("undefine", name)?;
+    if is_special_form(name.as_str()) {
+        return Err(
+            LinslErr::SyntaxError(
+                // TODO: Fix pos
+                format!("Cannot undefine \'{}\', which is the name of a special form", name),
+                (0, 0)
+            )
+        );
+    };
+
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        let mut scope = frame.scope_mut();
+        if scope.inner.remove(&name).is_some() {
+            scope.help.remove(&name);
+            return Ok(LinslExpr::Bool(true));
+        };
+        current = scope.outer.clone();
+    }
+    Ok(LinslExpr::Bool(false))
+}
+
+/// Bind a primitive to its name again in the outermost frame, where the primitives are bound, e.g.
+/// after it has been undefined or redefined there. Only the primitives that frame was built with
+/// can be restored, so that e.g. a sandboxed environment cannot gain any. Returns #t.
+pub(crate) fn restore_primitive(name: LinslExpr, env: &LinslEnv) -> LinslRes {
+    let name = expect_symbol("restore-primitive", name)?;
+    let mut global = env.clone();
+    while let Some(outer) = global.outer() {
+        global = outer;
+    }
+
+    let primitive = global.scope().primitives.get(&name).cloned();
+    let Some(primitive) = primitive else {
+        return Err(
+            LinslErr::SyntaxError(
+                // TODO: Fix pos
+                format!("\'{}\' is not the name of a primitive of this environment", name),
+                (0, 0)
+            )
+        );
+    };
+    global.insert(name, primitive);
+    Ok(LinslExpr::Bool(true))
+}
+
+/// Evaluation of the special form "env-symbols", which like "help" evaluates its form, if it has
+/// one.
+fn evaluate_env_symbols(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    match exprs {
+        [] => env_symbols(None, env),
+        [expr] => env_symbols(Some(evaluate(expr, env, ctx)?), env),
+        _ => Err(
+            LinslErr::SyntaxError(
+                format!("env-symbols must have at most one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        ),
+    }
+}
+
+/// A sorted list of the symbols bound in the environment, including the ones bound in the frames
+/// it is nested in, or only the ones in the innermost frame if the argument is the symbol `local`.
+pub(crate) fn env_symbols(arg: Option<LinslExpr>, env: &LinslEnv) -> LinslRes {
+    let local = match &arg {
+        None => false,
+        Some(LinslExpr::Symbol(s)) if s == "local" => true,
+        Some(arg) => return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos
+                format!("env-symbols expected local, found \'{}\'", arg),
+                (0, 0)
+            )
+        ),
+    };
+
+    let mut names: Vec<Symbol> = Vec::new();
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        let scope = frame.scope();
+        names.extend(scope.inner.keys().cloned());
+        current = if local { None } else { scope.outer.clone() };
+    }
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    // A name bound in several frames is only listed once.
+    names.dedup();
+    Ok(LinslExpr::List(names.into_iter().map(LinslExpr::Symbol).collect()))
+}
+
+/// The parts of a do loop: each variable with its initial value and its step, if it has one, the
+/// test ending the loop, the forms giving its result, and the body.
+pub(crate) struct DoForms<'a> {
+    pub(crate) vars: Vec<(&'a Symbol, &'a LinslExpr, Option<&'a LinslExpr>)>,
+    pub(crate) test: &'a LinslExpr,
+    pub(crate) result: &'a [LinslExpr],
+    pub(crate) body: &'a [LinslExpr],
+}
+
+/// Split the forms of a do loop into its parts, or give the message to fail with if they are
+/// malformed.
+pub(crate) fn do_forms(exprs: &[LinslExpr]) -> Result<DoForms<'_>, String> {
+    let (specs, rest) = match exprs.split_first() {
+        Some((LinslExpr::List(specs), rest)) => (specs, rest),
+        _ => return Err(format!(
+            "Expected the variables of do as a list of (name init step) lists, found \'{}\'",
+            exprs.first().map(|e| e.to_string()).unwrap_or_default()
+        )),
+    };
+    let mut vars: Vec<(&Symbol, _, _)> = Vec::with_capacity(specs.len());
+    for spec in specs.iter() {
+        let (name, init, step) = match spec {
+            LinslExpr::List(l) => match &l[..] {
+                [LinslExpr::Symbol(name), init] => (name, init, None),
+                [LinslExpr::Symbol(name), init, step] => (name, init, Some(step)),
+                _ => return Err(
+                    format!("Expected a variable of do as (name init step), found \'{}\'", spec)
+                ),
+            },
+            _ => return Err(
+                format!("Expected a variable of do as (name init step), found \'{}\'", spec)
+            ),
+        };
+        check_bindable(name)?;
+        if vars.iter().any(|(other, ..)| *other == name) {
+            return Err(format!("The variable \'{}\' appears more than once in do", name));
+        };
+        vars.push((name, init, step));
+    }
+    match rest.split_first() {
+        Some((LinslExpr::List(clause), body)) if !clause.is_empty() => {
+            Ok(DoForms { vars, test: &clause[0], result: &clause[1..], body })
+        },
+        _ => Err("do must have a termination clause (test result ...) after its variables"
+            .to_string()),
+    }
+}
+
+/// Evaluation of the special form "do". `(do ((name init step) ...) (test result ...) body ...)`
+/// binds each name to the value of its init in a new frame, and then, as long as test is #f,
+/// evaluates the body followed by the steps, and binds the names to the values of their steps in
+/// a frame of the next round. The steps are all evaluated before any name is bound again, and a
+/// name without a step keeps its value. Once test is true, the result forms are evaluated, the
+/// last of them in tail position; without any, the loop returns ().
+fn evaluate_do(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> Result<Tail, LinslErr> {
+    let forms = do_forms(exprs).map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
+    let mut vals = Vec::with_capacity(forms.vars.len());
+    for (_, init, _) in &forms.vars {
+        vals.push(evaluate(init, env, ctx)?);
+    }
+
+    loop {
+        let scope = LinslEnv::new(env);
+        for ((name, ..), val) in forms.vars.iter().zip(vals.drain(..)) {
+            scope.insert((*name).clone(), val);
+        }
+        if is_truthy(&evaluate(forms.test, &scope, ctx)?) {
+            return match forms.result.split_last() {
+                Some((last, init)) => {
+                    for form in init {
+                        evaluate(form, &scope, ctx)?;
+                    }
+                    Ok(Tail::Eval(last.clone(), scope))
+                },
+                None => Ok(Tail::Done(LinslExpr::List(vec![].into()))),
+            };
+        };
+        for form in forms.body {
+            evaluate(form, &scope, ctx)?;
+        }
+        for (name, _, step) in &forms.vars {
+            vals.push(match step {
+                Some(step) => evaluate(step, &scope, ctx)?,
+                None => env_get(name, &scope).ok_or_else(|| undefined_symbol(name, ctx))?,
+            });
+        }
+    }
+}
+
+/// Evaluation of the special form "while". `(while test body ...)` evaluates the test, and if it is
+/// true (in the same sense as the test of an if) the body forms, over and over until the test is
+/// false. Returns the empty list.
+///
+/// The loop is run right here rather than by recursion, so it runs in constant stack space however
+/// many times it goes around.
+fn evaluate_while(exprs: &[LinslExpr], env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let (test_form, body) = exprs.split_first()
+        .ok_or(
+            LinslErr::SyntaxError(
+                "while must have a test form".to_string(),
+                ctx.pos
+            )
+        )?;
+
+    loop {
+        if !is_truthy(&evaluate(test_form, env, ctx)?) {
+            return Ok(LinslExpr::List(vec![].into()));
+        };
+        for form in body {
+            evaluate(form, env, ctx)?;
+        }
+    }
+}
+
+/// Evaluation of the special form "lambda" used to create a closure. The closure captures the
+/// environment it is created in, so that its free symbols are resolved there rather than where it
+/// is eventually called (i.e. Linsl is lexically scoped).
+fn evaluate_lambda(expr: &[LinslExpr], env: &LinslEnv, pos: Pos) -> LinslRes {
+    let (params_form, body_form, doc) = get_params_and_body(expr)
+        .map_err(|msg| LinslErr::SyntaxError(msg, pos))?;
+    Ok(
+        LinslExpr::Closure(
+            Arc::new(params_form),
+            Arc::new(body_form),
+            env.clone(),
+            None,
+            doc,
+        )
+    )
+}
+
+/// The parameters and body of a clause of a case-lambda.
+pub(crate) type CaseLambdaClause = (Arc<LinslExpr>, Arc<LinslExpr>);
+
+/// Evaluation of the special form "case-lambda". `(case-lambda (params body) ...)` creates a
+/// function with a closure for each clause, which is applied as the first of them accepting as many
+/// arguments as it is given.
+fn evaluate_case_lambda(exprs: &[LinslExpr], env: &LinslEnv, pos: Pos) -> LinslRes {
+    let clauses = case_lambda_clauses(exprs).map_err(|msg| LinslErr::SyntaxError(msg, pos))?;
+    Ok(make_case_lambda(&clauses, env))
+}
+
+/// The parameters and body of each clause of a case-lambda, or the message to fail with if they
+/// are malformed. A clause whose parameters are a single symbol, e.g. (args body), accepts any
+/// number of arguments, as a list, and so must be the last one.
+pub(crate) fn case_lambda_clauses(exprs: &[LinslExpr]) -> Result<Vec<CaseLambdaClause>, String> {
+    if exprs.is_empty() {
+        return Err("case-lambda must be given at least one clause".to_string());
+    };
+    let mut clauses = Vec::with_capacity(exprs.len());
+    for (i, clause) in exprs.iter().enumerate() {
+        let (params, body) = match clause {
+            LinslExpr::List(l) if l.len() == 2 => (&l[0], &l[1]),
+            _ => return Err(format!("Expected a clause (params body), found \'{}\'", clause)),
+        };
+        let params = match params {
+            LinslExpr::Symbol(_) if i + 1 == exprs.len() => {
+                LinslExpr::List(vec![LinslExpr::Symbol(Symbol::new(".")), params.clone()].into())
+            },
+            LinslExpr::Symbol(_) => return Err(
+                format!("The clause \'{}\' accepts any arguments, so it must be the last", clause)
+            ),
+            _ => params.clone(),
+        };
+        // Anything else wrong with the parameters is reported once a clause is chosen.
+        for symbol in param_symbols(&params) {
+            check_bindable(&symbol)?;
+        }
+        clauses.push((Arc::new(params), Arc::new(body.clone())));
+    }
+    Ok(clauses)
+}
+
+/// A case-lambda with a closure for each of the clauses, capturing `env`.
+pub(crate) fn make_case_lambda(
+    clauses: &[CaseLambdaClause],
+    env: &LinslEnv
+) -> LinslExpr {
+    LinslExpr::CaseLambda(
+        clauses.iter()
+            .map(|(params, body)| {
+                LinslExpr::Closure(params.clone(), body.clone(), env.clone(), None, None)
+            })
+            .collect()
+    )
+}
+
+/// The first clause of a case-lambda accepting `n` arguments. If there is none, the error lists
+/// every number of arguments the clauses accept.
+pub(crate) fn select_clause(clauses: &[LinslExpr], n: usize) -> Result<&LinslExpr, LinslErr> {
+    let mut arities = Vec::with_capacity(clauses.len());
+    for clause in clauses {
+        if let LinslExpr::Closure(params, ..) = clause {
+            let arity = params_arity(params)?;
+            if arity.accepts(n) {
+                return Ok(clause);
+            };
+            arities.push(arity);
+        };
+    }
+    // TODO: Fix pos
+    Err(LinslErr::ClauseArityError(String::new(), arities, n, (0, 0)))
+}
+
+/// Evaluation of a list, i.e. either a special form or an application. Applications of closures
+/// and macros are completed by evaluating an expression in tail position (the closure body and the
+/// macro expansion, respectively), which is handed back to `evaluate`.
+fn evaluate_list(
+    exprs: &LinslList,
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> Result<Tail, LinslErr> {
+    // Errors about the application as a whole are reported at the list.
+    let pos = exprs.pos().unwrap_or(ctx.pos);
+    let head = exprs
+        .first()
+        .ok_or(LinslErr::SyntaxError(EMPTY_APPLICATION.to_string(), pos))?;
+    let param_forms = exprs.tail();
+
+    match evaluate_built_in_form(head, &param_forms, env, ctx) {
+        Some(res) => res,
+        None => {
+            if let Some(debugger) = &ctx.debugger {
+                let expr = LinslExpr::List(exprs.clone());
+                debugger.before_apply(&expr, pos, env, ctx.depth)?;
+            };
+            let mut primitive = evaluate(head, env, ctx)?;
+            // Arity errors name the function by the symbol it was looked up by, if any.
+            let head_symbol = match head {
+                LinslExpr::Symbol(s) => Some(s),
+                _ => None,
+            };
+            // A case-lambda is applied as its clause accepting as many arguments as there are.
+            if let LinslExpr::CaseLambda(clauses) = &primitive {
+                let clause = select_clause(clauses, param_forms.len())
+                    .map_err(|err| name_callee(err, head_symbol, &primitive, pos))?;
+                primitive = clause.clone();
+            };
+            let named = |err| name_callee(err, head_symbol, &primitive, pos);
+            match &primitive {
+                LinslExpr::Closure(param, body, closure_env, _, _) => {
+                    let evals = evaluate_forms(&param_forms, env, ctx)?;
+                    // The parameters are bound in a new frame on top of the frame the closure was
+                    // created in, not the one it is called from.
+                    let lambda_env = bind(param, &evals, closure_env).map_err(named)?;
+                    // The closure returns once the evaluation at this depth is done, and a call
+                    // in tail position replaces the one it is made from.
+                    if let Some(profile) = &mut ctx.profile {
+                        profile.enter_tail(profile_name(head_symbol, &primitive), ctx.depth);
+                    };
+                    Ok(Tail::Eval(body.as_ref().clone(), lambda_env))
+                },
+                LinslExpr::Primitive(_, f) => {
+                    let params_eval = evaluate_forms(&param_forms, env, ctx)?;
+                    if let Some(profile) = &mut ctx.profile {
+                        profile.enter(profile_name(head_symbol, &primitive), ctx.depth);
+                    };
+                    let res = f(&params_eval).map(Tail::Done).map_err(named);
+                    if let Some(profile) = &mut ctx.profile {
+                        profile.exit();
+                    };
+                    res
+                },
+                LinslExpr::CtxPrimitive(_, f) => {
+                    let params_eval = evaluate_forms(&param_forms, env, ctx)?;
+                    if let Some(profile) = &mut ctx.profile {
+                        profile.enter(profile_name(head_symbol, &primitive), ctx.depth);
+                    };
+                    let res = f(&params_eval, ctx).map(Tail::Done).map_err(named);
+                    if let Some(profile) = &mut ctx.profile {
+                        profile.exit();
+                    };
+                    res
+                },
+                LinslExpr::RecordFn(kind, op) => {
+                    let params_eval = evaluate_forms(&param_forms, env, ctx)?;
+                    apply_record_fn(kind, *op, &params_eval).map(Tail::Done).map_err(named)
+                },
+                LinslExpr::Escape(escape) => {
+                    let params_eval = evaluate_forms(&param_forms, env, ctx)?;
+                    apply_escape(escape, &params_eval).map(Tail::Done).map_err(named)
+                },
+                LinslExpr::Parameter(param) => {
+                    let params_eval = evaluate_forms(&param_forms, env, ctx)?;
+                    apply_parameter(param, &params_eval, ctx).map(Tail::Done).map_err(named)
+                },
+                LinslExpr::Macro(param, body, _) => {
+                    let macro_env = bind(param, &param_forms, env).map_err(named)?;
+                    Ok(Tail::Eval(evaluate(body, &macro_env, ctx)?, env.clone()))
+                },
+                _ => Err(
+                    LinslErr::SyntaxError(
+                        format!("Expected the head of list to be a primitive, found \'{}\'", primitive),
+                        pos
+                    )
+                )
+            }
+        },
+    }
+}
+
+fn evaluate_macro(exprs: &[LinslExpr], pos: Pos) -> LinslRes {
+    let (params_form, body_form, doc) = get_params_and_body(exprs)
+        .map_err(|msg| LinslErr::SyntaxError(msg, pos))?;
+    Ok(
+        LinslExpr::Macro(
+            Arc::new(params_form),
+            Arc::new(body_form),
+            doc
+        )
+    )
+}
+
+/// Split the forms of a lambda or macro into the parameters, the body and the docstring, giving
+/// the message to fail with if they are malformed. A string followed by the body is a docstring,
+/// but a string on its own is the body.
+pub(crate) fn get_params_and_body(
+    exprs: &[LinslExpr]
+) -> Result<(LinslExpr, LinslExpr, Option<Arc<str>>), String> {
+    let (params_form, body_form, doc) = match exprs {
+        [params_form, body_form] => (params_form, body_form, None),
+        [params_form, LinslExpr::String(doc), body_form] => {
+            (params_form, body_form, Some(doc.clone()))
+        },
+        _ => return Err(format!("Lambda must be given two expressions, found {}", exprs.len())),
+    };
+    // Anything else wrong with the parameters is reported once they are bound.
+    if let LinslExpr::List(params) = params_form {
+        for param in params.iter() {
+            if let LinslExpr::Symbol(s) = param {
+                check_bindable(s)?;
+            };
+        }
+    };
+    Ok((params_form.clone(), body_form.clone(), doc))
+}
+
+/// The application of a lambda which a let form stands for, i.e. (let ((p x) ...) body) is
+/// ((lambda (p ...) body) x ...), or the message to fail with if the form is malformed. The
+/// patterns p are bound as the parameters of the lambda are, so they can take their values apart.
+pub(crate) fn let_to_lambda(exprs: &[LinslExpr]) -> Result<LinslExpr, String> {
+    let [LinslExpr::List(bindings), body] = exprs else {
+        return Err(
+            format!("let must be given a list of bindings and a body, found {}", exprs.len())
+        );
+    };
+    let mut params = Vec::with_capacity(bindings.len());
+    let mut application = vec![LinslExpr::Symbol(Symbol::new("lambda"))];
+    for binding in bindings.iter() {
+        match binding {
+            LinslExpr::List(pair) if pair.len() == 2 => {
+                params.push(pair[0].clone());
+                application.push(pair[1].clone());
+            },
+            _ => return Err(format!("Expected a binding (pattern x), found \'{}\'", binding)),
+        }
+    }
+    let lambda = vec![application[0].clone(), LinslExpr::List(params.into()), body.clone()];
+    application[0] = LinslExpr::List(lambda.into());
+    Ok(LinslExpr::List(application.into()))
+}
+
+/// Whether `name` is the name of a special form.
+pub fn is_special_form(name: &str) -> bool {
+    SPECIAL_FORM_HELP.iter().any(|(form, _)| *form == name)
+}
+
+/// Check that `name` can be bound, giving the message to fail with if it cannot. Special forms
+/// are recognized by their names before the environment is consulted, so a binding of such a name
+/// could never be used; rather than silently having no effect, defining one or using one as a
+/// parameter is an error.
+pub(crate) fn check_bindable(name: &Symbol) -> Result<(), String> {
+    if is_special_form(name.as_str()) {
+        return Err(format!("Cannot bind \'{}\', which is the name of a special form", name));
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::io::{BufRead, Cursor};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::datatypes::{LinslEnvBuilder, DEFAULT_MAX_DEPTH, DEFAULT_STACK_SIZE};
+    use crate::primitives::list;
+    use crate::parsing::{parse, Tokenizer};
+
+    thread_local! {
+        /// The engine used by the tests running on this thread.
+        static ENGINE: Cell<Engine> = const { Cell::new(Engine::TreeWalker) };
+    }
+
+    /// Run a test with each engine in turn, since they must give the same results.
+    fn on_both_engines(test: impl Fn()) {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            ENGINE.set(engine);
+            test();
+        }
+    }
+
+    fn ctx() -> LinslCtx {
+        LinslCtx { engine: ENGINE.get(), ..Default::default() }
+    }
+
+    fn eval_str(s: &str, env: &LinslEnv) -> LinslRes {
+        let mut vec: VecDeque<Box<dyn BufRead>> = VecDeque::new();
+        vec.push_back(Box::new(Cursor::new(format!("{}\n", s))));
+        let mut tokenizer = Tokenizer::new(vec)?;
+        evaluate(&parse(&mut tokenizer)?, env, &mut ctx())
+    }
+
+    #[test]
+    fn printed_values_evaluate_to_equivalent_values() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define swap (macro (x y) (list y x)))", &env).unwrap();
+
+            let swap = eval_str("swap", &env).unwrap().to_string();
+            assert_eq!("(macro (x y) (list y x))", swap);
+            assert_eq!(
+                "(#<primitive +> #<primitive car>)",
+                eval_str("(list + car)", &env).unwrap().to_string()
+            );
+            assert_eq!("#<primitive map>", eval_str("map", &env).unwrap().to_string());
+            assert_eq!("#t", eval_str("(= car (car (list car)))", &env).unwrap().to_string());
+            assert_eq!("#f", eval_str("(= car cdr)", &env).unwrap().to_string());
+            assert_eq!("#f", eval_str("(= map car)", &env).unwrap().to_string());
+
+            // Reading back what was printed gives values which behave the same.
+            eval_str(&format!("(define swap2 {})", swap), &env).unwrap();
+            assert_eq!("(1)", eval_str("(swap2 1 list)", &env).unwrap().to_string());
+            for name in ["+", "neg", "*", "inv", "=", ">", "car", "cdr", "empty?", "eqt?", "list"] {
+                let prim = eval_str(name, &env).unwrap();
+                assert_eq!(format!("#<primitive {}>", name), prim.to_string());
+                assert!(matches!(prim, LinslExpr::Primitive(n, _) if n == name));
+            }
+        });
+    }
+
+    #[test]
+    fn promises() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // The delayed expression counts how many times it has been evaluated.
+            eval("(define count (make-vector 1 0))").unwrap();
+            eval("(define tick (lambda ()
+                (vector-ref (vector-set! count 0 (+ (vector-ref count 0) 1)) 0)))").unwrap();
+            eval("(define p (delay (list 'ticks (tick))))").unwrap();
+            assert_eq!("#<promise>", eval("p").unwrap());
+            assert_eq!("0", eval("(vector-ref count 0)").unwrap());
+
+            assert_eq!("(ticks 1)", eval("(force p)").unwrap());
+            assert_eq!("#<promise (forced)>", eval("p").unwrap());
+            assert_eq!("(ticks 1)", eval("(force p)").unwrap());
+            assert_eq!("1", eval("(vector-ref count 0)").unwrap());
+
+            // Forcing something which is not a promise gives the value itself.
+            assert_eq!("3", eval("(force (+ 1 2))").unwrap());
+            assert_eq!("#t", eval("(eqt? p (delay 1))").unwrap());
+
+            // The environment is captured where the promise is created.
+            eval("(define make (lambda (x) (delay (+ x 1))))").unwrap();
+            assert_eq!("2", eval("(force (make 1))").unwrap());
+
+            // A promise which fails is not forced, and is evaluated again when forced again.
+            eval("(define q (delay (+ (tick) undefined)))").unwrap();
+            assert!(eval("(force q)").is_err());
+            assert!(eval("(force q)").is_err());
+            assert_eq!("3", eval("(vector-ref count 0)").unwrap());
+            assert_eq!("#<promise>", eval("q").unwrap());
+
+            for code in ["(delay)", "(delay 1 2)", "(force)", "(force 1 2)"] {
+                let res = eval_str(code, &env);
+                assert!(matches!(res, Err(LinslErr::SyntaxError(_, _))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn promises_forcing_themselves() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // A promise forced while it is being forced keeps the first value computed. Here the
+            // inner force finishes first, so its value is the one kept.
+            eval("(define n (make-vector 1 0))").unwrap();
+            eval("(define p (delay (if (= (vector-ref n 0) 0)
+                (list 'outer (vector-set! n 0 1) (force p))
+                'inner)))").unwrap();
+            assert_eq!("inner", eval("(force p)").unwrap());
+            assert_eq!("inner", eval("(force p)").unwrap());
+
+            // A promise which forces itself forever is stopped by the recursion limit, before the
+            // stack overflows.
+            let engine = ENGINE.get();
+            let handle = std::thread::Builder::new()
+                .stack_size(DEFAULT_STACK_SIZE)
+                .spawn(move || {
+                    ENGINE.set(engine);
+                    let env = LinslEnv::default();
+                    eval_str("(define r (delay (force r)))", &env).unwrap();
+                    eval_str("(force r)", &env).err()
+                })
+                .unwrap();
+            assert!(matches!(handle.join().unwrap(), Some(LinslErr::RecursionLimit(_, _))));
+        });
+    }
+
+    #[test]
+    fn streams() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define ints-from (lambda (n) (stream-cons n (ints-from (+ n 1)))))").unwrap();
+            eval("(define nats (ints-from 0))").unwrap();
+            eval("(define even? (lambda (n) (if (= n 0) #t (if (= n 1) #f (even? (+ n -2))))))")
+                .unwrap();
+
+            assert_eq!("0", eval("(stream-car nats)").unwrap());
+            assert_eq!("1", eval("(stream-car (stream-cdr nats))").unwrap());
+            assert_eq!("(0 1 2 3)", eval("(stream-take 4 nats)").unwrap());
+            assert_eq!(
+                "(0 4 16 36 64 100 144 196 256 324)",
+                eval("(stream-take 10
+                    (stream-filter even? (stream-map (lambda (x) (* x x)) nats)))").unwrap()
+            );
+
+            // Neither the tail of a stream-cons nor the elements which are not taken are
+            // evaluated, so errors in them go unnoticed.
+            eval("(define s (stream-cons 1 undefined))").unwrap();
+            assert_eq!("(1)", eval("(stream-take 1 s)").unwrap());
+            assert!(eval("(stream-cdr s)").is_err());
+            eval("(define inverses (stream-map (lambda (x) (if (= x 3) undefined (inv x)))
+                (ints-from 1)))").unwrap();
+            assert_eq!("(1 0.5)", eval("(stream-take 2 inverses)").unwrap());
+
+            // Finite streams end with the empty list.
+            eval("(define short (stream-cons 1 (stream-cons 2 '())))").unwrap();
+            assert_eq!("(1 2)", eval("(stream-take 5 short)").unwrap());
+            assert_eq!("(2)", eval("(stream-take 5 (stream-filter even? short))").unwrap());
+            assert_eq!("()", eval("(stream-map inv '())").unwrap());
+
+            assert!(matches!(eval("(stream-car '())"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(stream-car '(1 2))"), Err(LinslErr::TypeError(_, _))));
+            // A predicate is true unless it returns #f, so numbers keep every element.
+            assert_eq!("(0 1)", eval("(stream-take 2 (stream-filter neg nats))").unwrap());
+            for code in ["(stream-cons 1)", "(stream-car)", "(stream-take 1 nats nats)"] {
+                let res = eval_str(code, &env);
+                assert!(matches!(res, Err(LinslErr::SyntaxError(_, _))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn iteration() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Stepping through a collection by hand.
+            eval("(define step (iter-next (iterate '(1 2))))").unwrap();
+            assert_eq!("(1 #<iterator>)", eval("step").unwrap());
+            assert_eq!("2", eval("(car (iter-next (car (cdr step))))").unwrap());
+            assert_eq!("()", eval("(iter-next (car (cdr (iter-next (car (cdr step))))))").unwrap());
+            assert_eq!("()", eval("(iter-next (iterate #()))").unwrap());
+
+            // Iterators are immutable, so stepping one again gives the same element.
+            eval("(define it (iterate #(a b)))").unwrap();
+            eval("(iter-next it)").unwrap();
+            assert_eq!("a", eval("(car (iter-next it))").unwrap());
+            assert_eq!("#t", eval("(eqt? it (iterate '()))").unwrap());
+
+            // map works the same over every kind of collection.
+            eval("(define double (lambda (x) (* 2 x)))").unwrap();
+            assert_eq!("(2 4 6)", eval("(map double '(1 2 3))").unwrap());
+            assert_eq!("(2 4 6)", eval("(map double #(1 2 3))").unwrap());
+            assert_eq!("(2 4 6)", eval("(map double (iterate #(1 2 3)))").unwrap());
+            assert_eq!("(2 4)", eval("(map double (car (cdr (iter-next (iterate '(0 1 2))))))")
+                .unwrap());
+            assert_eq!("()", eval("(map double '())").unwrap());
+            eval("(define d (alist->dict '((b 2) (a 1))))").unwrap();
+            assert_eq!("((a 1) (b 2))", eval("(map (lambda (entry) entry) d)").unwrap());
+            assert_eq!("(a b)", eval("(map car d)").unwrap());
+            assert_eq!("(-1 -2)", eval("(map neg #(1 2))").unwrap());
+            // A string gives its characters, however many bytes they take.
+            assert_eq!(
+                r#"("aa" "éé" "bb")"#,
+                eval(r#"(map (lambda (c) (string-append c c)) "aéb")"#).unwrap()
+            );
+            assert_eq!(r#"("a" #<iterator>)"#, eval(r#"(iter-next (iterate "ab"))"#).unwrap());
+            assert_eq!("()", eval(r#"(iter-next (iterate ""))"#).unwrap());
+
+            // Built in functions, including map itself, can be applied too.
+            assert_eq!("((2) (4))", eval("(map (lambda (xs) (map double xs)) '((1) (2)))")
+                .unwrap());
+            assert_eq!("(#t #f)", eval("(map (lambda (f) (eqt? f map)) (list for-each 1))")
+                .unwrap());
+
+            // for-each is run for its side effects, in order.
+            eval("(define total (make-vector 1 0))").unwrap();
+            eval("(define add! (lambda (x) (vector-set! total 0 (+ x (vector-ref total 0)))))")
+                .unwrap();
+            assert_eq!("()", eval("(for-each add! #(1 2 3))").unwrap());
+            assert_eq!("6", eval("(vector-ref total 0)").unwrap());
+            assert_eq!("()", eval(r#"(for-each (lambda (c) (add! 1)) "ab")"#).unwrap());
+            assert_eq!("8", eval("(vector-ref total 0)").unwrap());
+            assert_eq!("#<primitive map>", eval("map").unwrap());
+
+            assert!(matches!(eval("(iterate 1)"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(iter-next '(1))"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(map 1 '(1))"), Err(LinslErr::TypeError(_, _))));
+            assert!(eval("(map double '(a))").is_err());
+            assert!(matches!(eval("(map double)"), Err(LinslErr::ArityError(_, _, _, _))));
+        });
+    }
+
+    #[test]
+    fn escapes() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("3", eval("(call/ec (lambda (return) (+ 1 2)))").unwrap());
+            assert_eq!("2", eval("(call/ec (lambda (return) (+ 1 (return 2))))").unwrap());
+            assert_eq!("2", eval("(call-with-escape-continuation (lambda (k) (k 2)))").unwrap());
+
+            // Searching a nested list stops as soon as a match is found, from deep inside nested
+            // closures.
+            eval("(define visited (make-vector 1 0))").unwrap();
+            eval("(define visit! (lambda () (vector-set! visited 0 (+ 1 (vector-ref visited 0)))))")
+                .unwrap();
+            eval("(define big? (lambda (x) (if (eqt? (visit!) #()) (> x 2) #f)))").unwrap();
+            eval("(define walk (lambda (tree return)
+                (if (eqt? tree '())
+                    (for-each (lambda (t) (walk t return)) tree)
+                    (if (big? tree) (return tree) #f))))").unwrap();
+            eval("(define find-big (lambda (tree) (call/ec (lambda (return) (walk tree return)))))")
+                .unwrap();
+            assert_eq!("3", eval("(find-big '(1 (2 (3 4)) 5))").unwrap());
+            assert_eq!("3", eval("(vector-ref visited 0)").unwrap());
+            assert_eq!("()", eval("(find-big '(1 (2)))").unwrap());
+
+            // An escape only returns from its own call/ec, even through another one.
+            assert_eq!("10", eval("(call/ec (lambda (outer)
+                (+ 1 (call/ec (lambda (inner) (outer 10))))))").unwrap());
+            assert_eq!("11", eval("(call/ec (lambda (outer)
+                (+ 1 (call/ec (lambda (inner) (inner 10))))))").unwrap());
+
+            // Escaping out of a deep recursion leaves the recursion limit as it was.
+            eval("(define deep (lambda (n k) (if (= n 0) (k 'out) (+ 1 (deep (+ n -1) k)))))")
+                .unwrap();
+            assert_eq!("out", eval("(call/ec (lambda (k) (deep 100 k)))").unwrap());
+            assert_eq!("out", eval("(call/ec (lambda (k) (deep 100 k)))").unwrap());
+
+            // Once the call/ec has returned, its escape cannot be used.
+            eval("(define saved (call/ec (lambda (k) k)))").unwrap();
+            assert_eq!("#<escape>", eval("saved").unwrap());
+            assert!(matches!(eval("(saved 1)"), Err(LinslErr::SyntaxError(_, _))));
+            assert!(matches!(eval("(call/ec 1)"), Err(LinslErr::TypeError(_, _))));
+        });
+    }
+
+    #[test]
+    fn parameters() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define p (make-parameter 1))").unwrap();
+            eval("(define q (make-parameter 'q))").unwrap();
+            assert_eq!("#<parameter>", eval("p").unwrap());
+            assert_eq!("1", eval("(p)").unwrap());
+            assert_eq!("2", eval("(parameterize ((p 2)) (p))").unwrap());
+            assert_eq!("1", eval("(p)").unwrap());
+            assert_eq!("6", eval("(parameterize ((p 5)) (p) 6)").unwrap());
+
+            // The new values are seen by everything evaluated during the form, not only by the
+            // code within it.
+            eval("(define get-p (lambda () (p)))").unwrap();
+            assert_eq!("3", eval("(parameterize ((p 3)) (get-p))").unwrap());
+
+            // Nested forms override the outer ones, and restore them when done.
+            assert_eq!(
+                "(2 (3 4) (2 q))",
+                eval("(parameterize ((p 2))
+                    (list (p) (parameterize ((p 3) (q 4)) (list (p) (q))) (list (get-p) (q))))")
+                    .unwrap()
+            );
+
+            // The previous values are restored when the body fails,
+            assert!(eval("(parameterize ((p 2)) (parameterize ((p 3)) undefined))").is_err());
+            assert_eq!("1", eval("(p)").unwrap());
+
+            // and when it is escaped from.
+            assert_eq!("7", eval("(call/ec (lambda (k) (parameterize ((p 7)) (k (p)))))").unwrap());
+            assert_eq!("1", eval("(p)").unwrap());
+            assert_eq!(
+                "(0 2)",
+                eval("(parameterize ((p 2))
+                    (list (call/ec (lambda (k) (parameterize ((p 3)) (k 0)))) (p)))").unwrap()
+            );
+
+            assert!(matches!(eval("(parameterize ((1 2)) 3)"), Err(LinslErr::TypeError(_, _))));
+            for code in ["(parameterize ((p)) 1)", "(parameterize ((p 1)))", "(parameterize p 1)"] {
+                let res = eval_str(code, &env);
+                assert!(matches!(res, Err(LinslErr::SyntaxError(_, _))), "{}", code);
+            }
+            for code in ["(p 1)", "(make-parameter)"] {
+                let res = eval_str(code, &env);
+                assert!(matches!(res, Err(LinslErr::ArityError(_, _, _, _))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn multiple_values() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Division with remainder by repeated subtraction, returning both results.
+            eval("(define div/mod (lambda (n d)
+                (if (> d n)
+                    (values 0 n)
+                    (call-with-values
+                        (lambda () (div/mod (+ n (neg d)) d))
+                        (lambda (q r) (values (+ q 1) r))))))").unwrap();
+            assert_eq!(
+                "(3 2)",
+                eval("(call-with-values (lambda () (div/mod 17 5)) (lambda (q r) (list q r)))")
+                    .unwrap()
+            );
+            assert_eq!("#<values 3 2>", eval("(div/mod 17 5)").unwrap());
+            assert_eq!("5", eval("(call-with-values (lambda () (div/mod 17 5)) +)").unwrap());
+
+            // A single value is just that value, and no values is a values object of its own.
+            assert_eq!("1", eval("(values 1)").unwrap());
+            assert_eq!("#<values>", eval("(values)").unwrap());
+            assert_eq!("(1)", eval("(call-with-values (lambda () 1) list)").unwrap());
+            assert_eq!("()", eval("(call-with-values values list)").unwrap());
+
+            // Multiple values are not a single value of any other type.
+            assert!(eval("(+ 1 (values 1 2))").is_err());
+            assert!(matches!(
+                eval("(call-with-values (lambda () (values 1 2)) (lambda (x y z) x))"),
+                Err(LinslErr::ArityError(_, _, _, _))
+            ));
+            assert!(matches!(eval("(call-with-values 1 list)"), Err(LinslErr::TypeError(_, _))));
+        });
+    }
+
+    #[test]
+    fn closure_names() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("#<closure (x)>", eval("(lambda (x) x)").unwrap());
+
+            eval("(define fact (lambda (n) (if (= n 0) 1 (* n (fact (+ n -1))))))").unwrap();
+            assert_eq!("#<closure fact (n)>", eval("fact").unwrap());
+            assert_eq!("120", eval("(fact 5)").unwrap());
+
+            // A closure keeps the first name it is defined under.
+            eval("(define g fact)").unwrap();
+            assert_eq!("#<closure fact (n)>", eval("g").unwrap());
+            assert_eq!("#t", eval("(eqt? g fact)").unwrap());
+            assert_eq!(
+                "Arity error at (0, 0): fact expects 1 argument, but was given 0",
+                eval("(g)").unwrap_err().to_string()
+            );
+            assert_eq!(
+                "Arity error at (0, 0): fact expects 1 argument, but was given 0",
+                eval("(call-with-values (lambda () (values)) fact)").unwrap_err().to_string()
+            );
+
+            // Closures created by a named closure are not named themselves.
+            eval("(define adder (lambda (n) (lambda (x) (+ x n))))").unwrap();
+            assert_eq!("#<closure (x)>", eval("(adder 1)").unwrap());
+        });
+    }
+
+    #[test]
+    fn procedure_reflection() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define add (lambda (a b) (+ a b)))").unwrap();
+            assert_eq!("(2)", eval("(procedure-arity add)").unwrap());
+            assert_eq!("(a b)", eval("(closure-params add)").unwrap());
+            assert_eq!("(+ a b)", eval("(closure-body add)").unwrap());
+
+            eval("(define tally (lambda (x . rest) (list x rest)))").unwrap();
+            assert_eq!("(1 . variadic)", eval("(procedure-arity tally)").unwrap());
+            assert_eq!("(x . rest)", eval("(closure-params tally)").unwrap());
+            assert_eq!("(list x rest)", eval("(closure-body tally)").unwrap());
+
+            // Primitives have the arity their table declares, but are otherwise opaque.
+            assert_eq!("(1)", eval("(procedure-arity car)").unwrap());
+            assert_eq!("(0 . variadic)", eval("(procedure-arity +)").unwrap());
+            assert_eq!("(1 2)", eval("(procedure-arity make-vector)").unwrap());
+            assert!(matches!(eval("(closure-params car)"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(closure-body car)"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(procedure-arity 1)"), Err(LinslErr::TypeError(_, _))));
+
+            // Primitives of the host are not in any table.
+            env.insert("host-fn", LinslExpr::Primitive("host-fn", list));
+            assert_eq!("#f", eval("(procedure-arity host-fn)").unwrap());
+        });
+    }
+
+
+    #[test]
+    fn docstrings() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Strings evaluate to themselves, and print as they are written.
+            assert_eq!("\"a \\\"b\\\"\"", eval("\"a \\\"b\\\"\"").unwrap());
+            assert_eq!("#t", eval("(= \"ab\" \"ab\")").unwrap());
+            assert_eq!("#f", eval("(= \"ab\" \"a\")").unwrap());
+            assert_eq!("#t", eval("(eqt? \"ab\" \"\")").unwrap());
+
+            eval("(define area (lambda (r) \"The area of a circle of radius r.\" (* 3 r r)))")
+                .unwrap();
+            assert_eq!("12", eval("(area 2)").unwrap());
+            assert_eq!("\"The area of a circle of radius r.\"", eval("(doc 'area)").unwrap());
+            assert_eq!(eval("(doc 'area)").unwrap(), eval("(doc area)").unwrap());
+
+            // A string on its own is the body rather than a docstring.
+            eval("(define greeting (lambda () \"hello\"))").unwrap();
+            assert_eq!("\"hello\"", eval("(greeting)").unwrap());
+            assert_eq!("#f", eval("(doc 'greeting)").unwrap());
+
+            eval("(define swap (macro (x y) \"Swap two forms.\" (list y x)))").unwrap();
+            assert_eq!("(2)", eval("(swap 2 list)").unwrap());
+            assert_eq!("\"Swap two forms.\"", eval("(doc 'swap)").unwrap());
+            assert_eq!("(macro (x y) \"Swap two forms.\" (list y x))", eval("swap").unwrap());
+
+            assert_eq!("#f", eval("(doc 'car)").unwrap());
+            assert_eq!("#f", eval("(doc 1)").unwrap());
+            assert!(matches!(eval("(doc 'undefined)"), Err(LinslErr::SyntaxError(_, _))));
+            assert!(matches!(eval("(doc)"), Err(LinslErr::SyntaxError(_, _))));
+            assert!(matches!(eval("(lambda (x) 1 x)"), Err(LinslErr::SyntaxError(_, _))));
+        });
+    }
+
+    #[test]
+    fn help() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Everything bound in the default environment is documented.
+            let names: Vec<Symbol> = env.scope().inner.keys().cloned().collect();
+            for name in names {
+                let res = eval_str(&format!("(help '{})", name), &env).unwrap();
+                assert!(matches!(&res, LinslExpr::String(s) if !s.is_empty()), "{}", name);
+            }
+
+            assert_eq!(
+                "\"(car l): The first element of the non-empty list l.\"",
+                eval("(help 'car)").unwrap()
+            );
+            assert_eq!(eval("(help 'car)").unwrap(), eval("(help car)").unwrap());
+            eval("(define first car)").unwrap();
+            assert_eq!(eval("(help 'car)").unwrap(), eval("(help 'first)").unwrap());
+            assert!(eval("(help 'if)").unwrap().starts_with("\"(if test then [else]): "));
+
+            eval("(define area (lambda (r) \"The area of a circle.\" (* 3 r r)))").unwrap();
+            assert_eq!("\"The area of a circle.\"", eval("(help 'area)").unwrap());
+            assert_eq!("#f", eval("(help 'undefined)").unwrap());
+            assert_eq!("#f", eval("(help 1)").unwrap());
+            assert!(matches!(eval("(help 'car 'cdr)"), Err(LinslErr::SyntaxError(_, _))));
+
+            let index = eval("(help)").unwrap();
+            assert!(index.starts_with(
+                "((arithmetic + neg * inv nan? infinite? finite? make-rectangular real-part \
+                 imag-part magnitude angle) (comparison = > eqv? eq? equal? eqt?) "
+            ));
+            assert!(index.contains(" (special-forms define define-record delay doc "));
+            assert!(index.ends_with("stream-take stream-map stream-filter))"));
+
+            // Primitives registered by the host can be documented as well.
+            let entity_help = Help::new("entities", "(entity-id e)", "The id of the entity e.");
+            env.insert_with_help("entity-id", LinslExpr::Primitive("entity-id", list), entity_help);
+            let res = eval("(help 'entity-id)").unwrap();
+            assert_eq!("\"(entity-id e): The id of the entity e.\"", res);
+            assert!(eval("(help)").unwrap().contains(" (entities entity-id) "));
+        });
+    }
+
+    #[test]
+    fn equality_ladder() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define xs (list 1 (list 2 3)))").unwrap();
+            eval("(define ys (list 1 (list 2 3)))").unwrap();
+            // Two distinct lists with the same elements are equal?, but not eqv?.
+            assert_eq!("#f", eval("(eqv? xs ys)").unwrap());
+            assert_eq!("#t", eval("(equal? xs ys)").unwrap());
+            assert_eq!("#t", eval("(eqv? xs xs)").unwrap());
+            assert_eq!("#t", eval("(eq? (cdr xs) (cdr xs))").unwrap());
+            assert_eq!("#t", eval("(eqv? '() nil)").unwrap());
+            assert_eq!("#f", eval("(equal? xs (list 1 (list 2 4)))").unwrap());
+
+            assert_eq!("#t", eval("(eqv? 1 1)").unwrap());
+            assert_eq!("#t", eval("(eqv? 'a 'a)").unwrap());
+            assert_eq!("#t", eval("(eqv? :k :k)").unwrap());
+            assert_eq!("#f", eval("(eqv? 1 'a)").unwrap());
+            assert_eq!("#t", eval("(eqv? car car)").unwrap());
+            assert_eq!("#f", eval("(eqv? (lambda (x) x) (lambda (x) x))").unwrap());
+            assert_eq!("#f", eval("(eqv? \"ab\" \"ab\")").unwrap());
+            assert_eq!("#t", eval("(equal? \"ab\" \"ab\")").unwrap());
+
+            eval("(define v (vector 1 '(2)))").unwrap();
+            assert_eq!("#f", eval("(eqv? v (vector 1 '(2)))").unwrap());
+            assert_eq!("#t", eval("(equal? v (vector 1 '(2)))").unwrap());
+            assert_eq!("#t", eval("(eqv? v v)").unwrap());
+            eval("(define d (make-dict))").unwrap();
+            eval("(define e (make-dict))").unwrap();
+            eval("(dict-set! d 'a '(1))").unwrap();
+            eval("(dict-set! e 'a (list 1))").unwrap();
+            assert_eq!("#f", eval("(eqv? d e)").unwrap());
+            assert_eq!("#t", eval("(equal? d e)").unwrap());
+
+            assert_eq!("(c d)", eval("(memq 'c '(a b c d))").unwrap());
+            assert_eq!("#f", eval("(memq 'e '(a b c d))").unwrap());
+            assert_eq!("#f", eval("(memq (list 2 3) xs)").unwrap());
+            assert_eq!("((2 3))", eval("(member (list 2 3) xs)").unwrap());
+            assert_eq!("(b 2)", eval("(assq 'b '((a 1) (b 2)))").unwrap());
+            assert_eq!("#f", eval("(assq '(b) '(((b) 2)))").unwrap());
+            assert_eq!("((b) 2)", eval("(assoc '(b) '(((b) 2)))").unwrap());
+            assert!(matches!(eval("(memq 1 2)"), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval("(assq 1 '(1))"), Err(LinslErr::TypeError(_, _))));
+        });
+    }
+
+    #[test]
+    fn deep_equality() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            let nest = |innermost: f64| {
+                let mut deep = LinslExpr::Number(innermost);
+                for _ in 0..200_000 {
+                    deep = LinslExpr::List(vec![LinslExpr::Vector(vec![deep].into())].into());
+                }
+                deep
+            };
+            let (mut xs, mut ys, mut zs) = (nest(0.0), nest(0.0), nest(1.0));
+            env.insert("xs", xs.clone());
+            env.insert("ys", ys.clone());
+            env.insert("zs", zs.clone());
+            assert_eq!("#t", eval("(equal? xs ys)").unwrap());
+            assert_eq!("#f", eval("(equal? xs zs)").unwrap());
+            drop(env);
+
+            // Dropping the structures at once would recurse once per level, so they are taken
+            // apart from the outside in.
+            for deep in [&mut xs, &mut ys, &mut zs] {
+                while let LinslExpr::List(elems) = &*deep {
+                    let LinslExpr::Vector(v) = &elems[0] else { unreachable!() };
+                    let inner = v.elems()[0].clone();
+                    *deep = inner;
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn list_utilities() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!(
+                "(1 2 3 #(4 (5)) 6)",
+                eval("(flatten '(1 (2 (3 ())) () #(4 (5)) ((6))))").unwrap()
+            );
+            assert_eq!("()", eval("(flatten '(() (())))").unwrap());
+            // Deeply nested lists are flattened too.
+            eval("(define deep '(1))").unwrap();
+            eval("(define n 0)").unwrap();
+            eval("(while (> 1000 n) (define deep (list deep)) (define n (+ n 1)))").unwrap();
+            assert_eq!("(1)", eval("(flatten deep)").unwrap());
+
+            assert_eq!("((1 a) (2 b))", eval("(zip '(1 2 3) '(a b))").unwrap());
+            assert_eq!("((1 (a) #t))", eval("(zip '(1) '((a)) '(#t #f))").unwrap());
+            assert_eq!("()", eval("(zip '(1 2) '())").unwrap());
+            assert!(matches!(eval("(zip '(1) 2)"), Err(LinslErr::TypeError(_, _))));
+
+            assert_eq!(
+                "((3 4) (1 2))",
+                eval("(partition (lambda (x) (> x 2)) '(1 3 2 4))").unwrap()
+            );
+            assert_eq!("((() 0) (#f))", eval("(partition (lambda (x) x) '(() #f 0))").unwrap());
+            assert_eq!("(() ())", eval("(partition car '())").unwrap());
+            assert!(eval("(partition car '(1))").is_err());
+
+            assert_eq!(
+                "(1 (2 (3)) \"a\" 2)",
+                eval("(remove-duplicates (list 1 '(2 (3)) \"a\" 1 (list 2 '(3)) \"a\" 2))").unwrap()
+            );
+            assert_eq!("()", eval("(remove-duplicates '())").unwrap());
+            // Vectors are compared by their contents, and NaN differs from itself, as for equal?.
+            assert_eq!(
+                "(#(1) (#(2)) +nan.0 +nan.0)",
+                eval("(remove-duplicates (list #(1) (list #(2)) #(1) (list #(2)) +nan.0 +nan.0))")
+                    .unwrap()
+            );
+            assert_eq!("(0)", eval("(remove-duplicates '(0 -0))").unwrap());
+        });
+    }
+
+    #[test]
+    fn list_searching() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define big? (lambda (x) (> x 2)))").unwrap();
+            assert_eq!(
+                "(3 #f 2 0)",
+                eval("(list (find big? '(1 3 5)) (find big? '(1 2)) (count big? '(1 3 5)) \
+                      (count big? '()))").unwrap()
+            );
+            assert_eq!(
+                "(() #f #t (3) #f #t)",
+                eval("(list (any cdr '((1) (1 2) (3))) (any big? '()) (any big? '(1 4)) \
+                      (every cdr '((1 2) (2 3))) (every big? '(3 1)) (every big? '()))").unwrap()
+            );
+
+            // The predicate is not applied to the elements after the one deciding the result, and
+            // an error it raises is passed on.
+            assert_eq!("(1)", eval("(find car '((#f) (1) 3))").unwrap());
+            assert_eq!("1", eval("(any car '((#f) (1) 3))").unwrap());
+            assert_eq!("#f", eval("(every car '((1) (#f) 3))").unwrap());
+            for f in ["find", "count", "any"] {
+                let res = eval(&format!("({} car '((#f) 3))", f));
+                assert!(matches!(res, Err(LinslErr::TypeError(_, _))), "{}", f);
+            }
+            assert!(matches!(eval("(every car '((1) 3))"), Err(LinslErr::TypeError(_, _))));
+            assert!(eval("(count big? 1)").is_err());
+        });
+    }
+
+    #[test]
+    fn function_combinators() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define cadr (compose car cdr))").unwrap();
+            assert_eq!("2", eval("(cadr '(1 2 3))").unwrap());
+            assert_eq!("#<closure cadr (. args)>", eval("cadr").unwrap());
+            assert_eq!("((1 2))", eval("((compose list list) 1 2)").unwrap());
+            assert_eq!("8", eval("((compose (partial * 2) (partial + 1) car) '(3))").unwrap());
+
+            assert_eq!("10", eval("((partial + 1 2) 3 4)").unwrap());
+            assert_eq!("(1 2)", eval("((partial list 1 2))").unwrap());
+            assert_eq!("(1)", eval("((partial list) 1)").unwrap());
+
+            eval("(define add3 (curry (lambda (a b c) (+ a (+ b c))) 3))").unwrap();
+            assert_eq!(
+                "(6 6 6 6)",
+                eval("(list (((add3 1) 2) 3) ((add3 1 2) 3) (add3 1 2 3) ((((add3) 1)) 2 3))")
+                    .unwrap()
+            );
+            // The collected arguments are not shared between the functions collecting them.
+            eval("(define add1 (add3 1))").unwrap();
+            assert_eq!("(13 112)", eval("(list (add1 2 10) (add1 11 100))").unwrap());
+            assert_eq!("()", eval("((curry list 0))").unwrap());
+            assert!(matches!(eval("(curry list -1)"), Err(LinslErr::TypeError(_, _))));
+
+            // Arity errors name the function given, not the one made from it.
+            eval("(define pair (lambda (a b) (list a b)))").unwrap();
+            for (code, name) in [
+                ("((partial car '(1)) '(2))", "car"),
+                ("((compose car cdr) '(1) '(2))", "cdr"),
+                ("((curry pair 1) 1 2 3)", "pair"),
+            ] {
+                match eval(code) {
+                    Err(LinslErr::ArityError(callee, ..)) => assert_eq!(name, callee, "{}", code),
+                    res => panic!("{}: {:?}", code, res),
+                };
+            }
+            assert!(matches!(eval("(compose)"), Err(LinslErr::ArityError(_, _, _, _))));
+        });
+    }
+
+    #[test]
+    fn destructuring() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("(1 2 3)", eval("(let (((a b) (list 1 2)) (c 3)) (list a b c))").unwrap());
+            assert_eq!(
+                "(1 2 3 4)",
+                eval("((lambda ((x (y z)) rest) (list x y z rest)) '(1 (2 3)) 4)").unwrap()
+            );
+            assert_eq!("(1)", eval("(let () (list 1))").unwrap());
+            // Patterns and the rest marker combine, at the top and within a pattern.
+            assert_eq!("(1 (2 3))", eval("(let (((a . more) '(1 2 3))) (list a more))").unwrap());
+            assert_eq!("(1 2 3)", eval("((lambda (a . (b c)) (list a b c)) 1 2 3)").unwrap());
+            assert_eq!(
+                "(1 2 (3))",
+                eval("((lambda ((a b) . rest) (list a b rest)) '(1 2) 3)").unwrap()
+            );
+            // `_` binds nothing, so it can be used more than once.
+            assert_eq!("2", eval("(let (((_ b _) '(1 2 3))) b)").unwrap());
+            assert_eq!("1", eval("(let (((a . _) '(1 2))) a)").unwrap());
+            assert!(eval("((lambda (_) _) 1)").is_err());
+            assert_eq!(
+                "(2 . variadic)",
+                eval("(procedure-arity (lambda ((a b) c . d) a))").unwrap()
+            );
+
+            // The values must have the shape of the patterns.
+            let mismatches = [
+                ("(let (((a b) 5)) a)", "The pattern '(a b)' expected a list of 2, found '5'"),
+                (
+                    "(let ((((a) b) '((1 2) 3))) a)",
+                    "The pattern '(a)' expected a list of 1, found a list of 2"
+                ),
+                (
+                    "((lambda ((a . r)) a) '())",
+                    "The pattern '(a . r)' expected a list of at least 1, found a list of 0"
+                ),
+            ];
+            for (code, message) in mismatches {
+                match eval(code) {
+                    Err(LinslErr::TypeError(msg, _)) => assert_eq!(message, msg),
+                    res => panic!("{}: {:?}", code, res),
+                };
+            }
+            assert!(matches!(eval("((lambda ((a b)) a))"), Err(LinslErr::ArityError(..))));
+            for code in ["(let ((a)) a)", "(let (((a 1) '(1 2))) a)", "(let (a 1) a)", "(let ())"] {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+            // Errors are reported at the form binding the values.
+            assert!(matches!(
+                eval("(list 1\n  (define-values (x y) (values 1 2 3)))"),
+                Err(LinslErr::ArityError(_, _, 3, (1, 2)))
+            ));
+            assert!(matches!(
+                eval("(list (let-values (((a) (values 1 2))) a))"),
+                Err(LinslErr::ArityError(_, _, 2, (0, 6)))
+            ));
+            for code in ["(list (define-values a 1))", "(list (let-values ((a 1)) a))"] {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(_, (0, 6)))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn pattern_matching() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define describe (lambda (x) (match x \
+                    (0 'zero) \
+                    (#t 'true) \
+                    (\"hi\" 'greeting) \
+                    ('a 'the-symbol-a) \
+                    ((0 y) (list 'zero-then y)) \
+                    ((a (b c)) (list a b c)) \
+                    ((h . t) #:when (eqt? h 0) (list 'numbers h t)) \
+                    ((h . t) (list 'other h t)) \
+                    (other (list 'anything other)))))").unwrap();
+            // Literals only match values equal to them, while symbols match anything.
+            assert_eq!("zero", eval("(describe 0)").unwrap());
+            assert_eq!("true", eval("(describe #t)").unwrap());
+            assert_eq!("greeting", eval("(describe \"hi\")").unwrap());
+            assert_eq!("the-symbol-a", eval("(describe 'a)").unwrap());
+            assert_eq!("(anything b)", eval("(describe 'b)").unwrap());
+            assert_eq!("(anything 1)", eval("(describe 1)").unwrap());
+            // Nested lists, and rest patterns, with and without a guard.
+            assert_eq!("(zero-then 5)", eval("(describe '(0 5))").unwrap());
+            assert_eq!("(1 2 3)", eval("(describe '(1 (2 3)))").unwrap());
+            assert_eq!("(numbers 1 (2))", eval("(describe '(1 2))").unwrap());
+            assert_eq!("(numbers 1 ())", eval("(describe '(1))").unwrap());
+            assert_eq!("(other x (y z))", eval("(describe '(x y z))").unwrap());
+            assert_eq!("(anything ())", eval("(describe '())").unwrap());
+
+            // A guard which fails moves on to the next clause, and sees the symbols bound.
+            assert_eq!(
+                "small",
+                eval("(match 3 (n #:when (> n 10) 'big) (n #:when (> n 1) 'small) (_ 'tiny))")
+                    .unwrap()
+            );
+            assert_eq!("2", eval("(match '(1 2) ((a b) #:when (> b a) b) ((a b) a))").unwrap());
+            assert_eq!("2", eval("(match '(2 1) ((a b) #:when (> b a) b) ((a b) a))").unwrap());
+            // A symbol used twice only matches equal values.
+            assert_eq!("same", eval("(match '(1 1) ((x x) 'same) (_ 'different))").unwrap());
+            assert_eq!("different", eval("(match '(1 2) ((x x) 'same) (_ 'different))").unwrap());
+            assert_eq!("quoted", eval("(match '(1 2) ('(1 2) 'quoted) (_ 'not))").unwrap());
+
+            // The body is evaluated in order in a fresh scope, and the last form gives the value.
+            assert_eq!("3", eval("(match 1 (x (define y 2) (+ x y)))").unwrap());
+            assert!(eval("y").is_err());
+            assert!(eval("x").is_err());
+            // The last form is in tail position, so a loop through match runs in constant space.
+            eval("(define count-down (lambda (n) (match n (0 'done) (_ (count-down (+ n -1))))))")
+                .unwrap();
+            assert_eq!("done", eval("(count-down 100000)").unwrap());
+
+            // No clause matching is an error giving the value.
+            match eval("(match '(1 2 3) ((a b) a) (0 'zero))") {
+                Err(LinslErr::MatchError(val, _)) => assert_eq!("(1 2 3)", val),
+                res => panic!("{:?}", res),
+            };
+            assert!(matches!(eval("(match 5 (n #:when #f n))"), Err(LinslErr::MatchError(..))));
+            // Both the failed match and a malformed one are reported at the match.
+            assert!(matches!(
+                eval("(list 1\n  (match 5 (0 'zero)))"),
+                Err(LinslErr::MatchError(_, (1, 2)))
+            ));
+            assert!(matches!(eval("(list (match 1 x))"), Err(LinslErr::SyntaxError(_, (0, 6)))));
+
+            // A guard recursing forever is stopped by the recursion limit, before the stack
+            // overflows.
+            let engine = ENGINE.get();
+            let handle = std::thread::Builder::new()
+                .stack_size(DEFAULT_STACK_SIZE)
+                .spawn(move || {
+                    ENGINE.set(engine);
+                    let env = LinslEnv::default();
+                    eval_str("(define f (lambda (n) (match n (x #:when (f (+ n 1)) 1))))", &env)
+                        .unwrap();
+                    eval_str("(f 0)", &env).err()
+                })
+                .unwrap();
+            assert!(matches!(handle.join().unwrap(), Some(LinslErr::RecursionLimit(_, _))));
+            let malformed = [
+                "(match)",
+                "(match 1 (x))",
+                "(match 1 x)",
+                "(match 1 (x #:when #t))",
+                "(match 1 ((a . b c) a))",
+                "(match 1 (if 1))",
+                "(match 1 (#(1) 1))",
+            ];
+            for code in malformed {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn binding_multiple_values() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("#<values 1 2 3>", eval("(define-values (a b c) (values 1 2 3))").unwrap());
+            assert_eq!("(1 2 3)", eval("(list a b c)").unwrap());
+            // A rest name takes the values left over, and `_` binds nothing.
+            eval("(define-values (d _ . more) (values 4 5 6 7))").unwrap();
+            assert_eq!("(4 (6 7))", eval("(list d more)").unwrap());
+            // Anything but multiple values is a single value, so a list is not taken apart.
+            eval("(define-values (l) '(1 2))").unwrap();
+            assert_eq!("(1 2)", eval("l").unwrap());
+
+            // Too few and too many values are both arity errors.
+            for (code, given) in [
+                ("(define-values (x y z) (values 1 2))", 2),
+                ("(define-values (x y z) (values 1 2 3 4))", 4),
+                ("(define-values (x y z) '(1 2 3))", 1),
+            ] {
+                match eval(code) {
+                    Err(LinslErr::ArityError(name, Arity::Exactly(3), n, _)) => {
+                        assert_eq!(("define-values", given), (name.as_str(), n), "{}", code);
+                    },
+                    res => panic!("{}: {:?}", code, res),
+                };
+            }
+            assert!(eval("x").is_err());
+
+            // Within a lambda the names are bound in its frame, not at the top level.
+            eval("(define f (lambda () (cdr (list (define-values (p q) (values 1 2)) q p))))")
+                .unwrap();
+            assert_eq!("(2 1)", eval("(f)").unwrap());
+            assert!(eval("p").is_err());
+
+            assert_eq!(
+                "(1 2 3 (4))",
+                eval("(let-values (((a b) (values 1 2)) ((c . d) (values 3 4))) (list a b c d))")
+                    .unwrap()
+            );
+            // The values are computed in the enclosing scope, before anything is bound.
+            assert_eq!(
+                "(2 1)",
+                eval("(let-values (((a b) (values b a)) ((c) 3)) (list a b))").unwrap()
+            );
+            assert_eq!(
+                "(1 2)",
+                eval("(let-values (((a (b)) (values 1 '(2)))) (list a b))").unwrap()
+            );
+            assert_eq!("4", eval("(let-values () 4)").unwrap());
+            match eval("(let-values (((a b) (values 1 2 3))) a)") {
+                Err(LinslErr::ArityError(name, Arity::Exactly(2), 3, _)) => {
+                    assert_eq!("let-values", name)
+                },
+                res => panic!("{:?}", res),
+            };
+            for code in [
+                "(define-values a 1)",
+                "(define-values (if) 1)",
+                "(let-values ((a 1)) a)",
+                "(let-values (((a) 1)))",
+            ] {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn case_lambda() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define f (case-lambda ((x) (list 'one x)) ((x y) (list 'two x y))))").unwrap();
+            assert_eq!("((one 1) (two 1 2))", eval("(list (f 1) (f 1 2))").unwrap());
+            assert_eq!("#<case-lambda f (x) (x y)>", eval("f").unwrap());
+            assert_eq!("((one 1) (one 2))", eval("(map f '(1 2))").unwrap());
+            assert_eq!("((1) (2))", eval("(procedure-arity f)").unwrap());
+            match eval("(f 1 2 3)") {
+                Err(LinslErr::ClauseArityError(name, arities, 3, _)) => {
+                    assert_eq!("f", name);
+                    assert_eq!(vec![Arity::Exactly(1), Arity::Exactly(2)], arities);
+                },
+                res => panic!("{:?}", res),
+            };
+
+            // The first clause which fits is chosen, and a lone symbol takes any arguments.
+            eval("(define g (case-lambda ((x) x) ((x . rest) rest) (args (list 'none args))))")
+                .unwrap();
+            assert_eq!("(1 (2 3) (none ()))", eval("(list (g 1) (g 1 2 3) (g))").unwrap());
+            assert_eq!("((1) (1 . variadic) (0 . variadic))", eval("(procedure-arity g)").unwrap());
+
+            // The clauses are applied in tail position.
+            eval("(define count (case-lambda ((n) (count n 0)) ((n acc) \
+                  (if (> n 0) (count (+ n -1) (+ acc 1)) acc))))").unwrap();
+            assert_eq!("100000", eval("(count 100000)").unwrap());
+
+            for code in [
+                "(case-lambda)",
+                "(case-lambda (args 1) ((x) x))",
+                "(case-lambda ((x) x x))",
+                "(case-lambda ((if) 1))",
+            ] {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn do_loop() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!(
+                "(4 3 2 1 0)",
+                eval("(do ((i 0 (+ i 1)) (acc '() (append (list i) acc))) ((> i 4) acc))").unwrap()
+            );
+            // Every step sees the values of the previous round, so a and b are not updated one by
+            // one.
+            assert_eq!(
+                "55",
+                eval("(do ((a 0 b) (b 1 (+ a b)) (i 10 (+ i -1))) ((eqv? i 0) a))").unwrap()
+            );
+            // The body is evaluated for its effects, a variable without a step keeps its value,
+            // and the result forms are evaluated in order.
+            eval("(define v (vector 0))").unwrap();
+            assert_eq!(
+                "(6 10)",
+                eval("(do ((i 1 (+ i 1)) (k 10)) ((> i 3) (vector-set! v 0 (vector-ref v 0)) \
+                      (list (vector-ref v 0) k)) (vector-set! v 0 (+ (vector-ref v 0) i)))")
+                    .unwrap()
+            );
+            assert_eq!("()", eval("(do ((i 0 (+ i 1))) ((> i 2)))").unwrap());
+            // Each round has a frame of its own, which closures made in it keep.
+            assert_eq!(
+                "(2 1 0)",
+                eval("(do ((i 0 (+ i 1)) (fs '() (append (list (lambda () i)) fs))) ((> i 2) \
+                      (map (lambda (f) (f)) fs)))").unwrap()
+            );
+            // The loop runs in constant space.
+            assert_eq!("100000", eval("(do ((i 0 (+ i 1))) ((eqv? i 100000) i))").unwrap());
+            assert!(eval("i").is_err());
+
+            // A function recursing from within the loop is stopped by the recursion limit, before
+            // the stack overflows.
+            let engine = ENGINE.get();
+            let handle = std::thread::Builder::new()
+                .stack_size(DEFAULT_STACK_SIZE)
+                .spawn(move || {
+                    ENGINE.set(engine);
+                    let env = LinslEnv::default();
+                    eval_str("(define f (lambda (n) (do ((i 0 1)) ((= i 1) 0) (f (+ n 1)))))", &env)
+                        .unwrap();
+                    eval_str("(f 0)", &env).err()
+                })
+                .unwrap();
+            assert!(matches!(handle.join().unwrap(), Some(LinslErr::RecursionLimit(_, _))));
+
+            for code in [
+                "(do)",
+                "(do i (#t))",
+                "(do (i 0) (#t))",
+                "(do ((i)) (#t))",
+                "(do ((1 2)) (#t))",
+                "(do ((i 0 1 2)) (#t))",
+                "(do ((i 0) (i 1)) (#t))",
+                "(do ((if 0)) (#t))",
+                "(do ((i 0)))",
+                "(do ((i 0)) ())",
+                "(do ((i 0)) #t)",
+            ] {
+                assert!(matches!(eval(code), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+            // The error is reported at the do.
+            assert!(matches!(
+                eval("(list 1\n  (do ((i 0 1 2)) (#t)))"),
+                Err(LinslErr::SyntaxError(_, (1, 2)))
+            ));
+        });
+    }
+
+    #[test]
+    fn cycles() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define v (vector 1 2))").unwrap();
+            eval("(vector-set! v 1 v)").unwrap();
+            assert_eq!("#(1 #cycle)", eval("v").unwrap());
+            assert_eq!("(#(1 #cycle) #(1 #cycle))", eval("(list v v)").unwrap());
+            eval("(define d (make-dict))").unwrap();
+            eval("(dict-set! d 'self d)").unwrap();
+            eval("(dict-set! d 'v v)").unwrap();
+            assert_eq!("#<dict self: #cycle v: #(1 #cycle)>", eval("d").unwrap());
+            eval("(define-record node (next))").unwrap();
+            eval("(define n (make-node 0))").unwrap();
+            eval("(set-node-next! n (vector n))").unwrap();
+            assert_eq!("#<node next: #(#cycle)>", eval("n").unwrap());
+
+            // A vector shared without a cycle is printed in full wherever it is.
+            eval("(define w (vector 1))").unwrap();
+            assert_eq!("#(#(1) #(1))", eval("(vector w w)").unwrap());
+
+            // Cyclic structures are equal? if they cannot be told apart.
+            eval("(define u (vector 1 2))").unwrap();
+            eval("(vector-set! u 1 u)").unwrap();
+            assert_eq!("#t", eval("(equal? u v)").unwrap());
+            eval("(define x (vector 2 2))").unwrap();
+            eval("(vector-set! x 1 x)").unwrap();
+            assert_eq!("#f", eval("(equal? x v)").unwrap());
+            assert_eq!("#t", eval("(equal? d d)").unwrap());
+        });
+    }
+
+    #[test]
+    fn if_without_else() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("1", eval("(if #t 1)").unwrap());
+            assert_eq!("()", eval("(if #f 1)").unwrap());
+            assert_eq!("()", eval("(if (> 1 2) undefined)").unwrap());
+            // The branch is still in tail position.
+            eval("(define count (lambda (n) (if (> n 0) (count (+ n -1)))))").unwrap();
+            assert_eq!("()", eval("(count 100000)").unwrap());
+
+            assert_eq!(
+                "Syntax error at (0, 0): if must be either (if test then) or (if test then else), \
+                found 4 forms",
+                eval("(if #t 1 2 3)").unwrap_err().to_string()
+            );
+            assert!(matches!(eval("(if #t)"), Err(LinslErr::SyntaxError(_, _))));
+        });
+    }
+
+    #[test]
+    fn non_finite_numbers() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Infinities and NaN print the way they are read.
+            assert_eq!("+inf.0", eval("(* 1e308 10)").unwrap());
+            assert_eq!("-inf.0", eval("(neg +inf.0)").unwrap());
+            assert_eq!("+nan.0", eval("(+ +inf.0 -inf.0)").unwrap());
+            assert_eq!("(+inf.0 -inf.0 +nan.0)", eval("'(+inf.0 -inf.0 +nan.0)").unwrap());
+            assert_eq!("#t", eval("(> 1e300 1)").unwrap());
+
+            assert_eq!("#t", eval("(nan? +nan.0)").unwrap());
+            assert_eq!("#f", eval("(nan? +inf.0)").unwrap());
+            assert_eq!("#t", eval("(infinite? -inf.0)").unwrap());
+            assert_eq!("#f", eval("(infinite? 1)").unwrap());
+            assert_eq!("#t", eval("(finite? 1)").unwrap());
+            assert_eq!("#f", eval("(finite? +nan.0)").unwrap());
+            assert!(eval("(nan? 'a)").is_err());
+
+            // Comparisons involving NaN are false.
+            assert_eq!("#f", eval("(= +nan.0 +nan.0)").unwrap());
+            assert_eq!("#f", eval("(> +nan.0 1)").unwrap());
+            assert_eq!("#f", eval("(> 1 +nan.0)").unwrap());
+            assert_eq!("#t", eval("(> +inf.0 1e308)").unwrap());
+            assert_eq!("#t", eval("(= -inf.0 (neg +inf.0))").unwrap());
+
+            // The names Rust would read as numbers are symbols.
+            for name in ["inf", "-inf", "NaN", "infinity", "+Infinity"] {
+                assert_eq!(format!("({})", name), eval(&format!("(list '{})", name)).unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn complex_numbers() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Complex numbers print the way they are read, and are real if their imaginary part
+            // is zero.
+            assert_eq!("3+4i", eval("3+4i").unwrap());
+            assert_eq!("(-2i +1i 1-1i 100+3i 3)", eval("'(-2i +i 1-i 1e2+3i 3+0i)").unwrap());
+
+            // A real number mixed with complex ones is promoted, and results are real if they can
+            // be.
+            assert_eq!("3", eval("(+ 1 2)").unwrap());
+            assert_eq!("1+2i", eval("(+ 1 2i)").unwrap());
+            assert_eq!("4", eval("(+ 1+2i 3-2i)").unwrap());
+            assert_eq!("6+8i", eval("(* 2 3+4i)").unwrap());
+            assert_eq!("(-1 2)", eval("(list (* +i +i) (* 1+i 1-i))").unwrap());
+            assert_eq!("+inf.0+inf.0i", eval("(* +inf.0 1+i)").unwrap());
+            assert_eq!("-3-4i", eval("(neg 3+4i)").unwrap());
+            assert_eq!("(-0.5i 0.12-0.16i)", eval("(list (inv +2i) (inv 3+4i))").unwrap());
+            eval("(define - (lambda (a b) (+ a (neg b))))").unwrap();
+            eval("(define / (lambda (a b) (* a (inv b))))").unwrap();
+            assert_eq!("(5 1-1i 2+1i)", eval("(list (- 5+2i 2i) (/ 1+i +i) (/ 4+2i 2))").unwrap());
+
+            assert_eq!("3+4i", eval("(make-rectangular 3 4)").unwrap());
+            assert_eq!("3", eval("(make-rectangular 3 0)").unwrap());
+            assert_eq!("(3 4 5 0)", eval("(list (real-part 3+4i) (imag-part 3+4i) \
+                                          (real-part 5) (imag-part 5))").unwrap());
+            assert_eq!("(5 2)", eval("(list (magnitude 3+4i) (magnitude -2))").unwrap());
+            assert_eq!(
+                format!("({} {} 0)", std::f64::consts::FRAC_PI_2, std::f64::consts::PI),
+                eval("(list (angle +i) (angle -1) (angle 2))").unwrap()
+            );
+
+            assert_eq!(
+                "(#t #f #f)",
+                eval("(list (= 1+2i 1+2i) (= 1+2i 1-2i) (= 1 1+0.5i))").unwrap()
+            );
+            assert_eq!("(#t #t)", eval("(list (equal? '(1+2i) (list 1+2i)) (eqt? 1 2i))").unwrap());
+
+            // Complex numbers have no order, and are not real numbers.
+            match eval("(> 1+2i 0)") {
+                Err(LinslErr::TypeError(msg, _)) => assert!(msg.contains("cannot be ordered")),
+                res => panic!("{:?}", res),
+            };
+            for code in ["(> 0 +i)", "(nan? 1+i)", "(make-rectangular 1+i 1)", "(magnitude 'a)"] {
+                assert!(matches!(eval(code), Err(LinslErr::TypeError(..))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn truthiness() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+            eval("(define-record point (x y))").unwrap();
+
+            let truthy = [
+                "#t", "0", "1", "'()", "'(1)", ":key", "'sym", "\"\"", "\"text\"", "(vector)",
+                "(make-dict)", "(lambda (x) x)", "(macro (x) x)", "car", "map", "(delay 1)",
+                "(make-parameter #f)", "(values #f #f)", "(make-point 1 2)", "(iterate '())",
+                "make-point", "(call/ec (lambda (k) k))",
+            ];
+            for value in truthy {
+                assert_eq!("yes", eval(&format!("(if {} 'yes 'no)", value)).unwrap(), "{}", value);
+                let filtered = format!("(stream-car (stream-filter (lambda (x) {}) s))", value);
+                eval("(define s (stream-cons 1 '()))").unwrap();
+                assert_eq!("1", eval(&filtered).unwrap(), "{}", value);
+            }
+            assert_eq!("no", eval("(if #f 'yes 'no)").unwrap());
+            assert_eq!("()", eval("(stream-filter (lambda (x) #f) s)").unwrap());
+            assert!(is_truthy(&LinslExpr::Foreign("test", Arc::new(1))));
+        });
+    }
+
+    #[test]
+    fn empty_lists() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("()", eval("'()").unwrap());
+            assert_eq!("()", eval("nil").unwrap());
+            assert_eq!("#t", eval("(empty? (list))").unwrap());
+            assert_eq!("#t", eval("(empty? nil)").unwrap());
+            assert_eq!("#t", eval("(empty? (cdr '(1)))").unwrap());
+            assert_eq!("#f", eval("(empty? '(()))").unwrap());
+            assert_eq!("(1)", eval("(append nil '(1) '())").unwrap());
+
+            assert_eq!(
+                "Syntax error at (0, 0): Cannot call the empty list; the empty list itself is \
+                written '() or nil",
+                eval("()").unwrap_err().to_string()
+            );
+            // The error is reported at the empty list, as are those of malformed special forms.
+            assert!(matches!(
+                eval_str("(list 1\n  ())", &env),
+                Err(LinslErr::SyntaxError(_, (1, 2)))
+            ));
+            assert!(matches!(eval_str("(list (if))", &env), Err(LinslErr::SyntaxError(_, (0, 6)))));
+            assert_eq!(
+                "Type error at (0, 0): car was given the empty list",
+                eval("(car '())").unwrap_err().to_string()
+            );
+            assert_eq!(
+                "Type error at (0, 0): cdr was given the empty list",
+                eval("(cdr nil)").unwrap_err().to_string()
+            );
+            assert_eq!("()", eval("(car '(()))").unwrap());
+        });
+    }
+
+    #[test]
+    fn special_forms_cannot_be_bound() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+            let is_syntax_error = |s: &str| matches!(eval(s), Err(LinslErr::SyntaxError(_, _)));
+
+            for (name, _) in SPECIAL_FORM_HELP {
+                assert!(is_syntax_error(&format!("(define {} 1)", name)), "{}", name);
+            }
+            assert_eq!(
+                "Syntax error at (0, 0): Cannot bind 'if', which is the name of a special form",
+                eval("(define if list)").unwrap_err().to_string()
+            );
+            assert!(is_syntax_error("(define define 1)"));
+            assert!(is_syntax_error("(lambda (if) 1)"));
+            assert!(is_syntax_error("(lambda (a . quote) a)"));
+            assert!(is_syntax_error("(macro (x lambda) x)"));
+
+            // The special forms still work, and their names can be used as data.
+            assert_eq!("1", eval("(if #t 1 2)").unwrap());
+            assert_eq!("(if define)", eval("'(if define)").unwrap());
+        });
+    }
+
+    #[test]
+    fn rest_parameters() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Surplus arguments are an error, unless there is a rest parameter.
+            assert_eq!(
+                "Arity error at (0, 0): anonymous function expects 2 arguments, but was given 3",
+                eval("((lambda (a b) b) 1 2 3)").unwrap_err().to_string()
+            );
+
+            eval("(define f (lambda (a . rest) (list a rest)))").unwrap();
+            assert_eq!("(1 ())", eval("(f 1)").unwrap());
+            assert_eq!("(1 (2))", eval("(f 1 2)").unwrap());
+            assert_eq!("(1 (2 3 4))", eval("(f 1 2 3 4)").unwrap());
+            assert_eq!(
+                "Arity error at (0, 0): f expects at least 1 argument, but was given 0",
+                eval("(f)").unwrap_err().to_string()
+            );
+            assert_eq!("()", eval("((lambda (. xs) xs))").unwrap());
+            assert_eq!("(1 2)", eval("((lambda (. xs) xs) 1 2)").unwrap());
+
+            eval("(define my-list (macro (. xs) (append '(list) xs)))").unwrap();
+            assert_eq!("(1 2 3)", eval("(my-list 1 2 (+ 1 2))").unwrap());
+
+            assert!(matches!(eval("((lambda (a .) a) 1)"), Err(LinslErr::SyntaxError(_, _))));
+            assert!(matches!(eval("((lambda (. a b) a) 1 2)"), Err(LinslErr::SyntaxError(_, _))));
+        });
+    }
+
+    #[test]
+    fn arity_errors() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap_err().to_string();
+
+            eval_str("(define add2 (lambda (a b) (+ a b)))", &env).unwrap();
+            assert_eq!(
+                "Arity error at (0, 0): add2 expects 2 arguments, but was given 1",
+                eval("(add2 1)")
+            );
+            assert_eq!(
+                "Arity error at (0, 0): car expects 1 argument, but was given 2",
+                eval("(car '(1) '(2))")
+            );
+            assert_eq!(
+                "Arity error at (0, 0): anonymous function expects 2 arguments, but was given 1",
+                eval("((lambda (a b) a) 1)")
+            );
+
+            // Functions are named by the symbol they are applied through, if there is one.
+            eval_str("(define first car)", &env).unwrap();
+            assert_eq!(
+                "Arity error at (0, 0): first expects 1 argument, but was given 0",
+                eval("(first)")
+            );
+            assert_eq!(
+                "Arity error at (0, 0): vector-ref expects 2 arguments, but was given 1",
+                eval("(map vector-ref '(1))")
+            );
+
+            assert_eq!(
+                "Arity error at (0, 0): make-vector expects 1 to 2 arguments, but was given 0",
+                eval("(make-vector)")
+            );
+            assert_eq!(
+                "Arity error at (0, 0): append expects at least 1 argument, but was given 0",
+                eval("(append)")
+            );
+            eval_str("(define-record point (x y))", &env).unwrap();
+            assert_eq!(
+                "Arity error at (0, 0): make-point expects 2 arguments, but was given 1",
+                eval("(make-point 1)")
+            );
+
+            // The errors are reported at the call, wherever it is.
+            assert_eq!(
+                "Arity error at (1, 2): add2 expects 2 arguments, but was given 1",
+                eval("(list 1\n  (add2 1))")
+            );
+            assert_eq!(
+                "Arity error at (0, 6): car expects 1 argument, but was given 0",
+                eval("(list (car))")
+            );
+            assert_eq!(
+                "Arity error at (0, 8): vector-ref expects 2 arguments, but was given 1",
+                eval("(list 1 (map vector-ref '(1)))")
+            );
+        });
+    }
+
+    #[test]
+    fn while_loops() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define i (make-vector 1 0))").unwrap();
+            eval("(define sum (make-vector 1 0))").unwrap();
+            eval("(define inc! (lambda (v n) (vector-set! v 0 (+ n (vector-ref v 0)))))").unwrap();
+            assert_eq!(
+                "()",
+                eval("(while (> 5 (vector-ref i 0))
+                    (inc! sum (vector-ref i 0))
+                    (inc! i 1))").unwrap()
+            );
+            assert_eq!("10", eval("(vector-ref sum 0)").unwrap());
+
+            // A false test to begin with means the body is never evaluated.
+            assert_eq!("()", eval("(while #f undefined)").unwrap());
+
+            // The loop does not use up the stack, however long it runs.
+            eval("(vector-set! i 0 0)").unwrap();
+            eval("(while (> 100000 (vector-ref i 0)) (inc! i 1))").unwrap();
+            assert_eq!("100000", eval("(vector-ref i 0)").unwrap());
+
+            // Errors in the body abort the loop.
+            eval("(vector-set! i 0 0)").unwrap();
+            assert!(eval("(while #t (inc! i 1) (if (> (vector-ref i 0) 2) undefined 0))").is_err());
+            assert_eq!("3", eval("(vector-ref i 0)").unwrap());
+
+            // Any test but #f counts as true.
+            eval("(define flag (vector 1))").unwrap();
+            assert_eq!("()", eval("(while (vector-ref flag 0) (vector-set! flag 0 #f))").unwrap());
+            assert!(matches!(eval("(while)"), Err(LinslErr::SyntaxError(_, _))));
+        });
+    }
+
+    #[test]
+    fn keywords() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Keywords evaluate to themselves, even when a symbol of the same name is bound.
+            eval("(define foo 1)").unwrap();
+            assert_eq!(":foo", eval(":foo").unwrap());
+            assert_eq!(":foo", eval("#:foo").unwrap());
+            assert_eq!("(:a 1)", eval("(list :a foo)").unwrap());
+
+            assert_eq!("#t", eval("(= :foo #:foo)").unwrap());
+            assert_eq!("#f", eval("(= :foo :bar)").unwrap());
+            assert!(eval("(= :foo 'foo)").is_err());
+            assert_eq!("#t", eval("(keyword? :foo)").unwrap());
+            assert_eq!("#f", eval("(keyword? 'foo)").unwrap());
+            assert_eq!("#t", eval("(eqt? :a :b)").unwrap());
+
+            assert_eq!("foo", eval("(keyword->symbol :foo)").unwrap());
+            assert_eq!(":foo", eval("(symbol->keyword 'foo)").unwrap());
+            assert_eq!("#t", eval("(= :foo (symbol->keyword (keyword->symbol :foo)))").unwrap());
+            for code in ["(keyword->symbol 'foo)", "(symbol->keyword :foo)"] {
+                assert!(matches!(eval_str(code, &env), Err(LinslErr::TypeError(_, _))));
+            }
+
+            // Keywords can be used as dict keys, and are sorted after symbols.
+            assert_eq!("#<dict b: 2 :a: 1>", eval("(make-dict :a 1 'b 2)").unwrap());
+        });
+    }
+
+    #[test]
+    fn environment_introspection() {
+        on_both_engines(|| {
+            // The definitions go in a frame of their own, on top of the primitives.
+            let env = LinslEnv::new(&LinslEnv::default());
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("#t", eval("(bound? 'car)").unwrap());
+            assert_eq!("#f", eval("(bound? 'test-square)").unwrap());
+            eval("(define test-square (lambda (x) (* x x)))").unwrap();
+            assert_eq!("#t", eval("(bound? 'test-square)").unwrap());
+            // Special forms are not looked up, and so are not bound.
+            assert_eq!("#f", eval("(bound? 'if)").unwrap());
+
+            // A parameter is visible inside the lambda, and not outside.
+            let symbols = eval("(env-symbols)").unwrap();
+            assert!(symbols.contains(" test-square ") && symbols.contains(" car "), "{}", symbols);
+            assert!(!symbols.contains(" local-x "), "{}", symbols);
+            assert_eq!("#t", eval("((lambda (local-x) (bound? 'local-x)) 1)").unwrap());
+            assert_eq!("#f", eval("(bound? 'local-x)").unwrap());
+            let inside = eval("((lambda (local-x) (env-symbols)) 1)").unwrap();
+            assert!(inside.contains(" local-x ") && inside.contains(" test-square "), "{}", inside);
+            let local = eval("((lambda (local-x b) (env-symbols 'local)) 1 2)").unwrap();
+            assert_eq!("(b local-x)", local);
+            assert_eq!("(test-square)", eval("(env-symbols 'local)").unwrap());
+
+            // The names are sorted, and a name bound in several frames is only listed once.
+            let names = eval("((lambda (car) (env-symbols)) 1)").unwrap();
+            assert_eq!(1, names.matches(" car ").count());
+            let list: Vec<&str> = names.trim_matches(['(', ')']).split(' ').collect();
+            assert!(list.is_sorted(), "{}", names);
+
+            for code in ["(bound? 1)", "(env-symbols 'global)"] {
+                assert!(matches!(eval_str(code, &env), Err(LinslErr::TypeError(..))), "{}", code);
+            }
+            for code in ["(bound?)", "(bound? 'a 'b)", "(env-symbols 'local 'local)"] {
+                assert!(matches!(eval_str(code, &env), Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+        });
+    }
+
+    #[test]
+    fn undefine() {
+        on_both_engines(|| {
+            let env = LinslEnv::new(&LinslEnv::default());
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define scratch 1)").unwrap();
+            assert_eq!("#t", eval("(undefine 'scratch)").unwrap());
+            assert_eq!("#f", eval("(bound? 'scratch)").unwrap());
+            assert_eq!("#f", eval("(undefine 'scratch)").unwrap());
+
+            // Removing a shadowing binding uncovers the primitive again.
+            eval("(define list 'oops)").unwrap();
+            assert_eq!("oops", eval("list").unwrap());
+            assert_eq!("#t", eval("(undefine 'list)").unwrap());
+            assert_eq!("(1 2)", eval("(list 1 2)").unwrap());
+
+            // A primitive itself can be removed, and then restored.
+            assert_eq!("#t", eval("(undefine 'list)").unwrap());
+            assert!(eval("(list 1 2)").is_err());
+            assert_eq!("#t", eval("(restore-primitive 'list)").unwrap());
+            assert_eq!("(1 2)", eval("(list 1 2)").unwrap());
+            // The primitive is restored where the primitives are, not in the innermost frame.
+            assert_eq!("#t", eval("(undefine 'list)").unwrap());
+            assert_eq!("#f", eval("(undefine 'list)").unwrap());
+            eval("(restore-primitive 'list)").unwrap();
+
+            // Parameters are bindings like any other.
+            let res = eval("((lambda (x) (list (undefine 'x) (bound? 'x))) 1)").unwrap();
+            assert_eq!("(#t #f)", res);
+
+            assert!(matches!(eval_str("(undefine 'if)", &env), Err(LinslErr::SyntaxError(..))));
+            assert!(matches!(eval_str("(undefine 1)", &env), Err(LinslErr::TypeError(..))));
+            let res = eval_str("(restore-primitive 'scratch)", &env);
+            assert!(matches!(res, Err(LinslErr::SyntaxError(..))));
+            assert!(matches!(eval_str("(undefine)", &env), Err(LinslErr::SyntaxError(..))));
+
+            // Only the primitives the environment was built with can be restored.
+            let env = LinslEnv::new(&LinslEnvBuilder::new().with_math().build());
+            eval_str("(undefine '+)", &env).unwrap();
+            assert_eq!("#t", eval_str("(restore-primitive '+)", &env).unwrap().to_string());
+            assert_eq!("3", eval_str("(+ 1 2)", &env).unwrap().to_string());
+            let res = eval_str("(restore-primitive 'list)", &env);
+            assert!(matches!(res, Err(LinslErr::SyntaxError(..))));
+            assert!(matches!(eval_str("(list 1)", &env), Err(LinslErr::SyntaxError(..))));
+            let res = eval_str("(restore-primitive 'list)", &LinslEnv::empty());
+            assert!(matches!(res, Err(LinslErr::SyntaxError(..))));
+        });
+    }
+
+    #[test]
+    fn defconst() {
+        on_both_engines(|| {
+            let env = LinslEnv::new(&LinslEnv::default());
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("3.14", eval("(defconst pi 3.14)").unwrap());
+            assert_eq!("6.28", eval("(* 2 pi)").unwrap());
+
+            // Neither define nor defconst can bind the name again in the same frame.
+            for rebinding in ["(define pi 3)", "(defconst pi 3)"] {
+                match eval_str(rebinding, &env) {
+                    Err(LinslErr::ConstantError(name, _, _)) => assert_eq!("pi", name),
+                    res => panic!("Expected a constant error, found {:?}", res),
+                };
+            }
+            assert_eq!("3.14", eval("pi").unwrap());
+
+            // Inner frames may shadow the constant, e.g. as a parameter or by a define of their
+            // own, without affecting it.
+            assert_eq!("3", eval("((lambda (pi) pi) 3)").unwrap());
+            assert_eq!("(4 4)", eval("((lambda () (list (define pi 4) pi)))").unwrap());
+            assert_eq!("3.14", eval("pi").unwrap());
+
+            // A plain binding can be made a constant, which cannot be replaced anymore.
+            eval("(define e 2)").unwrap();
+            eval("(defconst e 2.72)").unwrap();
+            assert!(matches!(eval_str("(define e 2)", &env), Err(LinslErr::ConstantError(..))));
+
+            // The error gives both where the constant was defined and where it was replaced.
+            eval("(list 1\n  (defconst tau 6.28))").unwrap();
+            match eval_str("(list\n\n   (define tau 1))", &env) {
+                Err(LinslErr::ConstantError(name, def, pos)) => {
+                    assert_eq!(("tau", (1, 2), (2, 3)), (name.as_str(), def, pos));
+                },
+                res => panic!("Expected a constant error, found {:?}", res),
+            };
+
+            assert!(matches!(eval_str("(defconst 1 2)", &env), Err(LinslErr::SyntaxError(..))));
+            assert!(matches!(eval_str("(defconst x)", &env), Err(LinslErr::SyntaxError(..))));
+        });
+    }
+
+    #[test]
+    fn strings() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("\"a, b, c\"", eval("(string-join '(\"a\" \"b\" \"c\") \", \")").unwrap());
+            assert_eq!("\"abc\"", eval("(string-join '(\"a\" \"b\" \"c\"))").unwrap());
+            assert_eq!("\"a\"", eval("(string-join '(\"a\") \",\")").unwrap());
+            assert_eq!("\"\"", eval("(string-join '() \",\")").unwrap());
+            assert_eq!("\"ababab\"", eval("(string-repeat \"ab\" 3)").unwrap());
+            assert_eq!("\"\"", eval("(string-repeat \"ab\" 0)").unwrap());
+
+            // The error names the element which is not a string.
+            match eval_str("(string-join '(\"a\" \"b\" 3) \",\")", &env) {
+                Err(LinslErr::TypeError(msg, _)) => assert!(msg.contains("index 2"), "{}", msg),
+                res => panic!("Expected a type error, found {:?}", res),
+            };
+            assert!(matches!(eval_str("(string-join \"a\")", &env), Err(LinslErr::TypeError(..))));
+            assert!(eval("(string-join '(\"a\") 1)").is_err());
+            assert!(eval("(string-repeat \"a\" -1)").is_err());
+            assert!(eval("(string-repeat 'a 2)").is_err());
+
+            // Joining many pieces takes linear time. The bound is generous, but a quadratic join
+            // would take much longer.
+            let start = std::time::Instant::now();
+            let res = eval("(string-join (vector->list (make-vector 10000 \"piece\")) \",\")");
+            assert_eq!(10000 * 6 - 1 + 2, res.unwrap().len());
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn base64() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            // Multibyte UTF-8 round-trips, with either alphabet.
+            assert_eq!("\"aMOpbGxvIM67IPCfmIA=\"", eval("(base64-encode \"héllo λ 😀\")").unwrap());
+            assert_eq!("\"4oKsw59+Pw==\"", eval("(base64-encode \"€ß~?\" :standard)").unwrap());
+            assert_eq!("\"4oKsw59-Pw==\"", eval("(base64-encode \"€ß~?\" :url)").unwrap());
+            for s in ["", "a", "héllo λ 😀", "日本語", "€ß~?"] {
+                for alphabet in [":standard", ":url"] {
+                    let code = format!(
+                        "(base64-decode (base64-encode {:?} {}) {})", s, alphabet, alphabet
+                    );
+                    assert_eq!(format!("{:?}", s), eval(&code).unwrap());
+                }
+            }
+
+            // Decoding fails at the character at fault, and on bytes which are not UTF-8.
+            match eval_str("(base64-decode \"4oKsw59-Pw==\")", &env) {
+                Err(LinslErr::DecodeError(msg, 7, _)) => assert!(msg.contains("'-'"), "{}", msg),
+                res => panic!("Expected a decode error, found {:?}", res),
+            };
+            assert!(matches!(
+                eval_str("(base64-decode \"Zg=\")", &env),
+                Err(LinslErr::DecodeError(_, 2, _))
+            ));
+            assert!(matches!(
+                eval_str("(base64-decode \"YWL/\")", &env),
+                Err(LinslErr::DecodeError(_, 2, _))
+            ));
+            assert!(matches!(eval_str("(base64-encode 1)", &env), Err(LinslErr::TypeError(..))));
+            assert!(matches!(
+                eval_str("(base64-encode \"a\" :hex)", &env),
+                Err(LinslErr::TypeError(..))
+            ));
+        });
+    }
+
+    #[test]
+    fn string_utilities() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap().to_string();
+
+            assert_eq!(
+                "(\"STRASSE ÉTÉ\" \"ΣΟΦΊΑ\" \"été σοφία\")",
+                eval("(list (string-upcase \"straße été\") (string-upcase \"σοφία\") \
+                      (string-downcase \"ÉTÉ ΣΟΦΊΑ\"))")
+            );
+            assert_eq!(
+                "(\"a b\" \"a b \\n\" \"\\t a b\")",
+                eval("(list (string-trim \"\\t a b \\n\") (string-trim-left \"\\t a b \\n\") \
+                      (string-trim-right \"\\t a b \\n\"))")
+            );
+            assert_eq!(
+                "(\"ü-ü\" \"ü-ü«»\" \"«»ü-ü\")",
+                eval("(list (string-trim \"«»ü-ü«»\" \"»«\") (string-trim-left \"«»ü-ü«»\" \"»«\") \
+                      (string-trim-right \"«»ü-ü«»\" \"»«\"))")
+            );
+
+            // Padding counts characters rather than bytes, and does not cut longer strings.
+            assert_eq!(
+                "(\"  été\" \"été··\" \"été\" \"été\")",
+                eval("(list (string-pad-left \"été\" 5) (string-pad-right \"été\" 5 \"·\") \
+                      (string-pad-left \"été\" 2 \"x\") (string-pad-right \"été\" 3))")
+            );
+            for code in [
+                "(string-pad-left \"a\" 3 \"xy\")", "(string-pad-left \"a\" 3 \"\")",
+                "(string-pad-left \"a\" -1)", "(string-trim 1)", "(string-upcase \"a\" \"b\")",
+            ] {
+                assert!(eval_str(code, &env).is_err(), "{}", code);
+            }
 
-    Ok((params_form.clone(), body_form.clone()))
+            assert_eq!("\"😀λ€ba\"", eval("(string-reverse \"ab€λ😀\")"));
+            assert_eq!("\"\"", eval("(string-reverse \"\")"));
+        });
+    }
+
+    #[test]
+    fn string_searching() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap().to_string();
+            let s = "\"日本語 and €uro\"";
+
+            // Indices count characters, not bytes.
+            assert_eq!(
+                "(#t 2 8 #f)",
+                eval(&format!(
+                    "(list (string-contains? {s} \"語 a\") (string-index {s} \"語\") \
+                     (string-index {s} \"€\") (string-index {s} \"ß\"))"
+                ))
+            );
+            assert_eq!(
+                "(#t #f #t #f)",
+                eval(&format!(
+                    "(list (string-starts-with? {s} \"日本\") (string-starts-with? {s} \"本\") \
+                     (string-ends-with? {s} \"€uro\") (string-ends-with? {s} \"uro!\"))"
+                ))
+            );
+            assert_eq!(
+                "\"EUR1 EUR2 EUREUR\"",
+                eval("(string-replace \"€1 €2 €€\" \"€\" \"EUR\")")
+            );
+            assert_eq!("\"日本 and €uro\"", eval(&format!("(string-replace {s} \"語\" \"\")")));
+
+            // A needle longer than the haystack is never found.
+            assert_eq!(
+                "(#f #f #f #f \"ab\")",
+                eval("(list (string-contains? \"ab\" \"abc\") (string-index \"ab\" \"abc\") \
+                      (string-starts-with? \"ab\" \"abc\") (string-ends-with? \"ab\" \"zab\") \
+                      (string-replace \"ab\" \"abc\" \"x\"))")
+            );
+            // The empty needle occurs everywhere, but cannot be replaced.
+            assert_eq!(
+                "(#t 0 #t #t)",
+                eval("(list (string-contains? \"é\" \"\") (string-index \"é\" \"\") \
+                      (string-starts-with? \"é\" \"\") (string-ends-with? \"\" \"\"))")
+            );
+            assert!(eval_str("(string-replace \"abc\" \"\" \"x\")", &env).is_err());
+        });
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap().to_string();
+
+            eval("(define x 3)");
+            eval("(define name \"Ada\")");
+            assert_eq!(
+                r#""x is 3 and the sum is 5, (1 \"a\"), Ada!""#,
+                eval(r#"#"x is ${x} and the sum is ${(+ x 2)}, ${'(1 \"a\")}, ${name}!""#)
+            );
+            assert_eq!(r#""${x}""#, eval(r#"#"\${x}""#));
+            assert_eq!(r#""a1b""#, eval(r#"(string-append "a" (->string 1) "" "b")"#));
+            assert_eq!(r#""""#, eval("(string-append)"));
+            assert!(eval_str("(string-append \"a\" 1)", &env).is_err());
+        });
+    }
+
+    #[test]
+    fn regular_expressions() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap().to_string();
+
+            assert_eq!(
+                "(#t #f)",
+                eval("(list (re-match? \"^a+b\" \"aab\") (re-match? \"^a+b\" \"b\"))")
+            );
+            // The match comes first, then the groups, with #f for one which did not take part.
+            assert_eq!(
+                "(\"2024-10\" \"2024\" \"10\" #f)",
+                eval("(re-find \"(\\\\d{4})-(\\\\d{2})(-\\\\d{2})?\" \"on 2024-10, or\")")
+            );
+            assert_eq!("#f", eval("(re-find \"x\" \"abc\")"));
+            assert_eq!(
+                "((\"a=1\" \"a\" \"1\") (\"b=2\" \"b\" \"2\"))",
+                eval("(re-find-all \"(\\\\w)=(\\\\d)\" \"a=1, b=2\")")
+            );
+            assert_eq!("()", eval("(re-find-all \"x\" \"abc\")"));
+            assert_eq!(
+                "\"2=a, 4=b, $\"",
+                eval("(re-replace \"(?P<k>\\\\w)=(\\\\d)\" \"a=2, b=4, $\" \"$2=${k}\")")
+            );
+            assert_eq!("\"abc\"", eval("(re-replace \"x\" \"abc\" \"y\")"));
+            assert_eq!(
+                "(\"a\" \"b\" \"\" \"c\")",
+                eval("(re-split \"\\\\s*,\\\\s*\" \"a , b,, c\")")
+            );
+
+            // An invalid pattern gives the message of the regex crate, and can be caught.
+            match eval_str("(re-find \"(a\" \"a\")", &env) {
+                Err(LinslErr::PatternError(msg, _)) => {
+                    assert!(msg.contains("unclosed group"), "{}", msg)
+                },
+                res => panic!("Expected a pattern error, found {:?}", res),
+            };
+            let caught = eval("(assert-error (lambda () (re-split \"[\" \"a\")) \"regular\")");
+            assert!(caught.contains("unclosed character class"), "{}", caught);
+        });
+    }
+
+    #[test]
+    fn paths() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).unwrap().to_string();
+
+            assert_eq!("\"a/b/c.txt\"", eval("(path-join \"a\" \"b/\" \"c.txt\")"));
+            // Joining onto an absolute path replaces what came before it.
+            assert_eq!("\"/etc/hosts\"", eval("(path-join \"a\" \"/etc\" \"hosts\")"));
+            assert_eq!(
+                "(#t #f)",
+                eval("(list (path-absolute? \"/etc\") (path-absolute? \"etc/hosts\"))")
+            );
+
+            assert_eq!("\"a/b\"", eval("(path-parent \"a/b/c.txt\")"));
+            assert_eq!("\"c.txt\"", eval("(path-filename \"a/b/c.txt\")"));
+            assert_eq!("\"txt\"", eval("(path-extension \"a/b/c.tar.txt\")"));
+            // A filename without an extension, or starting with a dot.
+            assert_eq!(
+                "(#f #f)",
+                eval("(list (path-extension \"a/Makefile\") (path-extension \".bashrc\"))")
+            );
+            // A separator at the end is ignored.
+            assert_eq!(
+                "(\"b\" \"a\" \"d\")",
+                eval("(list (path-filename \"a/b/\") (path-parent \"a/b/\") \
+                      (path-extension \"c.d/\"))")
+            );
+            assert_eq!(
+                "(#f #f #f)",
+                eval("(list (path-parent \"a\") (path-parent \"/\") \
+                      (path-filename \"a/..\"))")
+            );
+
+            for (path, normalized) in [
+                ("a/../../b", "../b"), ("./a/./b/../c", "a/c"), ("/../a/..", "/"), ("a/..", "."),
+                ("../../a", "../../a"), ("a/b/", "a/b"),
+            ] {
+                assert_eq!(
+                    format!("{:?}", normalized),
+                    eval(&format!("(path-normalize {:?})", path)),
+                    "{}",
+                    path
+                );
+            }
+            assert!(matches!(
+                eval_str("(path-join \"a\" 1)", &env),
+                Err(LinslErr::TypeError(..))
+            ));
+        });
+    }
+
+    #[test]
+    fn vectors() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("#(1 (+ 1 1) x)", eval("#(1 (+ 1 1) x)").unwrap());
+            assert_eq!("#(1 2 #t)", eval("(vector 1 (+ 1 1) #t)").unwrap());
+            assert_eq!("#(0 0 0)", eval("(make-vector 3 0)").unwrap());
+            assert_eq!("#(() ())", eval("(make-vector 2)").unwrap());
+            assert_eq!("#()", eval("(list->vector '())").unwrap());
+            assert_eq!("(1 2)", eval("(vector->list #(1 2))").unwrap());
+            assert_eq!("#(a b)", eval("(list->vector '(a b))").unwrap());
+
+            // Vectors are mutable, and shared rather than copied.
+            eval("(define v (make-vector 3 0))").unwrap();
+            eval("(define w v)").unwrap();
+            eval("(vector-set! v 1 'x)").unwrap();
+            assert_eq!("x", eval("(vector-ref w 1)").unwrap());
+            assert_eq!("3", eval("(vector-length w)").unwrap());
+            assert_eq!("#t", eval("(= v w)").unwrap());
+            assert_eq!("#f", eval("(= v (vector 0 'x 0))").unwrap());
+            assert_eq!("#t", eval("(eqt? v #())").unwrap());
+            assert_eq!("#f", eval("(eqt? v '())").unwrap());
+
+            match eval_str("(vector-ref v 3)", &env) {
+                Err(LinslErr::IndexError(3, 3, _)) => (),
+                res => panic!("{:?}", res),
+            };
+            match eval_str("(vector-set! v 5 0)", &env) {
+                Err(LinslErr::IndexError(5, 3, _)) => (),
+                res => panic!("{:?}", res),
+            };
+            assert!(matches!(eval_str("(vector-ref v -1)", &env), Err(LinslErr::TypeError(_, _))));
+            assert!(matches!(eval_str("(vector-ref v 0.5)", &env), Err(LinslErr::TypeError(_, _))));
+            let res = eval_str("(vector-ref '(1) 0)", &env);
+            assert!(matches!(res, Err(LinslErr::TypeError(_, _))));
+            let res = eval_str("(vector-length)", &env);
+            assert!(matches!(res, Err(LinslErr::ArityError(_, _, _, _))));
+        });
+    }
+
+    #[test]
+    fn vector_operations() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define v (vector 0 1 2 3 4 5))").unwrap();
+            assert_eq!("#(0 1 2 3 4 5)", eval("(vector-copy v)").unwrap());
+            assert_eq!("#(2 3 4 5)", eval("(vector-copy v 2)").unwrap());
+            assert_eq!("#(2 3)", eval("(vector-copy v 2 4)").unwrap());
+            assert_eq!("#()", eval("(subvector v 6 6)").unwrap());
+            // A copy is a new vector.
+            eval("(vector-set! (vector-copy v) 0 'x)").unwrap();
+            assert_eq!("0", eval("(vector-ref v 0)").unwrap());
+
+            // Overlapping ranges within the same vector are copied as if through a temporary copy,
+            // whichever direction they are copied in.
+            assert_eq!("#(0 0 1 2 3 5)", eval("(vector-copy! v 1 v 0 4)").unwrap());
+            assert_eq!("#(0 1 2 3 5 5)", eval("(vector-copy! v 0 v 1)").unwrap());
+            assert_eq!("#(a b 2 3 5 5)", eval("(vector-copy! v 0 #(a b))").unwrap());
+
+            eval("(define w (make-vector 3 0))").unwrap();
+            assert_eq!("#(x x x)", eval("(vector-fill! w 'x)").unwrap());
+            let res = eval("(vector-map! (lambda (x) (* x x)) (vector 1 2 3))").unwrap();
+            assert_eq!("#(1 4 9)", res);
+            // The function applied by vector-map! sees the elements already replaced.
+            eval("(define u (vector 1 1 1))").unwrap();
+            eval("(vector-map! (lambda (x) (+ x (vector-ref u 0))) u)").unwrap();
+            assert_eq!("#(2 3 3)", eval("u").unwrap());
+
+            assert_eq!(
+                "Range error at (0, 0): the range from 4 to 2 is not within length 6",
+                eval("(vector-copy v 4 2)").unwrap_err().to_string()
+            );
+            match eval_str("(subvector v 1 7)", &env) {
+                Err(LinslErr::RangeError(1, 7, 6, _)) => (),
+                res => panic!("{:?}", res),
+            };
+            match eval_str("(vector-copy! w 2 v 0 2)", &env) {
+                Err(LinslErr::RangeError(2, 4, 3, _)) => (),
+                res => panic!("{:?}", res),
+            };
+            assert!(matches!(eval_str("(subvector v 1)", &env), Err(LinslErr::ArityError(..))));
+            let res = eval_str("(vector-fill! '(1) 0)", &env);
+            assert!(matches!(res, Err(LinslErr::TypeError(..))));
+        });
+    }
+
+    #[test]
+    fn dicts() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            eval("(define d (make-dict 'b 1 2 'two #t 'yes))").unwrap();
+            assert_eq!("#<dict #t: yes 2: two b: 1>", eval("d").unwrap());
+            assert_eq!("1", eval("(dict-ref d 'b)").unwrap());
+            assert_eq!("two", eval("(dict-ref d (+ 1 1))").unwrap());
+            assert_eq!("none", eval("(dict-ref d 'c 'none)").unwrap());
+            assert_eq!("#f", eval("(dict-has? d 'c)").unwrap());
+
+            // Setting a key which is already there replaces its value, and dicts are shared.
+            eval("(define e d)").unwrap();
+            eval("(dict-set! d 'b 10)").unwrap();
+            eval("(dict-set! d 'a 0)").unwrap();
+            assert_eq!("10", eval("(dict-ref e 'b)").unwrap());
+            assert_eq!("#t", eval("(dict-has? e 'a)").unwrap());
+
+            // Keys are listed in the same order every time, no matter the order of insertion.
+            assert_eq!("(#t 2 a b)", eval("(dict-keys d)").unwrap());
+            assert_eq!("(#f -1 0.5 x y)", eval("(dict-keys (make-dict 'y 0 0.5 0 'x 0 -1 0 #f 0))")
+                .unwrap());
+            assert_eq!("((#t yes) (2 two) (a 0) (b 10))", eval("(dict->alist d)").unwrap());
+
+            eval("(dict-remove! d 2)").unwrap();
+            eval("(dict-remove! d 'not-there)").unwrap();
+            assert_eq!("(#t a b)", eval("(dict-keys e)").unwrap());
+
+            // Dicts are = if they have the same entries.
+            assert_eq!("#t", eval("(= d (alist->dict (dict->alist d)))").unwrap());
+            assert_eq!("#t", eval("(= (make-dict 'a '(1)) (alist->dict '((a (1)))))").unwrap());
+            assert_eq!("#f", eval("(= d (make-dict))").unwrap());
+            assert_eq!("#t", eval("(eqt? d (make-dict))").unwrap());
+
+            match eval_str("(dict-ref d 'c)", &env) {
+                Err(LinslErr::KeyError(k, _)) => assert_eq!("c", k),
+                res => panic!("{:?}", res),
+            };
+            for code in ["(dict-set! d '(1) 0)", "(make-dict #(1) 0)", "(dict-ref 1 1)"] {
+                assert!(matches!(eval_str(code, &env), Err(LinslErr::TypeError(_, _))));
+            }
+            assert!(matches!(eval_str("(make-dict 'a)", &env), Err(LinslErr::SyntaxError(_, _))));
+        });
+    }
+
+    #[test]
+    fn records() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let eval = |s: &str| eval_str(s, &env).map(|res| res.to_string());
+
+            assert_eq!("point", eval("(define-record point (x y))").unwrap());
+            assert_eq!("color", eval("(define-record color (r g b))").unwrap());
+            eval("(define p (make-point 1 (+ 1 1)))").unwrap();
+            eval("(define c (make-color 0 0.5 1))").unwrap();
+
+            assert_eq!("#<point x: 1 y: 2>", eval("p").unwrap());
+            assert_eq!("#<color r: 0 g: 0.5 b: 1>", eval("c").unwrap());
+            assert_eq!("2", eval("(point-y p)").unwrap());
+            assert_eq!("point-y", eval("point-y").unwrap());
+
+            // The predicates only recognize instances of their own record type.
+            assert_eq!("#t", eval("(point? p)").unwrap());
+            assert_eq!("#f", eval("(point? c)").unwrap());
+            assert_eq!("#t", eval("(color? c)").unwrap());
+            assert_eq!("#f", eval("(color? p)").unwrap());
+            assert_eq!("#f", eval("(color? '(0 0.5 1))").unwrap());
+            assert_eq!("#t", eval("(eqt? p (make-point 0 0))").unwrap());
+            assert_eq!("#f", eval("(eqt? p c)").unwrap());
+
+            // Records are compared field by field.
+            assert_eq!("#t", eval("(= p (make-point 1 2))").unwrap());
+            assert_eq!("#f", eval("(= p (make-point 2 1))").unwrap());
+            eval("(set-point-x! p 2)").unwrap();
+            eval("(set-point-y! p 1)").unwrap();
+            assert_eq!("#<point x: 2 y: 1>", eval("p").unwrap());
+            assert_eq!("#t", eval("(= p (make-point 2 1))").unwrap());
+
+            // Accessors type check their argument, naming both record types.
+            match eval_str("(point-x c)", &env) {
+                Err(LinslErr::TypeError(msg, _)) => {
+                    assert!(msg.contains("point") && msg.contains("color"), "{}", msg)
+                },
+                res => panic!("{:?}", res),
+            };
+            assert!(matches!(eval_str("(color-g 1)", &env), Err(LinslErr::TypeError(_, _))));
+            let res = eval_str("(make-point 1)", &env);
+            assert!(matches!(res, Err(LinslErr::ArityError(_, _, _, _))));
+            for code in [
+                "(define-record (x y))",
+                "(define-record 1 (x y))",
+                "(define-record twice (x x))",
+                "(define-record fields (1))",
+            ] {
+                let res = eval_str(code, &env);
+                assert!(matches!(res, Err(LinslErr::SyntaxError(_, _))), "{}", code);
+            }
+        });
+    }
+
+    struct Entity {
+        id: f64,
+    }
+
+    fn entity_id(exprs: &[LinslExpr]) -> LinslRes {
+        Ok(LinslExpr::Number(exprs[0].downcast_foreign::<Entity>()?.id))
+    }
+
+    #[test]
+    fn foreign_round_trip() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let entity = LinslExpr::foreign(Entity { id: 7.0 });
+            env.insert("e", entity.clone());
+            env.insert("entity-id", LinslExpr::Primitive("entity-id", entity_id));
+
+            let res = eval_str("((lambda (x) x) e)", &env).unwrap();
+            assert_eq!("#<foreign Entity>", res.to_string());
+            assert!(Arc::ptr_eq(
+                &res.downcast_foreign::<Entity>().unwrap(),
+                &entity.downcast_foreign::<Entity>().unwrap()
+            ));
+
+            match eval_str("(entity-id ((lambda (x) x) e))", &env).unwrap() {
+                LinslExpr::Number(v) => assert_eq!(7.0, v),
+                _ => panic!(),
+            };
+        });
+    }
+
+    #[test]
+    fn foreign_type_mismatch() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            env.insert("e", LinslExpr::foreign(1_u8));
+            env.insert("entity-id", LinslExpr::Primitive("entity-id", entity_id));
+
+            match eval_str("(entity-id e)", &env) {
+                Err(LinslErr::TypeError(_, _)) => (),
+                _ => panic!(),
+            };
+            match eval_str("(entity-id 1)", &env) {
+                Err(LinslErr::TypeError(_, _)) => (),
+                _ => panic!(),
+            };
+        });
+    }
+
+    #[test]
+    fn foreign_predicates() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            let entity = LinslExpr::foreign(Entity { id: 1.0 });
+            env.insert("e1", entity.clone());
+            env.insert("e2", entity);
+            env.insert("e3", LinslExpr::foreign(Entity { id: 1.0 }));
+
+            for (code, expected) in [
+                ("(foreign? e1)", true),
+                ("(foreign? 1)", false),
+                ("(eqt? e1 e3)", true),
+                ("(eqt? e1 1)", false),
+                ("(= e1 e2)", true),
+                ("(= e1 e3)", false),
+            ] {
+                match eval_str(code, &env).unwrap() {
+                    LinslExpr::Bool(b) => assert_eq!(expected, b, "{}", code),
+                    _ => panic!(),
+                };
+            }
+        });
+    }
+
+    fn eval_num(s: &str, env: &LinslEnv) -> f64 {
+        match eval_str(s, env) {
+            Ok(LinslExpr::Number(v)) => v,
+            res => panic!("Expected number from {}, got {:?}", s, res),
+        }
+    }
+
+    #[test]
+    fn closure_captures_defining_env() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define make-adder (lambda (n) (lambda (x) (+ x n))))", &env).unwrap();
+            eval_str("(define add-five (make-adder 5))", &env).unwrap();
+
+            assert_eq!(8.0, eval_num("(add-five 3)", &env));
+            assert_eq!(13.0, eval_num("((make-adder 10) 3)", &env));
+        });
+    }
+
+    #[test]
+    fn counter_closure() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            // Each call returns the current count and the counter to use for the next count.
+            eval_str(
+                "(define make-counter (lambda (n) (lambda () (list n (make-counter (+ n 1))))))",
+                &env
+            ).unwrap();
+            eval_str("(define c0 (make-counter 0))", &env).unwrap();
+            eval_str("(define c1 (car (cdr (c0))))", &env).unwrap();
+            eval_str("(define c2 (car (cdr (c1))))", &env).unwrap();
+
+            assert_eq!(0.0, eval_num("(car (c0))", &env));
+            assert_eq!(1.0, eval_num("(car (c1))", &env));
+            assert_eq!(2.0, eval_num("(car (c2))", &env));
+            // Creating new counters does not affect the old ones.
+            assert_eq!(0.0, eval_num("(car (c0))", &env));
+        });
+    }
+
+    #[test]
+    fn captured_binding_shadows_caller_binding() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define n 100)", &env).unwrap();
+            eval_str("(define make-adder (lambda (n) (lambda (x) (+ x n))))", &env).unwrap();
+            eval_str("(define add-one (make-adder 1))", &env).unwrap();
+            eval_str("(define call-with-n (lambda (n f) (f 1)))", &env).unwrap();
+
+            assert_eq!(2.0, eval_num("(call-with-n 50 add-one)", &env));
+            assert_eq!(2.0, eval_num("(add-one 1)", &env));
+        });
+    }
+
+    #[test]
+    fn bind_does_not_copy_outer_frames() {
+        let env = LinslEnv::default();
+        for i in 0..10000 {
+            env.insert(&format!("x{}", i), LinslExpr::Number(i as f64));
+        }
+
+        let symbs = LinslExpr::List(vec![
+            LinslExpr::Symbol(Symbol::new("a")),
+            LinslExpr::Symbol(Symbol::new("b")),
+        ].into());
+        let vals = vec![LinslExpr::Number(1.0), LinslExpr::Number(2.0)];
+        let new_env = bind(&symbs, &vals, &env).unwrap();
+
+        // The new frame only holds the new bindings, and refers to the very same outer frame.
+        assert_eq!(2, new_env.scope().inner.len());
+        assert!(Arc::ptr_eq(&new_env.outer().unwrap().0, &env.0));
+
+        // Bindings added to the outer frame afterwards are visible through the new frame.
+        env.insert("late", LinslExpr::Number(3.0));
+        assert!(env_get(&Symbol::new("late"), &new_env).is_some());
+    }
+
+    #[test]
+    fn calls_do_not_retain_frames() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            for i in 0..10000 {
+                env.insert(&format!("x{}", i), LinslExpr::Number(i as f64));
+            }
+            eval_str("(define f (lambda (a) (+ a x9999)))", &env).unwrap();
+
+            let bindings = env.scope().inner.len();
+            let refs = Arc::strong_count(&env.0);
+            for _ in 0..1000 {
+                assert_eq!(10000.0, eval_num("(f 1)", &env));
+            }
+            assert_eq!(bindings, env.scope().inner.len());
+            assert_eq!(refs, Arc::strong_count(&env.0));
+        });
+    }
+
+    fn define_minus(env: &LinslEnv) {
+        eval_str("(define - (lambda (a b) (+ a (neg b))))", env).unwrap();
+    }
+
+    #[test]
+    fn recursive_fact() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            define_minus(&env);
+            eval_str("(define fact (lambda (n) (if (= n 0) 1 (* n (fact (- n 1))))))", &env).unwrap();
+
+            assert_eq!(1.0, eval_num("(fact 0)", &env));
+            assert_eq!(120.0, eval_num("(fact 5)", &env));
+            assert_eq!(3628800.0, eval_num("(fact 10)", &env));
+        });
+    }
+
+    #[test]
+    fn recursive_fib() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            define_minus(&env);
+            eval_str(
+                "(define fib (lambda (n) (if (> 2 n) n (+ (fib (- n 1)) (fib (- n 2))))))",
+                &env
+            ).unwrap();
+
+            assert_eq!(0.0, eval_num("(fib 0)", &env));
+            assert_eq!(1.0, eval_num("(fib 1)", &env));
+            assert_eq!(55.0, eval_num("(fib 10)", &env));
+        });
+    }
+
+    #[test]
+    fn mutually_recursive_functions() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            define_minus(&env);
+            // even? refers to odd? before it has been defined.
+            eval_str("(define even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))", &env).unwrap();
+            eval_str("(define odd? (lambda (n) (if (= n 0) #f (even? (- n 1)))))", &env).unwrap();
+
+            for (code, expected) in [
+                ("(even? 10)", true),
+                ("(odd? 10)", false),
+                ("(even? 7)", false),
+                ("(odd? 7)", true),
+            ] {
+                match eval_str(code, &env).unwrap() {
+                    LinslExpr::Bool(b) => assert_eq!(expected, b, "{}", code),
+                    _ => panic!(),
+                };
+            }
+        });
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_stack() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define count-down (lambda (n) (if (= n 0) 0 (count-down (+ n -1)))))", &env)
+                .unwrap();
+
+            assert_eq!(0.0, eval_num("(count-down 1000000)", &env));
+        });
+    }
+
+    #[test]
+    fn mutual_tail_calls_run_in_constant_stack() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define even? (lambda (n) (if (= n 0) #t (odd? (+ n -1)))))", &env).unwrap();
+            eval_str("(define odd? (lambda (n) (if (= n 0) #f (even? (+ n -1)))))", &env).unwrap();
+
+            match eval_str("(even? 100001)", &env).unwrap() {
+                LinslExpr::Bool(b) => assert!(!b),
+                _ => panic!(),
+            };
+        });
+    }
+
+    #[test]
+    fn recursion_limit() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            eval_str("(define sum (lambda (n) (if (= n 0) 0 (+ n (sum (+ n -1))))))", &env).unwrap();
+
+            let mut ctx = LinslCtx { max_depth: 100, ..ctx() };
+            let mut tokenizer = Tokenizer::new(vec![
+                Box::new(Cursor::new("(sum 10)\n(sum 100)\n(sum 10)\n")) as Box<dyn BufRead>
+            ].into()).unwrap();
+
+            assert!(evaluate(&parse(&mut tokenizer).unwrap(), &env, &mut ctx).is_ok());
+            // The error is reported within the body of sum, which starts at column 24.
+            assert!(matches!(
+                evaluate(&parse(&mut tokenizer).unwrap(), &env, &mut ctx),
+                Err(LinslErr::RecursionLimit(100, (0, col))) if col >= 24
+            ));
+            // Aborting the evaluation resets the depth, so later evaluations are unaffected.
+            assert_eq!(0, ctx.depth);
+            assert!(evaluate(&parse(&mut tokenizer).unwrap(), &env, &mut ctx).is_ok());
+        });
+    }
+
+    #[test]
+    fn default_recursion_limit_prevents_overflow() {
+        on_both_engines(|| {
+            // Infinite non-tail recursion must hit the default limit before the stack overflows.
+            let engine = ENGINE.get();
+            let handle = std::thread::Builder::new()
+                .stack_size(DEFAULT_STACK_SIZE)
+                .spawn(move || {
+                    ENGINE.set(engine);
+                    let env = LinslEnv::default();
+                    eval_str("(define f (lambda (n) (+ 1 (f n))))", &env).unwrap();
+                    eval_str("(f 1)", &env).err()
+                })
+                .unwrap();
+
+            match handle.join().unwrap() {
+                Some(LinslErr::RecursionLimit(d, _)) => assert_eq!(DEFAULT_MAX_DEPTH, d),
+                _ => panic!(),
+            };
+
+            // The same holds for recursion through primitives calling back into the code, which
+            // the virtual machine runs using a new run of its own each time.
+            for f in [
+                "(lambda (n) (+ 1 (car (map f (list n)))))",
+                "(lambda (n) (+ 1 (call/ec (lambda (k) (f n)))))",
+                "(lambda (n) (+ 1 (call-with-values (lambda () (f n)) (lambda (x) x))))",
+            ] {
+                let handle = std::thread::Builder::new()
+                    .stack_size(DEFAULT_STACK_SIZE)
+                    .spawn(move || {
+                        ENGINE.set(engine);
+                        let env = LinslEnv::default();
+                        eval_str(&format!("(define f {})", f), &env).unwrap();
+                        eval_str("(f 1)", &env).err()
+                    })
+                    .unwrap();
+                let res = handle.join().unwrap();
+                assert!(matches!(res, Some(LinslErr::RecursionLimit(..))), "{}: {:?}", f, res);
+            }
+        });
+    }
+
+    #[test]
+    fn symbols_are_interned() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            define_minus(&env);
+            eval_str("(define sum-to (lambda (n acc) (if (= n 0) acc (sum-to (- n 1) (+ acc n)))))", &env)
+                .unwrap();
+
+            // Thousands of lookups of the same few names. The environment is keyed by the very same
+            // allocation as the parsed code refers to, so none of the lookups copy or hash a name.
+            assert_eq!(5000.0 * 5001.0 / 2.0, eval_num("(sum-to 5000 0)", &env));
+            let key = env.scope().inner.keys().find(|k| *k == "sum-to").unwrap().clone();
+            assert_eq!(Symbol::new("sum-to").as_str().as_ptr(), key.as_str().as_ptr());
+
+            // All symbols with the same name share a single allocation, but still print as the name.
+            let a = Symbol::new("sum-to");
+            let b = Symbol::from(&"sum-to".to_string());
+            assert_eq!(a, b);
+            assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+            assert_eq!("sum-to", a.to_string());
+            assert_eq!("(sum-to 1)", eval_str("'(sum-to 1)", &env).unwrap().to_string());
+            assert_eq!("#t", eval_str("(= 'sum-to 'sum-to)", &env).unwrap().to_string());
+            assert_eq!("#f", eval_str("(= 'sum-to 'sum-too)", &env).unwrap().to_string());
+        });
+    }
+
+    #[test]
+    fn fold_over_long_list() {
+        on_both_engines(|| {
+            let env = LinslEnv::default();
+            env.insert("xs", LinslExpr::List((0..100000).map(|i| LinslExpr::Number(i as f64)).collect()));
+            eval_str("(define fold (lambda (f acc xs) (if (empty? xs) acc (fold f (f acc (car xs)) (cdr xs)))))", &env)
+                .unwrap();
+
+            assert_eq!(99999.0 * 100000.0 / 2.0, eval_num("(fold + 0 xs)", &env));
+        });
+    }
+
+    #[test]
+    fn tails_share_elements() {
+        let list: LinslList = vec![LinslExpr::Number(1.0), LinslExpr::Number(2.0)].into();
+        let tail = list.tail();
+
+        assert_eq!(1, tail.len());
+        assert!(std::ptr::eq(&list[1], &tail[0]));
+        assert!(tail.tail().is_empty());
+        assert!(tail.tail().tail().is_empty());
+    }
+
+    #[test]
+    fn run_program() {
+        fn run(code: &str, env: &LinslEnv) -> Result<Vec<String>, LinslErr> {
+            let input: Box<dyn BufRead> = Box::new(Cursor::new(code.to_string()));
+            let mut tokenizer = Tokenizer::new(vec![input].into())?;
+            let vals = super::run_program(&mut tokenizer, env, &mut ctx())?;
+            Ok(vals.iter().map(|val| val.to_string()).collect())
+        }
+
+        on_both_engines(|| {
+            // Every form is run, and not only the first.
+            let env = LinslEnv::default();
+            let code = "(define a 1)\n; a comment\n\n(define b (+ a 1)) (list a\n b)\n";
+            assert_eq!(vec!["1", "2", "(1 2)"], run(code, &env).unwrap());
+            assert!(run("", &env).unwrap().is_empty());
+            assert!(run("  ; nothing but a comment\n", &env).unwrap().is_empty());
+
+            // Input ending in the middle of a form is reported at the parenthesis left open,
+            // after the forms before it have run.
+            let code = "(define c 3)\n(list 1\n  (list 2 3)\n  (list 4\n";
+            assert!(matches!(run(code, &env), Err(LinslErr::UnbalancedParens(3, 2))));
+            assert_eq!("3", eval_str("c", &env).unwrap().to_string());
+            assert!(matches!(run("(a '", &env), Err(LinslErr::UnbalancedParens(0, 0))));
+            assert!(matches!(run("'", &env), Err(LinslErr::SyntaxError(..))));
+        });
+    }
 }
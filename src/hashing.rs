@@ -0,0 +1,282 @@
+//! Hashing of strings and files, e.g. for telling whether the contents of a file have changed.
+//!
+//! SHA-256 is a cryptographic hash. MD5 is not: collisions can be made for it at will, so it is
+//! only fit for checking against digests computed elsewhere, never for telling apart contents an
+//! attacker may have chosen. Both are computed a block at a time, so that a file can be hashed
+//! without reading all of it into memory.
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// How many bytes both hash functions process at a time.
+const BLOCK_SIZE: usize = 64;
+
+/// How many bytes of a file are read at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A hash function fed its input a piece at a time.
+pub trait Digest: Default {
+    /// Feed the next piece of the input.
+    fn update(&mut self, bytes: &[u8]);
+    /// The digest of all the input fed.
+    fn finish(self) -> Vec<u8>;
+}
+
+/// The input fed to a hash function which does not make up a full block yet, along with how much
+/// input there has been.
+struct Blocks {
+    buffer: [u8; BLOCK_SIZE],
+    len: usize,
+    total: u64,
+}
+
+impl Default for Blocks {
+    fn default() -> Self {
+        Blocks { buffer: [0; BLOCK_SIZE], len: 0, total: 0 }
+    }
+}
+
+impl Blocks {
+    /// Add the bytes to the input, compressing every block filled.
+    fn update(&mut self, mut bytes: &[u8], mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        self.total += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let n = bytes.len().min(BLOCK_SIZE - self.len);
+            self.buffer[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+            if self.len == BLOCK_SIZE {
+                compress(&self.buffer);
+                self.len = 0;
+            };
+        }
+    }
+
+    /// Pad the input with a one bit, zeros and the length of the input in bits, which SHA-256
+    /// writes big-endian and MD5 little-endian, compressing the last blocks.
+    fn finish(mut self, big_endian: bool, mut compress: impl FnMut(&[u8; BLOCK_SIZE])) {
+        let bits = self.total.wrapping_mul(8);
+        let length = if big_endian { bits.to_be_bytes() } else { bits.to_le_bytes() };
+        self.buffer[self.len] = 0x80;
+        self.buffer[self.len + 1..].fill(0);
+        if self.len + 1 > BLOCK_SIZE - length.len() {
+            compress(&self.buffer);
+            self.buffer.fill(0);
+        };
+        self.buffer[BLOCK_SIZE - length.len()..].copy_from_slice(&length);
+        compress(&self.buffer);
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256, as specified in FIPS 180-4.
+pub struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 { state: SHA256_INIT, blocks: Blocks::default() }
+    }
+}
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(add);
+    }
+}
+
+impl Digest for Sha256 {
+    fn update(&mut self, bytes: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(bytes, |block| sha256_compress(state, block));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(true, |block| sha256_compress(state, block));
+        self.state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+}
+
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// How far each step of MD5 rotates, by round.
+const MD5_SHIFTS: [[u32; 4]; 4] = [
+    [7, 12, 17, 22], [5, 9, 14, 20], [4, 11, 16, 23], [6, 10, 15, 21],
+];
+
+const MD5_INIT: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+/// MD5, as specified in RFC 1321. Not fit for cryptographic use.
+pub struct Md5 {
+    state: [u32; 4],
+    blocks: Blocks,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Md5 { state: MD5_INIT, blocks: Blocks::default() }
+    }
+}
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; BLOCK_SIZE]) {
+    let mut m = [0u32; 16];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let f = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+        (a, d, c) = (d, c, b);
+        b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i / 16][i % 4]));
+    }
+    for (word, add) in state.iter_mut().zip([a, b, c, d]) {
+        *word = word.wrapping_add(add);
+    }
+}
+
+impl Digest for Md5 {
+    fn update(&mut self, bytes: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(bytes, |block| md5_compress(state, block));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(false, |block| md5_compress(state, block));
+        self.state.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+/// The bytes written as lowercase hexadecimal, two digits each.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The digest of a string, as lowercase hexadecimal.
+pub fn hash_str<D: Digest>(s: &str) -> String {
+    let mut digest = D::default();
+    digest.update(s.as_bytes());
+    hex(&digest.finish())
+}
+
+/// The digest of the contents of a file, as lowercase hexadecimal. The file is read a chunk at a
+/// time rather than all at once.
+pub fn hash_file<D: Digest>(path: &str) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut digest = D::default();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => digest.update(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hex(&digest.finish()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_digests() {
+        let sha256 = [
+            ("", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            ("abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+            ),
+        ];
+        for (input, digest) in sha256 {
+            assert_eq!(digest, hash_str::<Sha256>(input), "{:?}", input);
+        }
+        let md5 = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a"
+            ),
+        ];
+        for (input, digest) in md5 {
+            assert_eq!(digest, hash_str::<Md5>(input), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn fed_in_pieces() {
+        // The digest does not depend on how the input is split, including around the padding.
+        let input: Vec<u8> = (0..200u8).collect();
+        for len in [55, 56, 63, 64, 65, 200] {
+            let mut whole = Sha256::default();
+            whole.update(&input[..len]);
+            let mut pieces = Sha256::default();
+            for piece in input[..len].chunks(7) {
+                pieces.update(piece);
+            }
+            assert_eq!(whole.finish(), pieces.finish(), "{}", len);
+        }
+        let mut digest = Sha256::default();
+        digest.update(&[b'a'; 1000]);
+        assert_eq!(
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3",
+            hex(&digest.finish())
+        );
+    }
+}
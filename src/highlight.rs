@@ -0,0 +1,181 @@
+//! Highlighting of the line being edited at the REPL. Parentheses are colored by how deeply they
+//! are nested, and the one at the cursor is highlighted along with the one it matches. Special
+//! forms, bound symbols, literals, strings and comments each have a color of their own, while
+//! unknown symbols are left plain.
+
+use std::borrow::Cow;
+
+use linsl::datatypes::LinslEnv;
+use linsl::evaluation::is_special_form;
+use linsl::parsing::{line_tokens, parse_complex};
+use rustyline::completion::Completer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::Helper;
+
+/// The colors of the parentheses, by how deeply they are nested.
+const PAREN_COLORS: [&str; 4] = ["\x1b[33m", "\x1b[94m", "\x1b[95m", "\x1b[96m"];
+const MATCHING: &str = "\x1b[1;7m";
+const SPECIAL_FORM: &str = "\x1b[1;35m";
+const BOUND: &str = "\x1b[36m";
+const LITERAL: &str = "\x1b[34m";
+const STRING: &str = "\x1b[32m";
+const COMMENT: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// The helper of the REPL's line editor, which highlights the line being edited.
+pub struct LinslHelper {
+    /// The environment of the REPL, which tells the bound symbols apart from the unknown ones.
+    pub env: LinslEnv,
+}
+
+fn is_open(token: &str) -> bool {
+    token == "(" || token == "#("
+}
+
+/// The byte offsets of the parenthesis at the cursor, i.e. the one the cursor is on or else the
+/// one just before it, and of the parenthesis matching it, if there is one. Parentheses in strings
+/// and comments are not counted.
+pub fn matching_paren(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let parens: Vec<(usize, &str)> = line_tokens(line)
+        .filter(|(_, token)| is_open(token) || *token == ")")
+        .collect();
+    let i = parens.iter()
+        .position(|(start, token)| (*start..start + token.len()).contains(&pos))
+        .or_else(|| parens.iter().position(|(start, token)| start + token.len() == pos))?;
+
+    let mut depth = 0;
+    let matching = if is_open(parens[i].1) {
+        parens[i..].iter().find(|(_, token)| {
+            depth += if is_open(token) { 1 } else { -1 };
+            depth == 0
+        })
+    } else {
+        parens[..=i].iter().rev().find(|(_, token)| {
+            depth += if is_open(token) { -1 } else { 1 };
+            depth == 0
+        })
+    };
+    matching.map(|(start, _)| (parens[i].0, *start))
+}
+
+/// Whether a token reads as a literal, i.e. a number, boolean or keyword.
+fn is_literal(token: &str) -> bool {
+    let numeric = token.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '.'));
+    (numeric && (token.parse::<f64>().is_ok() || parse_complex(token).is_some()))
+        || matches!(token, "#t" | "#f" | "#true" | "#false")
+        || (token.len() > 1 && token.starts_with(':'))
+}
+
+/// Whether a name is bound in the environment. The names are compared as strings, so that no
+/// symbols are created for what is typed.
+fn is_bound(name: &str, env: &LinslEnv) -> bool {
+    let mut frame = Some(env.clone());
+    while let Some(env) = frame {
+        if env.scope().inner.keys().any(|k| k.as_str() == name) {
+            return true;
+        };
+        frame = env.outer();
+    }
+    false
+}
+
+/// The line with its tokens colored, for the cursor at `pos`.
+pub fn highlight(line: &str, pos: usize, env: &LinslEnv) -> String {
+    let pair = matching_paren(line, pos);
+    let mut highlighted = String::with_capacity(2 * line.len());
+    let (mut depth, mut end): (usize, usize) = (0, 0);
+    for (start, token) in line_tokens(line) {
+        highlighted.push_str(&line[end..start]);
+        end = start + token.len();
+        let color = match token {
+            "(" | "#(" => {
+                depth += 1;
+                PAREN_COLORS[(depth - 1) % PAREN_COLORS.len()]
+            },
+            ")" => {
+                depth = depth.saturating_sub(1);
+                PAREN_COLORS[depth % PAREN_COLORS.len()]
+            },
+            "'" | "`" | "," | ",@" => "",
+            _ if token.starts_with(';') => COMMENT,
+            _ if token.starts_with('"') => STRING,
+            _ if is_special_form(token) => SPECIAL_FORM,
+            _ if is_literal(token) => LITERAL,
+            _ if is_bound(token, env) => BOUND,
+            _ => "",
+        };
+        let color = match pair {
+            Some((at, matching)) if start == at || start == matching => MATCHING,
+            _ => color,
+        };
+        if color.is_empty() {
+            highlighted.push_str(token);
+        } else {
+            highlighted.push_str(&format!("{}{}{}", color, token, RESET));
+        }
+    }
+    highlighted.push_str(&line[end..]);
+    highlighted
+}
+
+impl Highlighter for LinslHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight(line, pos, &self.env))
+    }
+
+    /// The highlighting depends on where the cursor is, so the line is highlighted again whenever
+    /// it moves.
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Completer for LinslHelper {
+    type Candidate = String;
+}
+
+impl Hinter for LinslHelper {
+    type Hint = String;
+}
+
+impl Validator for LinslHelper {}
+
+impl Helper for LinslHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use linsl::interpreter::Interpreter;
+
+    #[test]
+    fn matching_parens() {
+        let line = "(f \"(\" #(1) ; )\n";
+        // On a parenthesis, or just after one.
+        assert_eq!(Some((0, 15)), matching_paren("(f \"(\" #(1) (g))", 0));
+        assert_eq!(Some((15, 0)), matching_paren("(f \"(\" #(1) (g))", 16));
+        assert_eq!(Some((7, 10)), matching_paren(line, 8));
+        assert_eq!(Some((10, 7)), matching_paren(line, 10));
+        // The parentheses in strings and comments are not counted, so the first one is unmatched.
+        assert_eq!(None, matching_paren(line, 0));
+        assert_eq!(None, matching_paren(line, 4));
+        assert_eq!(None, matching_paren(line, 14));
+        assert_eq!(None, matching_paren("(a b) c", 3));
+    }
+
+    #[test]
+    fn highlighting() {
+        let interpreter = Interpreter::default();
+        assert_eq!(
+            "\x1b[33m(\x1b[0m\x1b[1;35mif\x1b[0m \x1b[36mcar\x1b[0m unknown \x1b[34m1.5\x1b[0m \
+             \x1b[32m\"s\"\x1b[0m \x1b[94m(\x1b[0m\x1b[34m:k\x1b[0m\x1b[94m)\x1b[0m \
+             \x1b[90m; c\x1b[0m",
+            highlight("(if car unknown 1.5 \"s\" (:k) ; c", 0, &interpreter.env)
+        );
+        assert_eq!(
+            "\x1b[1;7m(\x1b[0mx 'y\x1b[1;7m)\x1b[0m ",
+            highlight("(x 'y) ", 6, &interpreter.env)
+        );
+    }
+}
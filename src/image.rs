@@ -0,0 +1,518 @@
+//! Images, which save the bindings of an environment to a file, so that a later session can carry
+//! on where an earlier one left off without running its setup again.
+//!
+//! `(save-image "session.linsl-image")` saves every binding of the environment it is evaluated in,
+//! including the frames it is nested in, and `linsl --image session.linsl-image` restores them. An
+//! image is written in Linsl syntax, so it is read by the usual parser, but nothing in it is
+//! evaluated. It starts with `(linsl-image version depth)`, where depth is the number of frames
+//! saved, and an image of any other version is refused. Then come the frames and the vectors and
+//! dicts, declared before their contents, so that closures can refer to the frame they were
+//! created in and vectors can hold themselves:
+//!
+//! ```text
+//! (linsl-image 1 1)
+//! (frame 0 #f)
+//! (vector 0 2)
+//! (elems 0 1 (object 0))
+//! (bind 0 v (object 0) #f)
+//! (bind 0 f (closure 0 (x) (+ x 1) (quote f) #f) #f)
+//! ```
+//!
+//! Numbers, complex ones included, strings, bools and keywords are written as they are, and every
+//! other value as a list saying what it is: `(quote name)`, `(list x ...)`, `(object id)`,
+//! `(primitive name)`, `(closure frame params body name doc)` or `(macro params body doc)`.
+//! Primitives are saved by name, and looked up among the primitives again when restored. Values
+//! which belong to the running session, such as foreign values and promises, cannot be saved, and
+//! neither can values nested more deeply than the parser reads back, i.e. DEFAULT_MAX_NESTING.
+
+use std::fs;
+use std::sync::Arc;
+
+use crate::datatypes::{
+    quote_string, Binding, DictKey, LinslCtx, LinslDict, LinslEnv, LinslErr, LinslExpr, LinslList,
+    LinslRes, LinslVector, Num, PosNum, Symbol, DEFAULT_MAX_NESTING
+};
+use crate::evaluation::{env_get, evaluate};
+use crate::parsing::{parse_next, read_file, Tokenizer};
+
+/// The version of the image format. Images of other versions are refused rather than read into
+/// values which may not mean the same thing anymore.
+pub const IMAGE_VERSION: PosNum = 1;
+
+fn image_error(msg: String) -> LinslErr {
+    LinslErr::ImageError(msg)
+}
+
+/// Evaluation of the special form "save-image". `(save-image path)` evaluates path, which must give
+/// a string, and saves the environment to that file. Returns how many bindings were saved.
+pub(crate) fn evaluate_save_image(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    match exprs {
+        [form] => {
+            let path = evaluate(form, env, ctx)?;
+            save_image(&path, env, ctx)
+        },
+        _ => Err(
+            LinslErr::SyntaxError(
+                format!("save-image must have one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        ),
+    }
+}
+
+/// Save the bindings of `env`, and of the frames it is nested in, to the file at `path`. Returns
+/// how many bindings were saved.
+pub(crate) fn save_image(path: &LinslExpr, env: &LinslEnv, ctx: &LinslCtx) -> LinslRes {
+    ctx.policy.check("save-image")?;
+    let LinslExpr::String(path) = path else {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos
+                format!("save-image expected a string, found \'{}\'", path),
+                (0, 0)
+            )
+        );
+    };
+    let (image, count) = write_image(env)?;
+    fs::write(&**path, image)
+        .map_err(|e| image_error(format!("Could not write \'{}\': {}", path, e)))?;
+    Ok(LinslExpr::Number(count as Num))
+}
+
+/// A vector or a dict, which may be shared between several values and so is written only once.
+#[derive(Clone)]
+enum Object {
+    Vector(LinslVector),
+    Dict(LinslDict),
+}
+
+/// The frames and objects found while writing an image, numbered in the order they were found.
+#[derive(Default)]
+struct Writer {
+    frames: Vec<LinslEnv>,
+    objects: Vec<Object>,
+}
+
+impl Writer {
+    /// The number of a frame, which is added if it has not been found before.
+    fn frame(&mut self, env: &LinslEnv) -> usize {
+        match self.frames.iter().position(|f| Arc::ptr_eq(&f.0, &env.0)) {
+            Some(id) => id,
+            None => {
+                self.frames.push(env.clone());
+                self.frames.len() - 1
+            },
+        }
+    }
+
+    /// The number of an object, which is added if it has not been found before.
+    fn object(&mut self, obj: Object) -> usize {
+        let same = |other: &Object| match (&obj, other) {
+            (Object::Vector(a), Object::Vector(b)) => Arc::ptr_eq(&a.0, &b.0),
+            (Object::Dict(a), Object::Dict(b)) => Arc::ptr_eq(&a.0, &b.0),
+            _ => false,
+        };
+        match self.objects.iter().position(same) {
+            Some(id) => id,
+            None => {
+                self.objects.push(obj);
+                self.objects.len() - 1
+            },
+        }
+    }
+
+    /// A value as it is written in an image, nested in `depth` lists of it, or why it cannot be.
+    fn value(&mut self, val: &LinslExpr, depth: PosNum) -> Result<String, String> {
+        match val {
+            LinslExpr::Bool(_) | LinslExpr::Keyword(_) | LinslExpr::Number(_)
+            | LinslExpr::Complex(..) | LinslExpr::String(_) => Ok(val.to_string()),
+            // An image is parsed with the default nesting limit, so anything nested more deeply
+            // could not be read back.
+            _ if depth >= DEFAULT_MAX_NESTING => Err(format!(
+                "values nested more than {} levels deep cannot be saved in an image",
+                DEFAULT_MAX_NESTING
+            )),
+            LinslExpr::Symbol(s) => Ok(format!("(quote {})", s)),
+            LinslExpr::List(l) => {
+                let elems = l.iter()
+                    .map(|x| self.value(x, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(tagged("list", elems))
+            },
+            LinslExpr::Vector(v) => {
+                Ok(format!("(object {})", self.object(Object::Vector(v.clone()))))
+            },
+            LinslExpr::Dict(d) => Ok(format!("(object {})", self.object(Object::Dict(d.clone())))),
+            LinslExpr::Primitive(name, _) | LinslExpr::CtxPrimitive(name, _) => {
+                Ok(format!("(primitive {})", name))
+            },
+            LinslExpr::Closure(params, body, env, name, doc) => Ok(tagged("closure", vec![
+                self.frame(env).to_string(),
+                code(params, depth + 1)?,
+                code(body, depth + 1)?,
+                name.as_ref().map_or("#f".to_string(), |n| format!("(quote {})", n)),
+                doc.as_deref().map_or("#f".to_string(), quote_string),
+            ])),
+            LinslExpr::CaseLambda(clauses) => {
+                let clauses = clauses.iter()
+                    .map(|c| self.value(c, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Ok(tagged("case-lambda", clauses))
+            },
+            LinslExpr::Macro(params, body, doc) => Ok(tagged("macro", vec![
+                code(params, depth + 1)?,
+                code(body, depth + 1)?,
+                doc.as_deref().map_or("#f".to_string(), quote_string),
+            ])),
+            _ => Err(format!("\'{}\' cannot be saved in an image", val)),
+        }
+    }
+}
+
+/// A list of the items given, starting with the tag.
+fn tagged(tag: &str, items: Vec<String>) -> String {
+    let mut res = format!("({}", tag);
+    for item in items {
+        res.push(' ');
+        res.push_str(&item);
+    }
+    res.push(')');
+    res
+}
+
+/// The parameters or body of a closure or macro, nested in `depth` lists of the image, which are
+/// written as the code they are. Code is normally just what the parser read, but any other value
+/// put into it, e.g. by a macro, could not be read back in.
+fn code(expr: &LinslExpr, depth: PosNum) -> Result<String, String> {
+    fn readable(expr: &LinslExpr, depth: PosNum) -> bool {
+        match expr {
+            LinslExpr::Bool(_) | LinslExpr::Keyword(_) | LinslExpr::Number(_)
+            | LinslExpr::Complex(..) | LinslExpr::String(_) | LinslExpr::Symbol(_) => true,
+            // Neither is code nested too deeply to be parsed, e.g. a vector literal changed to
+            // hold itself.
+            LinslExpr::List(l) => {
+                depth < DEFAULT_MAX_NESTING && l.iter().all(|x| readable(x, depth + 1))
+            },
+            LinslExpr::Vector(v) => {
+                depth < DEFAULT_MAX_NESTING && v.elems().iter().all(|x| readable(x, depth + 1))
+            },
+            _ => false,
+        }
+    }
+    if readable(expr, depth) {
+        Ok(expr.to_string())
+    } else {
+        Err(format!("the code \'{}\' cannot be saved in an image", expr))
+    }
+}
+
+/// The image of `env`, along with how many bindings it holds.
+fn write_image(env: &LinslEnv) -> Result<(String, usize), LinslErr> {
+    let mut writer = Writer::default();
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        writer.frame(&frame);
+        current = frame.outer();
+    }
+    let depth = writer.frames.len();
+
+    // Writing the bindings and elements finds more frames and objects, which are written in turn.
+    let (mut frames, mut objects, mut contents, mut binds) = (vec![], vec![], vec![], vec![]);
+    while frames.len() < writer.frames.len() || contents.len() < writer.objects.len() {
+        if frames.len() < writer.frames.len() {
+            let id = frames.len();
+            let frame = writer.frames[id].clone();
+            let outer = frame.outer().map_or("#f".to_string(), |o| writer.frame(&o).to_string());
+            frames.push(format!("(frame {} {})", id, outer));
+
+            let mut bindings: Vec<(Symbol, Binding)> = frame.scope().inner.iter()
+                .map(|(name, binding)| (name.clone(), binding.clone()))
+                .collect();
+            bindings.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+            for (name, binding) in bindings {
+                let val = writer.value(&binding.val, 1).map_err(|msg| {
+                    image_error(format!("Cannot save the binding of \'{}\': {}", name, msg))
+                })?;
+                let constant = binding.constant
+                    .map_or("#f".to_string(), |(line, col)| format!("(list {} {})", line, col));
+                binds.push(format!("(bind {} {} {} {})", id, name, val, constant));
+            }
+        } else {
+            let id = contents.len();
+            let (kind, line) = match writer.objects[id].clone() {
+                Object::Vector(v) => {
+                    let elems = v.elems().clone();
+                    objects.push(format!("(vector {} {})", id, elems.len()));
+                    let elems = elems.iter().map(|x| writer.value(x, 1)).collect::<Result<_, _>>();
+                    ("vector", elems.map(|elems| tagged(&format!("elems {}", id), elems)))
+                },
+                Object::Dict(d) => {
+                    objects.push(format!("(dict {})", id));
+                    let entries = d.sorted().iter()
+                        .map(|(k, v)| {
+                            let k = writer.value(&LinslExpr::from(k), 2)?;
+                            let v = writer.value(v, 2)?;
+                            Ok(format!("(list {} {})", k, v))
+                        })
+                        .collect::<Result<_, String>>();
+                    ("dict", entries.map(|entries| tagged(&format!("entries {}", id), entries)))
+                },
+            };
+            contents.push(line.map_err(|msg| {
+                image_error(format!("Cannot save the elements of a {}: {}", kind, msg))
+            })?);
+        }
+    }
+
+    let count = binds.len();
+    let header = format!("(linsl-image {} {})", IMAGE_VERSION, depth);
+    let lines: Vec<String> = [vec![header], frames, objects, contents, binds].concat();
+    Ok((lines.join("\n") + "\n", count))
+}
+
+/// Restore the bindings saved in the image at `path` into `env`, and the frames it is nested in,
+/// which must be as many as when the image was saved. Bindings of the same names are replaced, and
+/// any others are kept. Nothing is restored unless the whole image can be read. Returns how many
+/// bindings were restored.
+pub fn load_image(path: &str, env: &LinslEnv) -> Result<usize, LinslErr> {
+    let mut tokenizer = Tokenizer::new(vec![read_file(path)?].into())?;
+    let mut forms = Vec::new();
+    while let Some(form) = parse_next(&mut tokenizer)? {
+        forms.push(form);
+    }
+
+    let mut chain = Vec::new();
+    let mut current = Some(env.clone());
+    while let Some(frame) = current {
+        current = frame.outer();
+        chain.push(frame);
+    }
+    let depth = match forms.first().map(header) {
+        Some(Some((IMAGE_VERSION, depth))) => depth,
+        Some(Some((version, _))) => return Err(image_error(format!(
+            "\'{}\' is an image of version {}, but only version {} can be read",
+            path, version, IMAGE_VERSION
+        ))),
+        _ => return Err(image_error(format!("\'{}\' is not a Linsl image", path))),
+    };
+    if depth != chain.len() {
+        return Err(image_error(format!(
+            "\'{}\' was saved from {} frames, but is restored into {}", path, depth, chain.len()
+        )));
+    };
+
+    let mut loader = Loader {
+        chain,
+        frames: Vec::new(),
+        outers: Vec::new(),
+        objects: Vec::new(),
+        binds: Vec::new(),
+        primitives: LinslEnv::default(),
+    };
+    for form in &forms[1..] {
+        loader.form(form).ok_or_else(|| {
+            image_error(format!("\'{}\' is not a valid image, at \'{}\'", path, form))
+        })?;
+    }
+    loader.finish()
+}
+
+/// The version and depth given by the header of an image.
+fn header(form: &LinslExpr) -> Option<(PosNum, PosNum)> {
+    match form {
+        LinslExpr::List(l) => match &l[..] {
+            [LinslExpr::Symbol(tag), version, depth] if tag == "linsl-image" => {
+                Some((index(version)?, index(depth)?))
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A number used as an index, e.g. the number of a frame.
+fn index(form: &LinslExpr) -> Option<usize> {
+    match form {
+        LinslExpr::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Some(*n as usize),
+        _ => None,
+    }
+}
+
+/// The state of restoring an image. The bindings are only made once every form has been read.
+struct Loader {
+    /// The frames the image is restored into, innermost first.
+    chain: Vec<LinslEnv>,
+    frames: Vec<LinslEnv>,
+    /// The frame every frame created for the image is nested in.
+    outers: Vec<(usize, usize)>,
+    objects: Vec<LinslExpr>,
+    binds: Vec<(usize, Symbol, Binding)>,
+    /// The primitives, which primitives are restored from by name.
+    primitives: LinslEnv,
+}
+
+impl Loader {
+    /// Read a form of the image following the header. Returns None if it is not valid.
+    fn form(&mut self, form: &LinslExpr) -> Option<()> {
+        let LinslExpr::List(l) = form else { return None };
+        let (LinslExpr::Symbol(tag), args) = l.split_first()? else { return None };
+        match (tag.as_str(), args) {
+            ("frame", [id, outer]) => {
+                let id = index(id)?;
+                if id != self.frames.len() {
+                    return None;
+                };
+                let frame = match self.chain.get(id) {
+                    Some(frame) => frame.clone(),
+                    None => {
+                        self.outers.push((id, index(outer)?));
+                        LinslEnv::new(&self.chain[0])
+                    },
+                };
+                self.frames.push(frame);
+            },
+            ("vector", [id, len]) => {
+                if index(id)? != self.objects.len() {
+                    return None;
+                };
+                let elems = vec![LinslExpr::List(LinslList::default()); index(len)?];
+                self.objects.push(LinslExpr::Vector(elems.into()));
+            },
+            ("dict", [id]) => {
+                if index(id)? != self.objects.len() {
+                    return None;
+                };
+                self.objects.push(LinslExpr::Dict(LinslDict::default()));
+            },
+            ("elems", [id, elems @ ..]) => {
+                let LinslExpr::Vector(v) = self.objects.get(index(id)?)? else { return None };
+                let elems = elems.iter().map(|x| self.value(x)).collect::<Option<Vec<_>>>()?;
+                if elems.len() != v.elems().len() {
+                    return None;
+                };
+                *v.elems_mut() = elems;
+            },
+            ("entries", [id, entries @ ..]) => {
+                let LinslExpr::Dict(d) = self.objects.get(index(id)?)? else { return None };
+                for entry in entries {
+                    let LinslExpr::List(entry) = self.value(entry)? else { return None };
+                    let [key, val] = &entry[..] else { return None };
+                    d.entries_mut().insert(DictKey::try_from(key).ok()?, val.clone());
+                }
+            },
+            ("bind", [frame, LinslExpr::Symbol(name), val, constant]) => {
+                let frame = index(frame)?;
+                self.frames.get(frame)?;
+                let val = match self.value(val) {
+                    Some(val) => val,
+                    // A primitive which is not built in was registered by the host, which
+                    // registers it again; its binding is left as it is.
+                    None if self.host_primitive(val) => return Some(()),
+                    None => return None,
+                };
+                let constant = match constant {
+                    LinslExpr::Bool(false) => None,
+                    _ => match &self.value(constant)? {
+                        LinslExpr::List(pos) => match &pos[..] {
+                            [line, col] => Some((index(line)?, index(col)?)),
+                            _ => return None,
+                        },
+                        _ => return None,
+                    },
+                };
+                self.binds.push((frame, name.clone(), Binding { val, constant }));
+            },
+            _ => return None,
+        };
+        Some(())
+    }
+
+    /// Whether the form is a primitive which is not one of the built in ones.
+    fn host_primitive(&self, form: &LinslExpr) -> bool {
+        match form {
+            LinslExpr::List(l) => match &l[..] {
+                [LinslExpr::Symbol(tag), LinslExpr::Symbol(name)] if tag == "primitive" => {
+                    env_get(name, &self.primitives).is_none()
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The value a form of the image stands for. Returns None if it is not valid.
+    fn value(&self, form: &LinslExpr) -> Option<LinslExpr> {
+        let l = match form {
+            LinslExpr::Bool(_) | LinslExpr::Keyword(_) | LinslExpr::Number(_)
+            | LinslExpr::Complex(..) | LinslExpr::String(_) => return Some(form.clone()),
+            LinslExpr::List(l) => l,
+            _ => return None,
+        };
+        let (LinslExpr::Symbol(tag), args) = l.split_first()? else { return None };
+        match (tag.as_str(), args) {
+            ("quote", [LinslExpr::Symbol(s)]) => Some(LinslExpr::Symbol(s.clone())),
+            ("list", elems) => {
+                let elems = elems.iter().map(|x| self.value(x)).collect::<Option<LinslList>>()?;
+                Some(LinslExpr::List(elems))
+            },
+            ("object", [id]) => self.objects.get(index(id)?).cloned(),
+            ("primitive", [LinslExpr::Symbol(name)]) => match env_get(name, &self.primitives)? {
+                prim @ (LinslExpr::Primitive(..) | LinslExpr::CtxPrimitive(..)) => Some(prim),
+                _ => None,
+            },
+            ("closure", [frame, params, body, name, doc]) => {
+                let name = match self.value(name)? {
+                    LinslExpr::Symbol(name) => Some(name),
+                    LinslExpr::Bool(false) => None,
+                    _ => return None,
+                };
+                Some(LinslExpr::Closure(
+                    Arc::new(params.clone()),
+                    Arc::new(body.clone()),
+                    self.frames.get(index(frame)?)?.clone(),
+                    name,
+                    docstring(doc)?,
+                ))
+            },
+            ("case-lambda", clauses) => {
+                let clauses = clauses.iter()
+                    .map(|c| self.value(c).filter(|c| matches!(c, LinslExpr::Closure(..))))
+                    .collect::<Option<_>>()?;
+                Some(LinslExpr::CaseLambda(clauses))
+            },
+            ("macro", [params, body, doc]) => Some(LinslExpr::Macro(
+                Arc::new(params.clone()),
+                Arc::new(body.clone()),
+                docstring(doc)?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Nest the frames created for the image in their outer frames, and make the bindings.
+    fn finish(self) -> Result<usize, LinslErr> {
+        for (id, outer) in &self.outers {
+            let outer = self.frames.get(*outer)
+                .ok_or_else(|| image_error(format!("The image has no frame {}", outer)))?;
+            self.frames[*id].scope_mut().outer = Some(outer.clone());
+        }
+        let count = self.binds.len();
+        for (frame, name, binding) in self.binds {
+            self.frames[frame].scope_mut().inner.insert(name, binding);
+        }
+        Ok(count)
+    }
+}
+
+/// The docstring of a closure or macro in an image, which is either a string or #f.
+fn docstring(form: &LinslExpr) -> Option<Option<Arc<str>>> {
+    match form {
+        LinslExpr::String(doc) => Some(Some(doc.clone())),
+        LinslExpr::Bool(false) => Some(None),
+        _ => None,
+    }
+}
@@ -0,0 +1,948 @@
+//! A self-contained interpreter, for applications embedding Linsl.
+
+use std::io::{BufRead, Cursor};
+use std::mem;
+
+use crate::datatypes::{LinslCtx, LinslEnv, LinslExpr, LinslList, LinslRes, LinslWarning};
+use crate::evaluation::{evaluate, run_program};
+use crate::parsing::{Readers, Tokenizer};
+use crate::sandbox::SandboxPolicy;
+
+/// An environment along with the state of the evaluations done in it. Everything it holds is Send,
+/// so an interpreter can be moved to (or created on) any thread.
+#[derive(Debug, Clone, Default)]
+pub struct Interpreter {
+    pub env: LinslEnv,
+    pub ctx: LinslCtx,
+    /// How the syntax starting with `#` is read by `eval_str`, e.g. with a reader registered by the
+    /// application for literals of its own.
+    pub readers: Readers,
+}
+
+impl Interpreter {
+    /// Create an interpreter on top of `base`, e.g. a prelude shared by several interpreters. The
+    /// interpreter's definitions go in a frame of its own, so `base` itself is only ever read.
+    pub fn with_base(base: &LinslEnv) -> Interpreter {
+        Interpreter {
+            env: LinslEnv::new(base),
+            ctx: LinslCtx::default(),
+            readers: Readers::default(),
+        }
+    }
+
+    /// Create an interpreter whose evaluations are limited by a sandbox policy, e.g. one allowing
+    /// only pure computation for running code written by the users of an application.
+    pub fn with_policy(policy: SandboxPolicy) -> Interpreter {
+        let mut interpreter = Interpreter::default();
+        if let Some(max_depth) = policy.max_depth {
+            interpreter.ctx.max_depth = interpreter.ctx.max_depth.min(max_depth);
+        };
+        interpreter.ctx.policy = policy;
+        interpreter
+    }
+
+    /// Evaluate a single expression.
+    pub fn evaluate(&mut self, expr: &LinslExpr) -> LinslRes {
+        evaluate(expr, &self.env, &mut self.ctx)
+    }
+
+    /// Parse and evaluate every expression in `code`, in order, and return the value of the last
+    /// one. If `code` holds no expressions, returns the empty list.
+    pub fn eval_str(&mut self, code: &str) -> LinslRes {
+        // The policy limits every evaluation, however much fuel it has been given.
+        if let Some(max_fuel) = self.ctx.policy.max_fuel
+            && self.ctx.fuel.is_none_or(|fuel| fuel > max_fuel)
+        {
+            return self.eval_str_with_fuel(code, max_fuel);
+        };
+        let input: Box<dyn BufRead> = Box::new(Cursor::new(format!("{}\n", code)));
+        let mut tokenizer = Tokenizer::new(vec![input].into())?;
+        tokenizer.readers = self.readers.clone();
+
+        let vals = run_program(&mut tokenizer, &self.env, &mut self.ctx)?;
+        Ok(vals.into_iter().last().unwrap_or(LinslExpr::List(LinslList::default())))
+    }
+
+    /// Take the warnings given by the evaluations so far, oldest first.
+    pub fn take_warnings(&mut self) -> Vec<LinslWarning> {
+        mem::take(&mut self.ctx.warnings)
+    }
+
+    /// Like `eval_str`, but aborting with a FuelExhausted error if the evaluation takes more than
+    /// `fuel` steps, e.g. to make sure that untrusted code terminates. The limit only applies to
+    /// this evaluation, and any limit set before is restored afterwards.
+    pub fn eval_str_with_fuel(&mut self, code: &str, fuel: usize) -> LinslRes {
+        let outer = self.ctx.fuel.replace(fuel);
+        let res = self.eval_str(code);
+        self.ctx.fuel = outer;
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+
+    use super::*;
+    use crate::datatypes::{Engine, LinslErr, WarningCategory, WarningPolicy};
+    use crate::image::{load_image, save_image};
+    use crate::testing::{report, run_tests, ReportOutput};
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn interpreter_is_send() {
+        assert_send::<Interpreter>();
+        assert_send::<LinslEnv>();
+        assert_send::<LinslExpr>();
+    }
+
+    #[test]
+    fn eval_str_returns_last_value() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            assert_eq!("3", interpreter.eval_str("(define x 1)\n(+ x 2)").unwrap().to_string());
+            assert_eq!("()", interpreter.eval_str("; nothing here").unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn threads_share_a_prelude() {
+        let mut prelude = Interpreter::default();
+        prelude.eval_str("
+            (define - (lambda (a b) (+ a (neg b))))
+            (define sum-to (lambda (n acc) (if (= n 0) acc (sum-to (- n 1) (+ acc n)))))
+        ").unwrap();
+
+        let handles: Vec<_> = (1..=4).map(|i| {
+            let mut interpreter = Interpreter::with_base(&prelude.env);
+            // Half of the threads use the virtual machine, with the very same prelude.
+            if i % 2 == 0 {
+                interpreter.ctx.engine = Engine::Vm;
+            };
+            thread::spawn(move || {
+                // Every thread defines the same name, which must not be seen by the others.
+                interpreter.eval_str(&format!("(define n {})", i * 1000)).unwrap();
+                interpreter.eval_str("(sum-to n 0)").unwrap().to_string()
+            })
+        }).collect();
+
+        for (i, handle) in (1..=4).zip(handles) {
+            let n = i * 1000;
+            assert_eq!((n * (n + 1) / 2).to_string(), handle.join().unwrap());
+        }
+        assert!(prelude.eval_str("n").is_err());
+    }
+
+    #[test]
+    fn define_returns_value() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            let mut eval = |code| interpreter.eval_str(code).unwrap().to_string();
+
+            // The REPL echoes the value rather than the name.
+            assert_eq!("3", eval("(define x (+ 1 2))"));
+            assert_eq!("#<closure f (y)>", eval("(define f (lambda (y) y))"));
+            assert_eq!("4", eval("(define y (define z 4))"));
+            assert_eq!("4", eval("y"));
+            assert!(interpreter.take_warnings().is_empty());
+
+            // Replacing a binding of the same frame, or hiding a primitive, gives a warning but is
+            // still done.
+            interpreter.eval_str("(define x 5) (define car cdr)").unwrap();
+            let warnings: Vec<String> =
+                interpreter.take_warnings().iter().map(|w| w.to_string()).collect();
+            assert_eq!(
+                vec![
+                    "Warning at (0, 0): Redefining 'x'",
+                    "Warning at (0, 13): Redefining the primitive 'car'",
+                ],
+                warnings
+            );
+            assert_eq!("(2)", interpreter.eval_str("(car '(1 2))").unwrap().to_string());
+            assert!(interpreter.take_warnings().is_empty());
+
+            // Shadowing a binding of an outer frame is what frames are for.
+            interpreter.eval_str("((lambda (a) (define x a)) 1)").unwrap();
+            assert!(interpreter.take_warnings().is_empty());
+        }
+    }
+
+    #[test]
+    fn warning_policy() {
+        let mut interpreter = Interpreter::default();
+        interpreter.eval_str("(define x 1) (define x 2) (define inf 3)").unwrap();
+        let categories: Vec<WarningCategory> =
+            interpreter.take_warnings().iter().map(|w| w.category).collect();
+        assert_eq!(vec![WarningCategory::Redefinition, WarningCategory::Syntax], categories);
+
+        // Silenced warnings are dropped, while denied ones abort the evaluation.
+        interpreter.ctx.warning_policy = WarningPolicy::Silent;
+        interpreter.eval_str("(define x 3)").unwrap();
+        assert!(interpreter.take_warnings().is_empty());
+        interpreter.ctx.warning_policy = WarningPolicy::Error;
+        match interpreter.eval_str("(define car 4)") {
+            Err(LinslErr::DeniedWarning(w)) => assert_eq!(WarningCategory::Shadowing, w.category),
+            res => panic!("Expected a denied warning, got {:?}", res),
+        }
+        assert!(interpreter.eval_str("nan").is_err());
+        assert_eq!("3", interpreter.eval_str("x").unwrap().to_string());
+    }
+
+    #[test]
+    fn define_test_and_assertions() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            let mut eval = |code| interpreter.eval_str(code).map(|res| res.to_string());
+
+            assert_eq!("3", eval("(assert-equal 3 (+ 1 2))").unwrap());
+            let err = eval("(assert-equal '(1 2) (list 1 3))").unwrap_err().to_string();
+            assert_eq!("Assertion failed at (0, 0): Expected '(1 2)', found '(1 3)'", err);
+            assert_eq!(
+                "\"Type error at (0, 0): Expected a list, found '1'\"",
+                eval("(assert-error (lambda () (car 1)) \"Expected a list\")").unwrap()
+            );
+            assert!(eval("(assert-error (lambda () 1))").is_err());
+            assert!(eval("(assert-error (lambda () (car 1)) \"Undefined\")").is_err());
+            assert!(matches!(eval("(define-test empty)"), Err(LinslErr::SyntaxError(..))));
+
+            // The tests see the environment they are defined in, and are run in order. A failure
+            // is reported along with where the test was defined.
+            eval("(define x 2)").unwrap();
+            assert_eq!("adds", eval("(define-test adds (assert-equal 4 (+ x x)))").unwrap());
+            eval("'fails\n(define-test fails (assert-equal 1 1)\n  (assert-equal 5 (+ x x)))")
+                .unwrap();
+            eval("'errs\n  (define-test errs (car x))").unwrap();
+            let results = run_tests(&mut interpreter.ctx).unwrap();
+            let expected = "test adds ... ok\n\
+                test fails ... FAILED (defined at (1, 0)): Assertion failed at (2, 2): \
+                Expected '5', found '4'\n\
+                test errs ... FAILED (defined at (1, 2)): Type error at (0, 0): Expected a list, \
+                found '2'\n\
+                1 passed, 2 failed\n";
+            assert_eq!(expected, report(&results));
+
+            // run-tests writes the report to the output the host supplies, if any.
+            assert_eq!("#f", interpreter.eval_str("(run-tests)").unwrap().to_string());
+            let buffer = Buffer::default();
+            interpreter.ctx.test_report = Some(ReportOutput::new(buffer.clone()));
+            assert_eq!("#f", interpreter.eval_str("(run-tests)").unwrap().to_string());
+            assert_eq!(expected, String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap());
+        }
+    }
+
+    /// A buffer the test keeps a handle to while the report is written to it.
+    #[derive(Clone, Default)]
+    struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fuel() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            interpreter.eval_str("(define loop (lambda (n) (loop (+ n 1))))").unwrap();
+
+            let res = interpreter.eval_str_with_fuel("(loop 0)", 10_000);
+            // The error is reported within the body of loop, which starts at column 25.
+            assert!(
+                matches!(res, Err(LinslErr::FuelExhausted((0, col))) if col >= 25),
+                "{:?}",
+                res
+            );
+            let res = interpreter.eval_str_with_fuel("(while #t (+ 1 2))", 10_000);
+            assert!(matches!(res, Err(LinslErr::FuelExhausted(_))), "{:?}", res);
+            let res = interpreter.eval_str_with_fuel("(do ((i 0)) (#f))", 10_000);
+            assert!(matches!(res, Err(LinslErr::FuelExhausted(_))), "{:?}", res);
+
+            // An exhausted evaluation does not affect the next one.
+            assert_eq!("3", interpreter.eval_str_with_fuel("(+ 1 2)", 10).unwrap().to_string());
+            assert_eq!(None, interpreter.ctx.fuel);
+            assert_eq!(0, interpreter.ctx.depth);
+            assert!(interpreter.eval_str_with_fuel("(loop 0)", 1).is_err());
+        }
+    }
+
+    #[test]
+    fn stats() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            let counters = |interpreter: &mut Interpreter| -> Vec<f64> {
+                let res = interpreter.eval_str("(stats)").unwrap().to_string();
+                res.trim_matches(|c| c == '(' || c == ')')
+                    .split(") (")
+                    .map(|pair| pair.split(' ').nth(1).unwrap().parse().unwrap())
+                    .collect()
+            };
+
+            interpreter.eval_str("
+                (define - (lambda (a b) (+ a (neg b))))
+                (define fib (lambda (n) (if (> 2 n) n (+ (fib (- n 1)) (fib (- n 2))))))
+                (fib 10)
+            ").unwrap();
+            let before = counters(&mut interpreter);
+            // Every counter but the current depth has been counting.
+            assert_eq!(6, before.len());
+            assert!(before.iter().enumerate().all(|(i, &n)| i == 1 || n > 0.0), "{:?}", before);
+
+            interpreter.eval_str("(map fib (list 1 2 3))").unwrap();
+            let after = counters(&mut interpreter);
+            assert!(before.iter().zip(&after).all(|(b, a)| a >= b), "{:?} {:?}", before, after);
+            assert!(after[0] > before[0]);
+
+            interpreter.eval_str("(stats-reset)").unwrap();
+            assert!(counters(&mut interpreter)[0] < before[0]);
+        }
+    }
+
+    #[test]
+    fn measuring_evaluations() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            interpreter.eval_str("
+                (define - (lambda (a b) (+ a (neg b))))
+                (define fib (lambda (n) (if (> 2 n) n (+ (fib (- n 1)) (fib (- n 2))))))
+            ").unwrap();
+            let mut measure = |code: &str| {
+                let measurement = interpreter.ctx.stats.measure();
+                interpreter.eval_str(code).unwrap();
+                measurement.finish(&mut interpreter.ctx.stats)
+            };
+
+            let small = measure("(fib 3)");
+            let large = measure("(fib 10)");
+            assert!(small.evaluations > 0 && small.max_depth > 0, "{:?}", small);
+            // The virtual machine only counts the evaluation it was started with.
+            if engine == Engine::TreeWalker {
+                assert!(large.evaluations > small.evaluations, "{:?} {:?}", small, large);
+            }
+            assert!(large.max_depth > small.max_depth, "{:?} {:?}", small, large);
+            assert!(large.frames > small.frames, "{:?} {:?}", small, large);
+            // The peak depth of a measurement is its own, and the total peak is kept.
+            assert!(measure("1").max_depth < small.max_depth);
+            assert_eq!(large.max_depth, interpreter.ctx.stats.max_depth);
+        }
+    }
+
+    #[test]
+    fn print_limits() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            let mut limited = |code: &str| {
+                let res = interpreter.eval_str(code).unwrap();
+                res.limited(interpreter.ctx.print_limits).to_string()
+            };
+            let long = "(define long (vector->list (make-vector 1000 0))) long";
+            let deep = "(define deep (list (list (list (list 1))))) deep";
+
+            // By default, the REPL prints the first 100 elements of a long list.
+            assert!(limited(long).ends_with(" 0 0 ... (900 more))"));
+            assert_eq!(100 + 3, limited(long).split(' ').count());
+            assert_eq!("((((1))))", limited(deep));
+
+            assert_eq!("100", limited("(set-print-length! 4)"));
+            assert_eq!("100", limited("(set-print-depth! 2)"));
+            assert_eq!("(0 0 0 0 ... (996 more))", limited("long"));
+            assert_eq!("((#))", limited("deep"));
+
+            // Without limits, everything is printed.
+            assert_eq!("4", limited("(set-print-length! #f)"));
+            assert_eq!("2", limited("(set-print-depth! #f)"));
+            assert_eq!(1000, limited("long").split(' ').count());
+            assert_eq!("((((1))))", limited("deep"));
+            assert_eq!("#f", limited("(set-print-length! 10)"));
+            assert!(interpreter.eval_str("(set-print-depth! -1)").is_err());
+        }
+    }
+
+    #[test]
+    fn modules() {
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            let mut eval = |code: &str| interpreter.eval_str(code).map(|res| res.to_string());
+
+            // Both modules define a helper of their own, and neither sees the other's.
+            assert_eq!("a", eval("
+                (module a (export f)
+                  (define helper (lambda (x) (* x 10)))
+                  (define f (lambda (x) (helper x))))
+            ").unwrap());
+            assert_eq!("b", eval("
+                (module b (export f)
+                  (define helper (lambda (x) (+ x 1)))
+                  (define f (lambda (x) (helper x))))
+            ").unwrap());
+            eval("(define helper 'mine)").unwrap();
+            assert_eq!("b", eval("(import a (b b-))").unwrap());
+            assert_eq!("(20 3 mine)", eval("(list (f 2) (b-f 2) helper)").unwrap());
+            let again = eval("(import a) (import (b b-)) (list (f 2) (b-f 2) helper)").unwrap();
+            assert_eq!("(20 3 mine)", again);
+
+            // Names which are not exported cannot be seen, and the error says where they are.
+            let err = eval("b-helper").unwrap_err().to_string();
+            let msg = "Undefined symbol 'b-helper', which module 'b' does not export";
+            assert!(err.contains(msg), "{}", err);
+            let err = eval("frobnicate").unwrap_err().to_string();
+            assert!(!err.contains("module"), "{}", err);
+
+            for code in [
+                "(import c)",
+                "(import)",
+                "(import (a))",
+                "(module m (f))",
+                "(module m (export f))",
+                "(module m (export f f) (define f 1))",
+                "(module (export f) (define f 1))",
+                "(module m (export if))",
+            ] {
+                let res = interpreter.eval_str(code);
+                assert!(matches!(res, Err(LinslErr::SyntaxError(..))), "{}", code);
+            }
+            // Importing again only warns if it replaces something else, as redefining does.
+            assert!(interpreter.take_warnings().is_empty());
+            interpreter.eval_str("(define f 1) (import a)").unwrap();
+            assert_eq!(2, interpreter.take_warnings().len());
+        }
+    }
+
+    #[test]
+    fn require_searches_the_path() {
+        let root = std::env::temp_dir().join(format!("linsl-require-{}", std::process::id()));
+        let (near, far) = (root.join("near"), root.join("far"));
+        let files = [
+            // app is only in far, and requires helper, which is found next to app rather than in
+            // near, which comes first in the library path.
+            (&far, "app", "
+                (require 'helper)
+                (module app (export run) (import helper) (define run (lambda () (where))))
+            "),
+            (&far, "helper", "(module helper (export where) (define where (lambda () 'far)))"),
+            (&near, "helper", "(module helper (export where) (define where (lambda () 'near)))"),
+            (&near, "dup", "(define dup 'near)"),
+            (&far, "dup", "(define dup 'far)"),
+            // Both count how many times they are loaded, and require each other.
+            (&near, "ping", "(define loads (+ loads 1)) (require 'pong)"),
+            (&near, "pong", "(define loads (+ loads 1)) (require 'ping)"),
+        ];
+        for (dir, name, code) in files {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join(format!("{}.linsl", name)), code).unwrap();
+        }
+
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::default();
+            interpreter.ctx.engine = engine;
+            interpreter.ctx.library_path = vec![near.clone(), far.clone()];
+            let mut eval = |code: &str| interpreter.eval_str(code).map(|res| res.to_string());
+
+            assert_eq!("app", eval("(require 'app)").unwrap());
+            assert_eq!("far", eval("(import app) (run)").unwrap());
+            // helper has been loaded already, and so it is not looked for again.
+            assert_eq!("far", eval("(require 'helper) (import helper) (where)").unwrap());
+            // Otherwise, the directories earlier in the library path come first.
+            assert_eq!("near", eval("(require 'dup) dup").unwrap());
+
+            eval("(define loads 0)").unwrap();
+            assert_eq!("ping", eval("(require 'ping) (require 'pong) (require 'ping)").unwrap());
+            assert_eq!("2", eval("loads").unwrap());
+
+            // The error lists every path searched, in order.
+            let err = eval("(require 'missing)").unwrap_err().to_string();
+            let searched: Vec<String> = [Path::new("."), &near, &far].iter()
+                .map(|dir| dir.join("missing.linsl").display().to_string())
+                .collect();
+            assert!(err.ends_with(&format!("searched {}", searched.join(", "))), "{}", err);
+            let res = interpreter.eval_str("(require \"app\")");
+            assert!(matches!(res, Err(LinslErr::TypeError(..))));
+            assert!(interpreter.ctx.current_file.is_none());
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn images_round_trip() {
+        let root = std::env::temp_dir().join(format!("linsl-image-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("session.linsl-image").display().to_string();
+
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut session = Interpreter::default();
+            session.ctx.engine = engine;
+            session.eval_str("
+                (define xs (list 1 (list 2.5 \"two\") 'three :four #t +inf.0 3-4i))
+                (define v (vector 1 2))
+                (define same v)
+                (define w (vector 0))
+                (vector-set! w 0 w)
+                (define d (make-dict 'a 1 :b (list v)))
+                (define square (lambda (x) \"Square x.\" (* x x)))
+                (define fact (lambda (n) (if (= n 0) 1 (* n (fact (+ n -1))))))
+                (define adder (lambda (n) (lambda (x) (+ x n))))
+                (define add2 (adder 2))
+                (define both (case-lambda ((x) x) ((x y) (+ x y))))
+                (define swap (macro (x y) (list y x)))
+                (define first car)
+                (defconst limit 10)
+            ").unwrap();
+            let saved = session.eval_str(&format!("(save-image \"{}\")", path)).unwrap();
+            // Along with the bindings of the environment, the frame add2 was created in is saved.
+            let bindings = session.env.scope().inner.len() + 1;
+            assert_eq!(bindings.to_string(), saved.to_string());
+
+            let mut restored = Interpreter::default();
+            restored.ctx.engine = engine;
+            assert_eq!(bindings, load_image(&path, &restored.env).unwrap());
+            let mut eval = |code: &str| restored.eval_str(code).map(|res| res.to_string());
+            assert_eq!("(1 (2.5 \"two\") three :four #t +inf.0 3-4i)", eval("xs").unwrap());
+            assert_eq!("(#t #t)", eval("(list (eq? v same) (eq? w (vector-ref w 0)))").unwrap());
+            // The values are shared as they were, so changing one changes all of them.
+            assert_eq!("#(9 2)", eval("(vector-set! same 0 9) (car (dict-ref d :b))").unwrap());
+            assert_eq!("1", eval("(dict-ref d 'a)").unwrap());
+            assert_eq!("(9 \"Square x.\")", eval("(list (square 3) (doc square))").unwrap());
+            assert_eq!("(120 5)", eval("(list (fact 5) (add2 3))").unwrap());
+            assert_eq!("(1 5)", eval("(list (both 1) (both 2 3))").unwrap());
+            assert_eq!("-1", eval("(swap 1 neg)").unwrap());
+            assert_eq!("1", eval("(first '(1 2))").unwrap());
+            let res = restored.eval_str("(define limit 1)");
+            assert!(matches!(res, Err(LinslErr::ConstantError(..))));
+        }
+
+        // Foreign values belong to the session, and cannot be saved.
+        let session = Interpreter::default();
+        session.env.insert("handle", LinslExpr::foreign(1_u8));
+        let target = LinslExpr::String(path.as_str().into());
+        let res = save_image(&target, &session.env, &session.ctx);
+        assert!(matches!(res, Err(LinslErr::ImageError(..))));
+
+        // An image which cannot be read in whole is refused without restoring anything.
+        let refused = [
+            "(linsl-image 99 1)\n(frame 0 #f)\n",
+            "(define x 1)\n",
+            "(linsl-image 1 2)\n(frame 0 1)\n(frame 1 #f)\n",
+            "(linsl-image 1 1)\n(frame 0 #f)\n(bind 0 x 1 #f)\n(bind 0 y (object 0) #f)\n",
+        ];
+        for image in refused {
+            fs::write(&path, image).unwrap();
+            let interpreter = Interpreter::default();
+            let res = load_image(&path, &interpreter.env);
+            assert!(matches!(res, Err(LinslErr::ImageError(..))), "{}", image);
+            assert!(!interpreter.env.scope().inner.contains_key(&"x".into()));
+        }
+        let res = load_image(&path, &Interpreter::with_base(&LinslEnv::default()).env);
+        assert!(matches!(res, Err(LinslErr::ImageError(..))));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn deep_images() {
+        use crate::datatypes::{DEFAULT_MAX_NESTING, DEFAULT_STACK_SIZE};
+
+        let root = std::env::temp_dir().join(format!("linsl-deep-image-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("session.linsl-image").display().to_string();
+        let nest = |depth: usize| {
+            let mut deep = LinslExpr::Number(0.0);
+            for _ in 0..depth {
+                deep = LinslExpr::List(vec![deep].into());
+            }
+            deep
+        };
+
+        let handle = thread::Builder::new()
+            .stack_size(DEFAULT_STACK_SIZE)
+            .spawn(move || {
+                // The binding takes up a level of the image, and every list one more.
+                let session = Interpreter::default();
+                session.env.insert("xs", nest(DEFAULT_MAX_NESTING - 1));
+                let target = LinslExpr::String(path.as_str().into());
+                let saved = save_image(&target, &session.env, &session.ctx).unwrap();
+                let mut restored = Interpreter::default();
+                let loaded = load_image(&path, &restored.env).unwrap();
+                assert_eq!(saved.to_string(), loaded.to_string());
+                restored.env.insert("ys", nest(DEFAULT_MAX_NESTING - 1));
+                assert_eq!("#t", restored.eval_str("(equal? xs ys)").unwrap().to_string());
+
+                // Anything nested more deeply could not be read back, so it is not saved.
+                for depth in [DEFAULT_MAX_NESTING, 200_000] {
+                    let mut deep = nest(depth);
+                    session.env.insert("xs", deep.clone());
+                    let res = save_image(&target, &session.env, &session.ctx);
+                    assert!(matches!(res, Err(LinslErr::ImageError(..))));
+
+                    session.env.insert("xs", LinslExpr::Bool(false));
+                    while let LinslExpr::List(xs) = &deep {
+                        let inner = xs[0].clone();
+                        deep = inner;
+                    }
+                }
+            })
+            .unwrap();
+        handle.join().unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn sandbox_denies_capabilities() {
+        let mut policy = SandboxPolicy::pure();
+        policy.max_fuel = Some(1000);
+        policy.max_depth = Some(50);
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut interpreter = Interpreter::with_policy(policy.clone());
+            interpreter.ctx.engine = engine;
+
+            assert_eq!("7", interpreter.eval_str("(+ 1 (* 2 3))").unwrap().to_string());
+            for code in [
+                "(read-file \"Cargo.toml\")", "(require 'x)", "(save-image \"x\")",
+                "(sha256-file \"Cargo.toml\")", "(md5-file \"Cargo.toml\")",
+                "(file-exists? \"Cargo.toml\")", "(directory? \"src\")", "(file-size \"x\")",
+                "(list-directory \"src\")", "(make-directory \"x\")", "(copy-file \"x\" \"y\")",
+                "(delete-file \"x\")", "(current-directory)", "(chdir \"src\")",
+                "(tcp-connect \"localhost\" 1)", "(tcp-listen \"localhost\" 0)",
+            ] {
+                match interpreter.eval_str(code) {
+                    Err(LinslErr::CapabilityDenied(name, _)) => assert!(code.contains(&name)),
+                    res => panic!("Expected {} to be denied, found {:?}", code, res),
+                };
+            }
+            // Hashing a string is pure, unlike hashing a file.
+            assert!(interpreter.eval_str("(sha256 \"abc\")").is_ok());
+            // Denied primitives are still bound, e.g. for help.
+            assert_eq!("#t", interpreter.eval_str("(bound? 'read-file)").unwrap().to_string());
+
+            // Every evaluation gets at most the fuel of the policy, even if given more.
+            interpreter.eval_str("(define loop (lambda (n) (loop n)))").unwrap();
+            let res = interpreter.eval_str_with_fuel("(loop 1)", 1_000_000);
+            assert!(matches!(res, Err(LinslErr::FuelExhausted(_))));
+            assert!(matches!(interpreter.eval_str("(loop 1)"), Err(LinslErr::FuelExhausted(_))));
+            assert_eq!(None, interpreter.ctx.fuel);
+            assert_eq!(50, interpreter.ctx.max_depth);
+        }
+        // Without a policy, everything is allowed.
+        let mut interpreter = Interpreter::default();
+        let res = interpreter.eval_str("(read-file \"Cargo.toml\")").unwrap().to_string();
+        assert!(res.contains("[package]"));
+    }
+
+    #[test]
+    fn random_numbers() {
+        let mut interpreter = Interpreter::default();
+        let mut eval = |code: &str| interpreter.eval_str(code).unwrap().to_string();
+        let draw = "(list (uuid) (random 1000) (random))";
+
+        // Unseeded, every draw differs.
+        assert_ne!(eval("(uuid)"), eval("(uuid)"));
+        assert_eq!("#t", eval("(uuid? (uuid))"));
+        assert_eq!("(#f #f)", eval("(list (uuid? \"not-a-uuid\") (uuid? 1))"));
+
+        // Seeded, the same draws are made every time.
+        eval("(set-random-seed! 7)");
+        let first = eval(draw);
+        assert_ne!(first, eval(draw));
+        eval("(set-random-seed! 7)");
+        assert_eq!(first, eval(draw));
+        assert!(interpreter.eval_str("(random 0)").is_err());
+        assert!(interpreter.eval_str("(random 1.5)").is_err());
+    }
+
+    #[test]
+    fn time_primitives() {
+        let mut interpreter = Interpreter::default();
+        let mut eval = |code: &str| interpreter.eval_str(code).unwrap().to_string();
+        assert_eq!(
+            "\"2009-02-13T23:31:30Z\"",
+            eval("(time->string 1234567890 \"%Y-%m-%dT%H:%M:%SZ\")")
+        );
+        assert_eq!(
+            "1234567890",
+            eval("(string->time \"2009-02-13 23:31:30\" \"%Y-%m-%d %H:%M:%S\")")
+        );
+
+        // Writing the current time and reading it back gives the same time.
+        eval("(define fmt \"%d/%m/%Y %H.%M.%S\")");
+        eval("(define now (current-seconds))");
+        assert_eq!("#t", eval("(= now (string->time (time->string now fmt) fmt))"));
+        assert_eq!("#t", eval("(> (+ (current-milliseconds) 1) (* now 1000))"));
+
+        // A string not matching the format gives an error which can be caught.
+        assert_eq!(
+            "\"Decode error at (0, 0): Expected 2 digits for the month, found 'x' in the UTC time \
+             \\\"2024-1x-01\\\" at index 6\"",
+            eval("(assert-error (lambda () (string->time \"2024-1x-01\" \"%Y-%m-%d\")))")
+        );
+        eval("(define m (measure-time (lambda () (+ 1 2))))");
+        assert_eq!("(3 #t)", eval("(list (car m) (> (car (cdr m)) -1))"));
+        assert!(interpreter.eval_str("(time->string 0 \"%Y %z\")").is_err());
+
+        // Times which cannot be written with a four digit year are not silently changed.
+        let last = interpreter.eval_str("(time->string 253402300799.5 \"%Y\")").unwrap();
+        assert_eq!("\"9999\"", last.to_string());
+        for secs in ["+nan.0", "+inf.0", "-1e20", "253402300800", "-62167219201"] {
+            let res = interpreter.eval_str(&format!("(time->string {} \"%Y\")", secs));
+            assert!(matches!(res, Err(LinslErr::TypeError(..))), "{:?}", res);
+        }
+    }
+
+    #[test]
+    fn hashing_files() {
+        let path = std::env::temp_dir().join("linsl-test-hashing.txt");
+        fs::write(&path, "abc").unwrap();
+        let mut interpreter = Interpreter::default();
+        let code = format!("(list (sha256-file {:?}) (md5-file {:?}))", path, path);
+        assert_eq!(
+            "(\"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\" \
+             \"900150983cd24fb0d6963f7d28e17f72\")",
+            interpreter.eval_str(&code).unwrap().to_string()
+        );
+        assert_eq!(
+            interpreter.eval_str(&format!("(sha256-file {:?})", path)).unwrap().to_string(),
+            interpreter.eval_str("(sha256 \"abc\")").unwrap().to_string()
+        );
+        fs::remove_file(&path).unwrap();
+
+        // A missing file gives an error naming it, which can be caught.
+        match interpreter.eval_str(&format!("(sha256-file {:?})", path)) {
+            Err(LinslErr::ReadError(Some(p), _)) => assert_eq!(path.to_str(), Some(p.as_str())),
+            res => panic!("Expected a read error, found {:?}", res),
+        }
+        let code = format!("(assert-error (lambda () (md5-file {:?})) \"Could not read\")", path);
+        assert!(interpreter.eval_str(&code).is_ok());
+    }
+
+    #[test]
+    fn tcp_connections() {
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+
+        // A server which answers every request with it in uppercase, then closes the connection.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf.to_ascii_uppercase()).unwrap();
+        });
+        let mut interpreter = Interpreter::default();
+        let mut eval = |code: &str| interpreter.eval_str(code).map(|res| res.to_string());
+
+        let conn = format!("127.0.0.1:{}", port);
+        eval(&format!("(define conn (tcp-connect \"127.0.0.1\" {} 1000))", port)).unwrap();
+        assert_eq!(format!("#<tcp {}>", conn), eval("conn").unwrap());
+        assert_eq!("5", eval("(tcp-send conn \"hello\")").unwrap());
+        assert_eq!("\"HELLO\"", eval("(tcp-recv conn 100 1000)").unwrap());
+        server.join().unwrap();
+        // The server has closed the connection, so there is nothing more to receive.
+        assert_eq!("\"\"", eval("(tcp-recv conn 100 1000)").unwrap());
+        assert_eq!("(#t #f)", eval("(list (tcp-close conn) (tcp-close conn))").unwrap());
+        assert_eq!(format!("#<tcp {} (closed)>", conn), eval("conn").unwrap());
+
+        // Using a closed connection, a timeout, and connecting to a port nothing listens on are
+        // errors which can be caught.
+        for (code, cause) in [
+            ("(tcp-send conn \"x\")", "It is closed"),
+            ("(tcp-recv conn 1)", "It is closed"),
+            ("(tcp-accept (tcp-listen \"127.0.0.1\" 0) 50)", "Timed out after 50 ms"),
+            (&format!("(tcp-connect \"127.0.0.1\" {} 1000)", port), ""),
+        ] {
+            match eval(code) {
+                Err(LinslErr::NetError(_, msg)) => assert!(msg.contains(cause), "{}", msg),
+                res => panic!("Expected a network error from {}, found {:?}", code, res),
+            };
+            let caught = format!("(assert-error (lambda () {}) \"Network error\")", code);
+            assert!(eval(&caught).is_ok());
+        }
+
+        // The other way around, a client connects to a listener, and is kept waiting for a reply.
+        eval("(define listener (tcp-listen \"127.0.0.1\" 0))").unwrap();
+        let port: u16 = eval("(tcp-port listener)").unwrap().parse().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(b"ping").unwrap();
+            let mut buf = String::new();
+            stream.read_to_string(&mut buf).unwrap();
+            buf
+        });
+        eval("(define client (tcp-accept listener 5000))").unwrap();
+        assert_eq!("\"ping\"", eval("(tcp-recv client 4)").unwrap());
+        match eval("(tcp-recv client 4 50)") {
+            Err(LinslErr::NetError(_, msg)) => assert_eq!("Timed out after 50 ms", msg),
+            res => panic!("Expected a timeout, found {:?}", res),
+        };
+        eval("(tcp-send client \"pong\") (tcp-close client) (tcp-close listener)").unwrap();
+        assert_eq!("pong", client.join().unwrap());
+        assert!(eval("listener").unwrap().ends_with("(closed)>"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn http_requests() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // A server answering three requests: a redirect, a page sent in chunks, and a POST, whose
+        // body and X-Test header it echoes.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    };
+                    head.push(line.trim_end().to_string());
+                }
+                let header = |name: &str| head.iter()
+                    .find_map(|line| {
+                        let (n, value) = line.split_once(": ")?;
+                        n.eq_ignore_ascii_case(name).then_some(value)
+                    })
+                    .unwrap_or_default()
+                    .to_string();
+                let mut body = vec![0; header("Content-Length").parse().unwrap_or(0)];
+                reader.read_exact(&mut body).unwrap();
+                // Every connection is closed after its response, so the client does not keep it.
+                let response = match head[0].as_str() {
+                    "GET /old HTTP/1.1" => {
+                        "HTTP/1.1 302 Found\r\nConnection: close\r\nContent-Length: 0\r\n\
+                         Location: /new\r\n\r\n".to_string()
+                    },
+                    "GET /new HTTP/1.1" => {
+                        "HTTP/1.1 200 OK\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         5\r\nhello\r\n0\r\n\r\n".to_string()
+                    },
+                    _ => format!(
+                        "HTTP/1.1 201 Created\r\nConnection: close\r\nContent-Length: {}\r\n\
+                         X-Test: {}\r\n\r\n{}",
+                        body.len(), header("X-Test"), String::from_utf8(body).unwrap()
+                    ),
+                };
+                reader.into_inner().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let mut interpreter = Interpreter::default();
+        let mut eval = |code: &str| interpreter.eval_str(code).map(|res| res.to_string());
+
+        let url = format!("http://127.0.0.1:{}", port);
+        assert_eq!(
+            "((status 200) (headers ((\"connection\" \"close\") \
+             (\"transfer-encoding\" \"chunked\"))) (body \"hello\"))",
+            eval(&format!("(http-get \"{}/old\" 5000)", url)).unwrap()
+        );
+        assert_eq!(
+            "((status 201) (headers ((\"connection\" \"close\") (\"content-length\" \"4\") \
+             (\"x-test\" \"yes\"))) (body \"ping\"))",
+            eval(&format!("(http-post \"{}/echo\" \"ping\" '((\"X-Test\" \"yes\")))", url))
+                .unwrap()
+        );
+        server.join().unwrap();
+
+        // Nothing listens any more, and ftp is not supported: both are errors naming the URL.
+        for url in [format!("{}/gone", url), "ftp://example.com".to_string()] {
+            match eval(&format!("(http-get \"{}\")", url)) {
+                Err(LinslErr::NetError(u, _)) => assert_eq!(url, u),
+                res => panic!("Expected a network error, found {:?}", res),
+            }
+        }
+        // A server which never answers runs out the time given.
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", silent.local_addr().unwrap());
+        match eval(&format!("(http-get \"{}\" 100)", url)) {
+            Err(LinslErr::NetError(_, msg)) => assert_eq!("Timed out after 100 ms", msg),
+            res => panic!("Expected a timeout, found {:?}", res),
+        }
+    }
+
+    #[test]
+    fn managing_files() {
+        let dir = std::env::temp_dir().join("linsl-test-managing-files");
+        let _ = fs::remove_dir_all(&dir);
+        let mut interpreter = Interpreter::default();
+        let mut eval = |code: String| interpreter.eval_str(&code).map(|res| res.to_string());
+        let path = |name: &str| format!("{:?}", dir.join(name));
+
+        assert_eq!("#f", eval(format!("(file-exists? {})", path(""))).unwrap());
+        eval(format!("(make-directory {})", path("sub/inner"))).unwrap();
+        fs::write(dir.join("b.txt"), "abc").unwrap();
+        assert_eq!("3", eval(format!("(copy-file {} {})", path("b.txt"), path("a.txt"))).unwrap());
+        assert_eq!(
+            "(\"a.txt\" \"b.txt\" \"sub\")",
+            eval(format!("(list-directory {})", path(""))).unwrap()
+        );
+        assert_eq!(
+            "(#t #t #t #f 3)",
+            eval(format!(
+                "(list (file-exists? {a}) (directory? {sub}) (directory? {inner}) (directory? {a}) \
+                 (file-size {a}))",
+                a = path("a.txt"), sub = path("sub"), inner = path("sub/inner")
+            )).unwrap()
+        );
+        eval(format!("(delete-file {})", path("a.txt"))).unwrap();
+        let code = format!("(list (file-exists? {a}) (directory? {a}))", a = path("a.txt"));
+        assert_eq!("(#f #f)", eval(code).unwrap());
+
+        // Missing paths, and files and directories mixed up, give errors naming the path.
+        for (code, cause) in [
+            (format!("(file-size {})", path("missing")), "get the size of"),
+            (format!("(file-size {})", path("sub")), "It is a directory"),
+            (format!("(list-directory {})", path("missing")), "list"),
+            (format!("(list-directory {})", path("b.txt")), "It is not a directory"),
+            (format!("(copy-file {} {})", path("missing"), path("c.txt")), "copy"),
+            (format!("(delete-file {})", path("missing")), "delete"),
+            (format!("(delete-file {})", path("sub")), "It is a directory"),
+            (format!("(make-directory {})", path("b.txt/sub")), "create"),
+        ] {
+            match eval(code.clone()) {
+                Err(LinslErr::FileError(action, p, msg)) => {
+                    assert!(action.contains(cause) || msg.contains(cause), "{}: {}", code, msg);
+                    assert!(code.contains(&format!("{:?}", p)), "{}: {}", code, p);
+                },
+                res => panic!("Expected a file error from {}, found {:?}", code, res),
+            }
+        }
+        let code = format!("(delete-file {})", path("sub"));
+        assert!(eval(format!("(assert-error (lambda () {}) \"Could not delete\")", code)).is_ok());
+        assert!(dir.join("sub/inner").is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn custom_readers() {
+        let mut interpreter = Interpreter::default();
+        // #n5 reads as (list 0 1 2 3 4).
+        interpreter.readers.insert("n", |rest, tokenizer| {
+            let n: usize = rest.parse().map_err(|_| {
+                LinslErr::SyntaxError(format!("Bad count \'{}\'", rest), tokenizer.get_pos())
+            })?;
+            let mut exprs = vec![LinslExpr::Symbol("list".into())];
+            exprs.extend((0..n).map(|i| LinslExpr::Number(i as f64)));
+            Ok(LinslExpr::List(exprs.into()))
+        });
+        assert_eq!("(0 1 2)", interpreter.eval_str("#n3").unwrap().to_string());
+        assert_eq!("#(0 #t)", interpreter.eval_str("(vector (car #n3) #t)").unwrap().to_string());
+        assert!(matches!(interpreter.eval_str("#nx"), Err(LinslErr::SyntaxError(..))));
+        assert!(matches!(Interpreter::default().eval_str("#n3"), Err(LinslErr::SyntaxError(..))));
+    }
+}
@@ -0,0 +1,27 @@
+//! A simple interpreter for a lisp/scheme like language, usable both from the bundled REPL and
+//! when embedded in a host application.
+
+pub mod datatypes;
+pub mod debugger;
+pub mod encoding;
+pub mod evaluation;
+pub mod hashing;
+pub mod image;
+pub mod interpreter;
+pub mod lint;
+pub mod matching;
+pub mod modules;
+pub mod optimization;
+pub mod parsing;
+pub mod primitives;
+pub mod printing;
+pub mod random;
+pub mod profiler;
+pub mod sandbox;
+pub mod streams;
+pub mod testing;
+pub mod time;
+pub mod trace;
+pub mod vm;
+
+use datatypes::{LinslErr, LinslExpr};
@@ -0,0 +1,372 @@
+//! The linter, which reports likely mistakes in code without evaluating it: parameters and
+//! top-level definitions which are never used, bindings hiding a primitive or an enclosing
+//! binding, and `if` forms whose test is a constant.
+//!
+//! The analysis follows the binding forms, i.e. lambda and macro parameters, define and defconst
+//! and the bodies of modules, and skips quoted expressions. Parameters whose names start with `_`
+//! are taken to be unused on purpose. Symbols have no positions of their own, so every warning is
+//! given the position of the innermost list it is found in, e.g. the parameter list of a lambda
+//! for its parameters, or the if form for its test.
+
+use std::mem;
+
+use crate::datatypes::{LinslErr, LinslExpr, LinslWarning, Pos, Symbol, WarningCategory};
+use crate::evaluation::{
+    case_lambda_clauses, do_forms, get_params_and_body, is_special_form, let_to_lambda,
+    let_values_forms, param_symbols, DoForms
+};
+use crate::matching::match_forms;
+use crate::parsing::{parse, Tokenizer};
+use crate::primitives::PRIMITIVE_TABLES;
+
+/// A binding made within a top-level form, e.g. by a lambda.
+struct LocalBinding {
+    name: Symbol,
+    /// Whether the binding is a parameter, which is reported if it is never used.
+    param: bool,
+    used: bool,
+    /// Where the binding is made.
+    pos: Pos,
+}
+
+/// The state of linting a single top-level form.
+struct Linter<'a> {
+    /// The names defined at the top level of the file.
+    top_level: &'a [Symbol],
+    /// The frames of bindings enclosing the expression being linted, innermost last.
+    scopes: Vec<Vec<LocalBinding>>,
+    /// The names referenced which are not bound within the form.
+    references: Vec<Symbol>,
+    warnings: Vec<LinslWarning>,
+    /// The position of the innermost list being linted.
+    pos: Pos,
+}
+
+/// Parse every form the tokenizer reads, and lint them. The warnings given while parsing are
+/// included.
+pub fn lint_input(tokenizer: &mut Tokenizer) -> Result<Vec<LinslWarning>, LinslErr> {
+    let mut forms = Vec::new();
+    while let Some(pos) = tokenizer.peek_pos() {
+        forms.push((parse(tokenizer)?, pos));
+    }
+    let mut warnings = mem::take(&mut tokenizer.warnings);
+    warnings.extend(lint(&forms));
+    warnings.sort_by_key(|warning| warning.pos);
+    Ok(warnings)
+}
+
+/// Lint the top-level forms of a file, given along with the position each starts at. Returns the
+/// warnings, in the order of the forms they are found in.
+pub fn lint(forms: &[(LinslExpr, Pos)]) -> Vec<LinslWarning> {
+    let defined: Vec<Option<Symbol>> = forms.iter().map(|(form, _)| defined_name(form)).collect();
+    let top_level: Vec<Symbol> = defined.iter().flatten().cloned().collect();
+
+    let mut warnings = Vec::new();
+    let mut references: Vec<Vec<Symbol>> = Vec::new();
+    for (form, pos) in forms {
+        let mut linter = Linter {
+            top_level: &top_level,
+            scopes: Vec::new(),
+            references: Vec::new(),
+            warnings: Vec::new(),
+            pos: *pos,
+        };
+        linter.form(form);
+        warnings.extend(linter.warnings);
+        references.push(linter.references);
+    }
+
+    // A top-level definition is used if any form other than a definition of the same name
+    // references it.
+    for (i, name) in defined.iter().enumerate() {
+        let Some(name) = name else {
+            continue;
+        };
+        let used = references.iter()
+            .zip(&defined)
+            .any(|(refs, def)| def.as_ref() != Some(name) && refs.contains(name));
+        if !used && !defined[..i].contains(&Some(name.clone())) {
+            warnings.push(warning(format!("\'{}\' is defined but never used", name), forms[i].1));
+        };
+    }
+    warnings.sort_by_key(|warning| warning.pos);
+    warnings
+}
+
+fn warning(message: String, pos: Pos) -> LinslWarning {
+    LinslWarning { category: WarningCategory::Lint, message, pos }
+}
+
+/// The name a top-level form defines, if it is a define or defconst.
+fn defined_name(form: &LinslExpr) -> Option<Symbol> {
+    match form {
+        LinslExpr::List(l) => match &l[..] {
+            [LinslExpr::Symbol(head), LinslExpr::Symbol(name), _]
+                if matches!(head.as_str(), "define" | "defconst") => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether an expression evaluates to itself (or to what it quotes), so that e.g. an if testing it
+/// always takes the same branch.
+fn is_constant(expr: &LinslExpr) -> bool {
+    match expr {
+        LinslExpr::Bool(_) | LinslExpr::Number(_) | LinslExpr::Complex(..) | LinslExpr::String(_)
+            | LinslExpr::Keyword(_) => true,
+        LinslExpr::List(l) => {
+            matches!(&l[..], [LinslExpr::Symbol(head), _] if head.as_str() == "quote")
+        },
+        _ => false,
+    }
+}
+
+fn is_primitive(name: &Symbol) -> bool {
+    PRIMITIVE_TABLES.iter().flat_map(|table| table.iter()).any(|entry| entry.name == name.as_str())
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, message: String) {
+        self.warnings.push(warning(message, self.pos));
+    }
+
+    /// Move to the position of a list being linted, if it has one, returning the position to move
+    /// back to once done with it.
+    fn enter(&mut self, pos: Option<Pos>) -> Pos {
+        let outer = self.pos;
+        if let Some(pos) = pos {
+            self.pos = pos;
+        };
+        outer
+    }
+
+    /// Lint a top-level form, which can define a name of the file's own.
+    fn form(&mut self, form: &LinslExpr) {
+        if let Some(name) = defined_name(form) {
+            if is_primitive(&name) {
+                self.warn(format!("\'{}\' shadows a primitive", name));
+            };
+            if let LinslExpr::List(l) = form {
+                self.expr(&l[2]);
+            };
+        } else {
+            self.expr(form);
+        }
+    }
+
+    fn expr(&mut self, expr: &LinslExpr) {
+        match expr {
+            LinslExpr::Symbol(s) => self.reference(s),
+            LinslExpr::List(l) => {
+                // Lists built by the linter itself, e.g. from a let, have no position.
+                let outer = self.enter(l.pos());
+                match &l[..] {
+                    [LinslExpr::Symbol(head), rest @ ..] if is_special_form(head.as_str()) => {
+                        self.special_form(head.as_str(), rest);
+                    },
+                    exprs => exprs.iter().for_each(|e| self.expr(e)),
+                };
+                self.pos = outer;
+            },
+            _ => (),
+        }
+    }
+
+    fn special_form(&mut self, form: &str, exprs: &[LinslExpr]) {
+        match (form, exprs) {
+            ("quote", _) | ("import", _) => (),
+            ("lambda" | "macro", _) => match get_params_and_body(exprs) {
+                Ok((params, body, _)) => self.lambda(&params, &body),
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("case-lambda", _) => match case_lambda_clauses(exprs) {
+                Ok(clauses) => clauses.iter().for_each(|(params, body)| self.lambda(params, body)),
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("let", _) => match let_to_lambda(exprs) {
+                Ok(application) => self.expr(&application),
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("match", _) => match match_forms(exprs) {
+                Ok((expr, clauses)) => {
+                    self.expr(expr);
+                    for (clause, form) in clauses.iter().zip(&exprs[1..]) {
+                        let outer = self.enter(form.pos());
+                        self.scopes.push(Vec::new());
+                        clause.pattern.symbols().iter().for_each(|s| self.bind(s, false));
+                        clause.guard.iter().chain(&clause.body).for_each(|e| self.expr(e));
+                        self.scopes.pop();
+                        self.pos = outer;
+                    }
+                },
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("define-values", [names, value]) => {
+                self.expr(value);
+                let outer = self.enter(names.pos());
+                param_symbols(names).iter().for_each(|name| self.bind(name, false));
+                self.pos = outer;
+            },
+            ("let-values", _) => match let_values_forms(exprs) {
+                Ok((bindings, body)) => {
+                    bindings.iter().for_each(|(_, value)| self.expr(value));
+                    let params = bindings.iter().map(|(params, _)| (*params).clone());
+                    self.lambda(&LinslExpr::List(params.collect()), body);
+                },
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("do", _) => match do_forms(exprs) {
+                Ok(DoForms { vars, test, result, body }) => {
+                    vars.iter().for_each(|(_, init, _)| self.expr(init));
+                    self.scopes.push(Vec::new());
+                    let outer = self.enter(exprs.first().and_then(LinslExpr::pos));
+                    vars.iter().for_each(|(name, ..)| self.bind(name, false));
+                    self.pos = outer;
+                    let steps = vars.iter().filter_map(|(.., step)| *step);
+                    std::iter::once(test).chain(result).chain(body).chain(steps)
+                        .for_each(|e| self.expr(e));
+                    self.scopes.pop();
+                },
+                Err(_) => exprs.iter().for_each(|e| self.expr(e)),
+            },
+            ("define" | "defconst", [LinslExpr::Symbol(name), value]) => {
+                self.expr(value);
+                self.bind(name, false);
+            },
+            ("define-record", [LinslExpr::Symbol(_), _]) => (),
+            ("module", [_, LinslExpr::List(export), body @ ..]) => {
+                self.scopes.push(Vec::new());
+                body.iter().for_each(|e| self.expr(e));
+                // Exporting a name counts as using it.
+                export.iter().skip(1).for_each(|e| self.expr(e));
+                self.scopes.pop();
+            },
+            ("if", [test, ..]) => {
+                if is_constant(test) {
+                    self.warn(
+                        format!("The test of if is the constant \'{}\', so it always takes the \
+                                 same branch", test)
+                    );
+                };
+                exprs.iter().for_each(|e| self.expr(e));
+            },
+            _ => exprs.iter().for_each(|e| self.expr(e)),
+        }
+    }
+
+    /// Lint the body of a lambda or macro with its parameters bound, and report the parameters
+    /// the body never uses.
+    fn lambda(&mut self, params: &LinslExpr, body: &LinslExpr) {
+        self.scopes.push(Vec::new());
+        let outer = self.enter(params.pos());
+        for param in param_symbols(params) {
+            self.bind(&param, true);
+        }
+        self.pos = outer;
+        self.expr(body);
+        let scope = self.scopes.pop().unwrap_or_default();
+        for binding in scope {
+            if binding.param && !binding.used && !binding.name.as_str().starts_with('_') {
+                let message = format!("Parameter \'{}\' is never used", binding.name);
+                self.warnings.push(warning(message, binding.pos));
+            };
+        }
+    }
+
+    /// Bind a name in the innermost frame at the current position, reporting it if it hides
+    /// another binding.
+    fn bind(&mut self, name: &Symbol, param: bool) {
+        let Some((innermost, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        let shadows = enclosing.iter().flatten().any(|b| b.name == *name)
+            || self.top_level.contains(name);
+        if innermost.iter().any(|b| b.name == *name) {
+            return;
+        };
+        innermost.push(LocalBinding { name: name.clone(), param, used: false, pos: self.pos });
+        if shadows {
+            self.warn(format!("\'{}\' shadows an enclosing binding", name));
+        } else if is_primitive(name) {
+            self.warn(format!("\'{}\' shadows a primitive", name));
+        };
+    }
+
+    /// Mark the innermost binding of a name as used, or record the reference if it is not bound
+    /// within the form.
+    fn reference(&mut self, name: &Symbol) {
+        let binding = self.scopes.iter_mut().rev().flatten().find(|b| b.name == *name);
+        match binding {
+            Some(binding) => binding.used = true,
+            None => self.references.push(name.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn lint_str(s: &str) -> Vec<String> {
+        let mut tokenizer = Tokenizer::new(vec![Box::new(Cursor::new(s.to_string())) as _].into())
+            .unwrap();
+        lint_input(&mut tokenizer).unwrap().iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn lint_warnings() {
+        assert!(lint_str("(define sq (lambda (x) (* x x)))\n(sq 2)\n").is_empty());
+        assert_eq!(
+            vec![
+                "Warning at (0, 0): 'f' is defined but never used",
+                "Warning at (0, 18): Parameter 'y' is never used",
+                "Warning at (1, 0): 'car' shadows a primitive",
+                "Warning at (1, 0): 'car' is defined but never used",
+                "Warning at (2, 2): The test of if is the constant '1', so it always takes the \
+                 same branch",
+            ],
+            lint_str("(define f (lambda (x y) x))\n(define car 1)\n  (if 1 2 3)\n")
+        );
+
+        // Parameters hiding others, and unused ones marked with _, and the quoted. Parameters are
+        // reported where their parameter list is.
+        assert_eq!(
+            vec![
+                "Warning at (0, 9): Parameter 'x' is never used",
+                "Warning at (0, 24): 'x' shadows an enclosing binding",
+                "Warning at (0, 24): 'list' shadows a primitive",
+            ],
+            lint_str("((lambda (x _y) (lambda (x list) (list x '(z))) ) 1 2)")
+        );
+        // A recursive function is not used by referencing itself, while rest parameters and
+        // exported names are.
+        assert_eq!(
+            vec!["Warning at (0, 0): 'loop' is defined but never used"],
+            lint_str(
+                "(define loop (lambda (n) (loop n)))\n\
+                 (module m (export g) (define g (lambda (a . rest) (list a rest))))"
+            )
+        );
+        // The symbols within patterns, of lambdas and of let, are parameters too.
+        assert_eq!(
+            vec![
+                "Warning at (0, 9): Parameter 'b' is never used",
+                "Warning at (0, 19): Parameter 'd' is never used",
+            ],
+            lint_str("((lambda ((a b) _) (let (((c . d) a)) c)) '(1 2) 3)")
+        );
+        // The symbols of match patterns are bound within their clause, and quoted ones are not.
+        assert_eq!(
+            vec!["Warning at (0, 12): 'list' shadows a primitive"],
+            lint_str("(match '(1) ((list . t) #:when t (list t)) ('x 'x))")
+        );
+        // The variables of a do loop are bound for its test, body and steps, but not its inits.
+        assert_eq!(
+            vec!["Warning at (0, 4): 'car' shadows a primitive"],
+            lint_str("(do ((i 0 (+ i car)) (car 1)) ((> i 2) i) (list i))")
+        );
+    }
+}
@@ -1,92 +1,568 @@
 //! A simple interpreter for a lisp/scheme like language
 
-use std::collections::VecDeque;
+mod cli;
+mod highlight;
+
 use std::env::args;
-use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Cursor, Result};
-
-mod datatypes;
-mod evaluation;
-mod primitives;
-mod parsing;
-
-use evaluation::evaluate;
-use parsing::{parse,  Tokenizer};
-use datatypes::{LinslEnv, LinslErr, LinslExpr, LinslRes};
-use rustyline::DefaultEditor;
-
-fn parse_eval(tokenizer: &mut Tokenizer, env: &mut LinslEnv) -> LinslRes {
-    let parse_res = parse(tokenizer)?;
-    let res = evaluate(&parse_res, env)?;
-    Ok(res)
-}
-
-/// Returns Stdin as an input source.
-fn get_stdin() -> Box<dyn BufRead> {
-    Box::new(io::stdin().lock())
-}
-
-/// Attempts to create an input source from a file.
-/// Returns an error if the file does not exist, or cannot be interpreted as a UTF-8 string.
-fn get_file(path: &str) -> Result<Box<dyn BufRead>> {
-    match fs::read_to_string(path) {
-        Ok(_) => Ok(Box::new(BufReader::new(File::open(path)?))),
-        Err(e) => Err(e)
-    }
-}
-
-/// Setup sources to read input from, and return them.
-fn get_input() -> Result<VecDeque<Box<dyn BufRead>>> {
-    // First, create vecdeque to store the inputs in.
-    let mut vec: VecDeque<Box<dyn BufRead>> = VecDeque::new();
-
-    // Then, check if any file paths were passed.
-    let args = args().skip(1);
-    if args.len() == 0 {
-        // If not, then simply return the vec containing stdin.
-        vec.push_back(get_stdin());
-        Ok(vec)
-    } else {
-        // If there are file specified, try to add them instead.
-        for path in args {
-            let file = get_file(&path)?;
-            vec.push_back(file);
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Cursor, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+
+use linsl::image::load_image;
+use linsl::interpreter::Interpreter;
+use linsl::lint::lint_input;
+use linsl::optimization::optimize;
+use linsl::parsing::{indent_code, parse_next, read_file, Tokenizer};
+use linsl::datatypes::{LinslEnv, LinslErr, LinslExpr, Stats, DEFAULT_STACK_SIZE};
+use linsl::debugger::{DebugHook, StepDebugger};
+use linsl::profiler::Profile;
+use linsl::testing::{report, run_tests, ReportOutput};
+use linsl::trace::Trace;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+
+use cli::{parse_args, Action, Color, Command, Config, HELP};
+use highlight::LinslHelper;
+
+/// Create an interpreter set up according to the configuration.
+fn new_interpreter(config: &Config) -> Interpreter {
+    let mut interpreter = Interpreter::default();
+    interpreter.ctx.engine = config.engine;
+    if config.debug {
+        let debugger = StepDebugger::new(read_command(), io::stdout());
+        interpreter.ctx.debugger = Some(DebugHook::new(debugger));
+    }
+    if config.profile {
+        interpreter.ctx.profile = Some(Profile::default());
+    }
+    if config.trace {
+        let mut trace = Trace::stderr();
+        trace.width = config.trace_width.unwrap_or(trace.width);
+        interpreter.ctx.trace = Some(trace);
+    }
+    interpreter.ctx.test_report = Some(ReportOutput::stdout());
+    interpreter.ctx.warning_policy = config.warnings;
+    if let Some(length) = config.print_length {
+        interpreter.ctx.print_limits.length = length;
+    }
+    if let Some(depth) = config.print_depth {
+        interpreter.ctx.print_limits.depth = depth;
+    }
+    interpreter
+}
+
+/// Print the warnings given since they were last printed to stderr, in yellow if `color` asks for
+/// it.
+fn print_warnings(interpreter: &mut Interpreter, color: Color) {
+    let color = color.enabled(io::stderr().is_terminal());
+    for warning in interpreter.take_warnings() {
+        if color {
+            eprintln!("\x1b[33m{}\x1b[0m", warning);
+        } else {
+            eprintln!("{}", warning);
+        }
+    }
+}
+
+/// Print the profile of the evaluation, if it was profiled.
+fn print_profile(interpreter: &Interpreter) {
+    if let Some(profile) = &interpreter.ctx.profile {
+        eprint!("{}", profile.report());
+    }
+}
+
+/// Read debugger commands with a line reader of their own, so that they do not end up in the
+/// history of the REPL.
+fn read_command() -> impl FnMut(&str) -> Option<String> + Send {
+    let mut reader = LineReader::new();
+    move |prompt| reader.read_line(prompt).ok().flatten()
+}
+
+/// Parse the next form and evaluate it, or return None if the input has been exhausted. This is
+/// the step of `run_program` which the runner and the REPL repeat, since they act on every value
+/// as it is returned. With --show-ast, the form is printed to stderr as it was parsed before it
+/// is evaluated.
+fn parse_eval(
+    tokenizer: &mut Tokenizer,
+    interpreter: &mut Interpreter,
+    config: &Config
+) -> Result<Option<LinslExpr>, LinslErr> {
+    let pos = tokenizer.peek_pos().unwrap_or_default();
+    let Some(mut parse_res) = parse_next(tokenizer)? else {
+        return Ok(None);
+    };
+    interpreter.ctx.take_parse_warnings(tokenizer)?;
+    if config.show_ast {
+        eprintln!(";; AST at ({}, {}): {}", pos.0, pos.1, parse_res);
+    }
+    if config.optimize {
+        parse_res = optimize(&parse_res);
+    }
+    let res = interpreter.evaluate(&parse_res)?;
+    Ok(Some(res))
+}
+
+/// Read the files to run, so that a missing one is reported before any of them are run.
+/// Returns an error naming the file if a file does not exist, or is not UTF-8.
+fn get_input(paths: &[String]) -> Result<Vec<Box<dyn BufRead>>, LinslErr> {
+    paths.iter().map(|path| read_file(path)).collect()
+}
+
+/// Why Linsl stopped before it was done, printed by `main` before exiting.
+enum Failure {
+    /// The command line arguments were not understood.
+    Usage(String),
+    /// Running the code failed.
+    Linsl(LinslErr),
+    /// Something around the code failed, e.g. reading from the terminal.
+    Io(String),
+    /// Checking gave warnings, and --deny-warnings was given.
+    Warnings(usize),
+    /// Some of the tests failed. Returns how many.
+    Tests(usize),
+    /// Some of the forms run with --keep-going failed. Returns how many.
+    Forms(usize),
+}
+
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Failure::Usage(_) => ExitCode::from(2),
+            _ => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Usage(e) => write!(f, "{}\nTry \'Linsl --help\' for more information", e),
+            Failure::Io(e) => write!(f, "{}", e),
+            Failure::Linsl(e) => write!(f, "{}", e),
+            Failure::Warnings(n) => write!(f, "Linting failed with {} warning(s)", n),
+            Failure::Tests(n) => write!(f, "{} test(s) failed", n),
+            Failure::Forms(n) => write!(f, "{} form(s) failed", n),
+        }
+    }
+}
+
+impl From<LinslErr> for Failure {
+    fn from(e: LinslErr) -> Self {
+        Failure::Linsl(e)
+    }
+}
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        },
+    }
+}
+
+fn try_main() -> Result<(), Failure> {
+    let config = match parse_args(args().skip(1)).map_err(Failure::Usage)? {
+        Action::Run(config) => config,
+        Action::Help => {
+            print!("{}", HELP);
+            return Ok(());
+        },
+        Action::Version => {
+            println!("Linsl {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        },
+    };
+
+    // The interpreter runs on a thread of its own, since the main thread's stack is not large
+    // enough for the default recursion limit to be reached before the stack overflows (in debug
+    // builds).
+    let interpreter = thread::Builder::new()
+        .stack_size(DEFAULT_STACK_SIZE)
+        .spawn(move || run(&config))
+        .map_err(|e| Failure::Io(format!("Could not start the interpreter: {}", e)))?;
+    // A panic has already been reported by the thread itself.
+    interpreter.join().unwrap_or(Err(Failure::Io("The interpreter crashed".to_string())))
+}
+
+/// Do what the command line asked for. Running files can be followed by the REPL, which shares the
+/// interpreter the files were run with, so everything they defined can be used at the prompt.
+fn run(config: &Config) -> Result<(), Failure> {
+    let (paths, eval, interactive) = match &config.command {
+        Command::Check { paths, deny_warnings } => return check(paths, *deny_warnings),
+        Command::Fmt { paths } => return fmt(paths),
+        Command::Repl => (&[][..], &[][..], true),
+        Command::Test { .. } => (&[][..], &[][..], false),
+        Command::Run { paths, eval, interactive } => {
+            (&paths[..], &eval[..], *interactive || paths.is_empty() && eval.is_empty())
+        },
+    };
+    let interpreter = &mut new_interpreter(config);
+    if let Some(path) = &config.image {
+        load_image(path, &interpreter.env)?;
+    }
+    if let Command::Test { paths } = &config.command {
+        let res = test(interpreter, paths, config);
+        print_profile(interpreter);
+        return res;
+    }
+    let res = script(interpreter, paths, config, true)
+        .and_then(|failed| Ok(failed + eval_code(interpreter, eval, config)?));
+    if !interactive {
+        print_profile(interpreter);
+        return match res? {
+            0 => Ok(()),
+            failed => Err(Failure::Forms(failed)),
         };
-        Ok(vec)
     }
+    match res {
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Starting the REPL, with the definitions made before the error");
+        },
+        Ok(0) => (),
+        Ok(failed) => eprintln!("{}", Failure::Forms(failed)),
+    }
+    let mut reader = LineReader::new();
+    if config.color.enabled(io::stdout().is_terminal()) {
+        reader.highlight(&interpreter.env);
+    }
+    let res = repl(interpreter, config, reader);
+    print_profile(interpreter);
+    res
+}
 
+/// Run the files, printing the value of every expression in them if `print_values` is set. Stops
+/// at the first error, which is returned, unless --keep-going was given; see `run_input`. While a
+/// file is run it is the current file, whose directory `require` searches first.
+fn script(
+    interpreter: &mut Interpreter,
+    paths: &[String],
+    config: &Config,
+    print_values: bool
+) -> Result<usize, LinslErr> {
+    let res = get_input(paths).and_then(|inputs| {
+        let mut failed = 0;
+        for (path, input) in paths.iter().zip(inputs) {
+            interpreter.ctx.current_file =
+                Some(std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path)));
+            failed += run_input(interpreter, input, config, print_values)?;
+        }
+        Ok(failed)
+    });
+    interpreter.ctx.current_file = None;
+    res
 }
 
-fn main() {
-    let env = &mut LinslEnv::default();
-    let mut tkzr = Tokenizer::new(vec![].into()).unwrap();
-    let mut editor = DefaultEditor::new().unwrap();
+/// Run the code given with -e, printing the value of every expression in it.
+fn eval_code(
+    interpreter: &mut Interpreter,
+    code: &[String],
+    config: &Config
+) -> Result<usize, LinslErr> {
+    let mut failed = 0;
+    for code in code {
+        failed += run_input(interpreter, Box::new(Cursor::new(code.clone() + "\n")), config, true)?;
+    }
+    Ok(failed)
+}
 
+/// Run every expression read from the input, printing its value if `print_values` is set. Returns
+/// how many of them failed, which is none unless --keep-going was given: the first error is
+/// returned otherwise. With it, the error is printed along with where the form starts, the rest of
+/// the form is skipped if it could not be parsed, and the next one is run.
+fn run_input(
+    interpreter: &mut Interpreter,
+    input: Box<dyn BufRead>,
+    config: &Config,
+    print_values: bool
+) -> Result<usize, LinslErr> {
+    let mut tkzr = Tokenizer::new(vec![input].into())?;
+    tkzr.readers = interpreter.readers.clone();
+    let mut failed = 0;
     loop {
-        let readline = editor.readline("Linsl> ");
-        match readline {
-            Ok(line) => {
-                if line.is_empty() {
-                    continue;
+        let (line, col) = tkzr.peek_pos().unwrap_or_default();
+        let res = parse_eval(&mut tkzr, interpreter, config);
+        print_warnings(interpreter, Color::Never);
+        match res {
+            Ok(None) => break,
+            Ok(Some(res)) if print_values => println!("{}", res),
+            Ok(Some(_)) => (),
+            Err(e) if config.keep_going => {
+                match &interpreter.ctx.current_file {
+                    Some(path) => eprintln!(
+                        "In the form at ({}, {}) of {}: {}", line, col, path.display(), e
+                    ),
+                    None => eprintln!("In the form at ({}, {}): {}", line, col, e),
                 }
-                if editor.add_history_entry(&line).is_err() {
-                    panic!("Could not add to history!");
-                };
-                let line1 = line + "\n";
-                tkzr.add_input(Box::new(Cursor::new(line1)));
+                tkzr.skip_form()?;
+                failed += 1;
             },
-            Err(err) => {
-                println!("Error: {:?}", err);
-                break;
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(failed)
+}
+
+/// Run the files, and then the tests they defined, printing how each went. Fails if any test
+/// failed.
+fn test(interpreter: &mut Interpreter, paths: &[String], config: &Config) -> Result<(), Failure> {
+    script(interpreter, paths, config, false)?;
+    let results = run_tests(&mut interpreter.ctx)?;
+    print!("{}", report(&results));
+    match results.iter().filter(|(.., res)| res.is_err()).count() {
+        0 => Ok(()),
+        failed => Err(Failure::Tests(failed)),
+    }
+}
+
+/// Lint the files rather than running them, printing the warnings. Fails if there are any and
+/// `deny_warnings` is set.
+fn check(paths: &[String], deny_warnings: bool) -> Result<(), Failure> {
+    let mut count = 0;
+    for input in get_input(paths)? {
+        let mut tkzr = Tokenizer::new(vec![input].into())?;
+        for warning in lint_input(&mut tkzr)? {
+            eprintln!("{}", warning);
+            count += 1;
+        }
+    }
+    if deny_warnings && count > 0 {
+        return Err(Failure::Warnings(count));
+    }
+    Ok(())
+}
+
+/// Print the files indented by how deeply they are nested. A file which does not parse is
+/// reported rather than printed.
+fn fmt(paths: &[String]) -> Result<(), Failure> {
+    for (path, mut input) in paths.iter().zip(get_input(paths)?) {
+        let mut code = String::new();
+        input.read_to_string(&mut code)
+            .map_err(|e| LinslErr::ReadError(Some(path.clone()), e.to_string()))?;
+        let mut tkzr = Tokenizer::new(vec![Box::new(Cursor::new(code.clone())) as _].into())?;
+        while parse_next(&mut tkzr)?.is_some() {}
+        print!("{}", indent_code(&code));
+    }
+    Ok(())
+}
+
+/// Where the REPL reads its lines from: a line editor with history if the terminal supports one,
+/// or else plain lines from stdin, e.g. when there is no controlling terminal.
+enum LineReader {
+    Editor(Box<Editor<LinslHelper, DefaultHistory>>),
+    Plain(Box<dyn BufRead + Send>),
+}
+
+impl LineReader {
+    fn new() -> LineReader {
+        match Editor::new() {
+            Ok(editor) => LineReader::Editor(Box::new(editor)),
+            Err(e) => {
+                eprintln!("Could not start the line editor ({}), reading plain lines instead", e);
+                LineReader::Plain(Box::new(BufReader::new(io::stdin())))
             },
         }
+    }
+
+    /// Highlight the line being edited, if there is a line editor, telling the symbols bound in
+    /// the environment apart from the unknown ones.
+    fn highlight(&mut self, env: &LinslEnv) {
+        if let LineReader::Editor(editor) = self {
+            editor.set_helper(Some(LinslHelper { env: env.clone() }));
+        }
+    }
+
+    /// Show the prompt and read a line, adding it to the history if there is one. Returns None
+    /// once the input is exhausted, or the user has interrupted it.
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>, Failure> {
+        match self {
+            LineReader::Editor(editor) => match editor.readline(prompt) {
+                Ok(line) => {
+                    // Without a history the REPL still works, so it goes on regardless.
+                    if !line.is_empty() && let Err(e) = editor.add_history_entry(&line) {
+                        eprintln!("Could not add to history: {}", e);
+                    }
+                    Ok(Some(line))
+                },
+                Err(ReadlineError::Eof | ReadlineError::Interrupted) => Ok(None),
+                Err(e) => Err(Failure::Io(format!("Could not read a line: {}", e))),
+            },
+            LineReader::Plain(input) => {
+                print!("{}", prompt);
+                let _ = io::stdout().flush();
+                let mut line = String::new();
+                match input.read_line(&mut line) {
+                    Ok(0) => Ok(None),
+                    Ok(_) => {
+                        let len = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(len);
+                        Ok(Some(line))
+                    },
+                    Err(e) => Err(Failure::Io(format!("Could not read a line: {}", e))),
+                }
+            },
+        }
+    }
+}
+
+/// The results of the REPL bound so far, as `$1`, `$2`, and so on.
+#[derive(Default)]
+struct History {
+    count: usize,
+}
+
+impl History {
+    /// Bind a result to the next `$n`, as well as to `$`, returning the name it is bound to. The
+    /// names are bound in the global environment, overwriting whatever was bound to them.
+    fn record(&mut self, interpreter: &Interpreter, val: LinslExpr) -> String {
+        self.count += 1;
+        let name = format!("${}", self.count);
+        interpreter.env.insert(name.as_str(), val.clone());
+        interpreter.env.insert("$", val);
+        name
+    }
+
+    /// Unbind every result, so that they can be freed, and start counting again. Whatever else
+    /// is bound to a `$n` name is unbound as well.
+    fn clear(&mut self, interpreter: &Interpreter) {
+        let is_result = |name: &str| {
+            name.strip_prefix('$').is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+        };
+        interpreter.env.scope_mut().inner.retain(|name, _| !is_result(name.as_str()));
+        self.count = 0;
+    }
+}
+
+/// Parse every form in the code without evaluating them, for `:ast` at the REPL.
+fn parse_forms(code: &str, interpreter: &Interpreter) -> Result<Vec<LinslExpr>, LinslErr> {
+    let input = Box::new(Cursor::new(code.to_string() + "\n"));
+    let mut tkzr = Tokenizer::new(vec![input as _].into())?;
+    tkzr.readers = interpreter.readers.clone();
+    let mut forms = Vec::new();
+    while let Some(form) = parse_next(&mut tkzr)? {
+        forms.push(form);
+    }
+    Ok(forms)
+}
+
+fn repl(
+    interpreter: &mut Interpreter,
+    config: &Config,
+    mut reader: LineReader
+) -> Result<(), Failure> {
+    let mut tkzr = Tokenizer::new(vec![].into())?;
+    tkzr.readers = interpreter.readers.clone();
+    let mut history = History::default();
+    // The work done by the latest evaluation, for `:stats last`.
+    let mut last = None;
+
+    while let Some(line) = reader.read_line("Linsl> ")? {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(args) = line.trim().strip_prefix(":stats") {
+            match args.trim() {
+                "" => println!("{}", interpreter.ctx.stats.snapshot()),
+                "last" => match &last {
+                    Some(last) => println!("{}", last),
+                    None => println!("Nothing has been evaluated yet"),
+                },
+                "reset" => interpreter.ctx.stats = Stats::new(),
+                args => println!("Unknown argument \'{}\', expected last or reset", args),
+            }
+            continue;
+        }
+        if line.trim() == ":clear-history" {
+            history.clear(interpreter);
+            continue;
+        }
+        if let Some(code) = line.trim_start().strip_prefix(":ast ") {
+            match parse_forms(code, interpreter) {
+                Ok(forms) => forms.iter().for_each(|form| println!("{}", form)),
+                Err(e) => println!("{}", e),
+            }
+            continue;
+        }
+        tkzr.add_input(Box::new(Cursor::new(line + "\n")));
 
-        match parse_eval(&mut tkzr, env) {
-            Ok(res) => println!("{}", res),
-            Err(e) => println!("{}", e),
+        // Every form on the line is evaluated. The rest of a form which fails is skipped, and the
+        // REPL goes on with the next one.
+        loop {
+            let measurement = interpreter.ctx.stats.measure();
+            let res = parse_eval(&mut tkzr, interpreter, config);
+            let stats = measurement.finish(&mut interpreter.ctx.stats);
+            match res {
+                Ok(None) => break,
+                Ok(Some(res)) => {
+                    let name = history.record(interpreter, res.clone());
+                    println!("{} = {}", name, res.limited(interpreter.ctx.print_limits));
+                },
+                Err(e) => {
+                    println!("{}", e);
+                    if let Err(e) = tkzr.skip_form() {
+                        println!("{}", e);
+                    }
+                },
+            }
+            last = Some(stats);
+            print_warnings(interpreter, config.color);
         }
     }
+    Ok(())
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_line_reader() {
+        let mut reader = LineReader::Plain(Box::new(Cursor::new("(+ 1 2)\r\n\n(car\n")));
+        assert_eq!(Some("(+ 1 2)".to_string()), reader.read_line("").ok().flatten());
+        assert_eq!(Some("".to_string()), reader.read_line("").ok().flatten());
+        assert_eq!(Some("(car".to_string()), reader.read_line("").ok().flatten());
+        assert_eq!(None, reader.read_line("").ok().flatten());
+
+        // The REPL runs on a plain reader as well.
+        let mut interpreter = Interpreter::default();
+        let input = "(define x 2)\n:stats\n:stats last\n:stats reset\n:stats x\n\n";
+        let reader = LineReader::Plain(Box::new(Cursor::new(input)));
+        assert!(repl(&mut interpreter, &Config::default(), reader).is_ok());
+        assert_eq!("2", interpreter.eval_str("x").unwrap().to_string());
+    }
+
+    #[test]
+    fn result_history() {
+        // Every result is bound to the next $n and to $, and errors are not bound.
+        let mut interpreter = Interpreter::default();
+        let reader = LineReader::Plain(Box::new(Cursor::new("(list 1 2)\n(car)\n(cdr $1)\n")));
+        assert!(repl(&mut interpreter, &Config::default(), reader).is_ok());
+        for (name, val) in [("$1", "(1 2)"), ("$2", "(2)"), ("$", "(2)")] {
+            assert_eq!(val, interpreter.eval_str(name).unwrap().to_string());
+        }
+        assert!(interpreter.eval_str("$3").is_err());
+
+        // Clearing the history unbinds the results, and counts from $1 again.
+        let reader = LineReader::Plain(Box::new(Cursor::new("3\n:clear-history\n4\n")));
+        assert!(repl(&mut interpreter, &Config::default(), reader).is_ok());
+        assert_eq!("4", interpreter.eval_str("$1").unwrap().to_string());
+        assert_eq!("4", interpreter.eval_str("$").unwrap().to_string());
+        assert!(interpreter.eval_str("$2").is_err());
+    }
+
+    #[test]
+    fn every_form_on_a_line() {
+        // The forms after one which fails are still run, and a form left open is not run.
+        let mut interpreter = Interpreter::default();
+        let reader = LineReader::Plain(Box::new(Cursor::new("1 (car) 2\n(list 3\n4\n")));
+        assert!(repl(&mut interpreter, &Config::default(), reader).is_ok());
+        for (name, val) in [("$1", "1"), ("$2", "2"), ("$3", "4")] {
+            assert_eq!(val, interpreter.eval_str(name).unwrap().to_string());
+        }
+    }
+}
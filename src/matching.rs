@@ -0,0 +1,247 @@
+//! Pattern matching for the special form `match`, which evaluates the first of its clauses whose
+//! pattern matches a value:
+//!
+//! (match x (pattern body ...) (pattern #:when test body ...) ...)
+//!
+//! A pattern is one of
+//! - `_`, which matches anything and binds nothing,
+//! - a symbol, which matches anything and binds it. A symbol appearing twice in a pattern only
+//!   matches values which are equal? at both places,
+//! - a number, string, bool or keyword, or a quoted datum such as 'a or '(1 2), which matches the
+//!   values equal? to it, and
+//! - a list of patterns, which matches a list of as many elements, each matching the pattern at
+//!   its place. As in a list of parameters, a last pattern preceded by a `.` matches the list of
+//!   the elements left over, so (h . t) matches any list with at least one element.
+//!
+//! Since every symbol is a variable, a symbol is matched by quoting it; the pattern x matches 'x
+//! and anything else, while 'x only matches 'x.
+//!
+//! The clauses are tried in order. A clause with a guard is only chosen if its test, evaluated
+//! with the symbols of the pattern bound, is true. The body of the clause chosen is evaluated in
+//! the same scope, and the value of its last form is the value of the match. If no clause is
+//! chosen, the match fails with a MatchError. Both engines read the clauses and choose one using
+//! this module, and only differ in how they evaluate the guards and the bodies.
+
+use crate::datatypes::{LinslEnv, LinslErr, LinslExpr, Pos, Symbol};
+use crate::evaluation::check_bindable;
+use crate::primitives::is_equal;
+
+/// A pattern, read from the form it is written as.
+#[derive(Debug)]
+pub(crate) enum Pattern {
+    /// `_`, matching anything.
+    Wildcard,
+    /// A symbol, matching anything and binding it.
+    Variable(Symbol),
+    /// A self-evaluating or quoted datum, matching the values equal? to it.
+    Literal(LinslExpr),
+    /// A list of patterns, along with the pattern for the elements left over, if there is one.
+    List(Vec<Pattern>, Option<Box<Pattern>>),
+}
+
+/// A clause of a match.
+#[derive(Debug)]
+pub(crate) struct MatchClause {
+    pub(crate) pattern: Pattern,
+    /// The test following `#:when`, if there is one.
+    pub(crate) guard: Option<LinslExpr>,
+    /// The forms of the body, of which there is at least one.
+    pub(crate) body: Vec<LinslExpr>,
+}
+
+/// Split the forms of a match into the expression whose value is matched and the clauses, or give
+/// the message to fail with if they are malformed.
+pub(crate) fn match_forms(forms: &[LinslExpr]) -> Result<(&LinslExpr, Vec<MatchClause>), String> {
+    let Some((expr, clauses)) = forms.split_first() else {
+        return Err("match must be given an expression followed by clauses".to_string());
+    };
+    Ok((expr, clauses.iter().map(read_clause).collect::<Result<_, _>>()?))
+}
+
+fn read_clause(form: &LinslExpr) -> Result<MatchClause, String> {
+    let parts = match form {
+        LinslExpr::List(parts) => &parts[..],
+        _ => &[],
+    };
+    let (pattern, guard, body) = match parts {
+        [pattern, LinslExpr::Keyword(k), guard, body @ ..] if k == "when" && !body.is_empty() => {
+            (pattern, Some(guard.clone()), body)
+        },
+        [_, LinslExpr::Keyword(k), ..] if k == "when" => return Err(
+            format!("Expected a clause (pattern #:when test body ...), found \'{}\'", form)
+        ),
+        [pattern, body @ ..] if !body.is_empty() => (pattern, None, body),
+        _ => return Err(format!("Expected a clause (pattern body ...), found \'{}\'", form)),
+    };
+    Ok(MatchClause { pattern: read_pattern(pattern)?, guard, body: body.to_vec() })
+}
+
+/// Read a pattern, giving the message to fail with if it is malformed.
+fn read_pattern(expr: &LinslExpr) -> Result<Pattern, String> {
+    match expr {
+        LinslExpr::Symbol(s) if s == "_" => Ok(Pattern::Wildcard),
+        LinslExpr::Symbol(s) if s == "." => Err(
+            "A '.' in a pattern must be followed by exactly one pattern".to_string()
+        ),
+        LinslExpr::Symbol(s) => {
+            check_bindable(s)?;
+            Ok(Pattern::Variable(s.clone()))
+        },
+        LinslExpr::Bool(_)
+        | LinslExpr::Keyword(_)
+        | LinslExpr::Number(_)
+        | LinslExpr::Complex(..)
+        | LinslExpr::String(_) => Ok(Pattern::Literal(expr.clone())),
+        LinslExpr::List(exprs) => match &exprs[..] {
+            [LinslExpr::Symbol(s), datum] if s == "quote" => Ok(Pattern::Literal(datum.clone())),
+            [fixed @ .., LinslExpr::Symbol(dot), rest] if dot == "." => {
+                Ok(Pattern::List(read_patterns(fixed)?, Some(Box::new(read_pattern(rest)?))))
+            },
+            _ => Ok(Pattern::List(read_patterns(exprs)?, None)),
+        },
+        _ => Err(format!("Expected a pattern, found \'{}\'", expr)),
+    }
+}
+
+fn read_patterns(exprs: &[LinslExpr]) -> Result<Vec<Pattern>, String> {
+    exprs.iter().map(read_pattern).collect()
+}
+
+impl Pattern {
+    /// The symbols the pattern binds.
+    pub(crate) fn symbols(&self) -> Vec<Symbol> {
+        fn collect(pattern: &Pattern, symbols: &mut Vec<Symbol>) {
+            match pattern {
+                Pattern::Variable(s) if !symbols.contains(s) => symbols.push(s.clone()),
+                Pattern::List(fixed, rest) => {
+                    fixed.iter().chain(rest.as_deref()).for_each(|p| collect(p, symbols))
+                },
+                _ => (),
+            }
+        }
+        let mut symbols = Vec::new();
+        collect(self, &mut symbols);
+        symbols
+    }
+
+    /// Whether `val` matches the pattern. The symbols bound are added to `bindings`, also if it
+    /// turns out not to match.
+    fn matches(&self, val: &LinslExpr, bindings: &mut Vec<(Symbol, LinslExpr)>) -> bool {
+        match self {
+            Pattern::Wildcard => true,
+            Pattern::Variable(s) => match bindings.iter().find(|(bound, _)| bound == s) {
+                Some((_, earlier)) => is_equal(earlier, val),
+                None => {
+                    bindings.push((s.clone(), val.clone()));
+                    true
+                },
+            },
+            Pattern::Literal(datum) => is_equal(datum, val),
+            Pattern::List(fixed, rest) => {
+                let LinslExpr::List(vals) = val else {
+                    return false;
+                };
+                let fits = match rest {
+                    Some(_) => vals.len() >= fixed.len(),
+                    None => vals.len() == fixed.len(),
+                };
+                if !fits {
+                    return false;
+                };
+                for (pattern, val) in fixed.iter().zip(vals.iter()) {
+                    if !pattern.matches(val, bindings) {
+                        return false;
+                    };
+                }
+                match rest {
+                    Some(rest) => {
+                        let left_over = LinslExpr::List(vals[fixed.len()..].to_vec().into());
+                        rest.matches(&left_over, bindings)
+                    },
+                    None => true,
+                }
+            },
+        }
+    }
+}
+
+/// Choose the clause for `val`, given the patterns of the clauses in order. Returns the index of
+/// the first clause whose pattern matches and whose guard, if it has one, `test` finds true, along
+/// with a new frame on top of `env` binding the symbols of its pattern. `test` is given the index
+/// of the clause and that frame. If no clause is chosen, the MatchError is reported at `pos`, the
+/// position of the match.
+pub(crate) fn choose_clause<'a>(
+    patterns: impl IntoIterator<Item = &'a Pattern>,
+    val: &LinslExpr,
+    env: &LinslEnv,
+    pos: Pos,
+    mut test: impl FnMut(usize, &LinslEnv) -> Result<bool, LinslErr>
+) -> Result<(usize, LinslEnv), LinslErr> {
+    let mut bindings = Vec::new();
+    for (i, pattern) in patterns.into_iter().enumerate() {
+        bindings.clear();
+        if !pattern.matches(val, &mut bindings) {
+            continue;
+        };
+        let scope = LinslEnv::new(env);
+        for (s, val) in bindings.drain(..) {
+            scope.insert(s, val);
+        }
+        if test(i, &scope)? {
+            return Ok((i, scope));
+        };
+    }
+    Err(LinslErr::MatchError(val.to_string(), pos))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, Cursor};
+
+    use super::*;
+    use crate::parsing::{parse, Tokenizer};
+
+    fn parse_str(s: &str) -> LinslExpr {
+        let input: Box<dyn BufRead> = Box::new(Cursor::new(format!("{}\n", s)));
+        parse(&mut Tokenizer::new(vec![input].into()).unwrap()).unwrap()
+    }
+
+    /// The bindings made by matching `val` against `pattern`, or None if it does not match.
+    fn bindings(pattern: &str, val: &str) -> Option<Vec<String>> {
+        let pattern = read_pattern(&parse_str(pattern)).unwrap();
+        let mut bindings = Vec::new();
+        pattern.matches(&parse_str(val), &mut bindings).then(|| {
+            bindings.iter().map(|(s, val)| format!("{}={}", s, val)).collect()
+        })
+    }
+
+    #[test]
+    fn matches_patterns() {
+        assert_eq!(Some(vec![]), bindings("_", "(1 2)"));
+        assert_eq!(Some(vec!["x=(1 2)".to_string()]), bindings("x", "(1 2)"));
+        assert_eq!(Some(vec![]), bindings("1", "1"));
+        assert_eq!(None, bindings("1", "2"));
+        assert_eq!(Some(vec![]), bindings("'a", "a"));
+        assert_eq!(None, bindings("'a", "b"));
+        assert_eq!(Some(vec![]), bindings("'(1 2)", "(1 2)"));
+        assert_eq!(
+            Some(vec!["a=1".to_string(), "c=3".to_string()]),
+            bindings("(a (_ c))", "(1 (2 3))")
+        );
+        assert_eq!(None, bindings("(a b)", "(1 2 3)"));
+        assert_eq!(
+            Some(vec!["h=1".to_string(), "t=(2 3)".to_string()]),
+            bindings("(h . t)", "(1 2 3)")
+        );
+        assert_eq!(None, bindings("(h . t)", "()"));
+        assert_eq!(Some(vec!["x=1".to_string()]), bindings("(x x)", "(1 1)"));
+        assert_eq!(None, bindings("(x x)", "(1 2)"));
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        for pattern in ["(a . b c)", "(a .)", ".", "if", "(1 (lambda))"] {
+            assert!(read_pattern(&parse_str(pattern)).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Modules, which keep the definitions of a piece of code apart from everything else.
+//!
+//! `(module name (export a b) body ...)` evaluates the body in a frame of its own, nested in the
+//! environment the module form is evaluated in, and registers the module in the context under its
+//! name. `(import name)` then binds the exported names in the current frame, and
+//! `(import (name prefix-))` does the same with every name prefixed. Everything else the body
+//! defines stays private to the module, even to the code importing it. Like the stream forms, the
+//! module forms are shared by both engines.
+//!
+//! `(require 'name)` loads the file `name.linsl`, which usually defines a module of the same name,
+//! looking for it in the directory of the file being run and then in the library path. A file is
+//! only loaded once, however many times it is required.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::datatypes::{LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslList, LinslRes, Symbol};
+use crate::evaluation::{check_bindable, define, evaluate, run_program};
+use crate::parsing::{parse_list_of_symbols, read_file, Tokenizer};
+use crate::primitives::is_eqv;
+
+/// The directory `require` searches last. It can be set with the `LINSL_LIBRARY_DIR` environment
+/// variable when building Linsl.
+pub const DEFAULT_LIBRARY_DIR: &str = match option_env!("LINSL_LIBRARY_DIR") {
+    Some(dir) => dir,
+    None => "/usr/local/share/linsl",
+};
+
+/// A module defined by a module form.
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// The frame the body of the module was evaluated in, holding everything it defined.
+    pub env: LinslEnv,
+    /// The names other code can import.
+    pub exports: Vec<Symbol>,
+    /// The prefixes the module has been imported with, which is how an undefined symbol can be
+    /// recognized as a private name of the module.
+    prefixes: Vec<String>,
+}
+
+/// The modules defined so far, by name.
+pub type Modules = HashMap<Symbol, Module>;
+
+fn syntax_error(msg: String) -> LinslErr {
+    // TODO: Fix pos
+    LinslErr::SyntaxError(msg, (0, 0))
+}
+
+/// Evaluation of the special form "module". Returns the name of the module. Defining a module with
+/// the name of an existing one replaces it, but code which has already imported the old one keeps
+/// its bindings.
+pub(crate) fn evaluate_module(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let (name, exports, body) = match exprs {
+        [LinslExpr::Symbol(name), LinslExpr::List(export), body @ ..]
+            if export.first() == Some(&LinslExpr::Symbol(Symbol::new("export"))) =>
+        {
+            let exports = parse_list_of_symbols(&LinslExpr::List(export.tail()))?;
+            (name, exports, body)
+        },
+        _ => return Err(
+            syntax_error("module must have a name and an (export name ...) list".to_string())
+        ),
+    };
+    for (i, export) in exports.iter().enumerate() {
+        check_bindable(export).map_err(syntax_error)?;
+        if exports[..i].contains(export) {
+            return Err(
+                syntax_error(format!("Module \'{}\' exports \'{}\' twice", name, export))
+            );
+        };
+    }
+
+    let module_env = LinslEnv::new(env);
+    for form in body {
+        evaluate(form, &module_env, ctx)?;
+    }
+    // Every name exported must have been defined by the body itself.
+    if let Some(missing) = exports.iter().find(|e| !module_env.scope().inner.contains_key(*e)) {
+        return Err(
+            syntax_error(
+                format!("Module \'{}\' exports \'{}\', which it does not define", name, missing)
+            )
+        );
+    };
+
+    ctx.modules.insert(name.clone(), Module { env: module_env, exports, prefixes: Vec::new() });
+    Ok(LinslExpr::Symbol(name.clone()))
+}
+
+/// Evaluation of the special form "import". Every form is either the name of a module, or a list
+/// of the name of a module and a prefix. Importing the same module again has no effect, unless it
+/// has been redefined since. Returns the name of the last module imported.
+pub(crate) fn evaluate_import(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    if exprs.is_empty() {
+        return Err(syntax_error("import must have at least one module to import".to_string()));
+    };
+
+    let mut res = LinslExpr::List(LinslList::default());
+    for spec in exprs {
+        let (name, prefix) = import_spec(spec).map_err(syntax_error)?;
+        let module = ctx.modules.get_mut(name)
+            .ok_or_else(|| syntax_error(format!("Unknown module \'{}\'", name)))?;
+        if !module.prefixes.iter().any(|p| p == prefix) {
+            module.prefixes.push(prefix.to_string());
+        };
+
+        let bindings: Vec<(Symbol, LinslExpr)> = {
+            let scope = module.env.scope();
+            module.exports.iter()
+                .filter_map(|e| {
+                    let val = scope.inner.get(e)?.val.clone();
+                    Some((Symbol::new(&format!("{}{}", prefix, e)), val))
+                })
+                .collect()
+        };
+        for (local, val) in bindings {
+            check_bindable(&local).map_err(syntax_error)?;
+            let imported = env.scope().inner.get(&local).is_some_and(|old| is_eqv(&old.val, &val));
+            if !imported {
+                define(&local, val, env, ctx)?;
+            };
+        }
+        res = LinslExpr::Symbol(name.clone());
+    }
+    Ok(res)
+}
+
+/// The name of the module and the prefix an import form asks for.
+fn import_spec(spec: &LinslExpr) -> Result<(&Symbol, &str), String> {
+    match spec {
+        LinslExpr::Symbol(name) => return Ok((name, "")),
+        LinslExpr::List(l) => {
+            if let [LinslExpr::Symbol(name), LinslExpr::Symbol(prefix)] = &l[..] {
+                return Ok((name, prefix.as_str()));
+            };
+        },
+        _ => (),
+    };
+    Err(format!("Expected the name of a module or a (module prefix) list, found \'{}\'", spec))
+}
+
+/// The directories in the `LINSL_PATH` environment variable, which are separated by colons (or
+/// semicolons on Windows), followed by `DEFAULT_LIBRARY_DIR`.
+pub(crate) fn default_library_path() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = match env::var_os("LINSL_PATH") {
+        Some(path) => env::split_paths(&path).filter(|dir| !dir.as_os_str().is_empty()).collect(),
+        None => Vec::new(),
+    };
+    dirs.push(PathBuf::from(DEFAULT_LIBRARY_DIR));
+    dirs
+}
+
+/// Evaluation of the special form "require". `(require name)` evaluates name, which must give a
+/// symbol, and loads the file for it by evaluating every expression in it, unless it has already
+/// been loaded. Returns the name.
+pub(crate) fn evaluate_require(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    match exprs {
+        [form] => {
+            let name = evaluate(form, env, ctx)?;
+            require(&name, env, ctx)
+        },
+        _ => Err(
+            LinslErr::SyntaxError(
+                format!("require must have one form, found {}", exprs.len()),
+                ctx.pos
+            )
+        ),
+    }
+}
+
+/// Load the file for the name given, if it has not been loaded already, evaluating it in `env`.
+/// The file is `name.linsl`, in the directory of the current file (or the working directory if
+/// there is none), or else in the first directory of the library path which has one.
+pub(crate) fn require(name: &LinslExpr, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("require")?;
+    let LinslExpr::Symbol(name) = name else {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos
+                format!("require expected a symbol, found \'{}\'", name),
+                (0, 0)
+            )
+        );
+    };
+    if ctx.required.contains(name) {
+        return Ok(LinslExpr::Symbol(name.clone()));
+    };
+
+    let current_dir = ctx.current_file.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+    let file = format!("{}.linsl", name);
+    let candidates: Vec<PathBuf> = [current_dir].into_iter()
+        .chain(ctx.library_path.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(&file))
+        .collect();
+    let Some(path) = candidates.iter().find(|path| path.is_file()) else {
+        let searched = candidates.iter().map(|path| path.display().to_string()).collect();
+        return Err(LinslErr::ModuleNotFound(name.to_string(), searched));
+    };
+
+    // The name counts as required while the file is loaded, so that files requiring each other
+    // are only loaded once. If loading fails, the file can be required again.
+    ctx.required.insert(name.clone());
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+    let outer = ctx.current_file.replace(path.clone());
+    let res = load(&path, env, ctx);
+    ctx.current_file = outer;
+    if res.is_err() {
+        ctx.required.remove(name);
+    };
+    res.map(|()| LinslExpr::Symbol(name.clone()))
+}
+
+/// Evaluate every expression in a file, in order.
+fn load(path: &Path, env: &LinslEnv, ctx: &mut LinslCtx) -> Result<(), LinslErr> {
+    let input = read_file(&path.display().to_string())?;
+    let mut tokenizer = Tokenizer::new(vec![input].into())?;
+    run_program(&mut tokenizer, env, ctx)?;
+    Ok(())
+}
+
+/// The error for looking up a symbol which is not bound. If it is the name of something a module
+/// imported under that prefix defines but does not export, the error says so.
+pub(crate) fn undefined_symbol(s: &Symbol, ctx: &LinslCtx) -> LinslErr {
+    // The names are compared as strings, so that no symbols are created for them.
+    let hides = |module: &Module, name: &str| {
+        module.env.scope().inner.keys().any(|k| k.as_str() == name)
+            && !module.exports.iter().any(|e| e.as_str() == name)
+    };
+    let mut hidden_by: Vec<&Symbol> = ctx.modules.iter()
+        .filter(|(_, module)| {
+            module.prefixes.iter().any(|prefix| {
+                s.as_str().strip_prefix(prefix.as_str()).is_some_and(|name| hides(module, name))
+            })
+        })
+        .map(|(name, _)| name)
+        .collect();
+    hidden_by.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    let msg = match hidden_by.first() {
+        Some(module) => format!(
+            "Undefined symbol \'{}\', which module \'{}\' does not export", s, module
+        ),
+        None => format!("Undefined symbol \'{}\'", s),
+    };
+    syntax_error(msg)
+}
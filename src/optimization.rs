@@ -0,0 +1,375 @@
+//! An optional optimization pass, simplifying expressions after they have been parsed but before
+//! they are evaluated.
+
+use crate::datatypes::{LinslExpr, LinslList, LinslRes, Symbol};
+use crate::evaluation::is_truthy;
+use crate::primitives::{add, append, list, mul, neg};
+use crate::streams::StreamForm;
+
+/// Simplify an expression without changing what it evaluates to. Specifically,
+/// - applications of the pure primitives +, *, neg, list and append to constant arguments are
+///   replaced by their results, and
+/// - ifs with a constant test are replaced by the branch which would be taken.
+///
+/// Only the arguments of those primitives and of special forms are simplified. The arguments of any
+/// other application are left alone, since what is applied may turn out to be a macro, which
+/// takes its arguments as data. Anything depending on the value of a symbol is left alone too, as
+/// are quoted expressions, the parameters of lambdas, macros and the clauses of case-lambdas, the
+/// names of define-values, the patterns of let and let-values bindings and of match clauses, the
+/// variables of do loops, the bindings of parameterize forms, record definitions, the exports of
+/// modules and imports. A primitive is not folded where its name is bound by the expression
+/// itself, whether as a parameter, by a let or by a define. Note though that the primitives named
+/// above are otherwise assumed to be bound to their usual names; code redefining e.g. + in an
+/// earlier form should not be optimized.
+pub fn optimize(expr: &LinslExpr) -> LinslExpr {
+    let mut shadowed = Vec::new();
+    add_defined_names(expr, &mut shadowed);
+    optimize_in(expr, &shadowed)
+}
+
+/// The names of the primitives which are folded.
+const FOLDED: &[&str] = &["+", "*", "neg", "list", "append"];
+
+/// Optimize an expression in which the folded primitives named in `shadowed` are bound by the code
+/// around it, and so are not folded.
+fn optimize_in(expr: &LinslExpr, shadowed: &[Symbol]) -> LinslExpr {
+    match expr {
+        LinslExpr::List(exprs) => optimize_list(exprs, shadowed),
+        _ => expr.clone(),
+    }
+}
+
+fn optimize_list(exprs: &LinslList, shadowed: &[Symbol]) -> LinslExpr {
+    let Some(LinslExpr::Symbol(head)) = exprs.first() else {
+        // Whatever the head evaluates to may be a macro.
+        return LinslExpr::List(exprs.clone());
+    };
+    if let [_, LinslExpr::List(bindings), body @ ..] = &exprs[..]
+        && matches!(head.as_str(), "let" | "let-values")
+    {
+        return optimize_let(&exprs[0], bindings, body, shadowed);
+    };
+    if let [_, params, body @ ..] = &exprs[..]
+        && matches!(head.as_str(), "lambda" | "macro")
+    {
+        let inner = scope(shadowed, params, body);
+        let body = body.iter().map(|expr| optimize_in(expr, &inner));
+        return LinslExpr::List(exprs[..2].iter().cloned().chain(body).collect());
+    };
+    if head == "case-lambda" {
+        let clauses = exprs[1..].iter().map(|clause| optimize_clause(clause, shadowed));
+        return LinslExpr::List(std::iter::once(exprs[0].clone()).chain(clauses).collect());
+    };
+    if let [_, LinslExpr::List(vars), LinslExpr::List(clause), body @ ..] = &exprs[..]
+        && head == "do"
+    {
+        let mut inner = shadowed.to_vec();
+        for var in vars.iter() {
+            if let LinslExpr::List(parts) = var && let Some(name) = parts.first() {
+                add_folded_names(name, &mut inner);
+            };
+        }
+        for expr in exprs[2..].iter() {
+            add_defined_names(expr, &mut inner);
+        }
+        // The initial values are optimized in the scope around the loop, and the steps in the one
+        // it makes.
+        let vars = vars.iter().map(|var| match var {
+            LinslExpr::List(parts) if parts.len() >= 2 => LinslExpr::List(
+                [parts[0].clone(), optimize_in(&parts[1], shadowed)].into_iter()
+                    .chain(parts[2..].iter().map(|step| optimize_in(step, &inner)))
+                    .collect()
+            ),
+            _ => var.clone(),
+        });
+        // The test and result forms are optimized one by one, as the clause is not an application.
+        let mut optimized = vec![
+            exprs[0].clone(),
+            LinslExpr::List(vars.collect()),
+            LinslExpr::List(clause.iter().map(|expr| optimize_in(expr, &inner)).collect()),
+        ];
+        optimized.extend(body.iter().map(|expr| optimize_in(expr, &inner)));
+        return LinslExpr::List(optimized.into());
+    };
+    if let [_, expr, clauses @ ..] = &exprs[..]
+        && head == "match"
+    {
+        let mut optimized = vec![exprs[0].clone(), optimize_in(expr, shadowed)];
+        optimized.extend(clauses.iter().map(|clause| optimize_clause(clause, shadowed)));
+        return LinslExpr::List(optimized.into());
+    };
+
+    // First, find out how many of the leading elements must be kept as they are. These are the
+    // name of a special form along with whatever it does not evaluate. Only the arguments of the
+    // special forms and of the folded primitives are optimized.
+    let keep = match head.as_str() {
+        "define" | "defconst" | "define-test" | "define-values" | "parameterize" => 2,
+        "module" => 3,
+        "if" | "while" | "delay" | "force" => 1,
+        name if FOLDED.contains(&name) && !shadowed.contains(head) => 1,
+        name if StreamForm::from_name(name).is_some() => 1,
+        _ => return LinslExpr::List(exprs.clone()),
+    };
+    let keep = keep.min(exprs.len());
+
+    // Then optimize the rest, innermost expressions first, so that constants propagate outwards.
+    let mut optimized: Vec<LinslExpr> = exprs[..keep].to_vec();
+    for expr in &exprs[keep..] {
+        optimized.push(optimize_in(expr, shadowed));
+    }
+
+    // Finally, check if the form itself can be simplified.
+    let primitive: fn(&[LinslExpr]) -> LinslRes = match head.as_str() {
+        "if" => return optimize_if(optimized),
+        "+" => add,
+        "*" => mul,
+        "neg" => neg,
+        "list" => list,
+        "append" => append,
+        _ => return LinslExpr::List(optimized.into()),
+    };
+
+    let args: Option<Vec<LinslExpr>> = optimized[1..].iter().map(constant_value).collect();
+    match args.map(|args| primitive(&args)) {
+        Some(Ok(val)) => constant_expr(val),
+        // If the application would cause an error, it is left for the evaluation to report it.
+        _ => LinslExpr::List(optimized.into()),
+    }
+}
+
+/// Replace an if with a constant test by the branch it would take. An if without an else branch
+/// would return the empty list.
+fn optimize_if(mut forms: Vec<LinslExpr>) -> LinslExpr {
+    if forms.len() == 3 || forms.len() == 4 {
+        match (constant_value(&forms[1]).map(|test| is_truthy(&test)), forms.len()) {
+            (Some(true), _) => return forms.swap_remove(2),
+            (Some(false), 4) => return forms.swap_remove(3),
+            (Some(false), _) => return constant_expr(LinslExpr::List(LinslList::default())),
+            (None, _) => (),
+        }
+    };
+    LinslExpr::List(forms.into())
+}
+
+/// Optimize a let or let-values, leaving alone the pattern each binding starts with, which is not
+/// evaluated; in particular (let ((+ 3)) ...) binds + rather than having (+ 3) folded. The values
+/// are optimized in the scope around the let, and the body in the one it makes.
+fn optimize_let(
+    head: &LinslExpr,
+    bindings: &LinslList,
+    body: &[LinslExpr],
+    shadowed: &[Symbol]
+) -> LinslExpr {
+    let mut inner = shadowed.to_vec();
+    for binding in bindings.iter() {
+        if let LinslExpr::List(parts) = binding && let Some(pattern) = parts.first() {
+            add_folded_names(pattern, &mut inner);
+        };
+    }
+    for expr in body {
+        add_defined_names(expr, &mut inner);
+    }
+    let bindings = bindings.iter().map(|binding| optimize_after_pattern(binding, shadowed));
+    let mut optimized = vec![head.clone(), LinslExpr::List(bindings.collect())];
+    optimized.extend(body.iter().map(|expr| optimize_in(expr, &inner)));
+    LinslExpr::List(optimized.into())
+}
+
+/// Optimize a clause of a case-lambda or match, whose forms are in the scope of the pattern it
+/// starts with.
+fn optimize_clause(clause: &LinslExpr, shadowed: &[Symbol]) -> LinslExpr {
+    match clause {
+        LinslExpr::List(forms) if !forms.is_empty() => {
+            optimize_after_pattern(clause, &scope(shadowed, &forms[0], &forms[1..]))
+        },
+        _ => clause.clone(),
+    }
+}
+
+/// Optimize the forms of a list but the first, which is a pattern.
+fn optimize_after_pattern(expr: &LinslExpr, shadowed: &[Symbol]) -> LinslExpr {
+    match expr {
+        LinslExpr::List(forms) if !forms.is_empty() => LinslExpr::List(
+            std::iter::once(forms[0].clone())
+                .chain(forms[1..].iter().map(|expr| optimize_in(expr, shadowed)))
+                .collect()
+        ),
+        _ => expr.clone(),
+    }
+}
+
+/// The folded primitives which are bound in a body, given those bound around it, the pattern
+/// binding its parameters, and its forms, which may define more.
+fn scope(shadowed: &[Symbol], pattern: &LinslExpr, body: &[LinslExpr]) -> Vec<Symbol> {
+    let mut inner = shadowed.to_vec();
+    add_folded_names(pattern, &mut inner);
+    for expr in body {
+        add_defined_names(expr, &mut inner);
+    }
+    inner
+}
+
+/// Add the folded primitives named anywhere in a pattern to `names`.
+fn add_folded_names(pattern: &LinslExpr, names: &mut Vec<Symbol>) {
+    match pattern {
+        LinslExpr::Symbol(s) if FOLDED.contains(&s.as_str()) && !names.contains(s) => {
+            names.push(s.clone())
+        },
+        LinslExpr::List(exprs) => {
+            for expr in exprs.iter() {
+                add_folded_names(expr, names);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Add the folded primitives defined anywhere within `expr` to `names`. Nested scopes are looked
+/// into as well, which at worst means folding less.
+fn add_defined_names(expr: &LinslExpr, names: &mut Vec<Symbol>) {
+    let LinslExpr::List(exprs) = expr else {
+        return;
+    };
+    if let [LinslExpr::Symbol(s), target, ..] = &exprs[..]
+        && matches!(s.as_str(), "define" | "defconst" | "define-values")
+    {
+        add_folded_names(target, names);
+    };
+    for expr in exprs.iter() {
+        add_defined_names(expr, names);
+    }
+}
+
+/// The value of an expression, if it is known without evaluating it, i.e. if the expression is a
+/// number, a bool, a keyword or quoted.
+fn constant_value(expr: &LinslExpr) -> Option<LinslExpr> {
+    match expr {
+        LinslExpr::Number(_)
+        | LinslExpr::Complex(..)
+        | LinslExpr::Bool(_)
+        | LinslExpr::Keyword(_) => Some(expr.clone()),
+        LinslExpr::List(exprs) => match &exprs[..] {
+            [LinslExpr::Symbol(s), quoted] if s == "quote" => Some(quoted.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// An expression evaluating to `val`.
+fn constant_expr(val: LinslExpr) -> LinslExpr {
+    match val {
+        LinslExpr::Number(_)
+        | LinslExpr::Complex(..)
+        | LinslExpr::Bool(_)
+        | LinslExpr::Keyword(_) => val,
+        _ => LinslExpr::List(vec![LinslExpr::Symbol(Symbol::new("quote")), val].into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, Cursor};
+
+    use super::*;
+    use crate::datatypes::Engine;
+    use crate::interpreter::Interpreter;
+    use crate::parsing::{parse, Tokenizer};
+
+    fn optimize_str(s: &str) -> String {
+        let input: Box<dyn BufRead> = Box::new(Cursor::new(format!("{}\n", s)));
+        let mut tokenizer = Tokenizer::new(vec![input].into()).unwrap();
+        optimize(&parse(&mut tokenizer).unwrap()).to_string()
+    }
+
+    #[test]
+    fn folds_constant_applications() {
+        assert_eq!("6", optimize_str("(+ 1 2 3)"));
+        assert_eq!("6", optimize_str("(* 2 (+ 1 2))"));
+        assert_eq!("-3", optimize_str("(neg (+ 1 2))"));
+        assert_eq!("(quote (1 2))", optimize_str("(list 1 2)"));
+        assert_eq!("(quote (1 2))", optimize_str("(append '(1) '(2))"));
+        assert_eq!("(quote (a (b c)))", optimize_str("`(a (b c))"));
+        assert_eq!("(quote ((quote a)))", optimize_str("(list ''a)"));
+    }
+
+    #[test]
+    fn collapses_constant_ifs() {
+        assert_eq!("a", optimize_str("(if #t a b)"));
+        assert_eq!("b", optimize_str("(if #f a b)"));
+        assert_eq!("3", optimize_str("(if #t (+ 1 2) b)"));
+        // Every constant but #f is true.
+        assert_eq!("a", optimize_str("(if 0 a b)"));
+        assert_eq!("a", optimize_str("(if '() a b)"));
+        assert_eq!("a", optimize_str("(if #t a)"));
+        assert_eq!("(quote ())", optimize_str("(if #f a)"));
+        assert_eq!("(if (> x 1) 3 b)", optimize_str("(if (> x 1) (+ 1 2) b)"));
+    }
+
+    #[test]
+    fn leaves_symbols_and_quotes_alone() {
+        assert_eq!("(+ x 3)", optimize_str("(+ x (+ 1 2))"));
+        assert_eq!("(quote (+ 1 2))", optimize_str("'(+ 1 2)"));
+        assert_eq!("(append (quote (a)) (list x))", optimize_str("`(a ,x)"));
+        assert_eq!("(define list 3)", optimize_str("(define list (+ 1 2))"));
+        // Nor are primitives folded where their names are bound to something else.
+        assert_eq!("(lambda (+) (+ x (+ 1 2)))", optimize_str("(lambda (+) (+ x (+ 1 2)))"));
+        assert_eq!("(lambda (list) (list 1 2))", optimize_str("(lambda (list) (list 1 2))"));
+        assert_eq!(
+            "(lambda (x) (define list x) (list 1 2))",
+            optimize_str("(lambda (x) (define list x) (list 1 2))")
+        );
+        assert_eq!(
+            "(let ((+ 3)) (+ x (+ 1 2)) (quote (2)))",
+            optimize_str("(let ((+ (+ 1 2))) (+ x (+ 1 2)) (list (* 1 2)))")
+        );
+        assert_eq!(
+            "(match 3 ((list a) (list 1)) (+ (+ 1 2)))",
+            optimize_str("(match (+ 1 2) ((list a) (list 1)) (+ (+ 1 2)))")
+        );
+        assert_eq!(
+            "(do ((+ 3 (list +))) (list (+ 1 2)))",
+            optimize_str("(do ((+ (+ 1 2) (list +))) (list (+ 1 2)))")
+        );
+        // The arguments of anything else may be data, as those of a macro are.
+        assert_eq!("(show (+ 1 2))", optimize_str("(show (+ 1 2))"));
+        assert_eq!("(f (if #t a b))", optimize_str("(f (if #t a b))"));
+        // Errors are left to be reported by the evaluation.
+        assert_eq!("(+ 1 #t)", optimize_str("(+ 1 #t)"));
+        assert_eq!("(if x a b)", optimize_str("(if x a b)"));
+    }
+
+    #[test]
+    fn preserves_semantics() {
+        let program = [
+            "(define - (lambda (a b) (+ a (neg b))))",
+            "(define fact (lambda (n) (if (= n 0) 1 (* n (fact (- n 1))))))",
+            "(define unless (macro (c a b) `(if ,c ,b ,a)))",
+            "(define xs `(1 ,(+ 1 1) ,@(list (* 1 3) (+ 2 2))))",
+            "(fact (+ 2 3))",
+            "(unless (> 1 2) (append xs (list 5 (if #t 6 7))) 'never)",
+            "(car (cdr `(a (b ,(* 2 (+ 1 1))))))",
+            "(define show (macro (x) (list 'quote x)))",
+            "(show (+ 1 2))",
+            "(define sum (lambda (list) (list 1 2)))",
+            "(sum +)",
+        ];
+
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let mut plain = Interpreter::default();
+            let mut optimized = Interpreter::default();
+            plain.ctx.engine = engine;
+            optimized.ctx.engine = engine;
+            for code in program {
+                let input: Box<dyn BufRead> = Box::new(Cursor::new(format!("{}\n", code)));
+                let expr = parse(&mut Tokenizer::new(vec![input].into()).unwrap()).unwrap();
+                let plain_res = plain.evaluate(&expr).unwrap().to_string();
+                let optimized_res = optimized.evaluate(&optimize(&expr)).unwrap().to_string();
+                // A macro prints as its code, which is what the optimization changes.
+                if !code.contains("(macro") {
+                    assert_eq!(plain_res, optimized_res);
+                };
+            }
+        }
+    }
+}
@@ -1,8 +1,26 @@
-use std::{collections::VecDeque, io::BufRead};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufRead, Cursor},
+    sync::LazyLock
+};
 
 use regex::Regex;
 
-use crate::datatypes::{LinslErr, LinslExpr, Num, Pos, PosNum};
+use crate::datatypes::{
+    LinslErr, LinslExpr, LinslList, LinslVector, LinslWarning, Num, Pos, PosNum, Symbol,
+    WarningCategory, DEFAULT_MAX_NESTING
+};
+
+/// Regex used for getting tokens. It is only compiled once, rather than for every line. A string
+/// following `#` and a reader key, such as `#"a ${b}"` or `#date"2024-01-01"`, is a single token,
+/// spaces and all.
+static TOKEN_REGEX: LazyLock<Regex> = LazyLock::new(||
+    Regex::new(concat!(
+        r#"\s*("(?:[^"\\]|\\.)*"?|#[^\s('`,;)"]*"(?:[^"\\]|\\.)*"?|"#,
+        r#",@|#\(|[('`,)]|;.*|[^\s('`,;)]*)"#
+    )).unwrap()
+);
 
 /// Tokenizer, resopnisble for the retrieval and tokenization of input strings.
 /// `inputs` are the sources to read from, such as files or stdin.
@@ -17,6 +35,20 @@ pub struct Tokenizer {
     tokens: VecDeque<(String, Pos)>,
     /// Location of the latest delivered token, to be used when reporting errors.
     latest_pos: Pos,
+    /// How many lines have been read from the current input, i.e. the number of the next one.
+    line: PosNum,
+    /// How many lists are currently being parsed, i.e. how deeply nested the parser is.
+    nesting: PosNum,
+    /// The positions of the opening parentheses handed out which have not been closed yet, the
+    /// innermost last.
+    open: Vec<Pos>,
+    /// How deeply nested lists may be before parsing is aborted with a RecursionLimit error.
+    pub max_nesting: PosNum,
+    /// How the syntax starting with `#` is read.
+    pub readers: Readers,
+    /// The warnings given while parsing, which are handed on to the context with
+    /// `LinslCtx::take_parse_warnings`.
+    pub warnings: Vec<LinslWarning>,
 }
 
 impl Tokenizer {
@@ -25,7 +57,13 @@ impl Tokenizer {
         let mut tokenizer = Self {
             inputs,
             tokens: VecDeque::new(),
-            latest_pos: (0, 0)
+            latest_pos: (0, 0),
+            line: 0,
+            nesting: 0,
+            open: Vec::new(),
+            max_nesting: DEFAULT_MAX_NESTING,
+            readers: Readers::default(),
+            warnings: Vec::new(),
         };
 
         tokenizer.tokenize_line()?;
@@ -40,24 +78,52 @@ impl Tokenizer {
 
     /// Returns the next token, or None if all inputs have been exhausted.
     pub fn next_token(&mut self) -> Result<Option<String>, LinslErr> {
-        // First, try to get the next token from the tokens.
-        // If there is one, update the latest position and return the token.
-        if let Some((token, pos)) = self.tokens.pop_front() {
-            self.latest_pos = pos;
-            Ok(Some(token))
-        }
-        else {
-        // else tokenize the next line.
+        // First, try to get the next token from the tokens, else tokenize the next line.
+        if self.tokens.is_empty() {
             self.tokenize_line()?;
-            match self.tokens.pop_front() {
-                // If that returns a new token, do same as above.
-                Some((t, p)) => {
-                    self.latest_pos = p;
-                    Ok(Some(t))
-                },
-                // If it doesn't, we're out of input; we signal this by returning None.
-                None => Ok(None),
-            }
+        };
+        // If there is one, update the latest position and return the token. If there isn't, we're
+        // out of input; we signal this by returning None.
+        let Some((token, pos)) = self.tokens.pop_front() else {
+            return Ok(None);
+        };
+        self.latest_pos = pos;
+        match token.as_str() {
+            "(" | "#(" => self.open.push(pos),
+            ")" => {
+                self.open.pop();
+            },
+            _ => (),
+        };
+        Ok(Some(token))
+    }
+
+    /// Skip the rest of the form a parse error was found in, discarding tokens until every
+    /// parenthesis opened has been closed, so that parsing can go on with the next form.
+    pub fn skip_form(&mut self) -> Result<(), LinslErr> {
+        while !self.open.is_empty() && self.next_token()?.is_some() {}
+        self.open.clear();
+        Ok(())
+    }
+
+    /// Whether all inputs have been exhausted. Unlike checking with `peek`, an error reading the
+    /// input is returned rather than taken for its end.
+    pub fn at_end(&mut self) -> Result<bool, LinslErr> {
+        if self.tokens.is_empty() {
+            self.tokenize_line()?;
+        };
+        Ok(self.tokens.is_empty())
+    }
+
+    /// The error for input which ended in the middle of a form: UnbalancedParens with the position
+    /// of the innermost parenthesis left open, if there is one. The parentheses are forgotten, so
+    /// that the tokenizer can go on with input added later.
+    fn unexpected_eof(&mut self) -> LinslErr {
+        let innermost = self.open.pop();
+        self.open.clear();
+        match innermost {
+            Some((line, col)) => LinslErr::UnbalancedParens(line, col),
+            None => LinslErr::SyntaxError("Unexpected EOF.".to_string(), self.get_pos()),
         }
     }
 
@@ -83,20 +149,34 @@ impl Tokenizer {
         }
     }
 
+    /// Returns the position of the next token without popping it, or None if there are no more.
+    pub fn peek_pos(&mut self) -> Option<Pos> {
+        self.peek()?;
+        Some(self.tokens[0].1)
+    }
+
     /// Returns the position of the latest retrieved token.
     pub fn get_pos(&self) -> Pos {
         self.latest_pos
     }
 
-    /// Regex used for getting tokens.
-    fn regex() -> Regex {
-        Regex::new(r"\s*(,@|[('`,)]|;.*|[^\s('`,;)]*)(.*)").unwrap()
+    /// Finds the next line with any tokens and tokenizes it. Lines without any, i.e. blank lines
+    /// and comments, are skipped, so that they are not taken for the end of the input. If no more
+    /// valid input exists returns None.
+    fn tokenize_line(&mut self) -> Result<Option<()>, LinslErr> {
+        let count = self.tokens.len();
+        while self.tokens.len() == count {
+            if self.tokenize_next_line()?.is_none() {
+                return Ok(None);
+            };
+        }
+        Ok(Some(()))
     }
 
-    /// Finds the next line and tokenizes it. If no more valid input exists returns None.
-    fn tokenize_line(&mut self) -> Result<Option<()>, LinslErr> {
+    /// Tokenizes the next line, whether or not it has any tokens.
+    fn tokenize_next_line(&mut self) -> Result<Option<()>, LinslErr> {
         // First, let's try to get the next line from the inputs.
-        let (line, line_num) = match self.get_line(self.latest_pos.0) {
+        let (line, line_num) = match self.get_line() {
             // If possible, we simply unwrap the line and the line number.
             Ok(Some((s, l))) => (s, l),
             // If we've run out of input, we simply return None.
@@ -105,41 +185,23 @@ impl Tokenizer {
             },
             // If something went wrong, we return an error.
             Err(e) => {
-                return Err(LinslErr::InternalError(format!("{:?}", e)));
+                return Err(LinslErr::ReadError(None, e.to_string()));
             }
         };
 
         // At this point, we know that line contains a line of text. We can therefore begin
         // tokenizing it.
-        // At the start of tokenization, the entire line is "the rest", and the position is the
-        // start of the line.
-        let mut rest: &str = &line;
-        let mut col: PosNum = 0;
-        let re = Tokenizer::regex();
-
-        // We then start the actual tokenization, and keep doing that until we've tokenized
-        // everything.
-        loop {
-            for (_, [result, unmatched]) in re.captures_iter(rest).map(|c| c.extract()) {
-                // If the token is non-empty and not a comment, add it to tokens.
-                if !result.is_empty() && result.chars().nth(0) != Some(';') {
-                    self.tokens.push_back((result.to_string(), (line_num, col)));
-                    // Then increment the column number so it points to after the read token.
-                    col += result.len() + 1;
-                }
-                if result.chars().nth(0) == Some(';') && self.tokens.is_empty() {
-                    // If we have a comment, the the rest of the line should have been captured, and
-                    // therefore nothing should be left.
-                    assert!(unmatched.is_empty());
-                    // Furthermore, if tokens is empty then this entire line was empty, and so we
-                    // need to tokenize another line.
-                    self.tokenize_line()?;
-                }
-                rest = unmatched;
-            };
-
-            if rest.is_empty() {
-                break;
+        // The regex matches one token at a time, so a single pass over the line finds all of them.
+        // Columns count characters rather than bytes, so that they line up with what is shown in a
+        // terminal even when the line contains e.g. `λ`. `offset` is the byte offset `col` is at.
+        let (mut col, mut offset): (PosNum, usize) = (0, 0);
+        for token in TOKEN_REGEX.captures_iter(&line).filter_map(|c| c.get(1)) {
+            let result = token.as_str();
+            // If the token is non-empty and not a comment, add it to tokens.
+            if !result.is_empty() && result.chars().nth(0) != Some(';') {
+                col += line[offset..token.start()].chars().count();
+                offset = token.start();
+                self.tokens.push_back((result.to_string(), (line_num, col)));
             }
         };
         Ok(Some(()))
@@ -148,7 +210,7 @@ impl Tokenizer {
     /// Gets the next line from the inputs, along with the corresponding line number. If the
     /// current head of the inputs is empty, will pop it and start reading from the next. If there
     /// is no valid input left, returns None.
-    fn get_line(&mut self, last_line: PosNum) -> Result<Option<(String, PosNum)>, std::io::Error> {
+    fn get_line(&mut self) -> Result<Option<(String, PosNum)>, io::Error> {
         // First, check if there exists an input to get text from.
         // If not, return none.
         if self.inputs.front().is_none() {
@@ -159,30 +221,287 @@ impl Tokenizer {
             // If it is, remove that input and continue to the next one.
             self.inputs.pop_front();
             // Since we've started on a new input, we reset the position.
-            return self.get_line(0);
+            self.line = 0;
+            return self.get_line();
         };
 
-        // With these checks done, we know that a non-empty input exists.
-        // Therefore, we create a string to read the line into,
-        let mut initial_rest = String::new();
-        // fill it,
-        let _ = self.inputs[0].read_line(&mut initial_rest);
-        // remove the \n character at the end (otherwise the regex won't work),
-        initial_rest.pop();
+        // With these checks done, we know that a non-empty input exists, so we read a line from
+        // it,
+        let mut line = read_line(&mut self.inputs[0])?;
+        // skipping the byte order mark some editors start files with,
+        if self.line == 0 && line.starts_with('\u{feff}') {
+            line.remove(0);
+        };
         // and return it.
-        Ok(
-            Some(
-                (initial_rest, 
-                 if last_line == 0 {0} else {last_line + 1})
-            )
-        )
+        self.line += 1;
+        Ok(Some((line, self.line - 1)))
+    }
+}
+
+/// Reads a line without its line ending, which may be `\n`, `\r\n` or a lone `\r`.
+fn read_line(input: &mut Box<dyn BufRead>) -> Result<String, io::Error> {
+    let mut bytes = Vec::new();
+    loop {
+        let buf = input.fill_buf()?;
+        let Some(i) = buf.iter().position(|b| *b == b'\n' || *b == b'\r') else {
+            // The line goes on past the buffer, or it is the last one and has no line ending.
+            if buf.is_empty() {
+                break;
+            };
+            let len = buf.len();
+            bytes.extend_from_slice(buf);
+            input.consume(len);
+            continue;
+        };
+        let carriage_return = buf[i] == b'\r';
+        bytes.extend_from_slice(&buf[..i]);
+        input.consume(i + 1);
+        if carriage_return && input.fill_buf()?.first() == Some(&b'\n') {
+            input.consume(1);
+        };
+        break;
     }
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads a file to use as an input for a Tokenizer. Fails with a ReadError naming the file if it
+/// cannot be read, or is not UTF-8.
+pub fn read_file(path: &str) -> Result<Box<dyn BufRead>, LinslErr> {
+    let error = |cause: String| LinslErr::ReadError(Some(path.to_string()), cause);
+    let bytes = fs::read(path).map_err(|e| error(e.to_string()))?;
+    match String::from_utf8(bytes) {
+        Ok(code) => Ok(Box::new(Cursor::new(code))),
+        Err(_) => Err(error("it is not valid UTF-8".to_string())),
+    }
+}
+
+/// A reader for syntax starting with `#`, such as `#(1 2)`. It is given the rest of the token after
+/// the `#` and the characters it is registered under, and the tokenizer, from which it may take
+/// more tokens, e.g. the elements of a vector. The position of the `#` is `tokenizer.get_pos()`.
+pub type Reader = fn(&str, &mut Tokenizer) -> Result<LinslExpr, LinslErr>;
+
+/// The readers for the syntax starting with `#`, keyed by the characters after the `#`. If several
+/// keys match a token, the longest one is used, so a reader registered under `date` reads
+/// `#date"2024-01-01"` even if there is one under `d` as well. The built in syntax, i.e. `#(`,
+/// `#t`, `#f`, `#:` and `#"`, is registered here too, and can be replaced.
+#[derive(Debug, Clone)]
+pub struct Readers {
+    readers: HashMap<String, Reader>,
+}
+
+impl Default for Readers {
+    fn default() -> Self {
+        let mut readers = Readers { readers: HashMap::new() };
+        readers.insert("(", read_vector);
+        readers.insert("t", read_true);
+        readers.insert("f", read_false);
+        readers.insert(":", read_keyword);
+        readers.insert("\"", read_interpolated);
+        readers
+    }
+}
+
+impl Readers {
+    /// Register a reader for the syntax starting with `#` followed by `key`, replacing any reader
+    /// registered under the same key.
+    pub fn insert(&mut self, key: &str, reader: Reader) {
+        self.readers.insert(key.to_string(), reader);
+    }
+
+    /// The reader for a token starting with `#`, along with the rest of the token after its key.
+    fn get<'a>(&self, token: &'a str) -> Option<(Reader, &'a str)> {
+        let after = token.strip_prefix('#')?;
+        self.readers.iter()
+            .filter(|(key, _)| after.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(key, reader)| (*reader, &after[key.len()..]))
+    }
+}
+
+/// Reads `#(`, i.e. a vector literal.
+fn read_vector(_: &str, tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    parse_vector(tokenizer)
+}
+
+/// Reads `#t`. Anything longer, such as `#tx`, is a symbol, as it was before there were readers.
+fn read_true(rest: &str, _: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    Ok(bool_or_symbol(true, rest))
+}
+
+/// Reads `#f`, in the same way as `read_true`.
+fn read_false(rest: &str, _: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    Ok(bool_or_symbol(false, rest))
+}
+
+fn bool_or_symbol(b: bool, rest: &str) -> LinslExpr {
+    match rest {
+        "" => LinslExpr::Bool(b),
+        _ => LinslExpr::Symbol(Symbol::new(&format!("#{}{}", if b { 't' } else { 'f' }, rest))),
+    }
+}
+
+/// Reads `#:foo`, which is the same keyword as `:foo`. A lone `#:` is a symbol, like `:` is.
+fn read_keyword(rest: &str, _: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    match rest {
+        "" => Ok(LinslExpr::Symbol(Symbol::new("#:"))),
+        _ => Ok(LinslExpr::Keyword(Symbol::new(rest))),
+    }
+}
+
+/// Reads `#"x is ${x}"`, an interpolated string, as (string-append "x is " (->string x)). Each
+/// `${...}` holds a single expression, which ends at the `}` balancing the `{`; braces within
+/// strings in the expression are not counted. As the whole literal is one string token, a `"` or
+/// `\` within an expression is written `\"` or `\\`. Outside of expressions the escapes are those
+/// of `parse_string`, along with `\$`, so that `\${` is a literal `${`. Errors in an expression
+/// are reported at their position within the literal.
+fn read_interpolated(rest: &str, tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    let pos = tokenizer.get_pos();
+    // The column of the character at index i of rest, which starts after the `#"`.
+    let col = |i: usize| pos.1 + 2 + i;
+    let chars: Vec<char> = rest.chars().collect();
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut interpolated = false;
+    let mut i = 0;
+    loop {
+        match chars.get(i) {
+            // The closing quote must end the token, which the regex ensures.
+            Some('"') => break,
+            Some('\\') => {
+                literal.push(match chars.get(i + 1) {
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('$') => '$',
+                    Some(c) => return Err(
+                        LinslErr::SyntaxError(
+                            format!("Unknown escape sequence \'\\{}\'", c),
+                            (pos.0, col(i))
+                        )
+                    ),
+                    None => break,
+                });
+                i += 2;
+            },
+            Some('$') if chars.get(i + 1) == Some(&'{') => {
+                if !literal.is_empty() {
+                    parts.push(LinslExpr::String(std::mem::take(&mut literal).into()));
+                };
+                let (code, cols, end) = interpolated_code(&chars, i + 2).ok_or_else(|| {
+                    LinslErr::SyntaxError(
+                        "Unterminated \'${\' in an interpolated string".to_string(),
+                        (pos.0, col(i))
+                    )
+                })?;
+                // Positions within the code are mapped back to the columns of the literal.
+                let map = |(_, c): Pos| (pos.0, col(cols.get(c).copied().unwrap_or(end)));
+                let expr = parse_interpolated(&code, tokenizer, map)?.ok_or_else(|| {
+                    LinslErr::SyntaxError(
+                        "Empty \'${}\' in an interpolated string".to_string(),
+                        (pos.0, col(i))
+                    )
+                })?;
+                parts.push(LinslExpr::List(
+                    vec![LinslExpr::Symbol(Symbol::new("->string")), expr].into()
+                ));
+                interpolated = true;
+                i = end + 1;
+            },
+            Some(c) => {
+                literal.push(*c);
+                i += 1;
+            },
+            None => break,
+        }
+    }
+    if chars.get(i) != Some(&'"') {
+        return Err(LinslErr::SyntaxError("Unterminated string literal.".to_string(), pos));
+    };
+    // Without any expressions, the literal is just a string.
+    if !interpolated {
+        return Ok(LinslExpr::String(literal.into()));
+    };
+    if !literal.is_empty() {
+        parts.push(LinslExpr::String(literal.into()));
+    };
+    parts.insert(0, LinslExpr::Symbol(Symbol::new("string-append")));
+    Ok(LinslExpr::List(parts.into()))
+}
+
+/// The code of a `${...}` in an interpolated string, starting at index start of its characters,
+/// with `\"` and `\\` unescaped. Returned along with the index each character of the code came
+/// from, and the index of the closing `}`, or None if the `${` is never closed.
+fn interpolated_code(chars: &[char], start: usize) -> Option<(String, Vec<usize>, usize)> {
+    let (mut code, mut cols) = (String::new(), Vec::new());
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    let mut i = start;
+    loop {
+        let (c, from) = match *chars.get(i)? {
+            // An unescaped quote ends the whole literal.
+            '"' => return None,
+            '\\' => match chars.get(i + 1) {
+                Some(c @ ('"' | '\\')) => {
+                    i += 1;
+                    (*c, i - 1)
+                },
+                _ => ('\\', i),
+            },
+            c => (c, i),
+        };
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string && depth == 0 => return Some((code, cols, i)),
+            '}' if !in_string => depth -= 1,
+            _ => (),
+        };
+        code.push(c);
+        cols.push(from);
+        i += 1;
+    }
+}
+
+/// Parse the code of a `${...}`, which must be a single expression, or nothing, in which case None
+/// is returned. Positions in errors and warnings are moved by map from the code to the literal.
+fn parse_interpolated(
+    code: &str,
+    tokenizer: &mut Tokenizer,
+    map: impl Fn(Pos) -> Pos,
+) -> Result<Option<LinslExpr>, LinslErr> {
+    let input: Box<dyn BufRead> = Box::new(Cursor::new(code.to_string()));
+    let mut inner = Tokenizer::new(VecDeque::from([input]))?;
+    inner.readers = tokenizer.readers.clone();
+    inner.nesting = tokenizer.nesting;
+    inner.max_nesting = tokenizer.max_nesting;
+    let parsed = parse_next(&mut inner).and_then(|expr| match (expr, inner.peek_pos()) {
+        (Some(_), Some(pos)) => Err(LinslErr::SyntaxError(
+            "Expected a single expression in \'${}\'".to_string(),
+            pos
+        )),
+        (expr, _) => Ok(expr),
+    });
+    tokenizer.warnings.extend(inner.warnings.into_iter().map(|mut warning| {
+        warning.pos = map(warning.pos);
+        warning
+    }));
+    parsed.map_err(|e| match e {
+        LinslErr::SyntaxError(msg, p) => LinslErr::SyntaxError(msg, map(p)),
+        LinslErr::RecursionLimit(depth, p) => LinslErr::RecursionLimit(depth, map(p)),
+        LinslErr::UnbalancedParens(l, c) => {
+            let (l, c) = map((l, c));
+            LinslErr::UnbalancedParens(l, c)
+        },
+        e => e,
+    })
 }
 
 /// Checks if there are as many opening as closing parentheses.
 /// If not, returns the number of parentheses found.
 /// Else, returns None.
-fn check_parens(string: &str) -> Option<(usize, usize)> {
+pub fn check_parens(string: &str) -> Option<(usize, usize)> {
     let opening = string.matches("(").count();
     let closing = string.matches(")").count();
     
@@ -193,6 +512,42 @@ fn check_parens(string: &str) -> Option<(usize, usize)> {
     }
 }
 
+/// The tokens of a single line, comments included, along with the byte offsets they start at. A
+/// string left open at the end of the line is a token of its own.
+pub fn line_tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    TOKEN_REGEX.captures_iter(line)
+        .filter_map(|c| c.get(1))
+        .filter(|token| !token.is_empty())
+        .map(|token| (token.start(), token.as_str()))
+}
+
+/// Indent code by how deeply it is nested, two spaces for every list or vector left open by the
+/// lines before. A line starting with closing parentheses is indented as deeply as the lines they
+/// close. Everything else, i.e. the tokens, strings and comments, is kept as it is, apart from the
+/// whitespace at the ends of the lines.
+pub fn indent_code(code: &str) -> String {
+    let mut depth: usize = 0;
+    let mut indented = String::new();
+    for line in code.lines() {
+        let line = line.trim();
+        let tokens: Vec<&str> = line_tokens(line).map(|(_, token)| token).collect();
+        let closing = tokens.iter().take_while(|token| **token == ")").count();
+        if !line.is_empty() {
+            indented.push_str(&"  ".repeat(depth.saturating_sub(closing)));
+            indented.push_str(line);
+        };
+        indented.push('\n');
+        for token in tokens {
+            match token {
+                "(" | "#(" => depth += 1,
+                ")" => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+    }
+    indented
+}
+
 /*
 pub fn handle_result<T>(res: Result<T, LinslErr>, pos: PosNum) -> Result<T, LinslErr> {
     match res {
@@ -210,24 +565,40 @@ pub fn handle_result<T>(res: Result<T, LinslErr>, pos: PosNum) -> Result<T, Lins
 }
 */
 
+/// Parse the next form, or return None if the input has been exhausted. Input which ends in the
+/// middle of a form gives an UnbalancedParens error with the position of the parenthesis left
+/// open.
+pub fn parse_next(tokenizer: &mut Tokenizer) -> Result<Option<LinslExpr>, LinslErr> {
+    if tokenizer.at_end()? {
+        return Ok(None);
+    };
+    parse(tokenizer).map(Some)
+}
+
 pub fn parse(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
     // We begin by retrieving the next token, if any.
     let token = match tokenizer.next_token()? {
         Some(t) => t,
-        // If no token exists, something had gone wrong; this function is only called when there
-        // needs to be more tokens in order to form a valid expression.
-        None => {
-            return Err(LinslErr::InternalError("Unexpected EOF.".to_string()));
-        },
+        // If no token exists, the input ended in the middle of a form; this function is only
+        // called when there needs to be more tokens in order to form a valid expression.
+        None => return Err(tokenizer.unexpected_eof()),
     };
 
     // We then check the token.
     match token.as_str() {
-        // If it is a quote or quasiquote, parse the rest accordingly.
-        "'" => parse_quote(tokenizer),
-        "`" => parse_quasiquote(tokenizer),
+        // If it is a quote or quasiquote, parse the rest accordingly. A chain of quotes, such as
+        // ''''x, nests just as a list does.
+        "'" => parse_nested(tokenizer, parse_quote),
+        "`" => parse_nested(tokenizer, parse_quasiquote),
         // An opening parenthesis means we start reading a new list.
         "(" => parse_list(tokenizer, parse),
+        // Commas only mean something within a quasi-quote, which handles them itself.
+        "," | ",@" => Err(
+            LinslErr::SyntaxError(
+                format!("Found \'{}\' outside of a quasi-quote", token),
+                tokenizer.get_pos()
+            )
+        ),
         // If we encounter a closing parenthesis something went wrong.
         ")" => Err(
             LinslErr::SyntaxError(
@@ -235,33 +606,188 @@ pub fn parse(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
                 tokenizer.get_pos()
             )
         ),
+        // A double quote starts a string literal, which is a single token.
+        _ if token.starts_with('"') => parse_string(&token, tokenizer.get_pos()),
+        // Syntax starting with `#`, such as `#(` starting a vector literal, is read by the reader
+        // registered for it.
+        _ if token.starts_with('#') => match tokenizer.readers.get(&token) {
+            Some((reader, rest)) => reader(rest, tokenizer),
+            None => Err(
+                LinslErr::SyntaxError(
+                    format!("Unknown syntax \'{}\'", token),
+                    tokenizer.get_pos()
+                )
+            ),
+        },
         // Otherwise we attempt to parse it as an atom.
-        _ => Ok(parse_atom(&token)),
+        _ => {
+            if is_non_finite_name(&token) {
+                tokenizer.warnings.push(LinslWarning {
+                    category: WarningCategory::Syntax,
+                    message: format!(
+                        "\'{}\' is a symbol; infinities and NaN are written +inf.0, -inf.0 and \
+                         +nan.0",
+                        token
+                    ),
+                    pos: tokenizer.get_pos(),
+                });
+            };
+            Ok(parse_atom(&token))
+        },
+    }
+}
+
+/// Parses a string literal, i.e. a token starting with a double quote, replacing the escape
+/// sequences `\\`, `\"`, `\n` and `\t` by the characters they stand for. String literals cannot
+/// span several lines. Readers may use it for syntax such as `#date"2024-01-01"`.
+pub fn parse_string(token: &str, pos: Pos) -> Result<LinslExpr, LinslErr> {
+    let mut string = String::new();
+    let mut chars = token.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            // The closing quote must end the token, which the regex ensures.
+            '"' => return Ok(LinslExpr::String(string.into())),
+            '\\' => string.push(match chars.next() {
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c) => return Err(
+                    LinslErr::SyntaxError(format!("Unknown escape sequence \'\\{}\'", c), pos)
+                ),
+                None => break,
+            }),
+            c => string.push(c),
+        }
     }
+    Err(LinslErr::SyntaxError("Unterminated string literal.".to_string(), pos))
 }
 
 /// This function is called during parsing, if we encounter anything which is not a list or a
 /// quoted expression.
 fn parse_atom(atom : &str) -> LinslExpr {
     match atom {
-        // If the atom is `#t` or `#f` we can instanly handle it.
-        "#t" => LinslExpr::Bool(true),
-        "#f" => LinslExpr::Bool(false),
+        // Names starting with `:` are keywords.
+        _ if atom.len() > 1 && atom.starts_with(':') => {
+            LinslExpr::Keyword(Symbol::new(&atom[1..]))
+        },
+        "+inf.0" => LinslExpr::Number(Num::INFINITY),
+        "-inf.0" => LinslExpr::Number(Num::NEG_INFINITY),
+        "+nan.0" => LinslExpr::Number(Num::NAN),
+        // Rust reads e.g. `inf` and `NaN` as numbers, but those are symbols in Linsl.
+        _ if is_non_finite_name(atom) => LinslExpr::Symbol(Symbol::new(atom)),
         // If it is not, we check if it is a number; if it is then good, otherwise we treat it as a
         // symbol. We DO NOT check if it is defined etc here, that is done during evaluation.
         _ => {
             let attempted_num : Result<Num, _> = atom.parse();
-            match attempted_num {
-                Ok(v) => LinslExpr::Number(v),
-                Err(_) => LinslExpr::Symbol(atom.to_string()),
+            match (attempted_num, parse_complex(atom)) {
+                (Ok(v), _) => LinslExpr::Number(v),
+                (Err(_), Some((re, im))) => LinslExpr::complex(re, im),
+                (Err(_), None) => LinslExpr::Symbol(Symbol::new(atom)),
             }
         }
     }
 }
 
+/// Read a complex number, written as a real part followed by a signed imaginary part, e.g. 3+4i,
+/// 1e2-0.5i or 1+i, or as just the imaginary part, e.g. -2i, 2i or +i. The imaginary part may be
+/// left out only if it is 1 and its sign is written, so `i` is a symbol.
+pub fn parse_complex(atom: &str) -> Option<(Num, Num)> {
+    let body = atom.strip_suffix('i')?;
+    // The imaginary part starts at the last sign which is not that of an exponent.
+    let split = body.char_indices()
+        .filter(|&(i, c)| {
+            matches!(c, '+' | '-') && i > 0 && !body[..i].ends_with(['e', 'E'])
+        })
+        .map(|(i, _)| i)
+        .next_back();
+    let (re, im) = match split {
+        Some(i) => (parse_real(&body[..i])?, &body[i..]),
+        None => (0 as Num, body),
+    };
+    let im = match im {
+        "+" => 1 as Num,
+        "-" => -1 as Num,
+        _ => parse_real(im)?,
+    };
+    Some((re, im))
+}
+
+/// Read a real number, as `parse_atom` does.
+fn parse_real(s: &str) -> Option<Num> {
+    match s {
+        "+inf.0" => Some(Num::INFINITY),
+        "-inf.0" => Some(Num::NEG_INFINITY),
+        "+nan.0" => Some(Num::NAN),
+        _ if is_non_finite_name(s) => None,
+        _ => s.parse().ok(),
+    }
+}
+
+/// Whether an atom is one of the names Rust reads as an infinity or NaN, such as `inf`, `-Infinity`
+/// or `nan`, ignoring case.
+fn is_non_finite_name(atom: &str) -> bool {
+    let name = atom.trim_start_matches(['+', '-']).to_ascii_lowercase();
+    matches!(name.as_str(), "inf" | "infinity" | "nan")
+}
+
 /// If an opening parenthesis is encountered, this function is called. It parses -- using the
 /// supplied parser function -- until it encounters a closing parenthesis.
+///
+/// Since lists are parsed recursively, the nesting is limited by the tokenizer's `max_nesting` to
+/// keep deeply nested input from overflowing the stack.
 fn parse_list(tokenizer: &mut Tokenizer, parser: fn(&mut Tokenizer) -> Result<LinslExpr, LinslErr>) -> Result<LinslExpr, LinslErr> {
+    parse_nested(tokenizer, |tokenizer| parse_list_elems(tokenizer, parser))
+}
+
+/// Parse something nested in the expression being parsed, i.e. the elements of a list or the
+/// expression after a quote, failing with a RecursionLimit error if that is nested too deeply.
+fn parse_nested(
+    tokenizer: &mut Tokenizer,
+    parser: impl FnOnce(&mut Tokenizer) -> Result<LinslExpr, LinslErr>
+) -> Result<LinslExpr, LinslErr> {
+    if tokenizer.nesting >= tokenizer.max_nesting {
+        return Err(LinslErr::RecursionLimit(tokenizer.nesting, tokenizer.get_pos()));
+    };
+
+    tokenizer.nesting += 1;
+    let res = parser(tokenizer);
+    tokenizer.nesting -= 1;
+    res
+}
+
+/// Like `parse_list`, but returns the elements rather than the list.
+fn parse_list_to_vec(
+    tokenizer: &mut Tokenizer,
+    parser: fn(&mut Tokenizer) -> Result<LinslExpr, LinslErr>
+) -> Result<Vec<LinslExpr>, LinslErr> {
+    match parse_list(tokenizer, parser)? {
+        LinslExpr::List(l) => Ok(l.to_vec()),
+        expr => Err(
+            LinslErr::InternalError(format!("Parsing a list gave \'{}\', not a list.", expr))
+        ),
+    }
+}
+
+/// Called when `#(` is encountered, and parses the rest of a vector literal.
+fn parse_vector(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+    Ok(LinslExpr::Vector(LinslVector::from(parse_list_to_vec(tokenizer, parse)?)))
+}
+
+/// Vector literals can not (yet) be quasi-quoted.
+fn quasiquoted_vector(tokenizer: &Tokenizer) -> Result<LinslExpr, LinslErr> {
+    Err(
+        LinslErr::SyntaxError(
+            "Vector literals cannot be quasi-quoted, use (vector ...) instead".to_string(),
+            tokenizer.get_pos()
+        )
+    )
+}
+
+/// Parses the elements of a list, up to and including the closing parenthesis.
+fn parse_list_elems(tokenizer: &mut Tokenizer, parser: fn(&mut Tokenizer) -> Result<LinslExpr, LinslErr>) -> Result<LinslExpr, LinslErr> {
+    // The opening parenthesis is the latest token, and its position is kept with the list.
+    let pos = tokenizer.get_pos();
     // FIrst, create a vec to keep the list elements in.
     let mut list_elems: Vec<LinslExpr> = Vec::new();
     // Then we start looping over tokens:
@@ -269,14 +795,7 @@ fn parse_list(tokenizer: &mut Tokenizer, parser: fn(&mut Tokenizer) -> Result<Li
         // Retrieve the next token, if one is available
         let token = match tokenizer.peek() {
             Some(t) => t,
-            None => {
-                return Err(
-                    LinslErr::SyntaxError(
-                        "Found only opening parentheses.".to_string(),
-                        tokenizer.get_pos()
-                    )
-                );
-            },
+            None => return Err(tokenizer.unexpected_eof()),
         };
 
         // If the token is `)` the list has ended,
@@ -294,18 +813,18 @@ fn parse_list(tokenizer: &mut Tokenizer, parser: fn(&mut Tokenizer) -> Result<Li
     };
 
     // When done looping, return a list expression.
-    Ok(LinslExpr::List(list_elems))
+    Ok(LinslExpr::List(LinslList::from(list_elems).with_pos(pos)))
 }
 
-pub fn parse_list_of_nums(nums: Box<[LinslExpr]>) -> Result<Vec<Num>, LinslErr>{
-    (*nums).iter()
+pub fn parse_list_of_nums(nums: &[LinslExpr]) -> Result<Vec<Num>, LinslErr>{
+    nums.iter()
         .map(parse_num)
         .collect::<Result<Vec<Num>, LinslErr>>()
 }
 
-pub fn parse_list_of_symbols(symbs: &LinslExpr) -> Result<Vec<String>, LinslErr> {
+pub fn parse_list_of_symbols(symbs: &LinslExpr) -> Result<Vec<Symbol>, LinslErr> {
     let list = match symbs {
-        LinslExpr::List(s) => Ok(s.clone()),
+        LinslExpr::List(s) => Ok(s),
         _ => Err(
             // TODO: Fix pos.
             LinslErr::SyntaxError(
@@ -348,8 +867,8 @@ pub fn parse_num(expr: &LinslExpr) -> Result<Num, LinslErr> {
 fn parse_quote(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
     Ok(
         LinslExpr::List(
-            vec![LinslExpr::Symbol("quote".to_string()),
-            parse(tokenizer)?]
+            vec![LinslExpr::Symbol(Symbol::new("quote")),
+            parse(tokenizer)?].into()
         )
     )
 }
@@ -364,12 +883,7 @@ fn parse_quasiquote(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
     let token = match tokenizer.peek() {
         // Take the token if there was one, else return an error.
         Some(t) => t,
-        None => return Err(
-            LinslErr::SyntaxError(
-                "Unexpected EOF.".to_string(),
-                tokenizer.get_pos()
-            )
-        ),
+        None => return Err(tokenizer.unexpected_eof()),
     };
 
     // We can now inspect the token.
@@ -379,24 +893,25 @@ fn parse_quasiquote(tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
             let _ = tokenizer.next_token();
             parse(tokenizer)
         },
-        // Here we do mutch the same as for a comma, but we need to add unwrap.
-        ",@" => Err(
-            LinslErr::SyntaxError(
-                "Cannot have ,@ at top level of `".to_string(),
-                tokenizer.get_pos()
+        // There is no list for ,@ to splice into at the top level.
+        ",@" => {
+            let _ = tokenizer.next_token();
+            Err(
+                LinslErr::SyntaxError(
+                    "Cannot have ,@ at top level of `".to_string(),
+                    tokenizer.get_pos()
+                )
             )
-        ),
+        },
         // If we encounter an opening parenthesis -- which is what we often do when the user uses
         // quasiquotes -- we parse the list.
         "(" => {
             let _ = tokenizer.next_token();
-            if let LinslExpr::List(mut v) = parse_list(tokenizer, parse_quasiquote_elem_in_list)? {
-                v.insert(0, LinslExpr::Symbol("append".to_string()));
-                Ok(LinslExpr::List(v))
-            } else {
-                panic!("parse_list did not return a list when parsing quasi-quote!")
-            }
+            let mut v = parse_list_to_vec(tokenizer, parse_quasiquote_elem_in_list)?;
+            v.insert(0, LinslExpr::Symbol(Symbol::new("append")));
+            Ok(LinslExpr::List(v.into()))
         },
+        "#(" => quasiquoted_vector(tokenizer),
         
         // If none of the others have matched, then we have a non-escaped atom; in this case a
         // quasiquote behaves the same as a regular quote.
@@ -409,14 +924,7 @@ fn parse_quasiquote_elem_in_list(tokenizer: &mut Tokenizer) -> Result<LinslExpr,
     // First, get token as always,
     let token = match tokenizer.peek() {
         Some(t) => t,
-        None => {
-            return Err(
-                LinslErr::SyntaxError(
-                    "Unexpected EOF.".to_string(),
-                    tokenizer.get_pos()
-                )
-            );
-        }
+        None => return Err(tokenizer.unexpected_eof()),
     };
     // then check it.
     match token.as_str() {
@@ -425,9 +933,9 @@ fn parse_quasiquote_elem_in_list(tokenizer: &mut Tokenizer) -> Result<LinslExpr,
             let _ = tokenizer.next_token();
             Ok(
                 LinslExpr::List(vec![
-                    LinslExpr::Symbol("list".to_string()),
+                    LinslExpr::Symbol(Symbol::new("list")),
                     parse(tokenizer)?
-                ])
+                ].into())
             )
         },
         // ,@x => x
@@ -439,27 +947,47 @@ fn parse_quasiquote_elem_in_list(tokenizer: &mut Tokenizer) -> Result<LinslExpr,
         },
         "(" => {
             let _ = tokenizer.next_token();
-             if let LinslExpr::List(mut v) = parse_list(tokenizer, parse_quasiquote_elem_in_list)? {
-                v.insert(0, LinslExpr::Symbol("append".to_string()));
-                Ok(
+            let mut v = parse_list_to_vec(tokenizer, parse_quasiquote_elem_in_list)?;
+            v.insert(0, LinslExpr::Symbol(Symbol::new("append")));
+            Ok(
+                LinslExpr::List(vec![
+                    LinslExpr::Symbol(Symbol::new("list")),
+                    LinslExpr::List(v.into())
+                ].into())
+            )
+        },
+        // Linsl has no pairs, so there is nothing for `(a . ,@x) to splice x into.
+        "." => {
+            let _ = tokenizer.next_token();
+            if tokenizer.peek().as_deref() == Some(",@") {
+                let _ = tokenizer.next_token();
+                return Err(
+                    LinslErr::SyntaxError(
+                        "Cannot have ,@ after . in `, write `(... ,@x) instead".to_string(),
+                        tokenizer.get_pos()
+                    )
+                );
+            };
+            Ok(
+                LinslExpr::List(vec![
+                    LinslExpr::Symbol(Symbol::new("list")),
                     LinslExpr::List(vec![
-                        LinslExpr::Symbol("list".to_string()),
-                        LinslExpr::List(v)
-                    ])
-                )
-            } else {
-                panic!("parse_list did not return a list when parsing quasi-quote!")
-            }
+                        LinslExpr::Symbol(Symbol::new("quote")),
+                        LinslExpr::Symbol(Symbol::new("."))
+                    ].into())
+                ].into())
+            )
         },
+        "#(" => quasiquoted_vector(tokenizer),
         // else: x => (list (quote x))
         _ => Ok(
             LinslExpr::List(vec![
-                LinslExpr::Symbol("list".to_string()),
+                LinslExpr::Symbol(Symbol::new("list")),
                 LinslExpr::List(vec![
-                    LinslExpr::Symbol("quote".to_string()),
+                    LinslExpr::Symbol(Symbol::new("quote")),
                     parse(tokenizer)?
-                ])
-            ])
+                ].into())
+            ].into())
         ),
     }
 }
@@ -502,6 +1030,132 @@ mod test {
         assert!(test);
     }
 
+    #[test]
+    fn blank_lines_are_skipped() {
+        let s = "(+ 1\n\n 2)\n   \n; a comment\n\n\n(+ 3 4)\n\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+        assert_eq!("(+ 1 2)", parse(&mut tokenizer).unwrap().to_string());
+        assert_eq!(Some((7, 0)), tokenizer.peek_pos());
+        assert_eq!("(+ 3 4)", parse(&mut tokenizer).unwrap().to_string());
+        assert_eq!(None, tokenizer.peek());
+    }
+
+    #[test]
+    fn skip_form() {
+        let s = "(list 1 #z
+ (2 3) 4) (+ 1 2)
+(car 1)
+";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+        assert!(parse(&mut tokenizer).is_err());
+        tokenizer.skip_form().unwrap();
+        assert_eq!("(+ 1 2)", parse(&mut tokenizer).unwrap().to_string());
+        // Nothing is skipped after a form which was parsed in full.
+        tokenizer.skip_form().unwrap();
+        assert_eq!("(car 1)", parse(&mut tokenizer).unwrap().to_string());
+    }
+
+    #[test]
+    fn tokenize_unicode() {
+        let s = "(define λ (lambda (αβ 数字) 数字)) 😀\n";
+        let tokenizer = setup(Box::new(s.as_bytes()));
+
+        let tokens: Vec<(&str, PosNum)> = tokenizer.tokens
+            .iter()
+            .map(|(token, (_, col))| (token.as_str(), *col))
+            .collect();
+        assert_eq!(
+            vec![
+                ("(", 0), ("define", 1), ("λ", 8), ("(", 10), ("lambda", 11), ("(", 18),
+                ("αβ", 19), ("数字", 22), (")", 24), ("数字", 26), (")", 28), (")", 29), ("😀", 31)
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn unicode_symbols() {
+        let s = "λ 数字 αβ-γ\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+
+        for name in ["λ", "数字", "αβ-γ"] {
+            match parse(&mut tokenizer).unwrap() {
+                LinslExpr::Symbol(val) => assert_eq!(name, val),
+                _ => panic!(),
+            };
+        }
+    }
+
+    #[test]
+    fn error_position_after_multibyte_char() {
+        // The string starts at the 11th character, but at the 12th byte, since λ takes two.
+        let s = "(define λ \"a\\q\")\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+
+        match parse(&mut tokenizer) {
+            Err(LinslErr::SyntaxError(_, pos)) => assert_eq!((0, 10), pos),
+            res => panic!("{:?}", res),
+        };
+    }
+
+    /// All the tokens of the input, along with their positions.
+    fn all_tokens(s: &str) -> Vec<(String, Pos)> {
+        let mut tokenizer = setup(Box::new(Cursor::new(s.to_string())));
+        let mut tokens = Vec::new();
+        while let Some(token) = tokenizer.next_token().unwrap() {
+            tokens.push((token, tokenizer.get_pos()));
+        }
+        tokens
+    }
+
+    #[test]
+    fn line_endings_and_bom() {
+        let lf = "(define x 1)\n(+ x \"a b\") ; c\n(λ x)";
+        let expected = all_tokens(lf);
+        assert_eq!(("1".to_string(), (0, 10)), expected[3]);
+        assert_eq!(("λ".to_string(), (2, 1)), expected[11]);
+
+        for s in [
+            lf.replace('\n', "\r\n"),
+            lf.replace('\n', "\r"),
+            format!("\u{feff}{}", lf),
+            format!("\u{feff}{}\r\n", lf.replace('\n', "\r\n")),
+        ] {
+            assert_eq!(expected, all_tokens(&s), "{:?}", s);
+        }
+
+        // Every input may start with a byte order mark.
+        let mut tokenizer = setup(Box::new("\u{feff}a\r\n".as_bytes()));
+        tokenizer.add_input(Box::new("\u{feff}b\r\n".as_bytes()));
+        assert_eq!(Some("a".to_string()), tokenizer.next_token().unwrap());
+        assert_eq!(Some("b".to_string()), tokenizer.next_token().unwrap());
+        assert_eq!((0, 0), tokenizer.get_pos());
+        assert_eq!(None, tokenizer.next_token().unwrap());
+    }
+
+    #[test]
+    fn file_errors_name_the_file() {
+        let missing = std::env::temp_dir().join("linsl-test-missing.linsl");
+        let missing = missing.to_str().unwrap();
+        let message = read_file(missing).err().unwrap().to_string();
+        assert!(message.starts_with(&format!("Could not read \'{}\': ", missing)), "{}", message);
+
+        let latin1 = std::env::temp_dir().join("linsl-test-latin1.linsl");
+        fs::write(&latin1, b"(define caf\xe9 1)\n").unwrap();
+        let latin1 = latin1.to_str().unwrap();
+        let res = read_file(latin1);
+        fs::remove_file(latin1).unwrap();
+        assert_eq!(
+            format!("Could not read \'{}\': it is not valid UTF-8", latin1),
+            res.err().unwrap().to_string()
+        );
+
+        // Inputs which are not files fail without a path.
+        let mut tokenizer = setup(Box::new(&b"a\n\xff\n"[..]));
+        assert_eq!(Some("a".to_string()), tokenizer.next_token().unwrap());
+        assert!(matches!(tokenizer.next_token(), Err(LinslErr::ReadError(None, _))));
+    }
+
     #[test]
     fn check_peek_length() {
         let s = "(+ 1 2 3)\n";
@@ -554,6 +1208,29 @@ mod test {
         assert!(pass);
     }
 
+    #[test]
+    fn parse_complex_numbers() {
+        for (atom, re, im) in [
+            ("3+4i", 3.0, 4.0),
+            ("3-4i", 3.0, -4.0),
+            ("-2i", 0.0, -2.0),
+            ("2.5i", 0.0, 2.5),
+            ("+i", 0.0, 1.0),
+            ("-i", 0.0, -1.0),
+            ("1+i", 1.0, 1.0),
+            ("1e2+3i", 100.0, 3.0),
+            ("1e-2-3e+1i", 0.01, -30.0),
+            ("-inf.0+inf.0i", Num::NEG_INFINITY, Num::INFINITY),
+        ] {
+            assert_eq!(LinslExpr::Complex(re, im), parse_atom(atom), "{}", atom);
+        }
+        // A complex number whose imaginary part is zero is real.
+        assert_eq!(LinslExpr::Number(3.0), parse_atom("3+0i"));
+        for atom in ["i", "e+i", "a-i", "1+2", "1+2j", "inf+i", "1+infi", "1++2i", "1+2ii"] {
+            assert_eq!(LinslExpr::Symbol(Symbol::new(atom)), parse_atom(atom), "{}", atom);
+        }
+    }
+
     #[test]
     fn parse_symbol() {
         let s = "+\n";
@@ -607,6 +1284,160 @@ mod test {
         };
     }
 
+    #[test]
+    fn parse_keyword() {
+        let s = ":foo #:foo : #: a:b\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+
+        for _ in 0..2 {
+            match parse(&mut tokenizer).unwrap() {
+                LinslExpr::Keyword(k) => assert_eq!("foo", k),
+                _ => panic!(),
+            };
+        }
+        // A lone `:` or `#:` has no name, and is a symbol, as is anything with a `:` later on.
+        for name in [":", "#:", "a:b"] {
+            match parse(&mut tokenizer).unwrap() {
+                LinslExpr::Symbol(s) => assert_eq!(name, s),
+                _ => panic!(),
+            };
+        }
+    }
+
+    #[test]
+    fn parse_string() {
+        let s = r#""a b" "" "(x) ;y" "q\"\\\n\t" 1"#.to_string() + "\n";
+        let mut tokenizer = setup(Box::new(std::io::Cursor::new(s)));
+
+        for expected in ["a b", "", "(x) ;y", "q\"\\\n\t"] {
+            match parse(&mut tokenizer).unwrap() {
+                LinslExpr::String(s) => assert_eq!(expected, &*s),
+                _ => panic!(),
+            };
+        }
+        assert!(matches!(parse(&mut tokenizer).unwrap(), LinslExpr::Number(1.0)));
+
+        for s in ["\"abc\n", "\"abc\\\"\n", "\"a\\qb\"\n"] {
+            let mut tokenizer = setup(Box::new(s.as_bytes()));
+            assert!(matches!(parse(&mut tokenizer), Err(LinslErr::SyntaxError(_, _))), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_vector() {
+        let s = "#(1 (2) #(x))\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+
+        let elems = match parse(&mut tokenizer).unwrap() {
+            LinslExpr::Vector(v) => v.elems().clone(),
+            _ => panic!(),
+        };
+        assert_eq!(3, elems.len());
+        assert!(matches!(elems[0], LinslExpr::Number(1.0)));
+        assert!(matches!(&elems[1], LinslExpr::List(l) if l.len() == 1));
+        assert!(matches!(&elems[2], LinslExpr::Vector(v) if v.elems().len() == 1));
+
+        // Quasi-quoting a vector is an error, whether it is at the top level or nested.
+        for s in ["`#(1 ,x)\n", "`(1 #(2))\n"] {
+            let mut tokenizer = setup(Box::new(s.as_bytes()));
+            assert!(matches!(parse(&mut tokenizer), Err(LinslErr::SyntaxError(_, _))));
+        }
+    }
+
+    /// Reads `#date"2024-01-01"` as (date 2024 1 1).
+    fn read_date(rest: &str, tokenizer: &mut Tokenizer) -> Result<LinslExpr, LinslErr> {
+        let LinslExpr::String(date) = super::parse_string(rest, tokenizer.get_pos())? else {
+            panic!();
+        };
+        let mut exprs = vec![LinslExpr::Symbol(Symbol::new("date"))];
+        for part in date.split('-') {
+            let num = part.parse().map_err(|_| {
+                LinslErr::SyntaxError(format!("Bad date \'{}\'", date), tokenizer.get_pos())
+            })?;
+            exprs.push(LinslExpr::Number(num));
+        }
+        Ok(LinslExpr::List(exprs.into()))
+    }
+
+    #[test]
+    fn reader_dispatch() {
+        let s = "(#date\"2024-01-01\" #d #t #(1) #:k)\n";
+        let mut tokenizer = setup(Box::new(s.as_bytes()));
+        tokenizer.readers.insert("date", read_date);
+        tokenizer.readers.insert("d", |_, _| Ok(LinslExpr::Keyword(Symbol::new("d"))));
+        assert_eq!("((date 2024 1 1) :d #t #(1) :k)", parse(&mut tokenizer).unwrap().to_string());
+
+        // A reader can fail, and report the position of the `#`.
+        let mut tokenizer = setup(Box::new("\n (#date\"2024-x\")\n".as_bytes()));
+        tokenizer.readers.insert("date", read_date);
+        match parse(&mut tokenizer) {
+            Err(LinslErr::SyntaxError(msg, pos)) => {
+                assert_eq!(("Bad date '2024-x'", (1, 2)), (msg.as_str(), pos));
+            },
+            res => panic!("{:?}", res),
+        };
+
+        // Syntax no reader is registered for is an error at the `#`.
+        let unknown = [
+            ("#%foo\n", (0, 0)), ("(a\n  #<closure>)\n", (1, 2)), ("#date\"x\"\n", (0, 0))
+        ];
+        for (s, pos) in unknown {
+            let mut tokenizer = setup(Box::new(s.as_bytes()));
+            match parse(&mut tokenizer) {
+                Err(LinslErr::SyntaxError(msg, p)) => {
+                    assert!(msg.starts_with("Unknown syntax"), "{}", msg);
+                    assert_eq!(pos, p);
+                },
+                res => panic!("{:?}", res),
+            };
+        }
+
+        // The built in syntax can be replaced.
+        let mut tokenizer = setup(Box::new("#t\n".as_bytes()));
+        tokenizer.readers.insert("t", |_, _| Ok(LinslExpr::Number(1.0)));
+        assert!(matches!(parse(&mut tokenizer).unwrap(), LinslExpr::Number(1.0)));
+    }
+
+    #[test]
+    fn read_interpolated() {
+        let strings = [
+            (
+                r#"#"x is ${x} and the sum is ${(+ a b)}""#,
+                r#"(string-append "x is " (->string x) " and the sum is " (->string (+ a b)))"#
+            ),
+            (r#"#"no ${x} here""#, r#"(string-append "no " (->string x) " here")"#),
+            // Without any expressions the literal is a plain string, in which `\${` is a `${`.
+            (r#"#"a \${x} \"b\"\n""#, r#""a ${x} \"b\"\n""#),
+            ("#\"\"", "\"\""),
+            // Braces nest, and do not count within strings.
+            (r#"#"${(f {a {b}})}!""#, r#"(string-append (->string (f {a {b}})) "!")"#),
+            (r#"#"${(g \"}\\\"\")}""#, r#"(string-append (->string (g "}\"")))"#),
+        ];
+        for (s, expected) in strings {
+            let mut tokenizer = setup(Box::new(std::io::Cursor::new(s.to_string() + "\n")));
+            assert_eq!(expected, parse(&mut tokenizer).unwrap().to_string(), "{}", s);
+        }
+
+        // Errors are reported at their position within the literal.
+        let errors = [
+            (r#"(f #"a ${x")"#, "Unterminated '${' in an interpolated string", (0, 7)),
+            (r#"#"${}""#, "Empty '${}' in an interpolated string", (0, 2)),
+            (r#"#"${a b}""#, "Expected a single expression in '${}'", (0, 6)),
+            (r#"#"${)}""#, "Unexpected closing parenthesis.", (0, 4)),
+            (r#"#"a\q""#, "Unknown escape sequence '\\q'", (0, 3)),
+            (r#"#"abc"#, "Unterminated string literal.", (0, 0)),
+        ];
+        for (s, message, pos) in errors {
+            let mut tokenizer = setup(Box::new(std::io::Cursor::new(s.to_string() + "\n")));
+            match parse(&mut tokenizer) {
+                Err(LinslErr::SyntaxError(msg, p)) => assert_eq!((message, pos), (msg.as_str(), p)),
+                res => panic!("{}: {:?}", s, res),
+            };
+        }
+        let mut tokenizer = setup(Box::new("\n  #\"${(+ 1}\"\n".as_bytes()));
+        assert!(matches!(parse(&mut tokenizer), Err(LinslErr::UnbalancedParens(1, 6))));
+    }
+
     #[test]
     fn parse_quasiquote_symbol_no_escape() {
         let s = "`x\n";
@@ -639,6 +1470,27 @@ mod test {
         };
     }
 
+    #[test]
+    fn parse_quasiquote_commas() {
+        let parse_str = |s: &str| parse(&mut setup(Box::new(Cursor::new(format!("{}\n", s)))));
+
+        // ,@ as the last element of a quasi-quoted list splices x into the end of the list.
+        assert_eq!(
+            parse_str("(append (list (quote 1)) (list y) x)").unwrap().to_string(),
+            parse_str("`(1 ,y ,@x)").unwrap().to_string()
+        );
+        assert!(matches!(parse_str("`,@x"), Err(LinslErr::SyntaxError(_, (0, 1)))));
+        assert!(matches!(parse_str("`(a . ,@x)"), Err(LinslErr::SyntaxError(_, (0, 6)))));
+
+        // Outside of a quasi-quote, commas are errors.
+        assert_eq!(
+            "Syntax error at (0, 0): Found ',' outside of a quasi-quote",
+            parse_str(",x").unwrap_err().to_string()
+        );
+        assert!(matches!(parse_str("(a ,@b)"), Err(LinslErr::SyntaxError(_, (0, 3)))));
+        assert!(matches!(parse_str("'(a ,b)"), Err(LinslErr::SyntaxError(_, (0, 4)))));
+    }
+
     #[test]
     fn parse_quasiquote_list_no_escape() {
         let s = "`(1 2)\n";
@@ -701,4 +1553,149 @@ mod test {
             panic!();
         };
     }
+
+    #[test]
+    fn parse_deep_nesting_fails_gracefully() {
+        use std::io::Cursor;
+        use crate::datatypes::DEFAULT_STACK_SIZE;
+
+        let depth = 100_000;
+        let handle = std::thread::Builder::new()
+            .stack_size(DEFAULT_STACK_SIZE)
+            .spawn(move || {
+                for s in [
+                    format!("{}\n", "(".repeat(depth)),
+                    format!("{}x\n", "'".repeat(depth)),
+                    format!("{}x\n", "`".repeat(depth)),
+                    format!("`{}\n", "(".repeat(depth)),
+                    format!("`{}\n", "(,`".repeat(depth)),
+                    format!("{}\n", "#(".repeat(depth)),
+                ] {
+                    let mut tokenizer = setup(Box::new(Cursor::new(s)));
+                    match parse(&mut tokenizer) {
+                        Err(LinslErr::RecursionLimit(d, _)) => assert_eq!(DEFAULT_MAX_NESTING, d),
+                        res => panic!("{:?}", res),
+                    };
+                }
+            })
+            .unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        use std::io::Cursor;
+        use crate::datatypes::DEFAULT_STACK_SIZE;
+
+        let nested = |n: usize| format!("{}1{}\n", "(".repeat(n), ")".repeat(n));
+
+        // The default limit must be reached before the stack overflows.
+        let handle = std::thread::Builder::new()
+            .stack_size(DEFAULT_STACK_SIZE)
+            .spawn(move || {
+                let s = nested(DEFAULT_MAX_NESTING);
+                let mut tokenizer = setup(Box::new(Cursor::new(s)));
+                assert!(parse(&mut tokenizer).is_ok());
+
+                let s = nested(DEFAULT_MAX_NESTING + 1);
+                let mut tokenizer = setup(Box::new(Cursor::new(s)));
+                match parse(&mut tokenizer) {
+                    Err(LinslErr::RecursionLimit(d, _)) => assert_eq!(DEFAULT_MAX_NESTING, d),
+                    _ => panic!(),
+                };
+            })
+            .unwrap();
+        handle.join().unwrap();
+
+        let s = nested(11);
+        let mut tokenizer = setup(Box::new(Cursor::new(s)));
+        tokenizer.max_nesting = 10;
+        assert!(matches!(parse(&mut tokenizer), Err(LinslErr::RecursionLimit(10, _))));
+    }
+
+    /// Whether two parsed expressions are the same, i.e. the same atoms in the same structure.
+    fn same(a: &LinslExpr, b: &LinslExpr) -> bool {
+        match (a, b) {
+            (LinslExpr::Bool(a), LinslExpr::Bool(b)) => a == b,
+            (LinslExpr::Number(a), LinslExpr::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (LinslExpr::Complex(..), LinslExpr::Complex(..)) => a == b,
+            (LinslExpr::Symbol(a), LinslExpr::Symbol(b)) => a == b,
+            (LinslExpr::Keyword(a), LinslExpr::Keyword(b)) => a == b,
+            (LinslExpr::String(a), LinslExpr::String(b)) => a == b,
+            (LinslExpr::List(a), LinslExpr::List(b)) =>
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(a, b)),
+            (LinslExpr::Vector(a), LinslExpr::Vector(b)) => {
+                let (a, b) = (a.elems(), b.elems());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(a, b))
+            },
+            _ => false,
+        }
+    }
+
+    /// Print `expr`, parse what was printed and check that the result is the same expression.
+    fn assert_round_trip(expr: &LinslExpr) {
+        let printed = format!("{}\n", expr);
+        let mut tokenizer = setup(Box::new(std::io::Cursor::new(printed.clone())));
+        let reparsed = parse(&mut tokenizer).unwrap();
+        assert!(same(expr, &reparsed), "{} was read back as {}", printed.trim(), reparsed);
+        assert!(tokenizer.peek().is_none());
+    }
+
+    #[test]
+    fn printed_expressions_parse_back() {
+        let corpus = [
+            "1", "-2.5", "1e300", "inf", "NaN", "#t", "#f", "x", "empty?", "+", "()",
+            "(1 2 3)", "(define f (lambda (x y) (+ x (neg y))))", "'x", "'(a 'b (c))",
+            "`(a ,b ,@c)", "`x", "(if (= 1 2) (quote ()) (list 1 #t x))", "((()))",
+            "(macro (c a b) `(if ,c ,b ,a))", "(a ; A comment\n b)", "#()", "#(1 #t x)",
+            "(#(#(1) (2)) '#(a))", ":key", "#:key", "(f :a 1 :b #:c)", "\"\"",
+            "(\"a (b\" \"\\\"c\\\\\\n\")", "(macro (x) \"Doc\" x)",
+            "(3+4i -2i +i 1e2-0.5i +nan.0+inf.0i)",
+        ];
+        for code in corpus {
+            let mut tokenizer = setup(Box::new(std::io::Cursor::new(format!("{}\n", code))));
+            assert_round_trip(&parse(&mut tokenizer).unwrap());
+        }
+    }
+
+    #[test]
+    fn generated_expressions_parse_back() {
+        // A small linear congruential generator, so that the expressions are the same every run.
+        let mut seed: u64 = 0x5eed;
+        let mut next = move |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let symbols = ["x", "foo-bar", "empty?", "+", "quote", "lambda", "a1", "->", "#tx"];
+
+        fn generate(next: &mut impl FnMut(u64) -> u64, symbols: &[&str], depth: u64) -> LinslExpr {
+            match next(if depth == 0 { 4 } else { 5 }) {
+                0 => LinslExpr::Bool(next(2) == 0),
+                1 => LinslExpr::Number((next(2000) as f64 - 1000.0) / (next(8) + 1) as f64),
+                2 | 3 => {
+                    let i = next(symbols.len() as u64) as usize;
+                    LinslExpr::Symbol(Symbol::new(symbols[i]))
+                },
+                _ => {
+                    let len = next(5);
+                    LinslExpr::List((0..len).map(|_| generate(next, symbols, depth - 1)).collect())
+                },
+            }
+        }
+
+        for _ in 0..1000 {
+            assert_round_trip(&generate(&mut next, &symbols, 6));
+        }
+    }
+
+    #[test]
+    fn indent_code() {
+        let code = "(define f\n(lambda (x)   \n\n  ; the \"(\" is ignored\n\
+                    (list \"(\" x\n#(1\n2))\n))\n(f 1)\n";
+        assert_eq!(
+            "(define f\n  (lambda (x)\n\n    ; the \"(\" is ignored\n    (list \"(\" x\n      #(1\n\
+             \x20       2))\n))\n(f 1)\n",
+            super::indent_code(code)
+        );
+    }
 }
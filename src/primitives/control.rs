@@ -0,0 +1,186 @@
+//! Escapes, parameters, multiple values and foreign values.
+
+use crate::datatypes::{
+    Arity, LinslCtx, LinslErr, LinslEscape, LinslExpr, LinslParameter, LinslRes
+};
+use crate::evaluation::apply;
+use crate::testing::is_failure;
+use super::{expect_arity, expect_n_args, get_string, is_equal, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const CONTROL: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "foreign?",
+        fun: PrimitiveFn::Plain(is_foreign, Arity::Exactly(1)),
+        help: Help::new(
+            "control", "(foreign? x)", "Whether x is a foreign value supplied by the host."
+        ),
+    },
+    PrimitiveEntry {
+        name: "call/ec",
+        fun: PrimitiveFn::Ctx(call_ec, Arity::Exactly(1)),
+        help: Help::new(
+            "control",
+            "(call/ec f)",
+            "Apply f to an escape, which returns its argument from the call/ec when applied."
+        ),
+    },
+    PrimitiveEntry {
+        name: "call-with-escape-continuation",
+        fun: PrimitiveFn::Ctx(call_ec, Arity::Exactly(1)),
+        help: Help::new("control", "(call-with-escape-continuation f)", "The same as call/ec."),
+    },
+    PrimitiveEntry {
+        name: "make-parameter",
+        fun: PrimitiveFn::Plain(make_parameter, Arity::Exactly(1)),
+        help: Help::new(
+            "control",
+            "(make-parameter x)",
+            "A parameter with the value x, which parameterize can override."
+        ),
+    },
+    PrimitiveEntry {
+        name: "values",
+        fun: PrimitiveFn::Plain(values, Arity::AtLeast(0)),
+        help: Help::new(
+            "control",
+            "(values x ...)",
+            "All of the arguments at once, to be given to call-with-values."
+        ),
+    },
+    PrimitiveEntry {
+        name: "call-with-values",
+        fun: PrimitiveFn::Ctx(call_with_values, Arity::Exactly(2)),
+        help: Help::new(
+            "control",
+            "(call-with-values producer consumer)",
+            "Apply consumer to the values producer returns."
+        ),
+    },
+    PrimitiveEntry {
+        name: "assert-equal",
+        fun: PrimitiveFn::Ctx(assert_equal, Arity::Exactly(2)),
+        help: Help::new(
+            "control",
+            "(assert-equal expected x)",
+            "x if it is equal? to expected, or else an error showing both."
+        ),
+    },
+    PrimitiveEntry {
+        name: "assert-error",
+        fun: PrimitiveFn::Ctx(assert_error, Arity::Between(1, 2)),
+        help: Help::new(
+            "control",
+            "(assert-error f msg)",
+            "The message of the error f gives when applied, which must contain msg if given."
+        ),
+    },
+];
+
+/// Check if a single element is a foreign value, i.e. a value supplied by the host application.
+pub fn is_foreign(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+
+    Ok(LinslExpr::Bool(matches!(expr[0], LinslExpr::Foreign(_, _))))
+}
+
+/// Apply a function to an escape, and return what the function returns, or the value the escape
+/// is applied to if it is applied before the function returns. For example,
+/// (call/ec (lambda (return) (+ 1 (return 2)))) becomes 2.
+pub fn call_ec(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let escape = LinslEscape::new();
+    let res = apply(&exprs[0], &[LinslExpr::Escape(escape.clone())], ctx);
+    escape.deactivate();
+    match res {
+        Err(LinslErr::Escape(e, val)) if e.same(&escape) => Ok(val),
+        res => res,
+    }
+}
+
+/// Apply an escape, which unwinds the evaluation up to its call/ec.
+pub fn apply_escape(escape: &LinslEscape, exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    if !escape.is_active() {
+        return Err(
+            LinslErr::SyntaxError(
+                // TODO: Fix pos.
+                "Applied an escape after its call/ec had already returned".to_string(),
+                (0, 0)
+            )
+        );
+    };
+    Err(LinslErr::Escape(escape.clone(), exprs[0].clone()))
+}
+
+/// Create a parameter with the value given, e.g. (define precision (make-parameter 2)), after
+/// which (precision) evaluates to 2.
+pub fn make_parameter(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Parameter(LinslParameter::new(exprs[0].clone())))
+}
+
+/// Apply a parameter, which takes no arguments, and gives its current value.
+pub fn apply_parameter(param: &LinslParameter, exprs: &[LinslExpr], ctx: &LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    Ok(ctx.parameter_value(param))
+}
+
+/// Return any number of values at once, e.g. (values 1 2). A single value is returned as it is, so
+/// (values 1) is just 1.
+pub fn values(exprs: &[LinslExpr]) -> LinslRes {
+    match exprs {
+        [val] => Ok(val.clone()),
+        _ => Ok(LinslExpr::Values(exprs.into())),
+    }
+}
+
+/// Apply a producer to no arguments, and then a consumer to the values it returns. For example,
+/// (call-with-values (lambda () (values 1 2)) +) becomes 3.
+pub fn call_with_values(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    match apply(&exprs[0], &[], ctx)? {
+        LinslExpr::Values(vals) => apply(&exprs[1], &vals, ctx),
+        val => apply(&exprs[1], &[val], ctx),
+    }
+}
+
+
+/// Check that a value is equal? to the one expected, e.g. (assert-equal 3 (+ 1 2)). Returns the
+/// value, or an error at the assertion showing both values if they differ.
+pub fn assert_equal(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    if !is_equal(&exprs[0], &exprs[1]) {
+        return Err(LinslErr::AssertionFailed(
+            format!("Expected \'{}\', found \'{}\'", exprs[0], exprs[1]),
+            ctx.pos
+        ));
+    };
+    Ok(exprs[1].clone())
+}
+
+/// Check that applying a function to no arguments gives an error, e.g.
+/// (assert-error (lambda () (car 1))), and that its message contains the string given, if any.
+/// Returns the message. Failures are reported at the assertion.
+pub fn assert_error(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let pos = ctx.pos;
+    let expected = exprs.get(1).map(get_string).transpose()?;
+    let msg = match apply(&exprs[0], &[], ctx) {
+        Ok(val) => return Err(LinslErr::AssertionFailed(
+            format!("Expected an error, but the function returned \'{}\'", val),
+            pos
+        )),
+        Err(e) if !is_failure(&e) => return Err(e),
+        Err(e) => e.to_string(),
+    };
+    if let Some(expected) = expected
+        && !msg.contains(expected)
+    {
+        return Err(LinslErr::AssertionFailed(
+            format!("Expected an error containing \"{}\", found: {}", expected, msg),
+            pos
+        ));
+    };
+    Ok(LinslExpr::String(msg.into()))
+}
@@ -0,0 +1,211 @@
+//! Dicts.
+
+use crate::datatypes::{Arity, DictKey, LinslDict, LinslErr, LinslExpr, LinslRes};
+use super::{expect_arity, expect_list, expect_n_args, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const DICTS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "make-dict",
+        fun: PrimitiveFn::Plain(make_dict, Arity::AtLeast(0)),
+        help: Help::new(
+            "dicts", "(make-dict k v ...)", "A dict mapping every key k to the value v after it."
+        ),
+    },
+    PrimitiveEntry {
+        name: "dict-set!",
+        fun: PrimitiveFn::Plain(dict_set, Arity::Exactly(3)),
+        help: Help::new("dicts", "(dict-set! d k v)", "Map k to v in d, and return d."),
+    },
+    PrimitiveEntry {
+        name: "dict-ref",
+        fun: PrimitiveFn::Plain(dict_ref, Arity::Between(2, 3)),
+        help: Help::new(
+            "dicts",
+            "(dict-ref d k x)",
+            "The value k maps to in d, or x if there is none and x is given."
+        ),
+    },
+    PrimitiveEntry {
+        name: "dict-remove!",
+        fun: PrimitiveFn::Plain(dict_remove, Arity::Exactly(2)),
+        help: Help::new(
+            "dicts", "(dict-remove! d k)", "Remove the entry for k from d, and return d."
+        ),
+    },
+    PrimitiveEntry {
+        name: "dict-keys",
+        fun: PrimitiveFn::Plain(dict_keys, Arity::Exactly(1)),
+        help: Help::new("dicts", "(dict-keys d)", "A sorted list of the keys of d."),
+    },
+    PrimitiveEntry {
+        name: "dict-has?",
+        fun: PrimitiveFn::Plain(dict_has, Arity::Exactly(2)),
+        help: Help::new("dicts", "(dict-has? d k)", "Whether d has an entry for k."),
+    },
+    PrimitiveEntry {
+        name: "dict->alist",
+        fun: PrimitiveFn::Plain(dict_to_alist, Arity::Exactly(1)),
+        help: Help::new(
+            "dicts", "(dict->alist d)", "A list of (k v) lists, one for every entry of d."
+        ),
+    },
+    PrimitiveEntry {
+        name: "alist->dict",
+        fun: PrimitiveFn::Plain(alist_to_dict, Arity::Exactly(1)),
+        help: Help::new(
+            "dicts", "(alist->dict l)", "A dict with an entry for every (k v) list in l."
+        ),
+    },
+];
+
+/// Retrieve the dict an argument evaluated to, or return an error if it is something else.
+fn get_dict(expr: &LinslExpr) -> Result<&LinslDict, LinslErr> {
+    match expr {
+        LinslExpr::Dict(d) => Ok(d),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a dict, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Create a dict. The arguments are alternating keys and values, so (make-dict 'a 1 'b 2) maps a
+/// to 1 and b to 2; later entries replace earlier ones with the same key.
+pub fn make_dict(exprs: &[LinslExpr]) -> LinslRes {
+    if !exprs.len().is_multiple_of(2) {
+        return Err(
+            LinslErr::SyntaxError(
+                // TODO: Fix pos.
+                format!("Expected keys and values in pairs, found {} arguments", exprs.len()),
+                (0, 0)
+            )
+        );
+    };
+
+    let dict = LinslDict::default();
+    {
+        let mut entries = dict.entries_mut();
+        for pair in exprs.chunks(2) {
+            entries.insert(DictKey::try_from(&pair[0])?, pair[1].clone());
+        }
+    }
+    Ok(LinslExpr::Dict(dict))
+}
+
+/// Map a key to a value in a dict, replacing any previous value, and return the dict.
+pub fn dict_set(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    let key = DictKey::try_from(&exprs[1])?;
+    get_dict(&exprs[0])?.entries_mut().insert(key, exprs[2].clone());
+    Ok(exprs[0].clone())
+}
+
+/// Return the value a key maps to in a dict. If there is none, return the third argument if there
+/// is one, or an error otherwise.
+pub fn dict_ref(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(2, 3))?;
+
+    let key = DictKey::try_from(&exprs[1])?;
+    match (get_dict(&exprs[0])?.entries().get(&key), exprs.get(2)) {
+        (Some(val), _) | (None, Some(val)) => Ok(val.clone()),
+        // TODO: Fix pos.
+        (None, None) => Err(LinslErr::KeyError(key.to_string(), (0, 0))),
+    }
+}
+
+/// Remove a key, and the value it maps to, from a dict if it is there, and return the dict.
+pub fn dict_remove(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let key = DictKey::try_from(&exprs[1])?;
+    get_dict(&exprs[0])?.entries_mut().remove(&key);
+    Ok(exprs[0].clone())
+}
+
+/// Return a list of the keys of a dict, sorted as described for `DictKey`.
+pub fn dict_keys(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let entries = get_dict(&exprs[0])?.sorted();
+    Ok(LinslExpr::List(entries.iter().map(|(k, _)| LinslExpr::from(k)).collect()))
+}
+
+/// Check if a dict has an entry for a key.
+pub fn dict_has(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let key = DictKey::try_from(&exprs[1])?;
+    Ok(LinslExpr::Bool(get_dict(&exprs[0])?.entries().contains_key(&key)))
+}
+
+/// Return the entries of a dict as a list of (key value) lists, sorted by key.
+pub fn dict_to_alist(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let entries = get_dict(&exprs[0])?.sorted();
+    Ok(LinslExpr::List(
+        entries
+            .iter()
+            .map(|(k, v)| LinslExpr::List(vec![LinslExpr::from(k), v.clone()].into()))
+            .collect()
+    ))
+}
+
+/// Create a dict from a list of (key value) lists, i.e. the inverse of dict->alist.
+pub fn alist_to_dict(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let pairs = expect_list(&exprs[0])?;
+
+    let mut args = Vec::with_capacity(pairs.len() * 2);
+    for pair in pairs.iter() {
+        match pair {
+            LinslExpr::List(kv) if kv.len() == 2 => args.extend_from_slice(kv),
+            _ => return Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos.
+                    format!("Expected a (key value) list, found \'{}\'", pair),
+                    (0, 0)
+                )
+            ),
+        }
+    }
+    make_dict(&args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> LinslExpr {
+        LinslExpr::String(s.into())
+    }
+
+    #[test]
+    fn string_keys() {
+        let dict = make_dict(&[]).unwrap();
+        dict_set(&[dict.clone(), string("b"), LinslExpr::Number(2.0)]).unwrap();
+        dict_set(&[dict.clone(), string("a"), LinslExpr::Number(1.0)]).unwrap();
+
+        // Strings with the same text are the same key, however they were made.
+        assert_eq!("1", dict_ref(&[dict.clone(), string("a")]).unwrap().to_string());
+        dict_set(&[dict.clone(), string("a"), LinslExpr::Number(3.0)]).unwrap();
+        assert_eq!("3", dict_ref(&[dict.clone(), string("a")]).unwrap().to_string());
+        assert!(matches!(dict_ref(&[dict.clone(), string("c")]), Err(LinslErr::KeyError(..))));
+        assert_eq!("0", dict_ref(&[dict.clone(), string("c"), LinslExpr::Number(0.0)])
+            .unwrap()
+            .to_string());
+        assert_eq!("(\"a\" \"b\")", dict_keys(std::slice::from_ref(&dict)).unwrap().to_string());
+
+        // Strings are sorted after numbers, and before symbols and keywords.
+        let keys = [
+            LinslExpr::Keyword("k".into()),
+            LinslExpr::Symbol("a".into()),
+            LinslExpr::Number(1.0),
+            LinslExpr::Bool(true),
+        ];
+        for key in keys {
+            dict_set(&[dict.clone(), key, LinslExpr::Number(0.0)]).unwrap();
+        }
+        assert_eq!("(#t 1 \"a\" \"b\" a :k)", dict_keys(&[dict]).unwrap().to_string());
+    }
+}
@@ -0,0 +1,132 @@
+//! Functions made from other functions: partial application, composition and currying.
+//!
+//! The functions made are closures, so that they can be applied, printed and named like any
+//! other. Each one's environment holds only what it captured, along with a primitive doing the
+//! work, so redefining a name such as `partial` does not change the functions made before.
+
+use crate::datatypes::{Arity, LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslRes, Symbol};
+use crate::evaluation::apply;
+use super::{
+    expect_arity, expect_list, expect_n_args, get_index, Help, PrimitiveEntry, PrimitiveFn
+};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const FUNCTIONS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "partial",
+        fun: PrimitiveFn::Plain(partial, Arity::AtLeast(1)),
+        help: Help::new(
+            "functions",
+            "(partial f x ...)",
+            "A function applying f to the arguments x followed by the ones it is given."
+        ),
+    },
+    PrimitiveEntry {
+        name: "compose",
+        fun: PrimitiveFn::Plain(compose, Arity::AtLeast(1)),
+        help: Help::new(
+            "functions",
+            "(compose f ... g)",
+            "A function applying g to the arguments it is given, and then every function before \
+             it to the result, from right to left."
+        ),
+    },
+    PrimitiveEntry {
+        name: "curry",
+        fun: PrimitiveFn::Plain(curry, Arity::Exactly(2)),
+        help: Help::new(
+            "functions",
+            "(curry f n)",
+            "A function collecting arguments until it has n, and then applying f to them."
+        ),
+    },
+];
+
+/// A closure taking any number of arguments, which applies `worker` to the bindings and then a
+/// list of the arguments, e.g. (worker f (x ...) args) for `partial`.
+fn make_closure(
+    worker: fn(&[LinslExpr], &mut LinslCtx) -> LinslRes,
+    bindings: &[(&str, LinslExpr)],
+) -> LinslExpr {
+    let env = LinslEnv::empty();
+    env.insert("worker", LinslExpr::CtxPrimitive("worker", worker));
+    let mut body = vec![LinslExpr::Symbol(Symbol::new("worker"))];
+    for (name, val) in bindings {
+        env.insert(*name, val.clone());
+        body.push(LinslExpr::Symbol(Symbol::new(name)));
+    }
+    body.push(LinslExpr::Symbol(Symbol::new("args")));
+    let params = vec![LinslExpr::Symbol(Symbol::new(".")), LinslExpr::Symbol(Symbol::new("args"))];
+    LinslExpr::Closure(
+        LinslExpr::List(params.into()).into(),
+        LinslExpr::List(body.into()).into(),
+        env,
+        None,
+        None
+    )
+}
+
+/// A function applying f to the arguments given to `partial`, followed by the ones it is given
+/// itself, e.g. ((partial + 1 2) 3) is 6.
+pub fn partial(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::AtLeast(1))?;
+    let bound = LinslExpr::List(exprs[1..].to_vec().into());
+    Ok(make_closure(apply_partial, &[("f", exprs[0].clone()), ("bound", bound)]))
+}
+
+/// Apply f to the arguments bound by `partial` followed by the ones given later.
+fn apply_partial(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    let mut args = expect_list(&exprs[1])?.to_vec();
+    args.extend_from_slice(expect_list(&exprs[2])?);
+    apply(&exprs[0], &args, ctx)
+}
+
+/// A function applying the last of the functions to its arguments, and then each of the others to
+/// the result, from right to left, e.g. ((compose car cdr) '(1 2)) is 2.
+pub fn compose(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::AtLeast(1))?;
+    let fs = LinslExpr::List(exprs.to_vec().into());
+    Ok(make_closure(apply_composed, &[("fs", fs)]))
+}
+
+/// Apply the composed functions to the arguments, the last one first.
+fn apply_composed(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let fs = expect_list(&exprs[0])?;
+    let Some((last, rest)) = fs.split_last() else {
+        return Err(LinslErr::InternalError("Composed no functions".to_string()));
+    };
+    let mut result = apply(last, expect_list(&exprs[1])?, ctx)?;
+    for f in rest.iter().rev() {
+        result = apply(f, std::slice::from_ref(&result), ctx)?;
+    }
+    Ok(result)
+}
+
+/// A function collecting arguments, over as many applications as it takes, until it has n of them,
+/// and then applying f to them, e.g. (((curry + 3) 1 2) 3) is 6. Should the last application bring
+/// more than n, f is applied to all of them.
+pub fn curry(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    get_index(&exprs[1])?;
+    Ok(curried(&exprs[0], &exprs[1], LinslExpr::List(vec![].into())))
+}
+
+fn curried(f: &LinslExpr, n: &LinslExpr, collected: LinslExpr) -> LinslExpr {
+    make_closure(apply_curried, &[("f", f.clone()), ("n", n.clone()), ("collected", collected)])
+}
+
+/// Add the arguments to the ones collected so far, applying f once there are enough of them, or
+/// else returning a function collecting the rest.
+fn apply_curried(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 4)?;
+    let n = get_index(&exprs[1])?;
+    let mut args = expect_list(&exprs[2])?.to_vec();
+    args.extend_from_slice(expect_list(&exprs[3])?);
+    if args.len() >= n {
+        apply(&exprs[0], &args, ctx)
+    } else {
+        Ok(curried(&exprs[0], &exprs[1], LinslExpr::List(args.into())))
+    }
+}
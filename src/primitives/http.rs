@@ -0,0 +1,206 @@
+//! HTTP requests, which need the Net capability. Only built with the `http` feature.
+//!
+//! The requests are made by the ureq client, which speaks both `http` and `https`, using rustls for
+//! the latter.
+
+use std::time::Duration;
+
+use ureq::Agent;
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num, Symbol};
+use super::{
+    expect_arity, expect_list, get_index, get_string, Help, PrimitiveEntry, PrimitiveFn
+};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const HTTP: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "http-get",
+        fun: PrimitiveFn::Ctx(http_get, Arity::Between(1, 2)),
+        help: Help::new(
+            "network",
+            "(http-get url timeout)",
+            "The response to a GET request for url, as a list of the lists (status n), (headers \
+             alist) and (body s). Gives up after timeout milliseconds, if given."
+        ),
+    },
+    PrimitiveEntry {
+        name: "http-post",
+        fun: PrimitiveFn::Ctx(http_post, Arity::Between(2, 4)),
+        help: Help::new(
+            "network",
+            "(http-post url body headers timeout)",
+            "The response to a POST request of the string body to url, as for http-get. headers \
+             is a list of (name value) lists."
+        ),
+    },
+];
+
+/// How many redirects are followed before giving up.
+pub const MAX_REDIRECTS: usize = 5;
+
+/// A response: the status, the headers with lowercase names, and the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// Fail if a part of a request contains a line break, since it would then end the line it is
+/// written on, and whatever follows would be read as headers of its own.
+fn check_line(url: &str, part: &str) -> Result<(), LinslErr> {
+    if part.contains(['\r', '\n']) {
+        return Err(
+            LinslErr::NetError(url.to_string(), format!("\'{}\' contains a line break", part))
+        );
+    };
+    Ok(())
+}
+
+/// Make a request, following redirects. A 303, or a 301 or 302 to a POST, is followed with a GET,
+/// as browsers do.
+fn fetch(
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &str,
+    timeout: Option<Duration>
+) -> Result<Response, LinslErr> {
+    check_line(url, url)?;
+    for (name, value) in headers {
+        check_line(url, name)?;
+        check_line(url, value)?;
+    }
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(timeout)
+        .max_redirects(MAX_REDIRECTS as u32)
+        .max_redirects_will_error(true)
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let net_error = |e: ureq::Error| {
+        let cause = match (e, timeout) {
+            (ureq::Error::Timeout(_), Some(timeout)) => {
+                format!("Timed out after {} ms", timeout.as_millis())
+            },
+            (ureq::Error::TooManyRedirects, _) => {
+                format!("Redirected more than {} times", MAX_REDIRECTS)
+            },
+            (e, _) => e.to_string(),
+        };
+        LinslErr::NetError(url.to_string(), cause)
+    };
+
+    let mut response = if method == "POST" {
+        let mut request = agent.post(url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.send(body).map_err(net_error)?
+    } else {
+        let mut request = agent.get(url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.call().map_err(net_error)?
+    };
+    let headers = response.headers()
+        .iter()
+        .map(|(name, value)| {
+            (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned())
+        })
+        .collect();
+    let body = response.body_mut().read_to_vec().map_err(net_error)?;
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// A response as a list of the lists (status n), (headers alist) and (body s).
+fn response_expr(response: Response) -> LinslExpr {
+    let field = |name: &str, value| {
+        LinslExpr::List(vec![LinslExpr::Symbol(Symbol::new(name)), value].into())
+    };
+    let headers = response.headers.into_iter()
+        .map(|(name, value)| {
+            let pair = vec![LinslExpr::String(name.into()), LinslExpr::String(value.into())];
+            LinslExpr::List(pair.into())
+        })
+        .collect();
+    LinslExpr::List(vec![
+        field("status", LinslExpr::Number(response.status as Num)),
+        field("headers", LinslExpr::List(headers)),
+        field("body", LinslExpr::String(response.body.into())),
+    ].into())
+}
+
+fn get_timeout(expr: Option<&LinslExpr>) -> Result<Option<Duration>, LinslErr> {
+    Ok(expr.map(get_index).transpose()?.map(|ms| Duration::from_millis(ms as u64)))
+}
+
+/// The response to a GET request. Needs the Net capability.
+pub fn http_get(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("http-get")?;
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let url = get_string(&exprs[0])?;
+    let timeout = get_timeout(exprs.get(1))?;
+    fetch("GET", url, &[], "", timeout).map(response_expr)
+}
+
+/// The response to a POST request, with headers given as a list of (name value) lists. Needs the
+/// Net capability.
+pub fn http_post(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("http-post")?;
+    expect_arity(exprs, Arity::Between(2, 4))?;
+    let url = get_string(&exprs[0])?;
+    let body = get_string(&exprs[1])?;
+    let mut headers = Vec::new();
+    if let Some(list) = exprs.get(2) {
+        for header in expect_list(list)?.iter() {
+            match &expect_list(header)?[..] {
+                [name, value] => {
+                    headers.push((get_string(name)?.to_string(), get_string(value)?.to_string()));
+                },
+                _ => return Err(LinslErr::TypeError(
+                    // TODO: Fix pos.
+                    format!(
+                        "Expected a header as a list of a name and a value, found \'{}\'", header
+                    ),
+                    (0, 0)
+                )),
+            }
+        }
+    };
+    let timeout = get_timeout(exprs.get(3))?;
+    fetch("POST", url, &headers, body, timeout).map(response_expr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_breaks() {
+        let post = |url: &str, header: (&str, &str)| {
+            let headers = [(header.0.to_string(), header.1.to_string())];
+            fetch("POST", url, &headers, "", Some(Duration::from_millis(1)))
+        };
+        // A line break anywhere in the head of the request is rejected before connecting.
+        for res in [
+            post("http://localhost/a\r\nX-Injected: 1", ("A", "b")),
+            post("http://localhost/", ("A\r\nX-Injected", "b")),
+            post("http://localhost/", ("A", "b\nX-Injected: 1")),
+        ] {
+            match res {
+                Err(LinslErr::NetError(_, cause)) => {
+                    assert!(cause.contains("line break"), "{}", cause)
+                },
+                res => panic!("{:?}", res),
+            }
+        }
+        assert!(check_line("http://localhost/", "text/plain").is_ok());
+    }
+}
@@ -0,0 +1,203 @@
+//! Introspection, of the evaluation and of procedures.
+
+use std::mem;
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num, Stats, Symbol};
+use crate::evaluation::params_arity;
+use super::{expect_n_args, get_index, Help, PrimitiveEntry, PrimitiveFn, PRIMITIVE_TABLES};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const INTROSPECTION: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "stats",
+        fun: PrimitiveFn::Ctx(stats, Arity::Exactly(0)),
+        help: Help::new(
+            "introspection",
+            "(stats)",
+            "The counters of the work done so far, as a list of (name value) lists."
+        ),
+    },
+    PrimitiveEntry {
+        name: "stats-reset",
+        fun: PrimitiveFn::Ctx(stats_reset, Arity::Exactly(0)),
+        help: Help::new(
+            "introspection", "(stats-reset)", "Start the counters of stats over from zero."
+        ),
+    },
+    PrimitiveEntry {
+        name: "procedure-arity",
+        fun: PrimitiveFn::Plain(procedure_arity, Arity::Exactly(1)),
+        help: Help::new(
+            "introspection",
+            "(procedure-arity f)",
+            "How many arguments f takes, e.g. (2), (2 . variadic) or (1 2), or #f if unknown."
+        ),
+    },
+    PrimitiveEntry {
+        name: "closure-params",
+        fun: PrimitiveFn::Plain(closure_params, Arity::Exactly(1)),
+        help: Help::new(
+            "introspection", "(closure-params f)", "The list of parameters of the lambda f."
+        ),
+    },
+    PrimitiveEntry {
+        name: "closure-body",
+        fun: PrimitiveFn::Plain(closure_body, Arity::Exactly(1)),
+        help: Help::new("introspection", "(closure-body f)", "The body of the lambda f, quoted."),
+    },
+    PrimitiveEntry {
+        name: "set-print-length!",
+        fun: PrimitiveFn::Ctx(set_print_length, Arity::Exactly(1)),
+        help: Help::new(
+            "introspection",
+            "(set-print-length! n)",
+            "Print at most n elements of a list at the REPL, or all of them if n is #f. Returns \
+             the previous limit."
+        ),
+    },
+    PrimitiveEntry {
+        name: "set-print-depth!",
+        fun: PrimitiveFn::Ctx(set_print_depth, Arity::Exactly(1)),
+        help: Help::new(
+            "introspection",
+            "(set-print-depth! n)",
+            "Print lists nested at most n levels deep at the REPL, or however deep if n is #f. \
+             Returns the previous limit."
+        ),
+    },
+];
+
+/// The counters describing the work done by the evaluation so far, as a list of (name value)
+/// lists, e.g. ((evaluations 12) (depth 1) ...).
+pub fn stats(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    let counters = [
+        ("evaluations", ctx.stats.evaluations),
+        ("depth", ctx.depth),
+        ("max-depth", ctx.stats.max_depth),
+        ("frames", ctx.stats.frames()),
+        ("symbols", Symbol::interned()),
+        ("lists", ctx.stats.lists()),
+    ];
+    Ok(LinslExpr::List(
+        counters
+            .iter()
+            .map(|(name, n)| {
+                let pair = vec![LinslExpr::Symbol(Symbol::new(name)), LinslExpr::Number(*n as Num)];
+                LinslExpr::List(pair.into())
+            })
+            .collect()
+    ))
+}
+
+/// Reset the counters returned by stats, except for the number of symbols, which are never
+/// forgotten. Returns the empty list.
+pub fn stats_reset(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    ctx.stats = Stats::new();
+    Ok(LinslExpr::List(vec![].into()))
+}
+
+/// A print limit as a Linsl value, i.e. a number, or #f if there is no limit.
+fn limit_to_expr(limit: Option<usize>) -> LinslExpr {
+    limit.map_or(LinslExpr::Bool(false), |n| LinslExpr::Number(n as Num))
+}
+
+/// A print limit given as a whole, non-negative number, or #f for no limit.
+fn get_limit(expr: &LinslExpr) -> Result<Option<usize>, LinslErr> {
+    match expr {
+        LinslExpr::Bool(false) => Ok(None),
+        _ => get_index(expr).map(Some),
+    }
+}
+
+/// Set how many elements of a list (or vector, dict or multiple values) the REPL prints. Returns
+/// the previous limit.
+pub fn set_print_length(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let length = get_limit(&exprs[0])?;
+    Ok(limit_to_expr(mem::replace(&mut ctx.print_limits.length, length)))
+}
+
+/// Set how deeply nested the lists the REPL prints may be. Returns the previous limit.
+pub fn set_print_depth(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let depth = get_limit(&exprs[0])?;
+    Ok(limit_to_expr(mem::replace(&mut ctx.print_limits.depth, depth)))
+}
+
+/// How many arguments a procedure accepts, as a list: (n) if exactly n, (n . variadic) if at least
+/// n, and (min max) if any number from min to max. For a primitive, it is the arity its table
+/// declares, or #f if it is not in one, e.g. if it was registered by the host. For a case-lambda,
+/// it is a list of the arities of its clauses, e.g. ((1) (2 . variadic)).
+pub fn procedure_arity(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let arity = match &exprs[0] {
+        LinslExpr::Closure(params, ..) => params_arity(params)?,
+        LinslExpr::CaseLambda(clauses) => {
+            let arities = clauses.iter()
+                .map(|clause| procedure_arity(std::slice::from_ref(clause)))
+                .collect::<Result<_, _>>()?;
+            return Ok(LinslExpr::List(arities));
+        },
+        LinslExpr::Primitive(name, _) | LinslExpr::CtxPrimitive(name, _) => {
+            let declared = PRIMITIVE_TABLES.iter()
+                .flat_map(|table| table.iter())
+                .find(|entry| entry.name == *name)
+                .and_then(PrimitiveEntry::arity);
+            match declared {
+                Some(arity) => arity,
+                None => return Ok(LinslExpr::Bool(false)),
+            }
+        },
+        LinslExpr::RecordFn(kind, op) => op.arity(kind),
+        LinslExpr::Escape(_) => Arity::Exactly(1),
+        LinslExpr::Parameter(_) => Arity::Exactly(0),
+        expr => return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a procedure, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    };
+
+    let num = |n: usize| LinslExpr::Number(n as Num);
+    let elems = match arity {
+        Arity::Exactly(n) => vec![num(n)],
+        Arity::AtLeast(n) => vec![
+            num(n),
+            LinslExpr::Symbol(Symbol::new(".")),
+            LinslExpr::Symbol(Symbol::new("variadic")),
+        ],
+        Arity::Between(min, max) => vec![num(min), num(max)],
+    };
+    Ok(LinslExpr::List(elems.into()))
+}
+
+/// Retrieve the parameters and body of the closure an argument evaluated to, or return an error if
+/// it is something else. Primitives have neither, as they are not written in Linsl.
+fn get_closure(expr: &LinslExpr) -> Result<(&LinslExpr, &LinslExpr), LinslErr> {
+    let msg = match expr {
+        LinslExpr::Closure(params, body, ..) => return Ok((params, body)),
+        LinslExpr::Primitive(name, _) | LinslExpr::CtxPrimitive(name, _) => format!(
+            "\'{}\' is a primitive, which is opaque; only lambdas have parameters and a body",
+            name
+        ),
+        _ => format!("Expected a lambda, found \'{}\'", expr),
+    };
+    // TODO: Fix pos.
+    Err(LinslErr::TypeError(msg, (0, 0)))
+}
+
+/// The parameters of a closure, as the list they were written as, e.g. (a . rest).
+pub fn closure_params(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(get_closure(&exprs[0])?.0.clone())
+}
+
+/// The body of a closure, as an expression, e.g. (+ a b).
+pub fn closure_body(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(get_closure(&exprs[0])?.1.clone())
+}
@@ -0,0 +1,261 @@
+//! Reading, hashing and managing files and directories, which needs the Fs capability.
+
+use std::env;
+use std::fs;
+use std::io;
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num};
+use crate::hashing::{hash_file, Digest, Md5, Sha256};
+use super::{expect_n_args, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const IO: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "read-file",
+        fun: PrimitiveFn::Ctx(read_file_string, Arity::Exactly(1)),
+        help: Help::new("files", "(read-file path)", "The contents of the file path."),
+    },
+    PrimitiveEntry {
+        name: "sha256-file",
+        fun: PrimitiveFn::Ctx(sha256_file, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(sha256-file path)",
+            "The SHA-256 digest of the contents of the file path, in hex."
+        ),
+    },
+    PrimitiveEntry {
+        name: "md5-file",
+        fun: PrimitiveFn::Ctx(md5_file, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(md5-file path)",
+            "The MD5 digest of the contents of the file path, in hex. Not cryptographically \
+             strong."
+        ),
+    },
+    PrimitiveEntry {
+        name: "file-exists?",
+        fun: PrimitiveFn::Ctx(file_exists, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(file-exists? path)",
+            "Whether there is a file or directory at path."
+        ),
+    },
+    PrimitiveEntry {
+        name: "directory?",
+        fun: PrimitiveFn::Ctx(is_directory, Arity::Exactly(1)),
+        help: Help::new("files", "(directory? path)", "Whether there is a directory at path."),
+    },
+    PrimitiveEntry {
+        name: "file-size",
+        fun: PrimitiveFn::Ctx(file_size, Arity::Exactly(1)),
+        help: Help::new("files", "(file-size path)", "The size of the file path, in bytes."),
+    },
+    PrimitiveEntry {
+        name: "list-directory",
+        fun: PrimitiveFn::Ctx(list_directory, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(list-directory path)",
+            "The names of the files and directories in the directory path, sorted."
+        ),
+    },
+    PrimitiveEntry {
+        name: "make-directory",
+        fun: PrimitiveFn::Ctx(make_directory, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(make-directory path)",
+            "Create the directory path, along with any parents it is missing. Returns path."
+        ),
+    },
+    PrimitiveEntry {
+        name: "copy-file",
+        fun: PrimitiveFn::Ctx(copy_file, Arity::Exactly(2)),
+        help: Help::new(
+            "files",
+            "(copy-file from to)",
+            "Copy the file from to to, replacing any file there. Returns the number of bytes \
+             copied."
+        ),
+    },
+    PrimitiveEntry {
+        name: "delete-file",
+        fun: PrimitiveFn::Ctx(delete_file, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(delete-file path)",
+            "Delete the file path, which must not be a directory. Returns path."
+        ),
+    },
+    PrimitiveEntry {
+        name: "current-directory",
+        fun: PrimitiveFn::Ctx(current_directory, Arity::Exactly(0)),
+        help: Help::new(
+            "files",
+            "(current-directory)",
+            "The working directory, which relative paths are resolved against, except by require."
+        ),
+    },
+    PrimitiveEntry {
+        name: "chdir",
+        fun: PrimitiveFn::Ctx(chdir, Arity::Exactly(1)),
+        help: Help::new(
+            "files",
+            "(chdir path)",
+            "Make the directory path the working directory. Returns path."
+        ),
+    },
+];
+
+/// The contents of the file at a path, as a string. Needs the Fs capability.
+pub fn read_file_string(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("read-file")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(LinslExpr::String(contents.into())),
+        Err(e) => Err(LinslErr::ReadError(Some(path.to_string()), e.to_string())),
+    }
+}
+
+/// The digest of the contents of the file at a path, as lowercase hexadecimal. The file is read a
+/// chunk at a time, so it need not fit in memory.
+fn digest_file<D: Digest>(name: &str, exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check(name)?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    match hash_file::<D>(path) {
+        Ok(digest) => Ok(LinslExpr::String(digest.into())),
+        Err(e) => Err(LinslErr::ReadError(Some(path.to_string()), e.to_string())),
+    }
+}
+
+/// The SHA-256 digest of the contents of a file. Needs the Fs capability.
+pub fn sha256_file(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    digest_file::<Sha256>("sha256-file", exprs, ctx)
+}
+
+/// The MD5 digest of the contents of a file. Needs the Fs capability.
+pub fn md5_file(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    digest_file::<Md5>("md5-file", exprs, ctx)
+}
+
+/// The error of doing something to the file or directory at a path.
+fn file_error(action: &str, path: &str, cause: impl ToString) -> LinslErr {
+    LinslErr::FileError(action.to_string(), path.to_string(), cause.to_string())
+}
+
+/// The metadata of the file or directory at a path, or None if there is nothing there.
+fn metadata(action: &str, path: &str) -> Result<Option<fs::Metadata>, LinslErr> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(file_error(action, path, e)),
+    }
+}
+
+/// Whether there is a file or directory at a path. Needs the Fs capability.
+pub fn file_exists(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("file-exists?")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    Ok(LinslExpr::Bool(metadata("look for", path)?.is_some()))
+}
+
+/// Whether there is a directory at a path. Needs the Fs capability.
+pub fn is_directory(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("directory?")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    Ok(LinslExpr::Bool(metadata("look for", path)?.is_some_and(|m| m.is_dir())))
+}
+
+/// The size of the file at a path, in bytes. A directory has no size. Needs the Fs capability.
+pub fn file_size(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("file-size")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(file_error("get the size of", path, "It is a directory"))
+        },
+        Ok(metadata) => Ok(LinslExpr::Number(metadata.len() as Num)),
+        Err(e) => Err(file_error("get the size of", path, e)),
+    }
+}
+
+/// The names of the entries of the directory at a path, sorted. Needs the Fs capability.
+pub fn list_directory(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("list-directory")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    let error = |e: io::Error| file_error("list", path, e);
+    if metadata("list", path)?.is_some_and(|m| !m.is_dir()) {
+        return Err(file_error("list", path, "It is not a directory"));
+    };
+    let mut names = fs::read_dir(path)
+        .map_err(error)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<String>, io::Error>>()
+        .map_err(error)?;
+    names.sort();
+    Ok(LinslExpr::List(names.into_iter().map(|name| LinslExpr::String(name.into())).collect()))
+}
+
+/// Create the directory at a path, along with any of its parents which are missing. Returns the
+/// path. Needs the Fs capability.
+pub fn make_directory(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("make-directory")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    fs::create_dir_all(path).map_err(|e| file_error("create", path, e))?;
+    Ok(exprs[0].clone())
+}
+
+/// Copy the file at a path to another, replacing any file there. Returns the number of bytes
+/// copied. Needs the Fs capability.
+pub fn copy_file(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("copy-file")?;
+    expect_n_args(exprs, 2)?;
+    let from = get_string(&exprs[0])?;
+    let to = get_string(&exprs[1])?;
+    match fs::copy(from, to) {
+        Ok(bytes) => Ok(LinslExpr::Number(bytes as Num)),
+        Err(e) => Err(file_error(&format!("copy \'{}\' to", from), to, e)),
+    }
+}
+
+/// Delete the file at a path, which must not be a directory. Returns the path. Needs the Fs
+/// capability.
+pub fn delete_file(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("delete-file")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    if metadata("delete", path)?.is_some_and(|m| m.is_dir()) {
+        return Err(file_error("delete", path, "It is a directory"));
+    };
+    fs::remove_file(path).map_err(|e| file_error("delete", path, e))?;
+    Ok(exprs[0].clone())
+}
+
+/// The working directory of the process. Needs the Fs capability.
+pub fn current_directory(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("current-directory")?;
+    expect_n_args(exprs, 0)?;
+    let dir = env::current_dir().map_err(|e| file_error("find", "the working directory", e))?;
+    Ok(LinslExpr::String(dir.to_string_lossy().into_owned().into()))
+}
+
+/// Change the working directory of the process, which every interpreter in it shares. Files being
+/// run are tracked by their absolute paths, so `require` still searches beside them afterwards.
+/// Returns the path. Needs the Fs capability.
+pub fn chdir(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("chdir")?;
+    expect_n_args(exprs, 1)?;
+    let path = get_string(&exprs[0])?;
+    env::set_current_dir(path).map_err(|e| file_error("change to", path, e))?;
+    Ok(exprs[0].clone())
+}
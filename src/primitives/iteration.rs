@@ -0,0 +1,118 @@
+//! Iteration.
+
+use std::sync::Arc;
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslIter, LinslRes};
+use crate::evaluation::apply;
+use super::{expect_n_args, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const ITERATION: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "iterate",
+        fun: PrimitiveFn::Plain(iterate, Arity::Exactly(1)),
+        help: Help::new(
+            "iteration", "(iterate c)", "An iterator over the list, vector, dict or string c."
+        ),
+    },
+    PrimitiveEntry {
+        name: "iter-next",
+        fun: PrimitiveFn::Plain(iter_next, Arity::Exactly(1)),
+        help: Help::new(
+            "iteration",
+            "(iter-next it)",
+            "A list of the next element and an iterator for the rest, or ()."
+        ),
+    },
+    PrimitiveEntry {
+        name: "map",
+        fun: PrimitiveFn::Ctx(map, Arity::Exactly(2)),
+        help: Help::new("iteration", "(map f c)", "A list of f applied to every element of c."),
+    },
+    PrimitiveEntry {
+        name: "for-each",
+        fun: PrimitiveFn::Ctx(for_each, Arity::Exactly(2)),
+        help: Help::new(
+            "iteration", "(for-each f c)", "Apply f to every element of c in order, and return ()."
+        ),
+    },
+];
+
+/// An iterator over the elements of a list or vector, over the entries of a dict as (key value)
+/// lists, sorted by key, or over the characters of a string. An iterator is itself iterable, and
+/// gives itself.
+pub(crate) fn get_iterator(expr: &LinslExpr) -> Result<Arc<LinslIter>, LinslErr> {
+    let iter = match expr {
+        LinslExpr::Iterator(it) => return Ok(it.clone()),
+        LinslExpr::List(xs) => LinslIter::List(xs.clone()),
+        LinslExpr::Vector(v) => LinslIter::Vector(v.clone(), 0),
+        LinslExpr::Dict(d) => LinslIter::Dict(
+            d.sorted()
+                .into_iter()
+                .map(|(k, v)| LinslExpr::List(vec![LinslExpr::from(&k), v].into()))
+                .collect(),
+            0
+        ),
+        LinslExpr::String(s) => LinslIter::String(s.clone(), 0),
+        _ => return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a list, vector, dict, string or iterator, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    };
+    Ok(Arc::new(iter))
+}
+
+/// Create an iterator over a list, vector, dict or string.
+pub fn iterate(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Iterator(get_iterator(&exprs[0])?))
+}
+
+/// Take an iterator, and return a list of its next element and an iterator for the rest, or the
+/// empty list if there are no more elements. For example, (iter-next (iterate '(1 2))) gives
+/// (1 #<iterator>).
+pub fn iter_next(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let it = match &exprs[0] {
+        LinslExpr::Iterator(it) => it,
+        _ => return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected an iterator, found \'{}\'", exprs[0]),
+                (0, 0)
+            )
+        ),
+    };
+    Ok(LinslExpr::List(match it.next() {
+        Some((x, rest)) => vec![x, LinslExpr::Iterator(Arc::new(rest))].into(),
+        None => vec![].into(),
+    }))
+}
+
+/// Apply a function to every element of something iterable, and return a list of the results. For
+/// example, (map neg #(1 2)) becomes (-1 -2).
+pub fn map(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let mut it = get_iterator(&exprs[1])?.next();
+    let mut vals = Vec::new();
+    while let Some((x, rest)) = it {
+        vals.push(apply(&exprs[0], &[x], ctx)?);
+        it = rest.next();
+    }
+    Ok(LinslExpr::List(vals.into()))
+}
+
+/// Apply a function to every element of something iterable, for its side effects. Returns the
+/// empty list.
+pub fn for_each(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let mut it = get_iterator(&exprs[1])?.next();
+    while let Some((x, rest)) = it {
+        apply(&exprs[0], &[x], ctx)?;
+        it = rest.next();
+    }
+    Ok(LinslExpr::List(vec![].into()))
+}
@@ -0,0 +1,63 @@
+//! Keywords.
+
+use crate::datatypes::{Arity, LinslErr, LinslExpr, LinslRes};
+use super::{expect_n_args, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const KEYWORDS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "keyword?",
+        fun: PrimitiveFn::Plain(is_keyword, Arity::Exactly(1)),
+        help: Help::new("keywords", "(keyword? x)", "Whether x is a keyword."),
+    },
+    PrimitiveEntry {
+        name: "keyword->symbol",
+        fun: PrimitiveFn::Plain(keyword_to_symbol, Arity::Exactly(1)),
+        help: Help::new(
+            "keywords", "(keyword->symbol k)", "The symbol with the same name as the keyword k."
+        ),
+    },
+    PrimitiveEntry {
+        name: "symbol->keyword",
+        fun: PrimitiveFn::Plain(symbol_to_keyword, Arity::Exactly(1)),
+        help: Help::new(
+            "keywords", "(symbol->keyword s)", "The keyword with the same name as the symbol s."
+        ),
+    },
+];
+
+/// Check if a single element is a keyword.
+pub fn is_keyword(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Bool(matches!(exprs[0], LinslExpr::Keyword(_))))
+}
+
+/// Return the symbol with the same name as a keyword, e.g. foo for :foo.
+pub fn keyword_to_symbol(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    match &exprs[0] {
+        LinslExpr::Keyword(k) => Ok(LinslExpr::Symbol(k.clone())),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a keyword, found \'{}\'", exprs[0]),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Return the keyword with the same name as a symbol, e.g. :foo for foo.
+pub fn symbol_to_keyword(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    match &exprs[0] {
+        LinslExpr::Symbol(s) => Ok(LinslExpr::Keyword(s.clone())),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a symbol, found \'{}\'", exprs[0]),
+                (0, 0)
+            )
+        ),
+    }
+}
@@ -0,0 +1,423 @@
+//! Lists.
+
+use std::collections::HashSet;
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslList, LinslRes, Num};
+use crate::evaluation::{apply, is_truthy};
+use super::predicates::{is_equal, is_eqv};
+use super::{expect_arity, expect_list, expect_n_args, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const LISTS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "nil",
+        fun: PrimitiveFn::Value(nil),
+        help: Help::new("lists", "nil", "The empty list, the same as '()."),
+    },
+    PrimitiveEntry {
+        name: "car",
+        fun: PrimitiveFn::Plain(car, Arity::Exactly(1)),
+        help: Help::new("lists", "(car l)", "The first element of the non-empty list l."),
+    },
+    PrimitiveEntry {
+        name: "cdr",
+        fun: PrimitiveFn::Plain(cdr, Arity::Exactly(1)),
+        help: Help::new("lists", "(cdr l)", "The non-empty list l without its first element."),
+    },
+    PrimitiveEntry {
+        name: "empty?",
+        fun: PrimitiveFn::Plain(is_nil, Arity::Exactly(1)),
+        help: Help::new("lists", "(empty? x)", "Whether x is the empty list."),
+    },
+    PrimitiveEntry {
+        name: "list",
+        fun: PrimitiveFn::Plain(list, Arity::AtLeast(0)),
+        help: Help::new("lists", "(list x ...)", "A list of the arguments."),
+    },
+    PrimitiveEntry {
+        name: "append",
+        fun: PrimitiveFn::Plain(append, Arity::AtLeast(1)),
+        help: Help::new(
+            "lists", "(append l ...)", "The elements of the lists, in order, in one list."
+        ),
+    },
+    PrimitiveEntry {
+        name: "memq",
+        fun: PrimitiveFn::Plain(memq, Arity::Exactly(2)),
+        help: Help::new(
+            "lists", "(memq x l)", "The rest of l from the first element eqv? to x, or #f."
+        ),
+    },
+    PrimitiveEntry {
+        name: "member",
+        fun: PrimitiveFn::Plain(member, Arity::Exactly(2)),
+        help: Help::new(
+            "lists", "(member x l)", "The rest of l from the first element equal? to x, or #f."
+        ),
+    },
+    PrimitiveEntry {
+        name: "assq",
+        fun: PrimitiveFn::Plain(assq, Arity::Exactly(2)),
+        help: Help::new(
+            "lists", "(assq k l)", "The first list in l whose first element is eqv? to k, or #f."
+        ),
+    },
+    PrimitiveEntry {
+        name: "assoc",
+        fun: PrimitiveFn::Plain(assoc, Arity::Exactly(2)),
+        help: Help::new(
+            "lists", "(assoc k l)", "The first list in l whose first element is equal? to k, or #f."
+        ),
+    },
+    PrimitiveEntry {
+        name: "flatten",
+        fun: PrimitiveFn::Plain(flatten, Arity::Exactly(1)),
+        help: Help::new(
+            "lists",
+            "(flatten l)",
+            "The elements of l and of every list nested in it, however deeply, in one list."
+        ),
+    },
+    PrimitiveEntry {
+        name: "zip",
+        fun: PrimitiveFn::Plain(zip, Arity::AtLeast(2)),
+        help: Help::new(
+            "lists",
+            "(zip l1 l2 ...)",
+            "A list of lists of the first elements of the lists, the second elements, and so on, \
+             up to the length of the shortest."
+        ),
+    },
+    PrimitiveEntry {
+        name: "partition",
+        fun: PrimitiveFn::Ctx(partition, Arity::Exactly(2)),
+        help: Help::new(
+            "lists",
+            "(partition f l)",
+            "A list of two lists: the elements of l for which f is true, and the rest."
+        ),
+    },
+    PrimitiveEntry {
+        name: "find",
+        fun: PrimitiveFn::Ctx(find, Arity::Exactly(2)),
+        help: Help::new(
+            "lists", "(find f l)", "The first element of l for which f is true, or #f if none."
+        ),
+    },
+    PrimitiveEntry {
+        name: "count",
+        fun: PrimitiveFn::Ctx(count, Arity::Exactly(2)),
+        help: Help::new("lists", "(count f l)", "How many elements of l f is true for."),
+    },
+    PrimitiveEntry {
+        name: "any",
+        fun: PrimitiveFn::Ctx(any, Arity::Exactly(2)),
+        help: Help::new(
+            "lists",
+            "(any f l)",
+            "The first result of f on the elements of l which is true, or #f if there is none."
+        ),
+    },
+    PrimitiveEntry {
+        name: "every",
+        fun: PrimitiveFn::Ctx(every, Arity::Exactly(2)),
+        help: Help::new(
+            "lists",
+            "(every f l)",
+            "#f if f is #f for an element of l, or else the result of f on the last element, or \
+             #t if l is empty."
+        ),
+    },
+    PrimitiveEntry {
+        name: "remove-duplicates",
+        fun: PrimitiveFn::Plain(remove_duplicates, Arity::Exactly(1)),
+        help: Help::new(
+            "lists",
+            "(remove-duplicates l)",
+            "l without the elements equal? to an earlier one."
+        ),
+    },
+];
+
+/// The empty list, which nil is bound to.
+fn nil() -> LinslExpr {
+    LinslExpr::List(LinslList::default())
+}
+
+/// Return the first element of a list.
+pub fn car(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+
+    match expect_list(&expr[0])?.first() {
+        Some(e) => Ok(e.clone()),
+        None => Err(empty_list("car")),
+    }
+}
+
+/// The error for taking the car or cdr of the empty list, which is an error rather than giving
+/// some value, so that walking past the end of a list is noticed where it happens.
+fn empty_list(name: &str) -> LinslErr {
+    // TODO: Fix pos.
+    LinslErr::TypeError(format!("{} was given the empty list", name), (0, 0))
+}
+
+/// Return the tail of a list.
+pub fn cdr(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+
+    let linsl_exprs = expect_list(&expr[0])?;
+    if linsl_exprs.is_empty() {
+        return Err(empty_list("cdr"));
+    };
+    // Taking the tail shares the elements rather than copying them, so walking a list with cdr
+    // takes linear rather than quadratic time.
+    Ok(LinslExpr::List(linsl_exprs.tail()))
+}
+
+pub fn is_nil(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+
+    match &expr[0] {
+        LinslExpr::List(linsl_exprs) => Ok(
+            LinslExpr::Bool(linsl_exprs.is_empty())
+        ),
+        _ => Ok(
+            LinslExpr::Bool(false)
+        )
+    }
+}
+
+/// Take an arbitrary number of elements, and return a list containing those elements. For example,
+/// (list 1 + 2) becomes (1 + 2), and (list) becomes ().
+pub fn list(exprs: &[LinslExpr]) -> LinslRes {
+    Ok(LinslExpr::List(exprs.to_vec().into()))
+}
+
+/// Combine supplied lists to one list, in the order they appear. That is,
+/// (append '(1) '(2) '(3)) becomes (1 2 3).
+///
+/// If only supplied with a single list, return that list.
+pub fn append(exprs: &[LinslExpr]) -> LinslRes {
+    // First, ensure that arguments were supplied.
+    expect_arity(exprs, Arity::AtLeast(1))?;
+
+    // If only a single argument was supplied, ensure it is a list and then return it.
+    if exprs.len() == 1 {
+        Ok(LinslExpr::List(expect_list(&exprs[0])?.clone()))
+    } else {
+        // Since we know that there are at least two arguments we create a vector to store all the
+        // arguments in..
+        let mut vec: Vec<LinslExpr> = Vec::new();
+        let mut pos: usize = 0;
+        // We then iterate over the arguments
+        while pos < exprs.len() {
+            // extracting their elements (returning an error for a non-list argument)
+            // and adding those to the vector created above.
+            vec.extend_from_slice(expect_list(&exprs[pos])?);
+            pos += 1;
+        };
+        // Finally, return a new list with all the elements from the lists supplied.
+        Ok(LinslExpr::List(vec.into()))
+    }
+}
+
+/// The first sublist of a list which starts with an element the same as x, or #f if there is
+/// none, e.g. (memq 'c '(a b c d)) is (c d). Elements are compared using eqv?.
+pub fn memq(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    find_sublist(&exprs[0], &exprs[1], is_eqv)
+}
+
+/// Like memq, but comparing the elements using equal?.
+pub fn member(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    find_sublist(&exprs[0], &exprs[1], is_equal)
+}
+
+fn find_sublist(
+    x: &LinslExpr,
+    l: &LinslExpr,
+    same: fn(&LinslExpr, &LinslExpr) -> bool
+) -> LinslRes {
+    let mut l = expect_list(l)?.clone();
+    while let Some(head) = l.first() {
+        if same(x, head) {
+            return Ok(LinslExpr::List(l));
+        };
+        l = l.tail();
+    }
+    Ok(LinslExpr::Bool(false))
+}
+
+/// The first (key value ...) list in a list of them whose key is the same as k, or #f if there
+/// is none, e.g. (assq 'b '((a 1) (b 2))) is (b 2). Keys are compared using eqv?.
+pub fn assq(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    find_entry("assq", &exprs[0], &exprs[1], is_eqv)
+}
+
+/// Like assq, but comparing the keys using equal?.
+pub fn assoc(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    find_entry("assoc", &exprs[0], &exprs[1], is_equal)
+}
+
+fn find_entry(
+    name: &str,
+    k: &LinslExpr,
+    alist: &LinslExpr,
+    same: fn(&LinslExpr, &LinslExpr) -> bool
+) -> LinslRes {
+    for entry in expect_list(alist)?.iter() {
+        match entry {
+            LinslExpr::List(pair) if !pair.is_empty() => if same(k, &pair[0]) {
+                return Ok(entry.clone());
+            },
+            _ => return Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos.
+                    format!("{} expected a list of non-empty lists, found \'{}\'", name, entry),
+                    (0, 0)
+                )
+            ),
+        }
+    }
+    Ok(LinslExpr::Bool(false))
+}
+
+/// The elements of a list and of the lists nested in it, however deeply, in the order they are
+/// written, e.g. (flatten '(1 (2 (3)) () 4)) is (1 2 3 4). Vectors are elements like any other.
+pub fn flatten(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let mut flat = Vec::new();
+    // The lists being walked, innermost last, so that deep nesting does not use up the stack.
+    let mut stack = vec![expect_list(&exprs[0])?.iter()];
+    while let Some(elems) = stack.last_mut() {
+        match elems.next() {
+            Some(LinslExpr::List(l)) => stack.push(l.iter()),
+            Some(elem) => flat.push(elem.clone()),
+            None => {
+                stack.pop();
+            },
+        }
+    }
+    Ok(LinslExpr::List(flat.into()))
+}
+
+/// A list of lists of the elements at the same index in each of the lists, e.g.
+/// (zip '(1 2 3) '(a b)) is ((1 a) (2 b)). It is as long as the shortest of the lists.
+pub fn zip(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::AtLeast(2))?;
+    let lists = exprs.iter().map(expect_list).collect::<Result<Vec<_>, _>>()?;
+    let len = lists.iter().map(|l| l.len()).min().unwrap_or(0);
+    let zipped = (0..len).map(|i| LinslExpr::List(lists.iter().map(|l| l[i].clone()).collect()));
+    Ok(LinslExpr::List(zipped.collect()))
+}
+
+/// Split a list into the elements a function is true for and the rest, keeping their order, e.g.
+/// (partition even? '(1 2 3 4)) is ((2 4) (1 3)).
+pub fn partition(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let (mut matching, mut rest) = (Vec::new(), Vec::new());
+    for elem in expect_list(&exprs[1])?.iter() {
+        if is_truthy(&apply(&exprs[0], std::slice::from_ref(elem), ctx)?) {
+            matching.push(elem.clone());
+        } else {
+            rest.push(elem.clone());
+        }
+    }
+    Ok(LinslExpr::List(vec![LinslExpr::List(matching.into()), LinslExpr::List(rest.into())].into()))
+}
+
+/// The first element of a list a function is true for, or #f if there is none, e.g.
+/// (find (lambda (x) (> x 2)) '(1 3 5)) is 3. The function is not applied to the elements after it.
+pub fn find(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    for elem in expect_list(&exprs[1])?.iter() {
+        if is_truthy(&apply(&exprs[0], std::slice::from_ref(elem), ctx)?) {
+            return Ok(elem.clone());
+        };
+    }
+    Ok(LinslExpr::Bool(false))
+}
+
+/// How many elements of a list a function is true for.
+pub fn count(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let mut count = 0;
+    for elem in expect_list(&exprs[1])?.iter() {
+        if is_truthy(&apply(&exprs[0], std::slice::from_ref(elem), ctx)?) {
+            count += 1;
+        };
+    }
+    Ok(LinslExpr::Number(count as Num))
+}
+
+/// The first result of a function on the elements of a list which is true, or #f if there is
+/// none, e.g. (any (lambda (x) (assq x al)) '(a b)) is the first entry found. The function is not
+/// applied to the elements after it.
+pub fn any(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    for elem in expect_list(&exprs[1])?.iter() {
+        let result = apply(&exprs[0], std::slice::from_ref(elem), ctx)?;
+        if is_truthy(&result) {
+            return Ok(result);
+        };
+    }
+    Ok(LinslExpr::Bool(false))
+}
+
+/// #f as soon as a function is #f for an element of a list, or else its result on the last
+/// element, or #t if the list is empty. The function is not applied to the elements after the
+/// first one it is #f for.
+pub fn every(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let mut result = LinslExpr::Bool(true);
+    for elem in expect_list(&exprs[1])?.iter() {
+        result = apply(&exprs[0], std::slice::from_ref(elem), ctx)?;
+        if !is_truthy(&result) {
+            break;
+        };
+    }
+    Ok(result)
+}
+
+/// A list without the elements equal? to an earlier one, e.g. (remove-duplicates '(1 (2) 1 (2)))
+/// is (1 (2)). Elements which `hashable` allows are looked up in a set, and only the others, such
+/// as vectors, are compared with every element kept before them.
+// Only the addresses of the mutable parts of an element, e.g. a closure's environment, are hashed.
+#[allow(clippy::mutable_key_type)]
+pub fn remove_duplicates(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let mut seen = HashSet::new();
+    let mut unhashable: Vec<&LinslExpr> = Vec::new();
+    let mut kept = Vec::new();
+    for elem in expect_list(&exprs[0])?.iter() {
+        let new = if hashable(elem) {
+            seen.insert(elem)
+        } else if unhashable.iter().any(|other| is_equal(elem, other)) {
+            false
+        } else {
+            unhashable.push(elem);
+            true
+        };
+        if new {
+            kept.push(elem.clone());
+        };
+    }
+    Ok(LinslExpr::List(kept.into()))
+}
+
+/// Whether equality of an expression, as `Eq` has it, agrees with equal?. It does not for vectors,
+/// records and dicts, which equal? compares by their contents, nor for NaN, which equal? takes to
+/// differ from itself. Nothing for which it does not is equal? to anything for which it does.
+fn hashable(expr: &LinslExpr) -> bool {
+    match expr {
+        LinslExpr::Number(n) => !n.is_nan(),
+        LinslExpr::Complex(re, im) => !re.is_nan() && !im.is_nan(),
+        LinslExpr::Dict(_) | LinslExpr::Record(_) | LinslExpr::Vector(_) => false,
+        LinslExpr::List(xs) => xs.iter().all(hashable),
+        LinslExpr::Values(xs) => xs.iter().all(hashable),
+        _ => true,
+    }
+}
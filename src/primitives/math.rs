@@ -0,0 +1,216 @@
+//! Arithmetic, on real and complex numbers. A real number mixed with complex ones is taken to be
+//! complex, with an imaginary part of zero, and a result whose imaginary part is zero is real.
+
+use crate::datatypes::{Arity, LinslErr, LinslExpr, LinslRes, Num};
+use super::{expect_arity, expect_n_args, expect_num, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const MATH: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "+",
+        fun: PrimitiveFn::Plain(add, Arity::AtLeast(0)),
+        help: Help::new("arithmetic", "(+ x ...)", "The sum of the numbers."),
+    },
+    PrimitiveEntry {
+        name: "neg",
+        fun: PrimitiveFn::Plain(neg, Arity::Between(0, 1)),
+        help: Help::new("arithmetic", "(neg x)", "The negation of the number x."),
+    },
+    PrimitiveEntry {
+        name: "*",
+        fun: PrimitiveFn::Plain(mul, Arity::AtLeast(0)),
+        help: Help::new("arithmetic", "(* x ...)", "The product of the numbers."),
+    },
+    PrimitiveEntry {
+        name: "inv",
+        fun: PrimitiveFn::Plain(inv, Arity::Exactly(1)),
+        help: Help::new("arithmetic", "(inv x)", "The reciprocal of the non-zero number x."),
+    },
+    PrimitiveEntry {
+        name: "nan?",
+        fun: PrimitiveFn::Plain(is_nan, Arity::Exactly(1)),
+        help: Help::new("arithmetic", "(nan? x)", "Whether the number x is +nan.0."),
+    },
+    PrimitiveEntry {
+        name: "infinite?",
+        fun: PrimitiveFn::Plain(is_infinite, Arity::Exactly(1)),
+        help: Help::new("arithmetic", "(infinite? x)", "Whether the number x is +inf.0 or -inf.0."),
+    },
+    PrimitiveEntry {
+        name: "finite?",
+        fun: PrimitiveFn::Plain(is_finite, Arity::Exactly(1)),
+        help: Help::new(
+            "arithmetic", "(finite? x)", "Whether the number x is neither infinite nor NaN."
+        ),
+    },
+    PrimitiveEntry {
+        name: "make-rectangular",
+        fun: PrimitiveFn::Plain(make_rectangular, Arity::Exactly(2)),
+        help: Help::new(
+            "arithmetic",
+            "(make-rectangular x y)",
+            "The complex number x+yi, of the real numbers x and y."
+        ),
+    },
+    PrimitiveEntry {
+        name: "real-part",
+        fun: PrimitiveFn::Plain(real_part, Arity::Exactly(1)),
+        help: Help::new("arithmetic", "(real-part z)", "The real part of the number z."),
+    },
+    PrimitiveEntry {
+        name: "imag-part",
+        fun: PrimitiveFn::Plain(imag_part, Arity::Exactly(1)),
+        help: Help::new(
+            "arithmetic", "(imag-part z)", "The imaginary part of the number z, 0 if it is real."
+        ),
+    },
+    PrimitiveEntry {
+        name: "magnitude",
+        fun: PrimitiveFn::Plain(magnitude, Arity::Exactly(1)),
+        help: Help::new(
+            "arithmetic", "(magnitude z)", "The absolute value of the number z, real or complex."
+        ),
+    },
+    PrimitiveEntry {
+        name: "angle",
+        fun: PrimitiveFn::Plain(angle, Arity::Exactly(1)),
+        help: Help::new(
+            "arithmetic",
+            "(angle z)",
+            "The angle of the number z from the positive real axis, from -pi to pi."
+        ),
+    },
+];
+
+/// Retrieve the real and imaginary parts of the number an argument evaluated to, or return an
+/// error if it is something else. The imaginary part of a real number is 0.
+fn expect_parts(expr: &LinslExpr) -> Result<(Num, Num), LinslErr> {
+    match expr {
+        LinslExpr::Complex(re, im) => Ok((*re, *im)),
+        _ => Ok((expect_num(expr)?, 0 as Num)),
+    }
+}
+
+/// Compute the sum of a list of (numeric) arguments.
+pub fn add(exprs: &[LinslExpr]) -> LinslRes {
+    let parts = exprs.iter().map(expect_parts).collect::<Result<Vec<_>, LinslErr>>()?;
+    let re = parts.iter().map(|(re, _)| re).sum();
+    let im = parts.iter().map(|(_, im)| im).sum();
+    Ok(LinslExpr::complex(re, im))
+}
+
+/// Compute the product of a list of (numeric) arguments.
+pub fn mul(exprs: &[LinslExpr]) -> LinslRes {
+    if !exprs.iter().any(|expr| matches!(expr, LinslExpr::Complex(..))) {
+        let mul = exprs.iter().map(expect_num).product::<Result<Num, LinslErr>>()?;
+        return Ok(LinslExpr::Number(mul));
+    };
+    let mut product = (1 as Num, 0 as Num);
+    for expr in exprs {
+        product = mul_parts(product, expect_parts(expr)?);
+    }
+    Ok(LinslExpr::complex(product.0, product.1))
+}
+
+/// The product of two complex numbers. A real factor scales the parts of the other one, so that
+/// e.g. an infinite real part does not make the imaginary part NaN by being multiplied by 0.
+fn mul_parts((a, b): (Num, Num), (c, d): (Num, Num)) -> (Num, Num) {
+    if b == 0 as Num {
+        (a * c, a * d)
+    } else if d == 0 as Num {
+        (a * c, b * c)
+    } else {
+        (a * c - b * d, a * d + b * c)
+    }
+}
+
+/// Negate a single element, or give 0 if there is none.
+pub fn neg(expr: &[LinslExpr]) -> LinslRes {
+    expect_arity(expr, Arity::Between(0, 1))?;
+
+    if let Some(LinslExpr::Complex(re, im)) = expr.first() {
+        return Ok(LinslExpr::Complex(-re, -im));
+    };
+    let mut num : Num = 0 as Num;
+    if !expr.is_empty() {
+        num = expect_num(&expr[0])?;
+    }
+    Ok(LinslExpr::Number(-num))
+}
+
+/// Compute the multiplicative inverse of a (numeric) argument.
+pub fn inv(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+
+    // The imaginary part of a complex number is never 0, so neither is the number.
+    if let LinslExpr::Complex(re, im) = &expr[0] {
+        let norm = re * re + im * im;
+        return Ok(LinslExpr::complex(re / norm, -im / norm));
+    };
+
+    let num = expect_num(&expr[0])?;
+
+    if num == 0 as Num {
+        return Err(
+            // TODO: Fix pos.
+            LinslErr::SyntaxError(
+                "Cannot invert 0".to_string(),
+                (0, 0)
+            )
+        );
+    };
+
+    Ok(LinslExpr::Number(1 as Num/num))
+}
+
+/// The complex number with the real parts given, e.g. (make-rectangular 3 4) is 3+4i. If the
+/// imaginary part is 0, the number is real.
+pub fn make_rectangular(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::complex(expect_num(&exprs[0])?, expect_num(&exprs[1])?))
+}
+
+/// The real part of a number, which is the number itself if it is real.
+pub fn real_part(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Number(expect_parts(&exprs[0])?.0))
+}
+
+/// The imaginary part of a number, which is 0 if it is real.
+pub fn imag_part(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Number(expect_parts(&exprs[0])?.1))
+}
+
+/// The absolute value of a number, i.e. its distance from 0 in the complex plane.
+pub fn magnitude(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let (re, im) = expect_parts(&exprs[0])?;
+    Ok(LinslExpr::Number(re.hypot(im)))
+}
+
+/// The angle of a number from the positive real axis, in radians from -pi to pi. The angle of a
+/// negative real number is pi.
+pub fn angle(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let (re, im) = expect_parts(&exprs[0])?;
+    Ok(LinslExpr::Number(im.atan2(re)))
+}
+
+/// Check if a single number is NaN, i.e. not a number.
+pub fn is_nan(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+    Ok(LinslExpr::Bool(expect_num(&expr[0])?.is_nan()))
+}
+
+/// Check if a single number is positive or negative infinity.
+pub fn is_infinite(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+    Ok(LinslExpr::Bool(expect_num(&expr[0])?.is_infinite()))
+}
+
+/// Check if a single number is neither infinite nor NaN.
+pub fn is_finite(expr: &[LinslExpr]) -> LinslRes {
+    expect_n_args(expr, 1)?;
+    Ok(LinslExpr::Bool(expect_num(&expr[0])?.is_finite()))
+}
@@ -0,0 +1,412 @@
+//! The built in functions/forms. Here we define precisely as much as we need to to be able to
+//! define any other functions/macros we desire in Linsl code.
+//!
+//! The primitives are split into a module per group, each with a table of its primitives, which
+//! is what `LinslEnvBuilder` registers and `help` documents. The functions of the primitives check
+//! their own arguments, using the helpers below, so that a wrong number of arguments always gives
+//! an ArityError and an argument of the wrong type a TypeError.
+
+use crate::datatypes::{Arity, LinslCtx, LinslList, LinslRes, Num, PosNum};
+use crate::{LinslExpr, LinslErr};
+
+pub mod control;
+pub mod dicts;
+pub mod functions;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod introspection;
+pub mod io;
+pub mod iteration;
+pub mod keywords;
+pub mod lists;
+pub mod math;
+pub mod net;
+pub mod paths;
+pub mod patterns;
+pub mod predicates;
+pub mod random;
+pub mod records;
+pub mod strings;
+pub mod testing;
+pub mod time;
+pub mod vectors;
+
+pub use control::*;
+pub use dicts::*;
+pub use functions::*;
+#[cfg(feature = "http")]
+pub use http::*;
+pub use introspection::*;
+pub use io::*;
+pub use iteration::*;
+pub use keywords::*;
+pub use lists::*;
+pub use math::*;
+pub use net::*;
+pub use paths::*;
+pub use patterns::*;
+pub use predicates::*;
+pub use random::*;
+pub use records::*;
+pub use strings::*;
+pub use testing::*;
+pub use time::*;
+pub use vectors::*;
+
+/// What a primitive is bound to.
+#[derive(Debug, Clone, Copy)]
+pub enum PrimitiveFn {
+    /// A function of its arguments, accepting as many as the arity allows.
+    Plain(fn(&[LinslExpr]) -> LinslRes, Arity),
+    /// A function which also needs the context of the evaluation, e.g. to apply its arguments.
+    Ctx(fn(&[LinslExpr], &mut LinslCtx) -> LinslRes, Arity),
+    /// A value rather than a function, e.g. nil.
+    Value(fn() -> LinslExpr),
+}
+
+/// An entry of the table of primitives of a module.
+#[derive(Debug, Clone, Copy)]
+pub struct PrimitiveEntry {
+    pub name: &'static str,
+    pub fun: PrimitiveFn,
+    pub help: Help,
+}
+
+impl PrimitiveEntry {
+    /// The value the name of the primitive is bound to.
+    pub fn value(&self) -> LinslExpr {
+        match self.fun {
+            PrimitiveFn::Plain(f, _) => LinslExpr::Primitive(self.name, f),
+            PrimitiveFn::Ctx(f, _) => LinslExpr::CtxPrimitive(self.name, f),
+            PrimitiveFn::Value(f) => f(),
+        }
+    }
+
+    /// How many arguments the primitive accepts, or None if it is not a function.
+    pub fn arity(&self) -> Option<Arity> {
+        match self.fun {
+            PrimitiveFn::Plain(_, arity) | PrimitiveFn::Ctx(_, arity) => Some(arity),
+            PrimitiveFn::Value(_) => None,
+        }
+    }
+}
+
+/// Ensure that a primitive was supplied with `n` arguments.
+pub(crate) fn expect_n_args(exprs: &[LinslExpr], n: usize) -> Result<(), LinslErr> {
+    expect_arity(exprs, Arity::Exactly(n))
+}
+
+/// Ensure that a primitive was supplied with as many arguments as it accepts. The error does not
+/// name the primitive; that is done where it is applied.
+pub(crate) fn expect_arity(exprs: &[LinslExpr], arity: Arity) -> Result<(), LinslErr> {
+    if !arity.accepts(exprs.len()) {
+        // TODO: Fix pos.
+        return Err(LinslErr::ArityError(String::new(), arity, exprs.len(), (0, 0)));
+    };
+    Ok(())
+}
+
+/// Retrieve the number an argument evaluated to, or return an error if it is something else.
+pub(crate) fn expect_num(expr: &LinslExpr) -> Result<Num, LinslErr> {
+    match expr {
+        LinslExpr::Number(num) => Ok(*num),
+        LinslExpr::Complex(..) => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a real number, found the complex number \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a number, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Retrieve the list an argument evaluated to, or return an error if it is something else.
+pub(crate) fn expect_list(expr: &LinslExpr) -> Result<&LinslList, LinslErr> {
+    match expr {
+        LinslExpr::List(l) => Ok(l),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a list, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Retrieve an index (or length), i.e. a whole, non-negative number.
+pub(crate) fn get_index(expr: &LinslExpr) -> Result<PosNum, LinslErr> {
+    let num = expect_num(expr)?;
+    if num < 0 as Num || num.fract() != 0 as Num {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a whole, non-negative number, found {}", num),
+                (0, 0)
+            )
+        );
+    };
+    Ok(num as PosNum)
+}
+
+/// Retrieve the string an argument evaluated to, or return an error if it is something else.
+pub(crate) fn get_string(expr: &LinslExpr) -> Result<&str, LinslErr> {
+    match expr {
+        LinslExpr::String(s) => Ok(s),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a string, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// The documentation of a built in, as given by `help`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Help {
+    /// The group the built in is listed under by `(help)`.
+    pub category: &'static str,
+    /// How the built in is applied, e.g. `(car l)`.
+    pub usage: &'static str,
+    /// What it does, in a sentence.
+    pub description: &'static str,
+}
+
+impl Help {
+    pub const fn new(
+        category: &'static str,
+        usage: &'static str,
+        description: &'static str
+    ) -> Help {
+        Help { category, usage, description }
+    }
+}
+
+/// The tables of primitives, in the order `(help)` lists them.
+pub const PRIMITIVE_TABLES: &[&[PrimitiveEntry]] = &[
+    MATH, PREDICATES, RANDOM, TIME, LISTS, VECTORS, DICTS, STRINGS, PATHS, PATTERNS, IO, NET,
+    #[cfg(feature = "http")]
+    HTTP,
+    KEYWORDS, ITERATION, CONTROL, FUNCTIONS, INTROSPECTION, TESTING,
+];
+
+/// The documentation of the primitives in the default environment, in the order `(help)` lists
+/// them. Primitives registered by the host are documented with `LinslEnv::insert_with_help`.
+pub fn primitive_help() -> impl Iterator<Item = (&'static str, Help)> {
+    PRIMITIVE_TABLES.iter().flat_map(|table| table.iter()).map(|entry| (entry.name, entry.help))
+}
+
+/// The documentation of the special forms, which are always listed by `(help)`.
+pub const SPECIAL_FORM_HELP: &[(&str, Help)] = &[
+    ("define", Help::new(
+        "special-forms",
+        "(define name x)",
+        "Bind name to the value of x in the current frame, and return the value."
+    )),
+    ("define-record", Help::new(
+        "special-forms",
+        "(define-record name (field ...))",
+        "Define a record type and its functions."
+    )),
+    ("delay", Help::new(
+        "special-forms", "(delay x)", "A promise to evaluate x when it is forced."
+    )),
+    ("doc", Help::new(
+        "special-forms", "(doc f)", "The docstring of the lambda or macro f, or #f."
+    )),
+    ("defconst", Help::new(
+        "special-forms",
+        "(defconst name x)",
+        "Like define, but name can not be bound again in the same frame."
+    )),
+    ("define-values", Help::new(
+        "special-forms",
+        "(define-values (name ...) x)",
+        "Bind each name to one of the values x returns, in the current frame, and return the \
+         values. A last name preceded by . is bound to a list of the values left over."
+    )),
+    ("force", Help::new(
+        "special-forms", "(force p)", "The value of the promise p, which is computed only once."
+    )),
+    ("help", Help::new(
+        "special-forms",
+        "(help 'name)",
+        "How to use a built in, or a list of all of them if no name is given."
+    )),
+    ("if", Help::new(
+        "special-forms",
+        "(if test then [else])",
+        "Evaluate else (or return () without one) if test is #f, and then otherwise."
+    )),
+    ("import", Help::new(
+        "special-forms",
+        "(import name) or (import (name prefix))",
+        "Bind the names the module exports, with the prefix (if given) in front of them."
+    )),
+    ("lambda", Help::new(
+        "special-forms",
+        "(lambda (param ...) doc body)",
+        "A function of the parameters, with an optional docstring. A parameter may be a list of \
+         parameters, which takes apart the list it is given, and `_` binds nothing."
+    )),
+    ("case-lambda", Help::new(
+        "special-forms",
+        "(case-lambda ((param ...) body) ...)",
+        "A function with a clause for each number of arguments it accepts, applying the body of \
+         the first clause whose parameters fit the arguments it is given."
+    )),
+    ("let", Help::new(
+        "special-forms",
+        "(let ((pattern x) ...) body)",
+        "Evaluate body with each pattern bound to the value of its x, as the parameters of a \
+         lambda are."
+    )),
+    ("let-values", Help::new(
+        "special-forms",
+        "(let-values (((param ...) x) ...) body)",
+        "Evaluate body with the parameters of each binding bound to the values its x returns."
+    )),
+    ("macro", Help::new(
+        "special-forms",
+        "(macro (param ...) doc body)",
+        "Like lambda, but given its argument forms rather than their values."
+    )),
+    ("match", Help::new(
+        "special-forms",
+        "(match x (pattern body ...) (pattern #:when test body ...) ...)",
+        "Evaluate the body of the first clause whose pattern matches the value of x, and whose \
+         test, if it has one, is true, with the symbols of the pattern bound. A pattern is `_`, a \
+         symbol, a literal, a quoted datum or a list of patterns, possibly ending in . rest."
+    )),
+    ("module", Help::new(
+        "special-forms",
+        "(module name (export x ...) body ...)",
+        "Evaluate the body in a scope of its own, from which the names x can be imported."
+    )),
+    ("parameterize", Help::new(
+        "special-forms",
+        "(parameterize ((p x) ...) body ...)",
+        "Evaluate the body with the parameters overridden."
+    )),
+    ("quote", Help::new(
+        "special-forms", "(quote x)", "The expression x, unevaluated; also written 'x."
+    )),
+    ("require", Help::new(
+        "special-forms",
+        "(require name)",
+        "Load name.linsl from the directory of the current file or the library path, once."
+    )),
+    ("while", Help::new(
+        "special-forms",
+        "(while test body ...)",
+        "Evaluate the body as long as test is not #f, and return ()."
+    )),
+    ("do", Help::new(
+        "special-forms",
+        "(do ((name init step) ...) (test result ...) body ...)",
+        "Bind each name to its init, and then until test is true evaluate the body and bind each \
+         name to its step, all computed before any is bound. Returns the value of the last result."
+    )),
+    ("bound?", Help::new(
+        "environment", "(bound? name)", "Whether the symbol name is bound in the current scope."
+    )),
+    ("env-symbols", Help::new(
+        "environment",
+        "(env-symbols [local])",
+        "A sorted list of the symbols visible here, or only those of the innermost scope."
+    )),
+    ("undefine", Help::new(
+        "environment",
+        "(undefine name)",
+        "Remove the innermost binding of the symbol name, and return whether there was one."
+    )),
+    ("restore-primitive", Help::new(
+        "environment",
+        "(restore-primitive name)",
+        "Bind the primitive called name to its name again, e.g. after undefining it."
+    )),
+    ("save-image", Help::new(
+        "environment",
+        "(save-image path)",
+        "Save the bindings of the environment to the file path, to be restored with --image."
+    )),
+    ("define-test", Help::new(
+        "special-forms",
+        "(define-test name body ...)",
+        "Define a test, which run-tests runs, passing if the body evaluates without an error."
+    )),
+    ("stream-cons", Help::new(
+        "streams",
+        "(stream-cons head tail)",
+        "A stream of head followed by the stream tail, which is delayed."
+    )),
+    ("stream-car", Help::new("streams", "(stream-car s)", "The first element of the stream s.")),
+    ("stream-cdr", Help::new(
+        "streams", "(stream-cdr s)", "The stream s without its first element."
+    )),
+    ("stream-take", Help::new(
+        "streams", "(stream-take n s)", "A list of the first n elements of the stream s."
+    )),
+    ("stream-map", Help::new(
+        "streams", "(stream-map f s)", "The stream of f applied to every element of s."
+    )),
+    ("stream-filter", Help::new(
+        "streams", "(stream-filter p s)", "The stream of the elements of s for which p is not #f."
+    )),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn declared_arities_are_enforced() {
+        for entry in PRIMITIVE_TABLES.iter().flat_map(|table| table.iter()) {
+            let Some(arity) = entry.arity() else {
+                continue;
+            };
+            for n in 0..7 {
+                // Numbers are of the wrong type for most primitives, which is fine, as long as
+                // the number of arguments is checked first.
+                let args = vec![LinslExpr::Number(1 as Num); n];
+                let res = match entry.fun {
+                    PrimitiveFn::Plain(f, _) => f(&args),
+                    PrimitiveFn::Ctx(f, _) => f(&args, &mut LinslCtx::default()),
+                    PrimitiveFn::Value(_) => unreachable!(),
+                };
+                assert_eq!(
+                    matches!(res, Err(LinslErr::ArityError(..))),
+                    !arity.accepts(n),
+                    "{} with {} arguments",
+                    entry.name,
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_types_are_type_errors() {
+        let num = LinslExpr::Number(1 as Num);
+        let list = LinslExpr::List(vec![num.clone()].into());
+        for res in [
+            car(std::slice::from_ref(&num)),
+            cdr(std::slice::from_ref(&num)),
+            append(&[list.clone(), num.clone()]),
+            add(&[num.clone(), list.clone()]),
+            gr(&[list.clone(), num.clone()]),
+            memq(&[num.clone(), num.clone()]),
+        ] {
+            assert!(matches!(res, Err(LinslErr::TypeError(_, _))), "{:?}", res);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! TCP connections and listeners, which need the Net capability.
+
+use std::any::Any;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num, PosNum};
+use super::{expect_arity, expect_n_args, get_index, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const NET: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "tcp-connect",
+        fun: PrimitiveFn::Ctx(tcp_connect, Arity::Between(2, 3)),
+        help: Help::new(
+            "network",
+            "(tcp-connect host port timeout)",
+            "A TCP connection to port of host, giving up after timeout milliseconds, or the \
+             default of the system if it is left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-send",
+        fun: PrimitiveFn::Ctx(tcp_send, Arity::Exactly(2)),
+        help: Help::new(
+            "network",
+            "(tcp-send conn s)",
+            "Send the string s over the connection conn. Returns the number of bytes sent."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-recv",
+        fun: PrimitiveFn::Ctx(tcp_recv, Arity::Between(2, 3)),
+        help: Help::new(
+            "network",
+            "(tcp-recv conn n timeout)",
+            "At most n bytes received over the connection conn, as a string, waiting at most \
+             timeout milliseconds, or for as long as it takes if it is left out. The string is \
+             empty once the other side has closed the connection."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-close",
+        fun: PrimitiveFn::Ctx(tcp_close, Arity::Exactly(1)),
+        help: Help::new(
+            "network",
+            "(tcp-close x)",
+            "Close the connection or listener x. Returns #f if it was closed already."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-listen",
+        fun: PrimitiveFn::Ctx(tcp_listen, Arity::Exactly(2)),
+        help: Help::new(
+            "network",
+            "(tcp-listen host port)",
+            "A listener for TCP connections to port of host. With port 0, a free port is picked."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-accept",
+        fun: PrimitiveFn::Ctx(tcp_accept, Arity::Between(1, 2)),
+        help: Help::new(
+            "network",
+            "(tcp-accept listener timeout)",
+            "The next connection made to listener, waiting at most timeout milliseconds, or for \
+             as long as it takes if it is left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "tcp-port",
+        fun: PrimitiveFn::Ctx(tcp_port, Arity::Exactly(1)),
+        help: Help::new(
+            "network",
+            "(tcp-port x)",
+            "The local port of the connection or listener x, e.g. the one picked for port 0."
+        ),
+    },
+];
+
+/// A TCP connection, which is closed by `tcp-close` or when the last value holding it is dropped.
+pub struct TcpConnection {
+    /// The address of the other side, as it was given, e.g. `localhost:8080`.
+    addr: String,
+    stream: Mutex<Option<TcpStream>>,
+}
+
+/// A listener for TCP connections, which is closed by `tcp-close` or when the last value holding
+/// it is dropped.
+pub struct TcpListenerHandle {
+    /// The address listened on.
+    addr: String,
+    listener: Mutex<Option<TcpListener>>,
+}
+
+impl fmt::Display for TcpConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *lock(&self.stream) {
+            Some(_) => write!(f, "#<tcp {}>", self.addr),
+            None => write!(f, "#<tcp {} (closed)>", self.addr),
+        }
+    }
+}
+
+impl fmt::Display for TcpListenerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *lock(&self.listener) {
+            Some(_) => write!(f, "#<tcp-listener {}>", self.addr),
+            None => write!(f, "#<tcp-listener {} (closed)>", self.addr),
+        }
+    }
+}
+
+/// How a foreign value from this module is printed, or None if it is not from this module.
+pub(crate) fn describe(value: &(dyn Any + Send + Sync)) -> Option<String> {
+    if let Some(conn) = value.downcast_ref::<TcpConnection>() {
+        Some(conn.to_string())
+    } else {
+        value.downcast_ref::<TcpListenerHandle>().map(ToString::to_string)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The error of something which could not be done with the address given. A timeout says how
+/// long was waited.
+fn net_error(addr: &str, e: io::Error, timeout: Option<Duration>) -> LinslErr {
+    let cause = match (e.kind(), timeout) {
+        (ErrorKind::WouldBlock | ErrorKind::TimedOut, Some(timeout)) => {
+            format!("Timed out after {} ms", timeout.as_millis())
+        },
+        _ => e.to_string(),
+    };
+    LinslErr::NetError(addr.to_string(), cause)
+}
+
+fn closed_error(addr: &str) -> LinslErr {
+    LinslErr::NetError(addr.to_string(), "It is closed".to_string())
+}
+
+fn get_port(expr: &LinslExpr) -> Result<u16, LinslErr> {
+    let port = get_index(expr)?;
+    u16::try_from(port).map_err(|_| LinslErr::TypeError(
+        // TODO: Fix pos.
+        format!("Expected a port from 0 to 65535, found {}", port),
+        (0, 0)
+    ))
+}
+
+/// The timeout given in milliseconds as the argument at `i`, if any. A timeout of 0 is an error,
+/// since the standard library takes it to mean no timeout.
+fn get_timeout(exprs: &[LinslExpr], i: PosNum) -> Result<Option<Duration>, LinslErr> {
+    match exprs.get(i).map(get_index).transpose()? {
+        Some(0) => Err(LinslErr::TypeError(
+            // TODO: Fix pos.
+            "Expected a timeout above 0 milliseconds, found 0".to_string(),
+            (0, 0)
+        )),
+        ms => Ok(ms.map(|ms| Duration::from_millis(ms as u64))),
+    }
+}
+
+/// A TCP connection to a port of a host, or to the first of its addresses which accepts it.
+/// Needs the Net capability.
+pub fn tcp_connect(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-connect")?;
+    expect_arity(exprs, Arity::Between(2, 3))?;
+    let host = get_string(&exprs[0])?;
+    let port = get_port(&exprs[1])?;
+    let timeout = get_timeout(exprs, 2)?;
+    let addr = format!("{}:{}", host, port);
+
+    let addrs: Vec<SocketAddr> =
+        (host, port).to_socket_addrs().map_err(|e| net_error(&addr, e, None))?.collect();
+    let mut last_error = io::Error::new(ErrorKind::NotFound, "The host has no addresses");
+    for socket_addr in addrs {
+        let res = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&socket_addr, timeout),
+            None => TcpStream::connect(socket_addr),
+        };
+        match res {
+            Ok(stream) => {
+                let conn = TcpConnection { addr, stream: Mutex::new(Some(stream)) };
+                return Ok(LinslExpr::foreign(conn));
+            },
+            Err(e) => last_error = e,
+        }
+    }
+    Err(net_error(&addr, last_error, timeout))
+}
+
+/// Send a string over a connection. Returns the number of bytes sent. Needs the Net capability.
+pub fn tcp_send(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-send")?;
+    expect_n_args(exprs, 2)?;
+    let conn = exprs[0].downcast_foreign::<TcpConnection>()?;
+    let s = get_string(&exprs[1])?;
+    let mut stream = lock(&conn.stream);
+    let stream = stream.as_mut().ok_or_else(|| closed_error(&conn.addr))?;
+    stream.write_all(s.as_bytes()).map_err(|e| net_error(&conn.addr, e, None))?;
+    Ok(LinslExpr::Number(s.len() as Num))
+}
+
+/// At most a number of bytes received over a connection, as a string, which is empty once the
+/// other side has closed it. Bytes which are not UTF-8, e.g. a character split between two
+/// receives, are replaced by U+FFFD. Needs the Net capability.
+pub fn tcp_recv(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-recv")?;
+    expect_arity(exprs, Arity::Between(2, 3))?;
+    let conn = exprs[0].downcast_foreign::<TcpConnection>()?;
+    let max = get_index(&exprs[1])?;
+    let timeout = get_timeout(exprs, 2)?;
+    let mut stream = lock(&conn.stream);
+    let stream = stream.as_mut().ok_or_else(|| closed_error(&conn.addr))?;
+
+    let error = |e| net_error(&conn.addr, e, timeout);
+    stream.set_read_timeout(timeout).map_err(error)?;
+    let mut buf = vec![0; max];
+    let n = stream.read(&mut buf).map_err(error)?;
+    Ok(LinslExpr::String(String::from_utf8_lossy(&buf[..n]).into_owned().into()))
+}
+
+/// Close a connection or a listener. Returns #t, or #f if it was closed already. Needs the Net
+/// capability.
+pub fn tcp_close(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-close")?;
+    expect_n_args(exprs, 1)?;
+    if let Ok(listener) = exprs[0].downcast_foreign::<TcpListenerHandle>() {
+        return Ok(LinslExpr::Bool(lock(&listener.listener).take().is_some()));
+    };
+    let conn = exprs[0].downcast_foreign::<TcpConnection>()?;
+    let stream = lock(&conn.stream).take();
+    Ok(LinslExpr::Bool(stream.is_some()))
+}
+
+/// A listener for connections to a port of a host. Needs the Net capability.
+pub fn tcp_listen(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-listen")?;
+    expect_n_args(exprs, 2)?;
+    let host = get_string(&exprs[0])?;
+    let port = get_port(&exprs[1])?;
+    let addr = format!("{}:{}", host, port);
+    let listener = TcpListener::bind((host, port)).map_err(|e| net_error(&addr, e, None))?;
+    // The port picked for port 0 is more useful to print.
+    let addr = listener.local_addr().map_or(addr, |local| local.to_string());
+    Ok(LinslExpr::foreign(TcpListenerHandle { addr, listener: Mutex::new(Some(listener)) }))
+}
+
+/// The next connection made to a listener. With a timeout, the listener is polled until it
+/// expires, since the standard library cannot wait for a connection for a limited time. Needs the
+/// Net capability.
+pub fn tcp_accept(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-accept")?;
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let handle = exprs[0].downcast_foreign::<TcpListenerHandle>()?;
+    let timeout = get_timeout(exprs, 1)?;
+    let listener = lock(&handle.listener);
+    let listener = listener.as_ref().ok_or_else(|| closed_error(&handle.addr))?;
+
+    let error = |e| net_error(&handle.addr, e, timeout);
+    listener.set_nonblocking(timeout.is_some()).map_err(error)?;
+    let start = Instant::now();
+    let (stream, peer) = loop {
+        match listener.accept() {
+            Err(e) if e.kind() == ErrorKind::WouldBlock
+                && timeout.is_some_and(|timeout| start.elapsed() < timeout) =>
+            {
+                thread::sleep(Duration::from_millis(5));
+            },
+            res => break res.map_err(error)?,
+        }
+    };
+    stream.set_nonblocking(false).map_err(error)?;
+    let conn = TcpConnection { addr: peer.to_string(), stream: Mutex::new(Some(stream)) };
+    Ok(LinslExpr::foreign(conn))
+}
+
+/// The local port of a connection or a listener. Needs the Net capability.
+pub fn tcp_port(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    ctx.policy.check("tcp-port")?;
+    expect_n_args(exprs, 1)?;
+    let (addr, local) = match exprs[0].downcast_foreign::<TcpListenerHandle>() {
+        Ok(handle) => {
+            let local = lock(&handle.listener).as_ref().map(TcpListener::local_addr);
+            (handle.addr.clone(), local)
+        },
+        Err(_) => {
+            let conn = exprs[0].downcast_foreign::<TcpConnection>()?;
+            let local = lock(&conn.stream).as_ref().map(TcpStream::local_addr);
+            (conn.addr.clone(), local)
+        },
+    };
+    match local {
+        Some(Ok(local)) => Ok(LinslExpr::Number(local.port() as Num)),
+        Some(Err(e)) => Err(net_error(&addr, e, None)),
+        None => Err(closed_error(&addr)),
+    }
+}
@@ -0,0 +1,143 @@
+//! File paths, as strings, taken apart and put together with the separators of the platform.
+//! Nothing here looks at the filesystem.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::datatypes::{Arity, LinslExpr, LinslRes};
+use super::{expect_arity, expect_n_args, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const PATHS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "path-join",
+        fun: PrimitiveFn::Plain(path_join, Arity::AtLeast(1)),
+        help: Help::new(
+            "paths",
+            "(path-join path ...)",
+            "The paths joined with the separator of the platform. An absolute path replaces \
+             everything before it."
+        ),
+    },
+    PrimitiveEntry {
+        name: "path-parent",
+        fun: PrimitiveFn::Plain(path_parent, Arity::Exactly(1)),
+        help: Help::new(
+            "paths",
+            "(path-parent path)",
+            "path without its last component, or #f if it has only one."
+        ),
+    },
+    PrimitiveEntry {
+        name: "path-filename",
+        fun: PrimitiveFn::Plain(path_filename, Arity::Exactly(1)),
+        help: Help::new(
+            "paths",
+            "(path-filename path)",
+            "The last component of path, or #f if it has none or it is '..'."
+        ),
+    },
+    PrimitiveEntry {
+        name: "path-extension",
+        fun: PrimitiveFn::Plain(path_extension, Arity::Exactly(1)),
+        help: Help::new(
+            "paths",
+            "(path-extension path)",
+            "The extension of the last component of path, without the dot, or #f if it has none."
+        ),
+    },
+    PrimitiveEntry {
+        name: "path-absolute?",
+        fun: PrimitiveFn::Plain(is_path_absolute, Arity::Exactly(1)),
+        help: Help::new("paths", "(path-absolute? path)", "Whether path is absolute."),
+    },
+    PrimitiveEntry {
+        name: "path-normalize",
+        fun: PrimitiveFn::Plain(path_normalize, Arity::Exactly(1)),
+        help: Help::new(
+            "paths",
+            "(path-normalize path)",
+            "path without any '.', and with every '..' taking away the component before it, \
+             without looking at the filesystem."
+        ),
+    },
+];
+
+fn path_string(path: &Path) -> LinslExpr {
+    LinslExpr::String(path.to_string_lossy().into_owned().into())
+}
+
+/// A string, or #f if there is none.
+fn string_or_false(s: Option<&std::ffi::OsStr>) -> LinslExpr {
+    match s {
+        Some(s) => LinslExpr::String(s.to_string_lossy().into_owned().into()),
+        None => LinslExpr::Bool(false),
+    }
+}
+
+/// Join paths with the separator of the platform. A path which is absolute replaces the ones
+/// before it.
+pub fn path_join(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::AtLeast(1))?;
+    let mut path = PathBuf::new();
+    for expr in exprs {
+        path.push(get_string(expr)?);
+    }
+    Ok(path_string(&path))
+}
+
+/// A path without its last component, or #f if it has only one, or none.
+pub fn path_parent(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    match Path::new(get_string(&exprs[0])?).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Ok(path_string(parent)),
+        _ => Ok(LinslExpr::Bool(false)),
+    }
+}
+
+/// The last component of a path, or #f if it has none, e.g. it is a root, or it ends in '..'.
+pub fn path_filename(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(string_or_false(Path::new(get_string(&exprs[0])?).file_name()))
+}
+
+/// The extension of the last component of a path, without the dot, or #f if it has none.
+pub fn path_extension(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(string_or_false(Path::new(get_string(&exprs[0])?).extension()))
+}
+
+/// Whether a path is absolute, i.e. does not depend on the current directory.
+pub fn is_path_absolute(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Bool(Path::new(get_string(&exprs[0])?).is_absolute()))
+}
+
+/// A path without '.' components, and with every '..' taking away the component before it, if
+/// there is one. A '..' at the root is dropped, and one at the start of a relative path is kept.
+/// The filesystem is not looked at, so symbolic links are not followed.
+pub fn path_normalize(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let mut normalized = PathBuf::new();
+    // The components kept which a '..' can take away.
+    let mut depth = 0;
+    for component in Path::new(get_string(&exprs[0])?).components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if depth > 0 => {
+                normalized.pop();
+                depth -= 1;
+            },
+            Component::ParentDir if normalized.has_root() => (),
+            Component::ParentDir => normalized.push(".."),
+            Component::Normal(name) => {
+                normalized.push(name);
+                depth += 1;
+            },
+            Component::Prefix(_) | Component::RootDir => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    };
+    Ok(path_string(&normalized))
+}
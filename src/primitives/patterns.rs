@@ -0,0 +1,139 @@
+//! Regular expressions, with the syntax of the regex crate.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use regex::{Captures, Regex};
+
+use crate::datatypes::{Arity, LinslErr, LinslExpr, LinslRes};
+use super::{expect_n_args, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const PATTERNS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "re-match?",
+        fun: PrimitiveFn::Plain(re_is_match, Arity::Exactly(2)),
+        help: Help::new(
+            "regex",
+            "(re-match? pattern s)",
+            "Whether the regular expression pattern matches somewhere in the string s."
+        ),
+    },
+    PrimitiveEntry {
+        name: "re-find",
+        fun: PrimitiveFn::Plain(re_find, Arity::Exactly(2)),
+        help: Help::new(
+            "regex",
+            "(re-find pattern s)",
+            "A list of the first match of pattern in s and its groups, with #f for a group which \
+             did not take part, or #f if there is no match."
+        ),
+    },
+    PrimitiveEntry {
+        name: "re-find-all",
+        fun: PrimitiveFn::Plain(re_find_all, Arity::Exactly(2)),
+        help: Help::new(
+            "regex",
+            "(re-find-all pattern s)",
+            "A list of every match of pattern in s, each as re-find gives it."
+        ),
+    },
+    PrimitiveEntry {
+        name: "re-replace",
+        fun: PrimitiveFn::Plain(re_replace, Arity::Exactly(3)),
+        help: Help::new(
+            "regex",
+            "(re-replace pattern s replacement)",
+            "s with every match of pattern replaced by replacement, in which $1 or ${name} stands \
+             for a group, and $$ for a $."
+        ),
+    },
+    PrimitiveEntry {
+        name: "re-split",
+        fun: PrimitiveFn::Plain(re_split, Arity::Exactly(2)),
+        help: Help::new(
+            "regex",
+            "(re-split pattern s)",
+            "The pieces of s between the matches of pattern."
+        ),
+    },
+];
+
+/// How many compiled patterns are kept. Once there are more, the cache is emptied, so that a
+/// program making up new patterns does not fill the memory.
+const CACHE_SIZE: usize = 256;
+
+/// The patterns compiled so far, by their source, so that using a pattern in a loop compiles it
+/// only once.
+static CACHE: LazyLock<Mutex<HashMap<String, Regex>>> = LazyLock::new(Mutex::default);
+
+/// The compiled pattern of a string, or a PatternError with the message of the regex crate.
+fn get_regex(expr: &LinslExpr) -> Result<Regex, LinslErr> {
+    let pattern = get_string(expr)?;
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(regex) = cache.get(pattern) {
+        // Clones share the compiled program.
+        return Ok(regex.clone());
+    };
+    // TODO: Fix pos.
+    let regex = Regex::new(pattern).map_err(|e| LinslErr::PatternError(e.to_string(), (0, 0)))?;
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    };
+    cache.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// A match and its groups, as a list of strings, or #f for a group which did not take part.
+fn captures_expr(captures: &Captures) -> LinslExpr {
+    let groups = captures.iter().map(|group| match group {
+        Some(group) => LinslExpr::String(group.as_str().to_string().into()),
+        None => LinslExpr::Bool(false),
+    });
+    LinslExpr::List(groups.collect())
+}
+
+/// Whether a pattern matches somewhere in a string.
+pub fn re_is_match(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let regex = get_regex(&exprs[0])?;
+    Ok(LinslExpr::Bool(regex.is_match(get_string(&exprs[1])?)))
+}
+
+/// The first match of a pattern in a string, along with its groups, or #f if there is none.
+pub fn re_find(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let regex = get_regex(&exprs[0])?;
+    match regex.captures(get_string(&exprs[1])?) {
+        Some(captures) => Ok(captures_expr(&captures)),
+        None => Ok(LinslExpr::Bool(false)),
+    }
+}
+
+/// Every match of a pattern in a string, each along with its groups.
+pub fn re_find_all(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let regex = get_regex(&exprs[0])?;
+    let s = get_string(&exprs[1])?;
+    Ok(LinslExpr::List(regex.captures_iter(s).map(|captures| captures_expr(&captures)).collect()))
+}
+
+/// A string with every match of a pattern replaced, with references to groups in the replacement
+/// expanded, e.g. (re-replace "(\\w+)@" "me@home" "$1 at ") becomes "me at home".
+pub fn re_replace(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    let regex = get_regex(&exprs[0])?;
+    let s = get_string(&exprs[1])?;
+    let replacement = get_string(&exprs[2])?;
+    Ok(LinslExpr::String(regex.replace_all(s, replacement).into_owned().into()))
+}
+
+/// The pieces of a string between the matches of a pattern.
+pub fn re_split(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let regex = get_regex(&exprs[0])?;
+    let s = get_string(&exprs[1])?;
+    Ok(LinslExpr::List(
+        regex.split(s).map(|piece| LinslExpr::String(piece.to_string().into())).collect()
+    ))
+}
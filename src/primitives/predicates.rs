@@ -0,0 +1,241 @@
+//! Equality and comparison.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::datatypes::{Arity, LinslErr, LinslExpr, LinslRes};
+use super::{expect_n_args, expect_num, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const PREDICATES: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "=",
+        fun: PrimitiveFn::Plain(eq, Arity::Exactly(2)),
+        help: Help::new(
+            "comparison",
+            "(= a b)",
+            "Whether a and b, which must be of the same type, are equal; see also eqv? and equal?."
+        ),
+    },
+    PrimitiveEntry {
+        name: ">",
+        fun: PrimitiveFn::Plain(gr, Arity::Exactly(2)),
+        help: Help::new(
+            "comparison", "(> a b)", "Whether the number a is greater than the number b."
+        ),
+    },
+    PrimitiveEntry {
+        name: "eqv?",
+        fun: PrimitiveFn::Plain(eqv, Arity::Exactly(2)),
+        help: Help::new(
+            "comparison",
+            "(eqv? a b)",
+            "Whether a and b are the very same value; unlike =, lists are only eqv? to themselves."
+        ),
+    },
+    PrimitiveEntry {
+        name: "eq?",
+        fun: PrimitiveFn::Plain(eqv, Arity::Exactly(2)),
+        help: Help::new("comparison", "(eq? a b)", "The same as eqv?."),
+    },
+    PrimitiveEntry {
+        name: "equal?",
+        fun: PrimitiveFn::Plain(equal, Arity::Exactly(2)),
+        help: Help::new(
+            "comparison",
+            "(equal? a b)",
+            "Whether a and b have the same structure; unlike eqv?, equal lists are equal?."
+        ),
+    },
+    PrimitiveEntry {
+        name: "eqt?",
+        fun: PrimitiveFn::Plain(eq_types, Arity::Exactly(2)),
+        help: Help::new("comparison", "(eqt? a b)", "Whether a and b are of the same type."),
+    },
+];
+
+/// Compare two numbers, symbols or booleans for equality. Dicts are equal if they have the same
+/// entries and records if they are of the same type and have the same field values, while foreign
+/// values and vectors are equal only if they are the very same value.
+pub fn eq(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+
+    let res: bool = match (&exprs[0], &exprs[1]) {
+        (LinslExpr::Bool(b1), LinslExpr::Bool(b2)) => b1 == b2,
+        // NaN is not equal to anything, not even itself.
+        (LinslExpr::Number(v1), LinslExpr::Number(v2)) => v1 == v2, 
+        (LinslExpr::Complex(re1, im1), LinslExpr::Complex(re2, im2)) => re1 == re2 && im1 == im2,
+        // A complex number's imaginary part is never 0, so it is never equal to a real number.
+        (LinslExpr::Number(_), LinslExpr::Complex(..))
+        | (LinslExpr::Complex(..), LinslExpr::Number(_)) => false,
+        (LinslExpr::Symbol(s1), LinslExpr::Symbol(s2)) => s1 == s2,
+        (LinslExpr::Keyword(k1), LinslExpr::Keyword(k2)) => k1 == k2,
+        (LinslExpr::String(s1), LinslExpr::String(s2)) => s1 == s2,
+        (LinslExpr::Foreign(_, v1), LinslExpr::Foreign(_, v2)) => Arc::ptr_eq(v1, v2),
+        (LinslExpr::Vector(v1), LinslExpr::Vector(v2)) => Arc::ptr_eq(&v1.0, &v2.0),
+        (
+            LinslExpr::Primitive(..) | LinslExpr::CtxPrimitive(..),
+            LinslExpr::Primitive(..) | LinslExpr::CtxPrimitive(..)
+        ) => exprs[0] == exprs[1],
+        (LinslExpr::Dict(d1), LinslExpr::Dict(d2)) =>
+            Arc::ptr_eq(&d1.0, &d2.0) || *d1.entries() == *d2.entries(),
+        (LinslExpr::Record(r1), LinslExpr::Record(r2)) => Arc::ptr_eq(&r1.kind, &r2.kind) && (
+            Arc::ptr_eq(&r1.fields.0, &r2.fields.0) || *r1.fields.elems() == *r2.fields.elems()
+        ),
+        _ => Err(
+            // TODO: Fix pos.
+            LinslErr::SyntaxError(
+                "Can only compare expressions the same types, and only bools, numbers and symbols."
+                    .to_string(),
+                    (0, 0)
+            )
+        )?,
+    };
+
+    Ok(LinslExpr::Bool(res))
+}
+
+/// Compare two numbers to see if the first is greater than the second. Complex numbers have no
+/// order, so comparing one is an error.
+pub fn gr(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    if let Some(z) = exprs.iter().find(|expr| matches!(expr, LinslExpr::Complex(..))) {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Complex numbers cannot be ordered, so \'{}\' cannot be compared", z),
+                (0, 0)
+            )
+        );
+    };
+
+    // Every comparison involving NaN is false.
+    Ok(LinslExpr::Bool(expect_num(&exprs[0])? > expect_num(&exprs[1])?))
+}
+
+/// Whether two values are the very same value, e.g. (eqv? '(1) '(1)) is #f, since the lists are
+/// two different lists, while (define l '(1)) (eqv? l l) is #t. This is the middle of the equality
+/// ladder: `=` only compares values of a few types, and fails for others, while `eqv?` compares
+/// any two values, and `equal?` compares them by their structure.
+pub fn eqv(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::Bool(is_eqv(&exprs[0], &exprs[1])))
+}
+
+/// Whether two values are the very same value. Numbers, bools, symbols and keywords are compared
+/// by value, and everything else by identity.
+pub(crate) fn is_eqv(a: &LinslExpr, b: &LinslExpr) -> bool {
+    match (a, b) {
+        (LinslExpr::Number(x), LinslExpr::Number(y)) => x == y,
+        (LinslExpr::Complex(a, b), LinslExpr::Complex(c, d)) => a == c && b == d,
+        (LinslExpr::String(x), LinslExpr::String(y)) => Arc::ptr_eq(x, y),
+        (LinslExpr::List(xs), LinslExpr::List(ys)) => xs.same(ys),
+        (LinslExpr::Values(_), LinslExpr::Values(_)) => false,
+        // Otherwise equality already means identity.
+        _ => a == b,
+    }
+}
+
+/// Whether two values have the same structure, i.e. are lists, vectors, dicts or records with
+/// equal elements, or strings with the same text, or are otherwise eqv?. For example,
+/// (equal? '(1 (2)) (list 1 (list 2))) is #t.
+pub fn equal(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::Bool(is_equal(&exprs[0], &exprs[1])))
+}
+
+pub(crate) fn is_equal(a: &LinslExpr, b: &LinslExpr) -> bool {
+    type Pending = Vec<(LinslExpr, LinslExpr)>;
+    /// Queue xs and ys to be compared pairwise, if there are as many of each.
+    fn push_all(pending: &mut Pending, xs: &[LinslExpr], ys: &[LinslExpr]) -> bool {
+        pending.extend(xs.iter().cloned().zip(ys.iter().cloned()).rev());
+        xs.len() == ys.len()
+    }
+    /// Whether the elements of the vectors, records or dicts at x and y must be compared. Since
+    /// those can contain themselves, a pair which is already being compared is taken to be equal,
+    /// as anything telling them apart is found elsewhere.
+    fn first_time(comparing: &mut HashSet<(usize, usize)>, x: usize, y: usize) -> bool {
+        x != y && comparing.insert((x, y))
+    }
+
+    // The pairs left to compare, so that deeply nested values do not use up the stack.
+    let mut pending = vec![(a.clone(), b.clone())];
+    let mut comparing = HashSet::new();
+    while let Some((a, b)) = pending.pop() {
+        let equal = match (&a, &b) {
+            (LinslExpr::String(x), LinslExpr::String(y)) => x == y,
+            (LinslExpr::List(xs), LinslExpr::List(ys)) => push_all(&mut pending, xs, ys),
+            (LinslExpr::Values(xs), LinslExpr::Values(ys)) => push_all(&mut pending, xs, ys),
+            (LinslExpr::Vector(v1), LinslExpr::Vector(v2)) => {
+                !first_time(&mut comparing, address(&v1.0), address(&v2.0))
+                    || push_all(&mut pending, &v1.elems(), &v2.elems())
+            },
+            (LinslExpr::Record(r1), LinslExpr::Record(r2)) if Arc::ptr_eq(&r1.kind, &r2.kind) => {
+                !first_time(&mut comparing, address(&r1.fields.0), address(&r2.fields.0))
+                    || push_all(&mut pending, &r1.fields.elems(), &r2.fields.elems())
+            },
+            (LinslExpr::Dict(d1), LinslExpr::Dict(d2)) => {
+                !first_time(&mut comparing, address(&d1.0), address(&d2.0)) || {
+                    let (e1, e2) = (d1.entries(), d2.entries());
+                    e1.len() == e2.len() && e1.iter().all(|(k, v)| match e2.get(k) {
+                        Some(w) => {
+                            pending.push((v.clone(), w.clone()));
+                            true
+                        },
+                        None => false,
+                    })
+                }
+            },
+            _ => is_eqv(&a, &b),
+        };
+        if !equal {
+            return false;
+        }
+    }
+    true
+}
+
+/// The address of a shared value, which identifies it.
+pub(crate) fn address<T: ?Sized>(shared: &Arc<T>) -> usize {
+    Arc::as_ptr(shared) as *const () as usize
+}
+
+pub fn eq_types(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let bool = matches!((&exprs[0], &exprs[1]), 
+        (LinslExpr::Bool(_), LinslExpr::Bool(_))
+        | (LinslExpr::Closure(..), LinslExpr::Closure(..))
+        | (LinslExpr::CaseLambda(_), LinslExpr::CaseLambda(_))
+        | (LinslExpr::Foreign(_, _), LinslExpr::Foreign(_, _))
+        | (LinslExpr::Keyword(_), LinslExpr::Keyword(_))
+        | (LinslExpr::List(_), LinslExpr::List(_))
+        | (
+            LinslExpr::Number(_) | LinslExpr::Complex(..),
+            LinslExpr::Number(_) | LinslExpr::Complex(..)
+        )
+        | (
+            LinslExpr::Primitive(_, _)
+            | LinslExpr::CtxPrimitive(_, _)
+            | LinslExpr::RecordFn(_, _)
+            | LinslExpr::Escape(_),
+            LinslExpr::Primitive(_, _)
+            | LinslExpr::CtxPrimitive(_, _)
+            | LinslExpr::RecordFn(_, _)
+            | LinslExpr::Escape(_)
+        )
+        | (LinslExpr::Symbol(_), LinslExpr::Symbol(_))
+        | (LinslExpr::Macro(..), LinslExpr::Macro(..))
+        | (LinslExpr::Vector(_), LinslExpr::Vector(_))
+        | (LinslExpr::Dict(_), LinslExpr::Dict(_))
+        | (LinslExpr::Promise(_), LinslExpr::Promise(_))
+        | (LinslExpr::Iterator(_), LinslExpr::Iterator(_))
+        | (LinslExpr::Parameter(_), LinslExpr::Parameter(_))
+        | (LinslExpr::Values(_), LinslExpr::Values(_))
+        | (LinslExpr::String(_), LinslExpr::String(_))
+    ) || matches!((&exprs[0], &exprs[1]),
+        // Records are only of the same type if they are instances of the same record type.
+        (LinslExpr::Record(r1), LinslExpr::Record(r2)) if Arc::ptr_eq(&r1.kind, &r2.kind)
+    );
+
+    Ok(LinslExpr::Bool(bool))
+}
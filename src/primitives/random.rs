@@ -0,0 +1,81 @@
+//! Random numbers and UUIDs, drawn from the generator of the context.
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num};
+use crate::random::{is_uuid, uuid_v4};
+use super::{expect_arity, expect_n_args, get_index, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const RANDOM: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "random",
+        fun: PrimitiveFn::Ctx(random, Arity::Between(0, 1)),
+        help: Help::new(
+            "random",
+            "(random n)",
+            "A random whole number from 0 up to n, not including n, or a random number from 0 up \
+             to 1 if n is left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "set-random-seed!",
+        fun: PrimitiveFn::Ctx(set_random_seed, Arity::Exactly(1)),
+        help: Help::new(
+            "random",
+            "(set-random-seed! n)",
+            "Seed random and uuid with the whole number n, so that they give the same results \
+             every time."
+        ),
+    },
+    PrimitiveEntry {
+        name: "uuid",
+        fun: PrimitiveFn::Ctx(uuid, Arity::Exactly(0)),
+        help: Help::new("random", "(uuid)", "A random (version 4) UUID, as a lowercase string."),
+    },
+    PrimitiveEntry {
+        name: "uuid?",
+        fun: PrimitiveFn::Plain(is_uuid_string, Arity::Exactly(1)),
+        help: Help::new(
+            "random",
+            "(uuid? x)",
+            "Whether x is a string holding a UUID, i.e. hex digits in groups of 8-4-4-4-12."
+        ),
+    },
+];
+
+/// A random whole number below the number given, or a random number below 1 if none is given.
+pub fn random(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_arity(exprs, Arity::Between(0, 1))?;
+    let Some(n) = exprs.first() else {
+        return Ok(LinslExpr::Number(ctx.rng.unit() as Num));
+    };
+    match get_index(n)? {
+        0 => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                "Expected a number above 0 for random, found 0".to_string(),
+                (0, 0)
+            )
+        ),
+        n => Ok(LinslExpr::Number(ctx.rng.below(n as u64) as Num)),
+    }
+}
+
+/// Seed the generator of the context, so that it gives the same numbers every time. Returns the
+/// seed.
+pub fn set_random_seed(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    ctx.rng.reseed(get_index(&exprs[0])? as u64);
+    Ok(exprs[0].clone())
+}
+
+/// A random (version 4) UUID, as a lowercase hyphenated string.
+pub fn uuid(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    Ok(LinslExpr::String(uuid_v4(&ctx.rng).into()))
+}
+
+/// Whether the argument is a string holding a UUID. Anything else gives #f rather than an error.
+pub fn is_uuid_string(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Bool(get_string(&exprs[0]).is_ok_and(is_uuid)))
+}
@@ -0,0 +1,55 @@
+//! The functions `define-record` defines for a record type, which are not bound by default.
+
+use std::sync::Arc;
+
+use crate::datatypes::{LinslErr, LinslExpr, LinslRecord, LinslRes, RecordOp, RecordType};
+use super::expect_arity;
+
+/// Apply one of the functions `define-record` defined for the record type `kind`.
+pub fn apply_record_fn(kind: &Arc<RecordType>, op: RecordOp, exprs: &[LinslExpr]) -> LinslRes {
+    match op {
+        RecordOp::Make => {
+            expect_arity(exprs, op.arity(kind))?;
+            Ok(LinslExpr::Record(LinslRecord { kind: kind.clone(), fields: exprs.to_vec().into() }))
+        },
+        RecordOp::Is => {
+            expect_arity(exprs, op.arity(kind))?;
+            let is = matches!(&exprs[0], LinslExpr::Record(r) if Arc::ptr_eq(&r.kind, kind));
+            Ok(LinslExpr::Bool(is))
+        },
+        RecordOp::Get(i) => {
+            expect_arity(exprs, op.arity(kind))?;
+            Ok(get_record(kind, &exprs[0])?.fields.elems()[i].clone())
+        },
+        RecordOp::Set(i) => {
+            expect_arity(exprs, op.arity(kind))?;
+            get_record(kind, &exprs[0])?.fields.elems_mut()[i] = exprs[1].clone();
+            Ok(exprs[0].clone())
+        },
+    }
+}
+
+/// Retrieve the record an argument evaluated to, or return an error if it is something else,
+/// including a record of some other type.
+fn get_record<'a>(
+    kind: &Arc<RecordType>,
+    expr: &'a LinslExpr
+) -> Result<&'a LinslRecord, LinslErr> {
+    match expr {
+        LinslExpr::Record(r) if Arc::ptr_eq(&r.kind, kind) => Ok(r),
+        LinslExpr::Record(r) => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a {} record, found a {} record", kind.name, r.kind.name),
+                (0, 0)
+            )
+        ),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a {} record, found \'{}\'", kind.name, expr),
+                (0, 0)
+            )
+        ),
+    }
+}
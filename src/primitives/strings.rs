@@ -0,0 +1,469 @@
+//! Strings.
+
+use crate::datatypes::{Arity, LinslErr, LinslExpr, LinslRes, Num};
+use crate::encoding::{decode, encode, Alphabet};
+use crate::hashing::{hash_str, Md5, Sha256};
+use super::{
+    expect_arity, expect_list, expect_n_args, get_index, get_string, Help, PrimitiveEntry,
+    PrimitiveFn
+};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const STRINGS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "string-append",
+        fun: PrimitiveFn::Plain(string_append, Arity::AtLeast(0)),
+        help: Help::new("strings", "(string-append s ...)", "The strings s joined into one."),
+    },
+    PrimitiveEntry {
+        name: "->string",
+        fun: PrimitiveFn::Plain(to_string, Arity::Exactly(1)),
+        help: Help::new(
+            "strings",
+            "(->string x)",
+            "x itself if it is a string, or else x written as the REPL prints it."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-join",
+        fun: PrimitiveFn::Plain(string_join, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(string-join l sep)",
+            "The strings in the list l joined into one, with the string sep (if given) between \
+             them."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-repeat",
+        fun: PrimitiveFn::Plain(string_repeat, Arity::Exactly(2)),
+        help: Help::new("strings", "(string-repeat s n)", "The string s repeated n times."),
+    },
+    PrimitiveEntry {
+        name: "string-upcase",
+        fun: PrimitiveFn::Plain(string_upcase, Arity::Exactly(1)),
+        help: Help::new("strings", "(string-upcase s)", "The string s in uppercase."),
+    },
+    PrimitiveEntry {
+        name: "string-downcase",
+        fun: PrimitiveFn::Plain(string_downcase, Arity::Exactly(1)),
+        help: Help::new("strings", "(string-downcase s)", "The string s in lowercase."),
+    },
+    PrimitiveEntry {
+        name: "string-trim",
+        fun: PrimitiveFn::Plain(string_trim, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(string-trim s chars)",
+            "The string s without the characters in the string chars at either end, or without \
+             whitespace if chars is left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-trim-left",
+        fun: PrimitiveFn::Plain(string_trim_left, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(string-trim-left s chars)",
+            "string-trim, but only at the start of s."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-trim-right",
+        fun: PrimitiveFn::Plain(string_trim_right, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(string-trim-right s chars)",
+            "string-trim, but only at the end of s."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-pad-left",
+        fun: PrimitiveFn::Plain(string_pad_left, Arity::Between(2, 3)),
+        help: Help::new(
+            "strings",
+            "(string-pad-left s n c)",
+            "The string s with the one-character string c (or a space if it is left out) added \
+             at the start until it is n characters long. A longer s is not cut."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-pad-right",
+        fun: PrimitiveFn::Plain(string_pad_right, Arity::Between(2, 3)),
+        help: Help::new(
+            "strings",
+            "(string-pad-right s n c)",
+            "string-pad-left, but adding c at the end of s."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-reverse",
+        fun: PrimitiveFn::Plain(string_reverse, Arity::Exactly(1)),
+        help: Help::new(
+            "strings",
+            "(string-reverse s)",
+            "The characters of the string s in reverse order."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-contains?",
+        fun: PrimitiveFn::Plain(string_contains, Arity::Exactly(2)),
+        help: Help::new(
+            "strings",
+            "(string-contains? s needle)",
+            "Whether the string needle occurs in the string s. The empty string occurs in any."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-index",
+        fun: PrimitiveFn::Plain(string_index, Arity::Exactly(2)),
+        help: Help::new(
+            "strings",
+            "(string-index s needle)",
+            "The index in characters of the first occurrence of the string needle in the string \
+             s, or #f if there is none. The empty string occurs at 0."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-starts-with?",
+        fun: PrimitiveFn::Plain(string_starts_with, Arity::Exactly(2)),
+        help: Help::new(
+            "strings",
+            "(string-starts-with? s prefix)",
+            "Whether the string s starts with the string prefix."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-ends-with?",
+        fun: PrimitiveFn::Plain(string_ends_with, Arity::Exactly(2)),
+        help: Help::new(
+            "strings",
+            "(string-ends-with? s suffix)",
+            "Whether the string s ends with the string suffix."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string-replace",
+        fun: PrimitiveFn::Plain(string_replace, Arity::Exactly(3)),
+        help: Help::new(
+            "strings",
+            "(string-replace s needle replacement)",
+            "The string s with every occurrence of the string needle replaced by the string \
+             replacement. needle must not be empty; re-replace replaces patterns."
+        ),
+    },
+    PrimitiveEntry {
+        name: "sha256",
+        fun: PrimitiveFn::Plain(sha256, Arity::Exactly(1)),
+        help: Help::new("strings", "(sha256 s)", "The SHA-256 digest of the string s, in hex."),
+    },
+    PrimitiveEntry {
+        name: "md5",
+        fun: PrimitiveFn::Plain(md5, Arity::Exactly(1)),
+        help: Help::new(
+            "strings",
+            "(md5 s)",
+            "The MD5 digest of the string s, in hex. Not cryptographically strong."
+        ),
+    },
+    PrimitiveEntry {
+        name: "base64-encode",
+        fun: PrimitiveFn::Plain(base64_encode, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(base64-encode s alphabet)",
+            "The UTF-8 bytes of the string s in base64, with the URL-safe alphabet if alphabet is \
+             :url, or the standard one if it is :standard or left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "base64-decode",
+        fun: PrimitiveFn::Plain(base64_decode, Arity::Between(1, 2)),
+        help: Help::new(
+            "strings",
+            "(base64-decode s alphabet)",
+            "The string encoded in base64 by s, with the alphabet as for base64-encode. The \
+             decoded bytes must be UTF-8."
+        ),
+    },
+];
+
+/// The alphabet asked for by the optional argument of the base64 primitives.
+fn get_alphabet(expr: Option<&LinslExpr>) -> Result<Alphabet, LinslErr> {
+    match expr {
+        None => Ok(Alphabet::Standard),
+        Some(LinslExpr::Keyword(k)) if k.as_str() == "standard" => Ok(Alphabet::Standard),
+        Some(LinslExpr::Keyword(k)) if k.as_str() == "url" => Ok(Alphabet::UrlSafe),
+        Some(expr) => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected :standard or :url, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Encode the UTF-8 bytes of a string as base64, e.g. (base64-encode "hi") becomes "aGk=".
+pub fn base64_encode(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let s = get_string(&exprs[0])?;
+    let alphabet = get_alphabet(exprs.get(1))?;
+    Ok(LinslExpr::String(encode(s.as_bytes(), alphabet).into()))
+}
+
+/// Decode a string encoded as base64. Input which is not base64 gives a DecodeError with the index
+/// of the character at fault, as do decoded bytes which are not UTF-8, since strings are; the
+/// index is then that of the character holding the first byte which is not.
+pub fn base64_decode(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let s = get_string(&exprs[0])?;
+    let alphabet = get_alphabet(exprs.get(1))?;
+    // TODO: Fix pos.
+    let bytes = decode(s, alphabet)
+        .map_err(|e| LinslErr::DecodeError(e.message, e.index, (0, 0)))?;
+    match String::from_utf8(bytes) {
+        Ok(decoded) => Ok(LinslExpr::String(decoded.into())),
+        Err(e) => {
+            let byte = e.utf8_error().valid_up_to();
+            Err(LinslErr::DecodeError(
+                format!("The decoded bytes are not UTF-8 from byte {} on", byte),
+                byte * 4 / 3,
+                (0, 0)
+            ))
+        },
+    }
+}
+
+/// A string in uppercase, e.g. (string-upcase "straße") becomes "STRASSE", since some characters
+/// have no single uppercase character.
+pub fn string_upcase(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::String(get_string(&exprs[0])?.to_uppercase().into()))
+}
+
+/// A string in lowercase.
+pub fn string_downcase(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::String(get_string(&exprs[0])?.to_lowercase().into()))
+}
+
+/// Which end, or ends, of a string to trim or pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Left,
+    Right,
+    Both,
+}
+
+/// A string without the characters in the second argument at the ends given, or without
+/// whitespace if there is no second argument.
+fn trim(exprs: &[LinslExpr], end: End) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let s = get_string(&exprs[0])?;
+    let chars = exprs.get(1).map(get_string).transpose()?;
+    let strip = |c: char| chars.map_or(c.is_whitespace(), |chars| chars.contains(c));
+    let trimmed = match end {
+        End::Left => s.trim_start_matches(strip),
+        End::Right => s.trim_end_matches(strip),
+        End::Both => s.trim_matches(strip),
+    };
+    Ok(LinslExpr::String(trimmed.to_string().into()))
+}
+
+/// A string without whitespace, or the characters given, at either end.
+pub fn string_trim(exprs: &[LinslExpr]) -> LinslRes {
+    trim(exprs, End::Both)
+}
+
+/// A string without whitespace, or the characters given, at the start.
+pub fn string_trim_left(exprs: &[LinslExpr]) -> LinslRes {
+    trim(exprs, End::Left)
+}
+
+/// A string without whitespace, or the characters given, at the end.
+pub fn string_trim_right(exprs: &[LinslExpr]) -> LinslRes {
+    trim(exprs, End::Right)
+}
+
+/// A string padded to a number of characters with the one-character string given, or spaces. A
+/// string which is already that long, or longer, is returned as it is rather than cut, so that no
+/// text is lost.
+fn pad(exprs: &[LinslExpr], end: End) -> LinslRes {
+    expect_arity(exprs, Arity::Between(2, 3))?;
+    let s = get_string(&exprs[0])?;
+    let width = get_index(&exprs[1])?;
+    let fill = match exprs.get(2).map(get_string).transpose()? {
+        None => ' ',
+        Some(fill) => {
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(
+                    LinslErr::TypeError(
+                        // TODO: Fix pos.
+                        format!("Expected one character to pad with, found {:?}", fill),
+                        (0, 0)
+                    )
+                ),
+            }
+        },
+    };
+    let missing = width.saturating_sub(s.chars().count());
+    let padding: String = std::iter::repeat_n(fill, missing).collect();
+    let padded = match end {
+        End::Left => padding + s,
+        _ => s.to_string() + &padding,
+    };
+    Ok(LinslExpr::String(padded.into()))
+}
+
+/// A string padded at the start to a number of characters.
+pub fn string_pad_left(exprs: &[LinslExpr]) -> LinslRes {
+    pad(exprs, End::Left)
+}
+
+/// A string padded at the end to a number of characters.
+pub fn string_pad_right(exprs: &[LinslExpr]) -> LinslRes {
+    pad(exprs, End::Right)
+}
+
+/// The characters of a string in reverse order. Characters made of several code points, e.g. a
+/// letter followed by a combining accent, are taken apart, so the accent ends up on the letter
+/// before.
+pub fn string_reverse(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::String(get_string(&exprs[0])?.chars().rev().collect::<String>().into()))
+}
+
+/// Whether a string occurs in another.
+pub fn string_contains(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::Bool(get_string(&exprs[0])?.contains(get_string(&exprs[1])?)))
+}
+
+/// The index, in characters, where a string first occurs in another, or #f if it does not.
+pub fn string_index(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let s = get_string(&exprs[0])?;
+    match s.find(get_string(&exprs[1])?) {
+        // The byte offset of a match is always at the start of a character.
+        Some(i) => Ok(LinslExpr::Number(s[..i].chars().count() as Num)),
+        None => Ok(LinslExpr::Bool(false)),
+    }
+}
+
+/// Whether a string starts with another.
+pub fn string_starts_with(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::Bool(get_string(&exprs[0])?.starts_with(get_string(&exprs[1])?)))
+}
+
+/// Whether a string ends with another.
+pub fn string_ends_with(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    Ok(LinslExpr::Bool(get_string(&exprs[0])?.ends_with(get_string(&exprs[1])?)))
+}
+
+/// A string with every occurrence of another replaced. Replacing the empty string is an error,
+/// since it occurs between every two characters, which is rarely what is meant.
+pub fn string_replace(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    let s = get_string(&exprs[0])?;
+    let needle = get_string(&exprs[1])?;
+    let replacement = get_string(&exprs[2])?;
+    if needle.is_empty() {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                "Expected a string to replace, found the empty string".to_string(),
+                (0, 0)
+            )
+        );
+    };
+    Ok(LinslExpr::String(s.replace(needle, replacement).into()))
+}
+
+/// The SHA-256 digest of the UTF-8 bytes of a string, as lowercase hexadecimal.
+pub fn sha256(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::String(hash_str::<Sha256>(get_string(&exprs[0])?).into()))
+}
+
+/// The MD5 digest of the UTF-8 bytes of a string, as lowercase hexadecimal. MD5 is only fit for
+/// checking against digests computed elsewhere, since collisions can be made for it.
+pub fn md5(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::String(hash_str::<Md5>(get_string(&exprs[0])?).into()))
+}
+
+/// Join strings into one, e.g. (string-append "a" "b") becomes "ab".
+pub fn string_append(exprs: &[LinslExpr]) -> LinslRes {
+    let mut appended = String::new();
+    for expr in exprs {
+        appended.push_str(get_string(expr)?);
+    }
+    Ok(LinslExpr::String(appended.into()))
+}
+
+/// A string as it is, or anything else as it is printed, e.g. (->string '(1 "a")) becomes
+/// "(1 \"a\")". Used by interpolated strings, `#"...${x}..."`.
+pub fn to_string(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    match &exprs[0] {
+        LinslExpr::String(s) => Ok(LinslExpr::String(s.clone())),
+        expr => Ok(LinslExpr::String(expr.to_string().into())),
+    }
+}
+
+/// Join a list of strings into one string, with the separator (if there is one) between every two
+/// of them, e.g. (string-join '("a" "b") ", ") becomes "a, b". The result is built in one pass.
+pub fn string_join(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+    let pieces = expect_list(&exprs[0])?;
+    let sep = exprs.get(1).map(get_string).transpose()?.unwrap_or("");
+
+    let mut joined = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        let LinslExpr::String(piece) = piece else {
+            return Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos.
+                    format!("Expected a string at index {} of the list, found \'{}\'", i, piece),
+                    (0, 0)
+                )
+            );
+        };
+        if i > 0 {
+            joined.push_str(sep);
+        };
+        joined.push_str(piece);
+    }
+    Ok(LinslExpr::String(joined.into()))
+}
+
+/// Repeat a string a number of times, e.g. (string-repeat "ab" 3) becomes "ababab".
+pub fn string_repeat(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let s = get_string(&exprs[0])?;
+    let count = get_index(&exprs[1])?;
+    let mut repeated = String::new();
+    let len = s.len().checked_mul(count);
+    if len.is_none_or(|len| repeated.try_reserve_exact(len).is_err()) {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Cannot repeat a string of length {} {} times", s.len(), count),
+                (0, 0)
+            )
+        );
+    };
+    // Repeating the empty string any number of times gives the empty string, without going
+    // around the loop.
+    if !s.is_empty() {
+        for _ in 0..count {
+            repeated.push_str(s);
+        }
+    };
+    Ok(LinslExpr::String(repeated.into()))
+}
@@ -0,0 +1,31 @@
+//! Running the tests defined with define-test.
+
+use crate::datatypes::{Arity, LinslCtx, LinslExpr, LinslRes};
+use crate::testing::report;
+use super::{expect_n_args, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const TESTING: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "run-tests",
+        fun: PrimitiveFn::Ctx(run_tests, Arity::Exactly(0)),
+        help: Help::new(
+            "testing",
+            "(run-tests)",
+            "Run every test defined with define-test, write a report, and return #f if any failed."
+        ),
+    },
+];
+
+/// Run every test defined so far, writing how each went followed by the numbers of tests which
+/// passed and failed to the report output of the context, if it has one. Returns #t if they all
+/// passed, and #f otherwise.
+pub fn run_tests(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    ctx.policy.check("run-tests")?;
+    let results = crate::testing::run_tests(ctx)?;
+    if let Some(output) = &ctx.test_report {
+        output.write(&report(&results));
+    };
+    Ok(LinslExpr::Bool(results.iter().all(|(.., res)| res.is_ok())))
+}
@@ -0,0 +1,132 @@
+//! The clock, times written as strings and read back, and timing functions. Times are in UTC.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::datatypes::{Arity, LinslCtx, LinslErr, LinslExpr, LinslRes, Num};
+use crate::evaluation::apply;
+use crate::time::{format_time, parse_time, SECS_RANGE};
+use super::{expect_n_args, expect_num, get_string, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const TIME: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "current-seconds",
+        fun: PrimitiveFn::Plain(current_seconds, Arity::Exactly(0)),
+        help: Help::new(
+            "time",
+            "(current-seconds)",
+            "The whole seconds since the Unix epoch, 1970-01-01 00:00:00 UTC."
+        ),
+    },
+    PrimitiveEntry {
+        name: "current-milliseconds",
+        fun: PrimitiveFn::Plain(current_milliseconds, Arity::Exactly(0)),
+        help: Help::new(
+            "time",
+            "(current-milliseconds)",
+            "The whole milliseconds since the Unix epoch, 1970-01-01 00:00:00 UTC."
+        ),
+    },
+    PrimitiveEntry {
+        name: "time->string",
+        fun: PrimitiveFn::Plain(time_to_string, Arity::Exactly(2)),
+        help: Help::new(
+            "time",
+            "(time->string secs fmt)",
+            "The UTC time secs seconds after the Unix epoch, written according to fmt, in which \
+             %Y is the year, %m the month, %d the day, %H the hour, %M the minute, %S the second \
+             and %% a %. The time must be within the years 0000 to 9999."
+        ),
+    },
+    PrimitiveEntry {
+        name: "string->time",
+        fun: PrimitiveFn::Plain(string_to_time, Arity::Exactly(2)),
+        help: Help::new(
+            "time",
+            "(string->time s fmt)",
+            "The seconds since the Unix epoch of the UTC time in s, written according to fmt as \
+             for time->string."
+        ),
+    },
+    PrimitiveEntry {
+        name: "measure-time",
+        fun: PrimitiveFn::Ctx(measure_time, Arity::Exactly(1)),
+        help: Help::new(
+            "time",
+            "(measure-time f)",
+            "Apply f to no arguments, and return a list of its result and the milliseconds it \
+             took."
+        ),
+    },
+];
+
+/// The time since the Unix epoch. The clock is not expected to be set before it.
+fn since_epoch() -> std::time::Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// The whole seconds since the Unix epoch.
+pub fn current_seconds(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    Ok(LinslExpr::Number(since_epoch().as_secs() as Num))
+}
+
+/// The whole milliseconds since the Unix epoch.
+pub fn current_milliseconds(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 0)?;
+    Ok(LinslExpr::Number(since_epoch().as_millis() as Num))
+}
+
+/// The error of a format with a directive which is not known.
+fn format_error(name: &str, msg: String) -> LinslErr {
+    // TODO: Fix pos.
+    LinslErr::TypeError(format!("{} in the format of {}", msg, name), (0, 0))
+}
+
+/// Write a time, in seconds since the Unix epoch, as a UTC time according to a format. A
+/// fractional second is left out, and a time outside the years 0000 to 9999 is a TypeError.
+pub fn time_to_string(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let secs = expect_num(&exprs[0])?.floor();
+    if !(SECS_RANGE.start as Num..SECS_RANGE.end as Num).contains(&secs) {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!(
+                    "time->string expected seconds within the years 0000 to 9999, found \'{}\'",
+                    exprs[0]
+                ),
+                (0, 0)
+            )
+        );
+    };
+    let secs = secs as i64;
+    let fmt = get_string(&exprs[1])?;
+    let formatted = format_time(secs, fmt).map_err(|msg| format_error("time->string", msg))?;
+    Ok(LinslExpr::String(formatted.into()))
+}
+
+/// Read a UTC time written according to a format. A string not matching the format gives a
+/// DecodeError with the index of the character where reading failed.
+pub fn string_to_time(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let s = get_string(&exprs[0])?;
+    let fmt = get_string(&exprs[1])?;
+    let secs = parse_time(s, fmt)
+        .map_err(|msg| format_error("string->time", msg))?
+        .map_err(|e| {
+            let msg = format!("{} in the UTC time \"{}\"", e.message, s);
+            // TODO: Fix pos.
+            LinslErr::DecodeError(msg, e.index, (0, 0))
+        })?;
+    Ok(LinslExpr::Number(secs as Num))
+}
+
+/// Apply a function to no arguments, returning a list of its result and the milliseconds it took.
+pub fn measure_time(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    let start = Instant::now();
+    let result = apply(&exprs[0], &[], ctx)?;
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    Ok(LinslExpr::List(vec![result, LinslExpr::Number(elapsed as Num)].into()))
+}
@@ -0,0 +1,268 @@
+//! Vectors.
+
+use std::ops::Range;
+
+use crate::datatypes::{
+    Arity, LinslCtx, LinslErr, LinslExpr, LinslList, LinslRes, LinslVector, Num, PosNum
+};
+use crate::evaluation::apply;
+use super::{expect_arity, expect_list, expect_n_args, get_index, Help, PrimitiveEntry, PrimitiveFn};
+
+/// The primitives of this module, in the order `help` lists them.
+pub const VECTORS: &[PrimitiveEntry] = &[
+    PrimitiveEntry {
+        name: "vector",
+        fun: PrimitiveFn::Plain(vector, Arity::AtLeast(0)),
+        help: Help::new("vectors", "(vector x ...)", "A vector of the arguments."),
+    },
+    PrimitiveEntry {
+        name: "make-vector",
+        fun: PrimitiveFn::Plain(make_vector, Arity::Between(1, 2)),
+        help: Help::new(
+            "vectors", "(make-vector n x)", "A vector of n elements, all x, or () if x is left out."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-ref",
+        fun: PrimitiveFn::Plain(vector_ref, Arity::Exactly(2)),
+        help: Help::new(
+            "vectors", "(vector-ref v i)", "The element of v at index i, counting from 0."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-set!",
+        fun: PrimitiveFn::Plain(vector_set, Arity::Exactly(3)),
+        help: Help::new(
+            "vectors",
+            "(vector-set! v i x)",
+            "Replace the element of v at index i by x, and return v."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-length",
+        fun: PrimitiveFn::Plain(vector_length, Arity::Exactly(1)),
+        help: Help::new("vectors", "(vector-length v)", "The number of elements of v."),
+    },
+    PrimitiveEntry {
+        name: "vector->list",
+        fun: PrimitiveFn::Plain(vector_to_list, Arity::Exactly(1)),
+        help: Help::new("vectors", "(vector->list v)", "A list of the elements of v."),
+    },
+    PrimitiveEntry {
+        name: "list->vector",
+        fun: PrimitiveFn::Plain(list_to_vector, Arity::Exactly(1)),
+        help: Help::new("vectors", "(list->vector l)", "A vector of the elements of l."),
+    },
+    PrimitiveEntry {
+        name: "vector-fill!",
+        fun: PrimitiveFn::Plain(vector_fill, Arity::Exactly(2)),
+        help: Help::new(
+            "vectors", "(vector-fill! v x)", "Replace every element of v by x, and return v."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-copy",
+        fun: PrimitiveFn::Plain(vector_copy, Arity::Between(1, 3)),
+        help: Help::new(
+            "vectors",
+            "(vector-copy v [start [end]])",
+            "A new vector of the elements of v from index start up to end, or all of them."
+        ),
+    },
+    PrimitiveEntry {
+        name: "subvector",
+        fun: PrimitiveFn::Plain(subvector, Arity::Exactly(3)),
+        help: Help::new(
+            "vectors",
+            "(subvector v start end)",
+            "A new vector of the elements of v from index start up to end."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-copy!",
+        fun: PrimitiveFn::Plain(vector_copy_into, Arity::Between(3, 5)),
+        help: Help::new(
+            "vectors",
+            "(vector-copy! to at from [start [end]])",
+            "Copy the elements of from, from index start up to end, into to from index at; \
+             returns to."
+        ),
+    },
+    PrimitiveEntry {
+        name: "vector-map!",
+        fun: PrimitiveFn::Ctx(vector_map, Arity::Exactly(2)),
+        help: Help::new(
+            "vectors", "(vector-map! f v)", "Replace every element x of v by (f x), and return v."
+        ),
+    },
+];
+
+/// Retrieve the vector an argument evaluated to, or return an error if it is something else.
+fn get_vector(expr: &LinslExpr) -> Result<&LinslVector, LinslErr> {
+    match expr {
+        LinslExpr::Vector(v) => Ok(v),
+        _ => Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Expected a vector, found \'{}\'", expr),
+                (0, 0)
+            )
+        ),
+    }
+}
+
+/// Check that `index` is within the bounds of a vector of length `len`.
+fn check_index(index: PosNum, len: PosNum) -> Result<(), LinslErr> {
+    if index >= len {
+        // TODO: Fix pos.
+        return Err(LinslErr::IndexError(index, len, (0, 0)));
+    };
+    Ok(())
+}
+
+/// Take an arbitrary number of elements, and return a vector containing those elements. For
+/// example, (vector 1 2 3) becomes #(1 2 3).
+pub fn vector(exprs: &[LinslExpr]) -> LinslRes {
+    Ok(LinslExpr::Vector(exprs.to_vec().into()))
+}
+
+/// Create a vector of a given length, with every element set to the second argument, or to the
+/// empty list if there is none.
+pub fn make_vector(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 2))?;
+
+    let len = get_index(&exprs[0])?;
+    let fill = exprs.get(1).cloned().unwrap_or(LinslExpr::List(LinslList::default()));
+    // A length too large to allocate is an error rather than an abort.
+    let mut elems = Vec::new();
+    if elems.try_reserve_exact(len).is_err() {
+        return Err(
+            LinslErr::TypeError(
+                // TODO: Fix pos.
+                format!("Cannot make a vector of {} elements", len),
+                (0, 0)
+            )
+        );
+    };
+    elems.resize(len, fill);
+    Ok(LinslExpr::Vector(elems.into()))
+}
+
+/// Return the element of a vector at an index, counting from 0.
+pub fn vector_ref(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let index = get_index(&exprs[1])?;
+    let elems = get_vector(&exprs[0])?.elems();
+    check_index(index, elems.len())?;
+    Ok(elems[index].clone())
+}
+
+/// Replace the element of a vector at an index with a new value, and return the vector.
+pub fn vector_set(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    // The index is read before the vector is locked, since an error for it prints it, and it may
+    // be the vector itself.
+    let index = get_index(&exprs[1])?;
+    let mut elems = get_vector(&exprs[0])?.elems_mut();
+    check_index(index, elems.len())?;
+    elems[index] = exprs[2].clone();
+    Ok(exprs[0].clone())
+}
+
+/// Return the number of elements in a vector.
+pub fn vector_length(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Number(get_vector(&exprs[0])?.elems().len() as Num))
+}
+
+/// Retrieve the range of indices from the optional arguments `start` and `end`, which default to
+/// the whole of a vector of length `len`, checking that it is within the vector.
+fn get_range(
+    start: Option<&LinslExpr>,
+    end: Option<&LinslExpr>,
+    len: PosNum
+) -> Result<Range<PosNum>, LinslErr> {
+    let start = start.map(get_index).transpose()?.unwrap_or(0);
+    let end = end.map(get_index).transpose()?.unwrap_or(len);
+    check_range(start, end, len)?;
+    Ok(start..end)
+}
+
+/// Check that the range from `start` to `end` is within the bounds of a vector of length `len`.
+fn check_range(start: PosNum, end: PosNum, len: PosNum) -> Result<(), LinslErr> {
+    if start > end || end > len {
+        // TODO: Fix pos.
+        return Err(LinslErr::RangeError(start, end, len, (0, 0)));
+    };
+    Ok(())
+}
+
+/// Replace every element of a vector with the same value, and return the vector.
+pub fn vector_fill(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    get_vector(&exprs[0])?.elems_mut().fill(exprs[1].clone());
+    Ok(exprs[0].clone())
+}
+
+/// Return a new vector of the elements of a vector from index `start` up to (but not including)
+/// `end`, or of all of them if they are left out.
+pub fn vector_copy(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(1, 3))?;
+    let elems = get_vector(&exprs[0])?.elems();
+    let range = get_range(exprs.get(1), exprs.get(2), elems.len())?;
+    Ok(LinslExpr::Vector(elems[range].to_vec().into()))
+}
+
+/// Like vector-copy, but with both ends of the range required.
+pub fn subvector(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 3)?;
+    vector_copy(exprs)
+}
+
+/// Copy the elements of the source vector from index `start` up to `end` (or all of them) into
+/// the destination vector, starting at index `at`, i.e. (vector-copy! to at from start end).
+/// The source and destination may be the same vector, and the ranges may overlap. Returns the
+/// destination.
+pub fn vector_copy_into(exprs: &[LinslExpr]) -> LinslRes {
+    expect_arity(exprs, Arity::Between(3, 5))?;
+    let to = get_vector(&exprs[0])?;
+    let at = get_index(&exprs[1])?;
+    // The elements are copied out first, so that the source is no longer locked when the
+    // destination is written to, even if it is the same vector.
+    let from = get_vector(&exprs[2])?;
+    let range = get_range(exprs.get(3), exprs.get(4), from.elems().len())?;
+    let part = from.elems()[range].to_vec();
+    let mut elems = to.elems_mut();
+    let end = at.saturating_add(part.len());
+    check_range(at, end, elems.len())?;
+    elems[at..end].clone_from_slice(&part);
+    Ok(exprs[0].clone())
+}
+
+/// Replace every element of a vector with the result of applying a function to it, from the first
+/// to the last, and return the vector.
+pub fn vector_map(exprs: &[LinslExpr], ctx: &mut LinslCtx) -> LinslRes {
+    expect_n_args(exprs, 2)?;
+    let v = get_vector(&exprs[1])?;
+    // The vector is not kept locked while the function is applied, since it may use the vector.
+    // Vectors never change length, so the indices stay valid.
+    let len = v.elems().len();
+    for i in 0..len {
+        let x = v.elems()[i].clone();
+        let y = apply(&exprs[0], &[x], ctx)?;
+        v.elems_mut()[i] = y;
+    }
+    Ok(exprs[1].clone())
+}
+
+/// Return a list of the elements of a vector.
+pub fn vector_to_list(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::List(get_vector(&exprs[0])?.elems().iter().cloned().collect()))
+}
+
+/// Return a vector of the elements of a list.
+pub fn list_to_vector(exprs: &[LinslExpr]) -> LinslRes {
+    expect_n_args(exprs, 1)?;
+    Ok(LinslExpr::Vector(expect_list(&exprs[0])?.to_vec().into()))
+}
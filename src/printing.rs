@@ -0,0 +1,347 @@
+//! Printing expressions as Linsl code.
+//!
+//! Expressions are printed without recursion, keeping the parts still to be printed on a stack of
+//! their own, so that however deeply nested an expression is, printing it cannot overflow the
+//! stack. Printing can also be limited to a number of elements per list and a depth of nesting,
+//! e.g. so that the REPL does not flood the terminal with a list of a million elements.
+//!
+//! Vectors, dicts and records are mutable, so they can end up containing themselves. Where one
+//! would be printed within itself, `#cycle` is printed instead.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::datatypes::{format_complex, format_num, quote_string, LinslExpr, Promise};
+use crate::primitives::{address, net};
+
+/// How much of an expression to print. Lists, vectors, dicts and multiple values with more than
+/// `length` elements only have their first `length` elements printed, followed by e.g.
+/// `... (49900 more)`, and those nested more than `depth` levels deep are printed as `#`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintLimits {
+    pub length: Option<usize>,
+    pub depth: Option<usize>,
+}
+
+impl PrintLimits {
+    /// Print everything, which is how expressions are displayed.
+    pub const EXACT: PrintLimits = PrintLimits { length: None, depth: None };
+    /// The limits the REPL prints its results with.
+    pub const REPL: PrintLimits = PrintLimits { length: Some(100), depth: Some(100) };
+}
+
+/// An expression printed with limits, see `LinslExpr::limited`.
+pub struct Limited<'a>(pub(crate) &'a LinslExpr, pub(crate) PrintLimits);
+
+impl fmt::Display for Limited<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expr(f, self.0, self.1)
+    }
+}
+
+/// What is left to print.
+enum Piece {
+    Text(Cow<'static, str>),
+    /// An expression, along with how deeply nested it is, or None if it is part of a macro and
+    /// the limits do not apply to it.
+    Expr(LinslExpr, Option<usize>),
+    /// The end of the elements of the innermost mutable expression being printed.
+    Leave,
+}
+
+/// How an expression containing other expressions is printed: the text before its elements, the
+/// elements, each with the text printed before it, and the text after them.
+struct Parts {
+    open: Cow<'static, str>,
+    elems: Vec<(Cow<'static, str>, LinslExpr)>,
+    close: &'static str,
+    /// The text printed after `elems` in place of the elements left out, if any are.
+    elided: Option<Cow<'static, str>>,
+    /// Whether the limits apply to the elements. Macros are printed in full, along with everything
+    /// in them, since they are code rather than data.
+    limited: bool,
+    /// The address of the elements, if they are mutable.
+    mutable: Option<usize>,
+}
+
+impl Parts {
+    /// The parts of a sequence of `len` elements, which only keeps the first `length` of them.
+    fn new(
+        open: impl Into<Cow<'static, str>>,
+        close: &'static str,
+        len: usize,
+        elems: impl IntoIterator<Item = (Cow<'static, str>, LinslExpr)>,
+        length: Option<usize>
+    ) -> Parts {
+        let kept = length.map_or(len, |n| n.min(len));
+        let mut parts = Parts {
+            open: open.into(),
+            elems: Vec::new(),
+            close,
+            elided: None,
+            limited: true,
+            mutable: None,
+        };
+        for (i, (before, elem)) in elems.into_iter().enumerate() {
+            if i == kept {
+                let space = if before.starts_with(' ') { " " } else { "" };
+                parts.elided = Some(Cow::Owned(format!("{}... ({} more)", space, len - kept)));
+                break;
+            };
+            parts.elems.push((before, elem));
+        }
+        parts
+    }
+}
+
+/// The text before the n:th element of a list, i.e. a space unless it is the first.
+fn separator(n: usize) -> Cow<'static, str> {
+    Cow::Borrowed(if n == 0 { "" } else { " " })
+}
+
+/// The parts of an expression containing other expressions, or None if it is printed as it is.
+fn parts(expr: &LinslExpr, length: Option<usize>) -> Option<Parts> {
+    let parts = match expr {
+        LinslExpr::List(xs) if !xs.is_empty() => Parts::new(
+            "(",
+            ")",
+            xs.len(),
+            xs.iter().enumerate().map(|(i, x)| (separator(i), x.clone())),
+            length,
+        ),
+        LinslExpr::Vector(v) => {
+            let elems = v.elems();
+            Parts {
+                mutable: Some(address(&v.0)),
+                ..Parts::new(
+                    "#(",
+                    ")",
+                    elems.len(),
+                    elems.iter().enumerate().map(|(i, x)| (separator(i), x.clone())),
+                    length,
+                )
+            }
+        },
+        LinslExpr::Dict(d) => {
+            let entries = d.sorted();
+            Parts {
+                mutable: Some(address(&d.0)),
+                ..Parts::new(
+                    "#<dict",
+                    ">",
+                    entries.len(),
+                    entries.into_iter().map(|(k, v)| (Cow::Owned(format!(" {}: ", k)), v)),
+                    length,
+                )
+            }
+        },
+        LinslExpr::Record(r) => {
+            let fields: Vec<_> = r.kind.fields
+                .iter()
+                .zip(r.fields.elems().iter())
+                .map(|(name, val)| (Cow::Owned(format!(" {}: ", name)), val.clone()))
+                .collect();
+            Parts {
+                mutable: Some(address(&r.fields.0)),
+                ..Parts::new(format!("#<{}", r.kind.name), ">", fields.len(), fields, None)
+            }
+        },
+        LinslExpr::Values(vs) => Parts::new(
+            "#<values",
+            ">",
+            vs.len(),
+            vs.iter().map(|v| (Cow::Borrowed(" "), v.clone())),
+            length,
+        ),
+        LinslExpr::Macro(ps, bd, doc) => {
+            let before_body = match doc {
+                Some(doc) => Cow::Owned(format!(" {} ", quote_string(doc))),
+                None => Cow::Borrowed(" "),
+            };
+            let elems = vec![(Cow::Borrowed(""), (**ps).clone()), (before_body, (**bd).clone())];
+            Parts {
+                open: "(macro ".into(),
+                elems,
+                close: ")",
+                elided: None,
+                limited: false,
+                mutable: None,
+            }
+        },
+        _ => return None,
+    };
+    Some(parts)
+}
+
+/// How an expression without any expressions in it is printed.
+fn atom_to_string(expr: &LinslExpr) -> String {
+    match expr {
+        LinslExpr::Bool(b)          => if *b {"#t".to_string()} else {"#f".to_string()}
+        LinslExpr::Closure(ps, _, _, n, _) => match n {
+            Some(n) => format!("#<closure {} {}>", n, ps),
+            None => format!("#<closure {}>", ps),
+        },
+        LinslExpr::CaseLambda(clauses) => {
+            // Like a closure, a case-lambda is printed with its name, and then the parameters.
+            let mut s = match clauses.first() {
+                Some(LinslExpr::Closure(_, _, _, Some(n), _)) => format!("#<case-lambda {}", n),
+                _ => "#<case-lambda".to_string(),
+            };
+            for clause in clauses.iter() {
+                if let LinslExpr::Closure(ps, ..) = clause {
+                    s.push_str(&format!(" {}", ps));
+                };
+            }
+            s.push('>');
+            s
+        },
+        LinslExpr::Escape(_)        => "#<escape>".to_string(),
+        LinslExpr::Foreign(n, v)    => {
+            net::describe(v.as_ref()).unwrap_or_else(|| format!("#<foreign {}>", n))
+        },
+        LinslExpr::Iterator(_)      => "#<iterator>".to_string(),
+        LinslExpr::Keyword(k)       => format!(":{}", k),
+        LinslExpr::Primitive(n, _)  => format!("#<primitive {}>", n),
+        LinslExpr::CtxPrimitive(n, _) => format!("#<primitive {}>", n),
+        LinslExpr::Promise(p)       => match *p.state() {
+            Promise::Delayed(_, _) => "#<promise>".to_string(),
+            Promise::Forced(_) => "#<promise (forced)>".to_string(),
+        },
+        LinslExpr::RecordFn(kind, op) => op.name(kind),
+        LinslExpr::List(_)          => "()".to_string(),
+        LinslExpr::Number(v)        => format_num(*v),
+        LinslExpr::Complex(re, im)  => format_complex(*re, *im),
+        LinslExpr::Parameter(_)     => "#<parameter>".to_string(),
+        LinslExpr::Symbol(s)        => s.to_string(),
+        LinslExpr::String(s)        => quote_string(s),
+        LinslExpr::Dict(_)
+        | LinslExpr::Macro(..)
+        | LinslExpr::Record(_)
+        | LinslExpr::Values(_)
+        | LinslExpr::Vector(_)      => unreachable!("{:?} has parts", expr),
+    }
+}
+
+/// Print an expression, within the limits given.
+pub(crate) fn write_expr(
+    f: &mut fmt::Formatter,
+    expr: &LinslExpr,
+    limits: PrintLimits
+) -> fmt::Result {
+    let mut pieces = vec![Piece::Expr(expr.clone(), Some(0))];
+    // The addresses of the mutable expressions whose elements are being printed, innermost last.
+    let mut within = Vec::new();
+    while let Some(piece) = pieces.pop() {
+        let (expr, depth) = match piece {
+            Piece::Text(text) => {
+                f.write_str(&text)?;
+                continue;
+            },
+            Piece::Leave => {
+                within.pop();
+                continue;
+            },
+            Piece::Expr(expr, depth) => (expr, depth),
+        };
+        let Some(parts) = parts(&expr, limits.length.filter(|_| depth.is_some())) else {
+            f.write_str(&atom_to_string(&expr))?;
+            continue;
+        };
+        if parts.mutable.is_some_and(|address| within.contains(&address)) {
+            f.write_str("#cycle")?;
+            continue;
+        };
+
+        let too_deep = depth.zip(limits.depth).is_some_and(|(depth, max)| depth >= max);
+        if too_deep && parts.limited && (!parts.elems.is_empty() || parts.elided.is_some()) {
+            f.write_str("#")?;
+            continue;
+        };
+
+        f.write_str(&parts.open)?;
+        let nested = depth.filter(|_| parts.limited).map(|depth| depth + 1);
+        let mut rest = Vec::with_capacity(2 * parts.elems.len() + 2);
+        for (before, elem) in parts.elems {
+            rest.push(Piece::Text(before));
+            rest.push(Piece::Expr(elem, nested));
+        }
+        if let Some(text) = parts.elided {
+            rest.push(Piece::Text(text));
+        };
+        rest.push(Piece::Text(Cow::Borrowed(parts.close)));
+        if let Some(address) = parts.mutable {
+            within.push(address);
+            rest.push(Piece::Leave);
+        };
+        // The pieces are popped in the reverse order of how they are pushed.
+        pieces.extend(rest.into_iter().rev());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn elision() {
+        let mut interpreter = Interpreter::default();
+        let mut eval = |s: &str, length, depth| {
+            let res = interpreter.eval_str(s).unwrap();
+            res.limited(PrintLimits { length, depth }).to_string()
+        };
+
+        let digits = "'(0 1 2 3 4 5 6 7 8 9)";
+        assert_eq!("(0 1 2 3 4 5 6 7 8 9)", eval(digits, None, None));
+        assert_eq!("(0 1 2 3 4 5 6 7 8 9)", eval(digits, Some(10), None));
+        assert_eq!("(0 1 2 3 ... (6 more))", eval(digits, Some(4), None));
+        assert_eq!("(0 1 2 ... (7 more))", eval(digits, Some(3), None));
+        assert_eq!("(0 ... (9 more))", eval(digits, Some(1), None));
+        assert_eq!("(... (10 more))", eval(digits, Some(0), None));
+        assert_eq!("#(0 1 ... (3 more))", eval("(list->vector (list 0 1 2 3 9))", Some(2), None));
+        assert_eq!("#<values 1 2 ... (1 more)>", eval("(values 1 2 3)", Some(2), None));
+        eval("(define d (make-dict))", None, None);
+        eval("(dict-set! d 'a 1)", None, None);
+        eval("(dict-set! d 'b 2)", None, None);
+        assert_eq!("#<dict a: 1 ... (1 more)>", eval("d", Some(1), None));
+        assert_eq!("#<dict ... (2 more)>", eval("d", Some(0), None));
+
+        let nested = "'(1 (2 (3 (4))) #(5 #(6)))";
+        assert_eq!("(1 (2 (3 (4))) #(5 #(6)))", eval(nested, None, Some(4)));
+        assert_eq!("(1 (2 #) #(5 #))", eval(nested, None, Some(2)));
+        assert_eq!("#", eval(nested, None, Some(0)));
+        assert_eq!("(() #())", eval("'(() #())", None, Some(1)));
+        assert_eq!("(#)", eval("(list d)", None, Some(1)));
+        assert_eq!("(1 # ... (1 more))", eval(nested, Some(2), Some(1)));
+        assert_eq!("(# ... (1 more))", eval("(list d '(1))", Some(1), Some(1)));
+
+        // Macros are code, and are printed in full.
+        assert_eq!(
+            "(macro (x) (list (quote quote) (list x x x)))",
+            eval("(macro (x) (list 'quote (list x x x)))", Some(1), Some(1))
+        );
+    }
+
+    #[test]
+    fn deep_structures() {
+        let depth = 1_000_000;
+        let mut deep = LinslExpr::Number(0.0);
+        for _ in 0..depth {
+            deep = LinslExpr::List(vec![deep].into());
+        }
+
+        let printed = deep.to_string();
+        assert_eq!(2 * depth + 1, printed.len());
+        assert!(printed.starts_with("(((") && printed.ends_with(")))"));
+        let limited = deep.limited(PrintLimits { length: None, depth: Some(2) }).to_string();
+        assert_eq!("((#))", limited);
+
+        // Dropping the structure at once would recurse once per level, so it is taken apart from
+        // the outside in.
+        while let LinslExpr::List(xs) = &deep {
+            let inner = xs[0].clone();
+            deep = inner;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Profiling of where the evaluation spends its time, function by function.
+//!
+//! When a profile is set in the context, the tree walker records every application of a closure
+//! or a primitive, under the name of the function applied. For every function the profile counts
+//! the calls, and accumulates the inclusive time, i.e. the time from the call until it returned,
+//! and the exclusive time, i.e. the inclusive time minus the time spent in the functions it
+//! called. The virtual machine does not record anything.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::datatypes::{LinslExpr, PosNum, Symbol};
+
+/// What has been recorded about a single function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub calls: usize,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+/// A call which has not returned yet.
+#[derive(Debug, Clone)]
+struct Frame {
+    name: String,
+    start: Instant,
+    /// The time spent in the calls made by this one so far.
+    children: Duration,
+    /// The depth of the evaluation the call is made at. A closure returns once the evaluation it
+    /// was called in is done, which is when the tree walker leaves that depth.
+    depth: PosNum,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    entries: HashMap<String, ProfileEntry>,
+    /// The calls in progress, innermost last.
+    stack: Vec<Frame>,
+}
+
+impl Profile {
+    /// What has been recorded about the function with this name, if it has been called.
+    pub fn entry(&self, name: &str) -> Option<&ProfileEntry> {
+        self.entries.get(name)
+    }
+
+    /// Record a call to the function named, made at the given depth.
+    pub(crate) fn enter(&mut self, name: String, depth: PosNum) {
+        self.entries.entry(name.clone()).or_default().calls += 1;
+        self.stack.push(Frame { name, start: Instant::now(), children: Duration::ZERO, depth });
+    }
+
+    /// Record a closure called in tail position, which replaces the call made at the same depth,
+    /// if there is one.
+    pub(crate) fn enter_tail(&mut self, name: String, depth: PosNum) {
+        if self.stack.last().is_some_and(|frame| frame.depth == depth) {
+            self.exit();
+        };
+        self.enter(name, depth);
+    }
+
+    /// Record that the innermost call has returned.
+    pub(crate) fn exit(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = frame.start.elapsed();
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        };
+        // The time of a recursive call is already part of the time of the outer call.
+        let recursive = self.stack.iter().any(|outer| outer.name == frame.name);
+        let entry = self.entries.entry(frame.name).or_default();
+        if !recursive {
+            entry.inclusive += elapsed;
+        };
+        entry.exclusive += elapsed.saturating_sub(frame.children);
+    }
+
+    /// Record that every call made at the given depth or deeper has returned.
+    pub(crate) fn exit_depth(&mut self, depth: PosNum) {
+        while self.stack.last().is_some_and(|frame| frame.depth >= depth) {
+            self.exit();
+        }
+    }
+
+    /// A table of the functions called, the ones with the most inclusive time first.
+    pub fn report(&self) -> String {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|(a_name, a), (b_name, b)| {
+            b.inclusive.cmp(&a.inclusive).then_with(|| a_name.cmp(b_name))
+        });
+
+        let mut report = format!(
+            "{:<24} {:>10} {:>16} {:>16}\n",
+            "function", "calls", "inclusive (ms)", "exclusive (ms)"
+        );
+        for (name, entry) in entries {
+            report.push_str(&format!(
+                "{:<24} {:>10} {:>16.3} {:>16.3}\n",
+                name,
+                entry.calls,
+                entry.inclusive.as_secs_f64() * 1000.0,
+                entry.exclusive.as_secs_f64() * 1000.0,
+            ));
+        }
+        report
+    }
+}
+
+/// The name a call is recorded under. A closure is named by the name it was defined under, and
+/// other functions by the symbol they were looked up by if there is one, or else by their own
+/// name. Anonymous closures are named by how they are printed.
+pub(crate) fn profile_name(head: Option<&Symbol>, f: &LinslExpr) -> String {
+    match (head, f) {
+        (_, LinslExpr::Closure(_, _, _, Some(n), _)) => n.to_string(),
+        (Some(s), _) => s.to_string(),
+        (None, LinslExpr::Primitive(n, _) | LinslExpr::CtxPrimitive(n, _)) => n.to_string(),
+        (None, _) => f.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    const FIB: &str = "
+        (define - (lambda (a b) (+ a (neg b))))
+        (define fib (lambda (n) (if (> 2 n) n (+ (fib (- n 1)) (fib (- n 2))))))
+        (define fibs (lambda (ns) (map fib ns)))
+        (fibs (list 5 10))
+    ";
+
+    #[test]
+    fn profiling() {
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.profile = Some(Profile::default());
+        assert_eq!("(5 55)", interpreter.eval_str(FIB).unwrap().to_string());
+        let profile = interpreter.ctx.profile.take().unwrap();
+
+        let calls = |name| profile.entry(name).map(|entry| entry.calls);
+        assert_eq!(Some(1), calls("fibs"));
+        // fib 5 and fib 10 take 15 and 177 calls, and the ones for n >= 2 subtract twice.
+        assert_eq!(Some(192), calls("fib"));
+        assert_eq!(Some(2 * (7 + 88)), calls("-"));
+        assert_eq!(Some(192), calls(">"));
+        assert_eq!(Some(1), calls("map"));
+        assert_eq!(None, calls("if"));
+
+        let fib = profile.entry("fib").unwrap();
+        assert!(fib.exclusive <= fib.inclusive);
+        assert!(fib.inclusive <= profile.entry("fibs").unwrap().inclusive);
+        // Every call has returned.
+        assert!(profile.stack.is_empty());
+
+        let report = profile.report();
+        assert!(report.starts_with("function"));
+        assert!(report.lines().nth(1).unwrap().starts_with("fibs "));
+        assert!(report.lines().any(|line| line.starts_with("fib ")));
+    }
+}
@@ -0,0 +1,142 @@
+//! The random number generator of the interpreter, which `random` and `uuid` draw from.
+//!
+//! It is seeded from the entropy of the operating system, unless a seed is set with
+//! `set-random-seed!`, after which it gives the same numbers every time, e.g. for reproducible
+//! test fixtures. It is a SplitMix64 generator: fast and evenly distributed, but not fit for
+//! cryptographic use, since its numbers can be predicted from earlier ones.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A random number generator, whose state is shared by the clones of a context, so that they do
+/// not draw the same numbers.
+#[derive(Clone)]
+pub struct Rng {
+    state: Arc<Mutex<u64>>,
+}
+
+impl Rng {
+    /// A generator seeded from the entropy of the operating system. The standard library seeds
+    /// the keys of `RandomState` from it, so a hash made with them is as good as a random number.
+    pub fn from_entropy() -> Rng {
+        let mut hasher = RandomState::new().build_hasher();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        hasher.write_u128(nanos);
+        Rng::seeded(hasher.finish())
+    }
+
+    /// A generator giving the same numbers every time it is given the same seed.
+    pub fn seeded(seed: u64) -> Rng {
+        Rng { state: Arc::new(Mutex::new(seed)) }
+    }
+
+    /// Start over from a seed, for this generator and every clone of it.
+    pub fn reseed(&self, seed: u64) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = seed;
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random number from 0 up to, but not including, `n`, which must not be 0. Every number is
+    /// as likely as the others: draws from the uneven remainder of the range are drawn again.
+    pub fn below(&self, n: u64) -> u64 {
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return x % n;
+            };
+        }
+    }
+
+    /// A random number from 0 up to, but not including, 1.
+    pub fn unit(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::from_entropy()
+    }
+}
+
+impl fmt::Debug for Rng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rng")
+    }
+}
+
+/// A random (version 4) UUID, e.g. `1b4e28ba-2fa1-4d2e-883f-0016d3cca427`: 122 random bits, with
+/// the bits marking the version and the variant set, written as lowercase hex in groups.
+pub fn uuid_v4(rng: &Rng) -> String {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&rng.next_u64().to_be_bytes());
+    bytes[8..].copy_from_slice(&rng.next_u64().to_be_bytes());
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Whether a string is a UUID written as hex in the groups of 8, 4, 4, 4 and 12 digits, in either
+/// case. Any version and variant is accepted.
+pub fn is_uuid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uuids() {
+        let rng = Rng::from_entropy();
+        let (a, b) = (uuid_v4(&rng), uuid_v4(&rng));
+        assert_ne!(a, b);
+        for uuid in [&a, &b] {
+            assert!(is_uuid(uuid), "{}", uuid);
+            assert_eq!(uuid.to_lowercase(), *uuid);
+            assert_eq!(Some('4'), uuid.chars().nth(14), "{}", uuid);
+            assert!(matches!(uuid.chars().nth(19), Some('8' | '9' | 'a' | 'b')), "{}", uuid);
+        }
+        assert!(is_uuid("1B4E28BA-2FA1-11D2-883F-0016D3CCA427"));
+        for s in [
+            "", "1b4e28ba2fa14d2e883f0016d3cca427", "1b4e28ba-2fa1-4d2e-883f-0016d3cca42",
+            "1b4e28ba-2fa1-4d2e-883f-0016d3cca427-", "1b4e28ba-2fa1-4d2e-883f-0016d3cca42g",
+            "1b4e28b-a2fa1-4d2e-883f-0016d3cca427",
+        ] {
+            assert!(!is_uuid(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn seeded() {
+        // The same seed gives the same numbers, also to the clones of a generator.
+        let draw = |rng: &Rng| (0..3).map(|_| rng.below(1000)).collect::<Vec<_>>();
+        let rng = Rng::seeded(42);
+        let first = draw(&rng);
+        assert_eq!(first, draw(&Rng::seeded(42)));
+        assert_ne!(first, draw(&Rng::seeded(43)));
+        let clone = rng.clone();
+        clone.reseed(42);
+        assert_eq!(first, draw(&rng));
+        assert!(first.iter().all(|&x| x < 1000));
+        assert!((0..100).map(|_| rng.unit()).all(|x| (0.0..1.0).contains(&x)));
+    }
+}
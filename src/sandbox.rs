@@ -0,0 +1,119 @@
+//! Sandboxing, which keeps code from reaching beyond the interpreter, e.g. when running formulas
+//! written by the users of an application.
+//!
+//! Every primitive and special form needs a capability: most only need `Pure`, since all they do
+//! is compute values, but e.g. `read-file` needs `Fs`. A `SandboxPolicy` lists the capabilities
+//! allowed, and anything needing another one fails with a CapabilityDenied error when applied,
+//! while still being bound, so that `help` and `bound?` see it as usual. A policy can also limit
+//! how many steps an evaluation may take, and how deeply it may nest.
+
+use std::collections::HashSet;
+
+use crate::datatypes::{LinslErr, PosNum};
+
+/// What a primitive or special form can reach beyond the values it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Nothing; the result only depends on the arguments.
+    Pure,
+    /// The input and output of the process, e.g. the terminal.
+    Io,
+    /// The filesystem.
+    Fs,
+    /// The network.
+    Net,
+    /// Other processes, and the environment variables.
+    Process,
+}
+
+/// The capabilities needed by the primitives and special forms which need any other than `Pure`.
+pub const CAPABILITIES: &[(&str, Capability)] = &[
+    ("chdir", Capability::Fs),
+    ("copy-file", Capability::Fs),
+    ("current-directory", Capability::Fs),
+    ("delete-file", Capability::Fs),
+    ("directory?", Capability::Fs),
+    ("file-exists?", Capability::Fs),
+    ("file-size", Capability::Fs),
+    ("list-directory", Capability::Fs),
+    ("make-directory", Capability::Fs),
+    #[cfg(feature = "http")]
+    ("http-get", Capability::Net),
+    #[cfg(feature = "http")]
+    ("http-post", Capability::Net),
+    ("md5-file", Capability::Fs),
+    ("read-file", Capability::Fs),
+    ("require", Capability::Fs),
+    ("run-tests", Capability::Io),
+    ("save-image", Capability::Fs),
+    ("sha256-file", Capability::Fs),
+    ("tcp-accept", Capability::Net),
+    ("tcp-close", Capability::Net),
+    ("tcp-connect", Capability::Net),
+    ("tcp-listen", Capability::Net),
+    ("tcp-port", Capability::Net),
+    ("tcp-recv", Capability::Net),
+    ("tcp-send", Capability::Net),
+];
+
+/// The capability needed by the primitive or special form of the name given.
+pub fn capability(name: &str) -> Capability {
+    CAPABILITIES.iter()
+        .find(|(n, _)| *n == name)
+        .map_or(Capability::Pure, |(_, capability)| *capability)
+}
+
+/// What code evaluated in an interpreter may do. By default everything is allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    /// The capabilities allowed.
+    pub capabilities: HashSet<Capability>,
+    /// The most steps an evaluation may take, however much fuel it is given, or None if there is
+    /// no limit.
+    pub max_fuel: Option<usize>,
+    /// The deepest an evaluation may be nested, however deep the interpreter allows, or None if
+    /// there is no limit.
+    pub max_depth: Option<PosNum>,
+}
+
+impl SandboxPolicy {
+    /// A policy allowing only pure computation.
+    pub fn pure() -> SandboxPolicy {
+        SandboxPolicy {
+            capabilities: HashSet::from([Capability::Pure]),
+            max_fuel: None,
+            max_depth: None,
+        }
+    }
+
+    /// Whether the policy allows a capability.
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Check that the primitive or special form of the name given may be used.
+    pub(crate) fn check(&self, name: &str) -> Result<(), LinslErr> {
+        if self.allows(capability(name)) {
+            Ok(())
+        } else {
+            // TODO: Fix pos
+            Err(LinslErr::CapabilityDenied(name.to_string(), (0, 0)))
+        }
+    }
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        SandboxPolicy {
+            capabilities: HashSet::from([
+                Capability::Pure,
+                Capability::Io,
+                Capability::Fs,
+                Capability::Net,
+                Capability::Process,
+            ]),
+            max_fuel: None,
+            max_depth: None,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Lazy streams, i.e. lists whose elements are only computed once they are needed. This allows
+//! infinite sequences, such as the stream of all natural numbers.
+//!
+//! A stream is either the empty list, or a pair of its first element and a promise of the rest of
+//! the stream. The stream forms are special forms rather than primitives, since most of them have
+//! to force promises, which means evaluating code. They are shared by both engines, which only
+//! differ in how the values of the arguments are computed.
+
+use crate::datatypes::{LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslPromise, LinslRes, Symbol};
+use crate::evaluation::{bind_symbols, evaluate, force, is_truthy};
+use crate::primitives::get_index;
+
+/// The special forms operating on streams.
+#[derive(Clone, Copy, Debug)]
+pub enum StreamForm {
+    /// `(stream-cons head tail)` evaluates head, and delays tail.
+    Cons,
+    /// `(stream-car s)` is the first element of s.
+    Car,
+    /// `(stream-cdr s)` forces the rest of s.
+    Cdr,
+    /// `(stream-take n s)` is a list of the first n elements of s, or all of them if s is shorter.
+    Take,
+    /// `(stream-map f s)` is the stream of f applied to every element of s.
+    Map,
+    /// `(stream-filter p s)` is the stream of the elements of s for which p is true.
+    Filter,
+}
+
+impl StreamForm {
+    /// The stream form with this name, if there is one.
+    pub fn from_name(name: &str) -> Option<StreamForm> {
+        match name {
+            "stream-cons" => Some(StreamForm::Cons),
+            "stream-car" => Some(StreamForm::Car),
+            "stream-cdr" => Some(StreamForm::Cdr),
+            "stream-take" => Some(StreamForm::Take),
+            "stream-map" => Some(StreamForm::Map),
+            "stream-filter" => Some(StreamForm::Filter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StreamForm::Cons => "stream-cons",
+            StreamForm::Car => "stream-car",
+            StreamForm::Cdr => "stream-cdr",
+            StreamForm::Take => "stream-take",
+            StreamForm::Map => "stream-map",
+            StreamForm::Filter => "stream-filter",
+        }
+    }
+
+    /// Check that the form is given the right number of forms, giving the message to fail with if
+    /// it is not.
+    pub(crate) fn check_arity(self, forms: usize) -> Result<(), String> {
+        let arity = match self {
+            StreamForm::Car | StreamForm::Cdr => 1,
+            _ => 2,
+        };
+        if forms != arity {
+            return Err(format!("{} must have {} forms, found {}", self.name(), arity, forms));
+        };
+        Ok(())
+    }
+}
+
+/// Evaluate a stream form. All its arguments are evaluated, except the tail of a stream-cons,
+/// which is turned into a promise instead.
+pub(crate) fn evaluate_stream_form(
+    form: StreamForm,
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    form.check_arity(exprs.len()).map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
+
+    let mut args = Vec::with_capacity(exprs.len());
+    for (i, expr) in exprs.iter().enumerate() {
+        args.push(match form {
+            StreamForm::Cons if i == 1 => {
+                LinslExpr::Promise(LinslPromise::new(expr.clone(), env.clone()))
+            },
+            _ => evaluate(expr, env, ctx)?,
+        });
+    }
+    apply_stream_form(form, &args, env, ctx)
+}
+
+/// Apply a stream form to the values of its arguments, where the tail of a stream-cons is already
+/// a promise.
+pub(crate) fn apply_stream_form(
+    form: StreamForm,
+    args: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    match (form, args) {
+        (StreamForm::Cons, [head, tail]) => Ok(stream_cons(head.clone(), tail.clone())),
+        (StreamForm::Car, [s]) => Ok(split_stream(form, s)?.0),
+        (StreamForm::Cdr, [s]) => force(split_stream(form, s)?.1, ctx),
+        (StreamForm::Take, [n, s]) => stream_take(n, s, ctx),
+        (StreamForm::Map, [f, s]) => stream_map(f, s, env, ctx),
+        (StreamForm::Filter, [p, s]) => stream_filter(p, s, env, ctx),
+        // The number of forms is checked before the arguments are evaluated.
+        _ => Err(
+            LinslErr::InternalError(format!("{} applied to {} values.", form.name(), args.len()))
+        ),
+    }
+}
+
+fn stream_cons(head: LinslExpr, tail: LinslExpr) -> LinslExpr {
+    LinslExpr::List(vec![head, tail].into())
+}
+
+fn empty_stream() -> LinslExpr {
+    LinslExpr::List(vec![].into())
+}
+
+/// Split a non-empty stream into its first element and the promise of the rest.
+fn split_stream(form: StreamForm, s: &LinslExpr) -> Result<(LinslExpr, LinslExpr), LinslErr> {
+    if let LinslExpr::List(exprs) = s {
+        match &exprs[..] {
+            [head, tail @ LinslExpr::Promise(_)] => return Ok((head.clone(), tail.clone())),
+            [] => return Err(
+                LinslErr::TypeError(
+                    // TODO: Fix pos
+                    format!("{} was given the empty stream", form.name()),
+                    (0, 0)
+                )
+            ),
+            _ => (),
+        }
+    };
+    Err(
+        LinslErr::TypeError(
+            // TODO: Fix pos
+            format!("Expected a stream, found \'{}\'", s),
+            (0, 0)
+        )
+    )
+}
+
+/// Split a stream, unless it is empty.
+fn next(form: StreamForm, s: &LinslExpr) -> Result<Option<(LinslExpr, LinslExpr)>, LinslErr> {
+    match s {
+        LinslExpr::List(exprs) if exprs.is_empty() => Ok(None),
+        _ => split_stream(form, s).map(Some),
+    }
+}
+
+/// The first n elements of a stream, as a list. The rest of the stream is left unforced.
+fn stream_take(n: &LinslExpr, s: &LinslExpr, ctx: &mut LinslCtx) -> LinslRes {
+    let n = get_index(n)?;
+    let mut elems = Vec::new();
+    let mut s = s.clone();
+    while elems.len() < n {
+        let Some((head, tail)) = next(StreamForm::Take, &s)? else {
+            break;
+        };
+        elems.push(head);
+        // Only the promises of elements which are taken are forced.
+        if elems.len() < n {
+            s = force(tail, ctx)?;
+        };
+    }
+    Ok(LinslExpr::List(elems.into()))
+}
+
+fn symbol(name: &str) -> LinslExpr {
+    LinslExpr::Symbol(Symbol::new(name))
+}
+
+/// The expression (head args ...), e.g. (stream-map f (stream-cdr s)), which is how the stream
+/// forms refer to their (already evaluated) arguments from within a promise.
+fn form(head: &str, args: Vec<LinslExpr>) -> LinslExpr {
+    let mut exprs = vec![symbol(head)];
+    exprs.extend(args);
+    LinslExpr::List(exprs.into())
+}
+
+/// Apply f to the first element of the stream right away, and to the rest once it is forced. The
+/// promise of the rest evaluates (stream-map f (stream-cdr s)) in a new frame binding f and s,
+/// which is how the closure is carried along until it is needed.
+fn stream_map(f: &LinslExpr, s: &LinslExpr, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    if next(StreamForm::Map, s)?.is_none() {
+        return Ok(empty_stream());
+    };
+
+    let frame = bind_symbols(&[Symbol::new("f"), Symbol::new("s")], &[f.clone(), s.clone()], env)?;
+    let head = evaluate(&form("f", vec![form("stream-car", vec![symbol("s")])]), &frame, ctx)?;
+    let tail = form("stream-map", vec![symbol("f"), form("stream-cdr", vec![symbol("s")])]);
+    Ok(stream_cons(head, LinslExpr::Promise(LinslPromise::new(tail, frame))))
+}
+
+/// Skip elements of the stream until one for which p is true (i.e. not #f) is found, and keep the
+/// rest of the stream to be filtered once it is forced, in the same way as stream-map does.
+fn stream_filter(p: &LinslExpr, s: &LinslExpr, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let mut s = s.clone();
+    loop {
+        let Some((head, tail)) = next(StreamForm::Filter, &s)? else {
+            return Ok(empty_stream());
+        };
+
+        let frame = bind_symbols(&[Symbol::new("p"), Symbol::new("s")], &[p.clone(), s], env)?;
+        let test = form("p", vec![form("stream-car", vec![symbol("s")])]);
+        if is_truthy(&evaluate(&test, &frame, ctx)?) {
+            let rest = form("stream-cdr", vec![symbol("s")]);
+            let tail = form("stream-filter", vec![symbol("p"), rest]);
+            return Ok(stream_cons(head, LinslExpr::Promise(LinslPromise::new(tail, frame))));
+        };
+        s = force(tail, ctx)?;
+    }
+}
@@ -0,0 +1,123 @@
+//! Unit tests written in Linsl, e.g. to ship along with a library.
+//!
+//! `(define-test name body ...)` registers a test in the context, capturing the environment the
+//! form is evaluated in, and `(run-tests)` runs every test registered so far, in the order they
+//! were defined. A test passes if every form of its body evaluates without an error; the
+//! assertions `assert-equal` and `assert-error` fail with an AssertionFailed error describing what
+//! was found. Like the module forms, `define-test` is shared by both engines.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::datatypes::{LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslRes, Pos, Symbol};
+use crate::evaluation::{check_bindable, evaluate};
+
+/// A test defined by a define-test form.
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: Symbol,
+    /// The position of the define-test form, which the report gives for a failure.
+    pub pos: Pos,
+    body: Vec<LinslExpr>,
+    /// The environment the test was defined in, which its body is evaluated in a frame of.
+    env: LinslEnv,
+}
+
+/// The outcome of running a test: its name, where it was defined, and the error it failed with, if
+/// any.
+pub type TestResult = (Symbol, Pos, Result<(), LinslErr>);
+
+/// Where `run-tests` writes its report. Shared by the clones of a context.
+#[derive(Clone)]
+pub struct ReportOutput(Arc<Mutex<dyn Write + Send>>);
+
+impl ReportOutput {
+    pub fn new(output: impl Write + Send + 'static) -> ReportOutput {
+        ReportOutput(Arc::new(Mutex::new(output)))
+    }
+
+    /// Reports written to stdout.
+    pub fn stdout() -> ReportOutput {
+        ReportOutput::new(io::stdout())
+    }
+
+    /// Write a report. As with a trace, failing to write it does not stop the evaluation.
+    pub(crate) fn write(&self, report: &str) {
+        let mut output = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = output.write_all(report.as_bytes()).and_then(|_| output.flush());
+    }
+}
+
+impl fmt::Debug for ReportOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReportOutput")
+    }
+}
+
+/// Evaluation of the special form "define-test". Defining a test with the name of an existing one
+/// replaces it, keeping its place in the order the tests are run in. The test is defined at
+/// `ctx.pos`, the position of the form. Returns the name.
+pub(crate) fn evaluate_define_test(
+    exprs: &[LinslExpr],
+    env: &LinslEnv,
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let (name, body) = match exprs {
+        [LinslExpr::Symbol(name), body @ ..] if !body.is_empty() => (name, body),
+        _ => return Err(
+            LinslErr::SyntaxError(
+                "define-test must have a name and at least one form".to_string(),
+                ctx.pos
+            )
+        ),
+    };
+    check_bindable(name).map_err(|msg| LinslErr::SyntaxError(msg, ctx.pos))?;
+
+    let test = Test { name: name.clone(), pos: ctx.pos, body: body.to_vec(), env: env.clone() };
+    match ctx.tests.iter_mut().find(|t| t.name == *name) {
+        Some(old) => *old = test,
+        None => ctx.tests.push(test),
+    };
+    Ok(LinslExpr::Symbol(name.clone()))
+}
+
+/// Whether a test failing with this error counts as a failure, rather than stopping the tests
+/// altogether. Aborting, running out of fuel and escaping to an enclosing `call/ec` are not
+/// failures of the test itself.
+pub(crate) fn is_failure(err: &LinslErr) -> bool {
+    !matches!(err, LinslErr::Aborted(_) | LinslErr::FuelExhausted(_) | LinslErr::Escape(..))
+}
+
+/// Run every test registered in the context, in order, each in a frame of its own. Returns how
+/// every test went, or the error which stopped them.
+pub fn run_tests(ctx: &mut LinslCtx) -> Result<Vec<TestResult>, LinslErr> {
+    let tests = ctx.tests.clone();
+    let mut results = Vec::new();
+    for test in tests {
+        let env = LinslEnv::new(&test.env);
+        let res = test.body.iter().try_for_each(|form| evaluate(form, &env, ctx).map(|_| ()));
+        match res {
+            Err(e) if !is_failure(&e) => return Err(e),
+            res => results.push((test.name, test.pos, res)),
+        }
+    }
+    Ok(results)
+}
+
+/// The report of running the tests, one line for every test followed by a summary. The line of a
+/// failed test gives where it was defined, along with the error.
+pub fn report(results: &[TestResult]) -> String {
+    let mut report = String::new();
+    for (name, pos, res) in results {
+        match res {
+            Ok(()) => report.push_str(&format!("test {} ... ok\n", name)),
+            Err(e) => report.push_str(
+                &format!("test {} ... FAILED (defined at ({}, {})): {}\n", name, pos.0, pos.1, e)
+            ),
+        }
+    }
+    let failed = results.iter().filter(|(.., res)| res.is_err()).count();
+    report.push_str(&format!("{} passed, {} failed\n", results.len() - failed, failed));
+    report
+}
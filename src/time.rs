@@ -0,0 +1,238 @@
+//! Writing times as strings and reading them back, with a small subset of the directives of
+//! `strftime`: `%Y` for the year, `%m` for the month, `%d` for the day of the month, `%H` for the
+//! hour, `%M` for the minute, `%S` for the second, and `%%` for a `%`. Times are counted in seconds
+//! since the Unix epoch, and are always in UTC: there are no time zones.
+
+/// The times which can be written, in seconds since the Unix epoch: those from 0000-01-01 00:00:00
+/// up to 10000-01-01 00:00:00, as the year has four digits.
+pub const SECS_RANGE: std::ops::Range<i64> = -62167219200..253402300800;
+
+/// Why a string could not be read as a time, and the index of the character where reading failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub index: usize,
+}
+
+/// A directive of a format, or a character standing for itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Piece {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Char(char),
+}
+
+/// The pieces of a format, or an error naming the directive which is not known.
+fn pieces(fmt: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            pieces.push(Piece::Char(c));
+            continue;
+        };
+        pieces.push(match chars.next() {
+            Some('Y') => Piece::Year,
+            Some('m') => Piece::Month,
+            Some('d') => Piece::Day,
+            Some('H') => Piece::Hour,
+            Some('M') => Piece::Minute,
+            Some('S') => Piece::Second,
+            Some('%') => Piece::Char('%'),
+            Some(c @ ('z' | 'Z')) => {
+                return Err(format!("Unknown directive \'%{}\'; times are always UTC", c));
+            },
+            Some(c) => return Err(format!(
+                "Unknown directive \'%{}\', expected one of %Y, %m, %d, %H, %M, %S and %%", c
+            )),
+            None => return Err("The format ends in the middle of a directive".to_string()),
+        });
+    }
+    Ok(pieces)
+}
+
+/// The number of days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Counting the years from March, the leap day is the last day of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of the proleptic Gregorian calendar a number of days from 1970-01-01, as (year,
+/// month, day).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Write a time, in whole seconds since the Unix epoch, according to a format. Fails if the
+/// format has a directive which is not known.
+pub fn format_time(secs: i64, fmt: &str) -> Result<String, String> {
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    let mut formatted = String::new();
+    for piece in pieces(fmt)? {
+        match piece {
+            Piece::Year => formatted.push_str(&format!("{:04}", year)),
+            Piece::Month => formatted.push_str(&format!("{:02}", month)),
+            Piece::Day => formatted.push_str(&format!("{:02}", day)),
+            Piece::Hour => formatted.push_str(&format!("{:02}", secs_of_day / 3600)),
+            Piece::Minute => formatted.push_str(&format!("{:02}", secs_of_day / 60 % 60)),
+            Piece::Second => formatted.push_str(&format!("{:02}", secs_of_day % 60)),
+            Piece::Char(c) => formatted.push(c),
+        }
+    }
+    Ok(formatted)
+}
+
+/// Read a time written according to a format, returning it in seconds since the Unix epoch. The
+/// year must have four digits and everything else two. What the format leaves out is taken from
+/// 1970-01-01 00:00:00. The outer error is for a format with a directive which is not known.
+pub fn parse_time(s: &str, fmt: &str) -> Result<Result<i64, ParseError>, String> {
+    let pieces = pieces(fmt)?;
+    let chars: Vec<char> = s.chars().collect();
+    let mut index = 0;
+    let err = |message: String, index: usize| Err(ParseError { message, index });
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    // Where the day was read, since whether it is in its month is known only at the end.
+    let mut day_index = 0;
+
+    for piece in pieces {
+        let (digits, name, field, range) = match piece {
+            Piece::Char(c) => {
+                match chars.get(index) {
+                    Some(&found) if found == c => index += 1,
+                    Some(found) => {
+                        let message = format!("Expected \'{}\', found \'{}\'", c, found);
+                        return Ok(err(message, index));
+                    },
+                    None => return Ok(err(format!("Expected \'{}\', found the end", c), index)),
+                };
+                continue;
+            },
+            Piece::Year => (4, "year", &mut year, 0..=9999),
+            Piece::Month => (2, "month", &mut month, 1..=12),
+            Piece::Day => (2, "day", &mut day, 1..=31),
+            Piece::Hour => (2, "hour", &mut hour, 0..=23),
+            Piece::Minute => (2, "minute", &mut minute, 0..=59),
+            Piece::Second => (2, "second", &mut second, 0..=59),
+        };
+        let mut value = 0;
+        for i in index..index + digits {
+            match chars.get(i).and_then(|c| c.to_digit(10)) {
+                Some(digit) => value = value * 10 + digit as i64,
+                None => {
+                    let found =
+                        chars.get(i).map_or("the end".to_string(), |c| format!("\'{}\'", c));
+                    return Ok(err(
+                        format!("Expected {} digits for the {}, found {}", digits, name, found),
+                        i
+                    ));
+                },
+            }
+        }
+        if !range.contains(&value) {
+            return Ok(err(format!("There is no {} {}", name, value), index));
+        };
+        if piece == Piece::Day {
+            day_index = index;
+        };
+        *field = value;
+        index += digits;
+    }
+
+    if let Some(c) = chars.get(index) {
+        let hint = if matches!(c, 'Z' | '+' | '-') { "; times are always UTC" } else { "" };
+        return Ok(err(format!("Found \'{}\' after the time{}", c, hint), index));
+    };
+    if day > days_in_month(year, month) {
+        let message = format!("There is no day {} in {:04}-{:02}", day, year, month);
+        return Ok(err(message, day_index));
+    };
+    let days = days_from_civil(year, month, day);
+    Ok(Ok(days * 86400 + hour * 3600 + minute * 60 + second))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FMT: &str = "%Y-%m-%d %H:%M:%S";
+
+    #[test]
+    fn formatting() {
+        assert_eq!(Ok("1970-01-01 00:00:00".to_string()), format_time(0, FMT));
+        assert_eq!(Ok("2009-02-13 23:31:30".to_string()), format_time(1234567890, FMT));
+        assert_eq!(Ok("2000-02-29 100%".to_string()), format_time(951782400, "%Y-%m-%d 100%%"));
+        assert_eq!(Ok("1969-12-31T23:59:59".to_string()), format_time(-1, "%Y-%m-%dT%H:%M:%S"));
+        assert!(format_time(0, "%Y %q").unwrap_err().contains("'%q'"));
+        assert!(format_time(0, "%Z").unwrap_err().contains("UTC"));
+        assert!(format_time(0, "%").is_err());
+
+        assert_eq!(days_from_civil(0, 1, 1) * 86400, SECS_RANGE.start);
+        assert_eq!(days_from_civil(10000, 1, 1) * 86400, SECS_RANGE.end);
+        assert_eq!(Ok("0000-01-01 00:00:00".to_string()), format_time(SECS_RANGE.start, FMT));
+        assert_eq!(Ok("9999-12-31 23:59:59".to_string()), format_time(SECS_RANGE.end - 1, FMT));
+    }
+
+    #[test]
+    fn round_trips() {
+        // Every day of a few leap and common years, at various times.
+        for secs in (-2208988800..4102444800i64).step_by(86400 * 7 + 3671) {
+            let formatted = format_time(secs, FMT).unwrap();
+            assert_eq!(Ok(Ok(secs)), parse_time(&formatted, FMT), "{}", formatted);
+        }
+        for date in ["2000-02-29", "1900-02-28", "2024-12-31", "0001-01-01"] {
+            let secs = parse_time(date, "%Y-%m-%d").unwrap().unwrap();
+            assert_eq!(Ok(date.to_string()), format_time(secs, "%Y-%m-%d"));
+        }
+        assert_eq!(Ok(Ok(3600 + 120)), parse_time("01:02", "%H:%M"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str, fmt: &str| parse_time(s, fmt).unwrap().unwrap_err();
+        let at = |message: &str, index| ParseError { message: message.to_string(), index };
+        assert_eq!(
+            at("Expected 2 digits for the month, found 'x'", 6),
+            err("2024-1x-01", "%Y-%m-%d")
+        );
+        assert_eq!(at("Expected '-', found '/'", 4), err("2024/10-01", "%Y-%m-%d"));
+        assert_eq!(at("There is no month 13", 5), err("2024-13-01", "%Y-%m-%d"));
+        assert_eq!(at("There is no day 30 in 2023-02", 8), err("2023-02-30", "%Y-%m-%d"));
+        assert_eq!(at("Expected 4 digits for the year, found the end", 2), err("20", "%Y"));
+        assert_eq!(
+            at("Found 'Z' after the time; times are always UTC", 10),
+            err("2024-10-01Z", "%Y-%m-%d")
+        );
+        assert!(parse_time("2024", "%y").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Tracing of the tree walker, e.g. for seeing (or showing) how an expression is evaluated.
+//!
+//! When a trace is set in the context, every evaluation the tree walker enters is logged as a line
+//! with the expression, and every one it leaves as a line with the value or error, each indented
+//! by how deeply nested the evaluation is. Expressions and values are cut off after a number of
+//! characters, and evaluations nested more deeply than a limit are not logged, so that runaway
+//! recursion does not flood the log before it hits the recursion limit. The virtual machine does
+//! not log anything.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::datatypes::{LinslExpr, LinslRes, PosNum};
+use crate::printing::PrintLimits;
+
+/// How many characters of an expression or value are logged by default.
+pub const DEFAULT_TRACE_WIDTH: usize = 80;
+/// How deeply nested the evaluations logged by default may be.
+pub const DEFAULT_TRACE_DEPTH: PosNum = 100;
+
+/// What the trace is written to.
+struct Output<W: ?Sized> {
+    /// Whether evaluations have been left out since the last line was logged, so that `...` is
+    /// only logged once for all of them.
+    elided: bool,
+    writer: W,
+}
+
+/// Where the trace is written to, along with how much of it. Shared by the clones of a context.
+#[derive(Clone)]
+pub struct Trace {
+    output: Arc<Mutex<Output<dyn Write + Send>>>,
+    /// How many characters of an expression or value are logged.
+    pub width: usize,
+    /// How deeply nested the evaluations logged may be.
+    pub max_depth: PosNum,
+}
+
+impl Trace {
+    pub fn new(output: impl Write + Send + 'static) -> Trace {
+        Trace {
+            output: Arc::new(Mutex::new(Output { elided: false, writer: output })),
+            width: DEFAULT_TRACE_WIDTH,
+            max_depth: DEFAULT_TRACE_DEPTH,
+        }
+    }
+
+    /// A trace written to stderr.
+    pub fn stderr() -> Trace {
+        Trace::new(io::stderr())
+    }
+
+    /// Log entering the evaluation of an expression at the given depth. Evaluations nested too
+    /// deeply are left out, with `...` logged in their place.
+    pub(crate) fn enter(&self, expr: &LinslExpr, depth: PosNum) {
+        if depth <= self.max_depth {
+            self.log(depth, &format!("-> {}", self.cut(expr)));
+        } else {
+            let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+            if !output.elided {
+                output.elided = true;
+                let indent = "  ".repeat(self.max_depth);
+                // The evaluation goes on whether or not the trace can be written.
+                let _ = writeln!(output.writer, "{}...", indent);
+            };
+        };
+    }
+
+    /// Log leaving an evaluation at the given depth with its result.
+    pub(crate) fn exit(&self, res: &LinslRes, depth: PosNum) {
+        if depth <= self.max_depth {
+            match res {
+                Ok(val) => self.log(depth, &format!("<- {}", self.cut(val))),
+                Err(e) => self.log(depth, &format!("<- {}", e)),
+            }
+        };
+    }
+
+    /// The expression as it is printed, cut off after `width` characters.
+    fn cut(&self, expr: &LinslExpr) -> String {
+        let printed = expr.limited(PrintLimits::REPL).to_string();
+        match printed.char_indices().nth(self.width) {
+            Some((end, _)) => format!("{}...", &printed[..end]),
+            None => printed,
+        }
+    }
+
+    fn log(&self, depth: PosNum, line: &str) {
+        let indent = "  ".repeat(depth.saturating_sub(1));
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        output.elided = false;
+        let _ = writeln!(output.writer, "{}{}", indent, line);
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Trace")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    /// A buffer the test keeps a handle to while the trace writes to it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(code: &str, width: usize, max_depth: PosNum) -> String {
+        let buffer = Buffer::default();
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.trace = Some(Trace { width, max_depth, ..Trace::new(buffer.clone()) });
+        let _ = interpreter.eval_str(code);
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn tracing() {
+        assert_eq!(
+            "-> (+ 1 (* 2 3))\n\
+             \x20 -> +\n\
+             \x20 <- #<primitive +>\n\
+             \x20 -> 1\n\
+             \x20 <- 1\n\
+             \x20 -> (* 2 3)\n\
+             \x20   -> *\n\
+             \x20   <- #<primitive *>\n\
+             \x20   -> 2\n\
+             \x20   <- 2\n\
+             \x20   -> 3\n\
+             \x20   <- 3\n\
+             \x20 <- 6\n\
+             <- 7\n",
+            trace("(+ 1 (* 2 3))", DEFAULT_TRACE_WIDTH, DEFAULT_TRACE_DEPTH)
+        );
+
+        // Long expressions are cut off, deep evaluations left out, and errors logged.
+        assert_eq!(
+            "-> (car (list \"abcdefgh...\n\
+             \x20 ...\n\
+             <- Arity error at (0, 0): car expects 1 argument, but was given 2\n",
+            trace("(car (list \"abcdefghijklmnopqrstuvwxyz\") 1)", 20, 1)
+        );
+    }
+}
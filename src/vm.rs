@@ -0,0 +1,1084 @@
+//! A compiler from expressions to a small instruction set, along with a stack based virtual machine
+//! running the instructions. It is an alternative to the tree walking evaluation in `evaluation`,
+//! which remains the reference implementation; the two must always give the same results.
+//!
+//! Since bindings can be added to any frame at any time (using define), symbols are still looked up
+//! by name when the code is run. What is gained is that every expression is only analysed once:
+//! the bodies of lambdas and macros are compiled along with the lambda or macro form, and are then
+//! reused by every call.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use crate::datatypes::{
+    LinslCtx, LinslEnv, LinslErr, LinslExpr, LinslList, LinslPromise, LinslRes, Pos, PosNum,
+    Symbol
+};
+use crate::evaluation::{
+    bind, bind_all_values, bind_symbols, case_lambda_clauses, check_bindable, CaseLambdaClause,
+    defconst, define, define_values, define_values_forms, do_forms, doc, DoForms,
+    EMPTY_APPLICATION, env_get, env_symbols, evaluate_define_record, force, get_params_and_body,
+    help, if_arity_message, is_bound, is_truthy, let_to_lambda, let_values_forms,
+    make_case_lambda, name_callee, nested, parameterize, parameterize_forms, restore_primitive,
+    select_clause, undefine
+};
+use crate::image::save_image;
+use crate::matching::{choose_clause, match_forms, MatchClause, Pattern};
+use crate::modules::{evaluate_import, evaluate_module, require, undefined_symbol};
+use crate::testing::evaluate_define_test;
+use crate::parsing::parse_list_of_symbols;
+use crate::primitives::{apply_escape, apply_parameter, apply_record_fn};
+use crate::streams::{apply_stream_form, StreamForm};
+
+/// How many compiled bodies are kept before the cache is cleared.
+const MAX_CACHED: usize = 10000;
+/// How many levels of nesting a run of the machine from within another one counts as. Such a run
+/// goes through a primitive and the machine's own loop, and so uses several times as much of the
+/// stack as an evaluation by the tree walker does.
+pub(crate) const RUN_DEPTH: PosNum = 3;
+
+/// The instructions of the virtual machine. They operate on the value stack of the machine, and
+/// positions refer to the code of the frame being run.
+#[derive(Debug)]
+enum Instr {
+    /// Push a value.
+    Const(LinslExpr),
+    /// Push the value bound to a symbol.
+    Load(Symbol),
+    /// Bind a symbol to the value on top of the stack in the innermost frame, and replace the value
+    /// by the symbol. The position is that of the form, which errors and warnings are reported at.
+    Define(Symbol, Pos),
+    /// Like Define, but the binding is a constant.
+    DefConst(Symbol, Pos),
+    /// Bind the names in the list given to the values on top of the stack in the innermost frame,
+    /// leaving the values. Like for Define, the position is that of the form.
+    DefineValues(LinslExpr, Pos),
+    /// Define a record type from the forms of a define-record, and push its name.
+    DefineRecord(LinslList),
+    /// Define a test from the forms of a define-test form, and push its name. The position is that
+    /// of the form, where the test is defined.
+    DefineTest(LinslList, Pos),
+    /// Define a module from the forms of a module form, and push its name.
+    Module(LinslList),
+    /// Import the modules named by the forms of an import form, and push the last name.
+    Import(LinslList),
+    /// Load the file for the name on top of the stack unless it has been loaded, leaving the name.
+    Require,
+    /// Save the environment to the file named on top of the stack, and replace the name by how
+    /// many bindings were saved.
+    SaveImage,
+    /// Pop a value, which must be a bool, and continue at the position given if it is false.
+    JumpIfFalse(usize),
+    /// Continue at the position given.
+    Jump(usize),
+    /// Push a closure with these parameters, body and docstring, capturing the current environment.
+    MakeClosure(Arc<LinslExpr>, Arc<LinslExpr>, Option<Arc<str>>),
+    /// Push a case-lambda with a closure for each of these parameters and bodies, capturing the
+    /// current environment.
+    MakeCaseLambda(Vec<CaseLambdaClause>),
+    /// Push a macro with these parameters, body and docstring.
+    MakeMacro(Arc<LinslExpr>, Arc<LinslExpr>, Option<Arc<str>>),
+    /// Push a promise to evaluate an expression in the current environment.
+    MakePromise(LinslExpr),
+    /// Force the value on top of the stack, and replace it by the result.
+    Force,
+    /// Replace the value on top of the stack by its docstring, looking it up if it is a symbol.
+    Doc,
+    /// Push the documentation given by help, for the value on top of the stack if there is one.
+    Help(bool),
+    /// Replace the name on top of the stack by whether it is bound.
+    Bound,
+    /// Replace the name on top of the stack by whether its binding was removed.
+    Undefine,
+    /// Bind the primitive named on top of the stack again, and replace the name by #t.
+    RestorePrimitive,
+    /// Push the symbols bound in the current environment, given the value on top of the stack as
+    /// the argument if there is one.
+    EnvSymbols(bool),
+    /// Pop a number of arguments, and push the result of applying a stream form to them.
+    Stream(StreamForm, usize),
+    /// Pop a number of parameters, each followed by its new value, and run the code given with
+    /// the parameters giving those values. Then push the value of the code.
+    Parameterize(usize, Arc<Chunk>),
+    /// Pop a value, and run the body of the first clause matching it, with the symbols of the
+    /// pattern bound. If the match is in tail position, the body replaces the current frame. If
+    /// no clause matches, the error is reported at the position given, that of the match.
+    Match(Arc<[MatchArm]>, bool, Pos),
+    /// Pop a value for each of the lists of parameters of a let-values, and run the body with the
+    /// parameters bound. Like for Match, a body in tail position replaces the current frame, and
+    /// errors binding the values are reported at the position given.
+    LetValues(Arc<[LinslExpr]>, Arc<Chunk>, bool, Pos),
+    /// Pop the initial value of each variable of a do loop, and run the loop. Once its test is
+    /// true, the result forms are run like the body of a let-values. Each round uses up a step of
+    /// fuel at the position given, that of the do.
+    Do(Arc<DoLoop>, bool, Pos),
+    /// Discard the value on top of the stack.
+    Pop,
+    /// Check the value on top of the stack, which is about to be applied to the argument forms
+    /// given. A macro is expanded right away and the expansion evaluated, after which execution
+    /// continues at the position given. Anything but closures, macros and primitives is an error.
+    /// The callee is the symbol the value was looked up by, if any, which names it in errors, and
+    /// `pos` the position of the application, which they are reported at.
+    Apply { forms: LinslList, after: usize, tail: bool, callee: Option<Symbol>, pos: Pos },
+    /// Pop a number of arguments and the value below them, and apply the value to the arguments.
+    /// Like for Apply, the symbol is the one the value was looked up by.
+    Call(usize, Option<Symbol>, Pos),
+    /// Like Call, but the current frame is replaced rather than returned to.
+    TailCall(usize, Option<Symbol>, Pos),
+    /// Return the value on top of the stack from the current frame.
+    Return,
+    /// Fail with a syntax error at the position given. Malformed forms are compiled to this, so
+    /// that the error occurs when (and only if) the form would have been evaluated.
+    Fail(String, Pos),
+}
+
+/// Compiled code, along with the parameters it is to be called with.
+#[derive(Debug)]
+pub struct Chunk {
+    code: Vec<Instr>,
+    /// The position of the form the code was compiled from, which errors raised while running it
+    /// are reported at when they have no position of their own.
+    pos: Pos,
+    /// The parameters if the code is the body of a closure or macro, unless they are malformed; the
+    /// error is then left to be reported when the closure or macro is called.
+    params: Option<Vec<Symbol>>,
+}
+
+/// A clause of a match, with its guard and body compiled.
+#[derive(Debug)]
+struct MatchArm {
+    pattern: Pattern,
+    guard: Option<Arc<Chunk>>,
+    body: Arc<Chunk>,
+}
+
+/// A do loop, with each of its parts compiled.
+#[derive(Debug)]
+struct DoLoop {
+    vars: Vec<Symbol>,
+    /// The step of each variable, if it has one.
+    steps: Vec<Option<Arc<Chunk>>>,
+    test: Arc<Chunk>,
+    result: Arc<Chunk>,
+    body: Option<Arc<Chunk>>,
+}
+
+/// The compiled bodies of closures and macros, keyed by the addresses of their parameters and
+/// body.
+#[derive(Clone, Default)]
+pub struct CodeCache(HashMap<(usize, usize), Compiled>);
+
+/// A compiled body, along with what it was compiled from. These are only kept so that their
+/// addresses cannot be reused while the code is cached.
+#[derive(Clone)]
+struct Compiled {
+    _params: Arc<LinslExpr>,
+    _body: Arc<LinslExpr>,
+    chunk: Arc<Chunk>,
+}
+
+impl fmt::Debug for CodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CodeCache({} bodies)", self.0.len())
+    }
+}
+
+/// A call in progress.
+struct Frame {
+    chunk: Arc<Chunk>,
+    /// The position of the next instruction to run.
+    pc: usize,
+    env: LinslEnv,
+    /// If the frame runs the body of a macro, the environment to evaluate the expansion in.
+    expand: Option<LinslEnv>,
+}
+
+/// Evaluate an expression by compiling it and running the code.
+pub fn evaluate(expr: &LinslExpr, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let chunk = Arc::new(compile_chunk(expr, None, ctx));
+    run(chunk, env, ctx)
+}
+
+/// Compile an expression to code which returns its value.
+fn compile_chunk(expr: &LinslExpr, params: Option<&LinslExpr>, ctx: &mut LinslCtx) -> Chunk {
+    let mut code = Vec::new();
+    compile(expr, true, &mut code, ctx);
+    code.push(Instr::Return);
+    Chunk {
+        code,
+        pos: expr.pos().unwrap_or(ctx.pos),
+        params: params.and_then(|p| parse_list_of_symbols(p).ok()),
+    }
+}
+
+/// The code for the body of a closure or macro, compiling it unless that has already been done.
+fn body_chunk(params: &Arc<LinslExpr>, body: &Arc<LinslExpr>, ctx: &mut LinslCtx) -> Arc<Chunk> {
+    let key = (Arc::as_ptr(params) as usize, Arc::as_ptr(body) as usize);
+    if let Some(compiled) = ctx.code.0.get(&key) {
+        return compiled.chunk.clone();
+    };
+
+    let chunk = Arc::new(compile_chunk(body, Some(params), ctx));
+    if ctx.code.0.len() >= MAX_CACHED {
+        ctx.code.0.clear();
+    };
+    ctx.code.0.insert(key, Compiled {
+        _params: params.clone(),
+        _body: body.clone(),
+        chunk: chunk.clone(),
+    });
+    chunk
+}
+
+/// Append code evaluating `expr` and pushing its value. If `tail` is set, the expression is in
+/// tail position, and calls are compiled so that they replace the current frame. While a list is
+/// compiled, its position is kept in the context, for the code compiled from its forms.
+fn compile(expr: &LinslExpr, tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    match expr {
+        LinslExpr::Bool(_)
+        | LinslExpr::Dict(_)
+        | LinslExpr::Foreign(_, _)
+        | LinslExpr::Iterator(_)
+        | LinslExpr::Keyword(_)
+        | LinslExpr::Number(_)
+        | LinslExpr::Complex(..)
+        | LinslExpr::Parameter(_)
+        | LinslExpr::Promise(_)
+        | LinslExpr::Record(_)
+        | LinslExpr::String(_)
+        | LinslExpr::Values(_)
+        | LinslExpr::Vector(_) => {
+            code.push(Instr::Const(expr.clone()))
+        },
+        LinslExpr::List(exprs) => {
+            let outer_pos = ctx.pos;
+            if let Some(pos) = exprs.pos() {
+                ctx.pos = pos;
+            };
+            compile_list(exprs, tail, code, ctx);
+            ctx.pos = outer_pos;
+        },
+        LinslExpr::Symbol(s) => code.push(Instr::Load(s.clone())),
+        _ => code.push(fail(ctx, format!("Expected list or atom, found \'{}\'", expr))),
+    }
+}
+
+/// Append code for a list, i.e. either a special form or an application.
+fn compile_list(exprs: &LinslList, tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let head = match exprs.first() {
+        Some(head) => head,
+        None => return code.push(fail(ctx, EMPTY_APPLICATION.to_string())),
+    };
+    let forms = exprs.tail();
+
+    if let LinslExpr::Symbol(s) = head {
+        match s.as_str() {
+            "bound?" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::Bound);
+                },
+                _ => code.push(
+                    fail(ctx, format!("bound? must have one form, found {}", forms.len()))
+                ),
+            },
+            form @ ("define" | "defconst") => return compile_define(form, &forms, code, ctx),
+            "undefine" | "restore-primitive" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(match s.as_str() {
+                        "undefine" => Instr::Undefine,
+                        _ => Instr::RestorePrimitive,
+                    });
+                },
+                _ => code.push(
+                    fail(ctx, format!("{} must have one form, found {}", s, forms.len()))
+                ),
+            },
+            "case-lambda" => return match case_lambda_clauses(&forms) {
+                Ok(clauses) => {
+                    // As for a lambda, the bodies are compiled along with the form.
+                    for (params, body) in &clauses {
+                        body_chunk(params, body, ctx);
+                    }
+                    code.push(Instr::MakeCaseLambda(clauses));
+                },
+                Err(msg) => code.push(fail(ctx, msg)),
+            },
+            "define-record" => return code.push(Instr::DefineRecord(forms)),
+            "define-values" => return match define_values_forms(&forms) {
+                Ok((names, val_form)) => {
+                    compile(val_form, false, code, ctx);
+                    code.push(Instr::DefineValues(names.clone(), ctx.pos));
+                },
+                Err(msg) => code.push(fail(ctx, msg)),
+            },
+            "define-test" => return code.push(Instr::DefineTest(forms, ctx.pos)),
+            "delay" => return code.push(match &forms[..] {
+                [expr] => Instr::MakePromise(expr.clone()),
+                _ => fail(ctx, format!("delay must have one form, found {}", forms.len())),
+            }),
+            "doc" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::Doc);
+                },
+                _ => code.push(
+                    fail(ctx, format!("doc must have one form, found {}", forms.len()))
+                ),
+            },
+            "help" => return match &forms[..] {
+                [] => code.push(Instr::Help(false)),
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::Help(true));
+                },
+                _ => code.push(
+                    fail(ctx, format!("help must have at most one form, found {}", forms.len()))
+                ),
+            },
+            "env-symbols" => return match &forms[..] {
+                [] => code.push(Instr::EnvSymbols(false)),
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::EnvSymbols(true));
+                },
+                _ => code.push(fail(ctx, 
+                    format!("env-symbols must have at most one form, found {}", forms.len())
+                )),
+            },
+            "force" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::Force);
+                },
+                _ => code.push(
+                    fail(ctx, format!("force must have one form, found {}", forms.len()))
+                ),
+            },
+            "if" => return compile_if(&forms, tail, code, ctx),
+            "import" => return code.push(Instr::Import(forms)),
+            "lambda" | "macro" => return compile_lambda(s, &forms, code, ctx),
+            "let" => return match let_to_lambda(&forms) {
+                Ok(application) => compile(&application, tail, code, ctx),
+                Err(msg) => code.push(fail(ctx, msg)),
+            },
+            "match" => return compile_match(&forms, tail, code, ctx),
+            "let-values" => return compile_let_values(&forms, tail, code, ctx),
+            "module" => return code.push(Instr::Module(forms)),
+            "parameterize" => return compile_parameterize(&forms, code, ctx),
+            "require" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::Require);
+                },
+                _ => code.push(
+                    fail(ctx, format!("require must have one form, found {}", forms.len()))
+                ),
+            },
+            "save-image" => return match &forms[..] {
+                [expr] => {
+                    compile(expr, false, code, ctx);
+                    code.push(Instr::SaveImage);
+                },
+                _ => code.push(
+                    fail(ctx, format!("save-image must have one form, found {}", forms.len()))
+                ),
+            },
+            "while" => return compile_while(&forms, code, ctx),
+            "do" => return compile_do(&forms, tail, code, ctx),
+            "quote" => return code.push(match forms.first() {
+                Some(e) => Instr::Const(e.clone()),
+                None => fail(ctx, "Found no expression to quote.".to_string()),
+            }),
+            name => if let Some(form) = StreamForm::from_name(name) {
+                return compile_stream_form(form, &forms, code, ctx);
+            },
+        }
+    };
+
+    // As when walking the expressions, the head is evaluated first, since a macro must be given
+    // the argument forms rather than their values.
+    compile(head, false, code, ctx);
+    let apply = code.len();
+    let callee = match head {
+        LinslExpr::Symbol(s) => Some(s.clone()),
+        _ => None,
+    };
+    let pos = ctx.pos;
+    code.push(Instr::Apply { forms: forms.clone(), after: 0, tail, callee: callee.clone(), pos });
+    for form in forms.iter() {
+        compile(form, false, code, ctx);
+    }
+    code.push(if tail {
+        Instr::TailCall(forms.len(), callee, pos)
+    } else {
+        Instr::Call(forms.len(), callee, pos)
+    });
+
+    let end = code.len();
+    if let Instr::Apply { after, .. } = &mut code[apply] {
+        *after = end;
+    };
+}
+
+fn compile_define(form: &str, forms: &[LinslExpr], code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    match forms {
+        [LinslExpr::Symbol(name), val_form] => match check_bindable(name) {
+            Ok(()) => {
+                compile(val_form, false, code, ctx);
+                code.push(match form {
+                    "defconst" => Instr::DefConst(name.clone(), ctx.pos),
+                    _ => Instr::Define(name.clone(), ctx.pos),
+                });
+            },
+            Err(msg) => code.push(fail(ctx, msg)),
+        },
+        [name_form, _] => code.push(fail(ctx, 
+            format!("First {} form must be a symbol, found \'{}\'", form, name_form)
+        )),
+        _ => code.push(fail(ctx, 
+            format!("{} must have two forms, found \'{}\'", form, forms.len())
+        )),
+    }
+}
+
+/// Append code for a stream form. The arguments are pushed in order, except that the tail of a
+/// stream-cons is pushed as a promise.
+fn compile_stream_form(
+    form: StreamForm,
+    forms: &[LinslExpr],
+    code: &mut Vec<Instr>,
+    ctx: &mut LinslCtx
+) {
+    if let Err(msg) = form.check_arity(forms.len()) {
+        return code.push(fail(ctx, msg));
+    };
+
+    for (i, expr) in forms.iter().enumerate() {
+        match form {
+            StreamForm::Cons if i == 1 => code.push(Instr::MakePromise(expr.clone())),
+            _ => compile(expr, false, code, ctx),
+        }
+    }
+    code.push(Instr::Stream(form, forms.len()));
+}
+
+/// Append code for a parameterize. The body is compiled to code of its own, which is run separately
+/// so that the parameters get their previous values back however it finishes.
+fn compile_parameterize(forms: &[LinslExpr], code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let (bindings, body) = match parameterize_forms(forms) {
+        Ok(split) => split,
+        Err(msg) => return code.push(fail(ctx, msg)),
+    };
+
+    for (param_form, val_form) in &bindings {
+        compile(param_form, false, code, ctx);
+        compile(val_form, false, code, ctx);
+    }
+
+    code.push(Instr::Parameterize(bindings.len(), Arc::new(compile_body(body, ctx))));
+}
+
+/// Compile a body of forms to code which evaluates them in order, and returns the value of the last
+/// one.
+fn compile_body(body: &[LinslExpr], ctx: &mut LinslCtx) -> Chunk {
+    let mut code = Vec::new();
+    for (i, form) in body.iter().enumerate() {
+        if i > 0 {
+            code.push(Instr::Pop);
+        };
+        compile(form, i + 1 == body.len(), &mut code, ctx);
+    }
+    code.push(Instr::Return);
+    Chunk { code, pos: ctx.pos, params: None }
+}
+
+/// Append code for a let-values. The values of the bindings are pushed in order, and the body is
+/// compiled to code of its own, which is run in the frame binding them.
+fn compile_let_values(forms: &[LinslExpr], tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let pos = ctx.pos;
+    let (bindings, body) = match let_values_forms(forms) {
+        Ok(split) => split,
+        Err(msg) => return code.push(fail(ctx, msg)),
+    };
+
+    for (_, val_form) in &bindings {
+        compile(val_form, false, code, ctx);
+    }
+    let params = bindings.iter().map(|(params, _)| (*params).clone()).collect();
+    let body = Arc::new(compile_chunk(body, None, ctx));
+    code.push(Instr::LetValues(params, body, tail, pos));
+}
+
+/// Append code for a match. The guards and bodies of the clauses are compiled to code of their own,
+/// which is run in the scope binding the symbols of the pattern.
+fn compile_match(forms: &[LinslExpr], tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let pos = ctx.pos;
+    let (expr, clauses) = match match_forms(forms) {
+        Ok(split) => split,
+        Err(msg) => return code.push(fail(ctx, msg)),
+    };
+
+    compile(expr, false, code, ctx);
+    let arms = clauses.into_iter().map(|MatchClause { pattern, guard, body }| MatchArm {
+        pattern,
+        guard: guard.map(|guard| Arc::new(compile_chunk(&guard, None, ctx))),
+        body: Arc::new(compile_body(&body, ctx)),
+    });
+    code.push(Instr::Match(arms.collect(), tail, pos));
+}
+
+fn compile_if(forms: &[LinslExpr], tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let (test_form, then_form, else_form) = match forms {
+        [test_form, then_form] => (test_form, then_form, None),
+        [test_form, then_form, else_form] => (test_form, then_form, Some(else_form)),
+        _ => return code.push(fail(ctx, if_arity_message(forms.len()))),
+    };
+
+    compile(test_form, false, code, ctx);
+    let jump_if_false = code.len();
+    code.push(Instr::JumpIfFalse(0));
+    compile(then_form, tail, code, ctx);
+    let jump = code.len();
+    code.push(Instr::Jump(0));
+    code[jump_if_false] = Instr::JumpIfFalse(code.len());
+    match else_form {
+        Some(else_form) => compile(else_form, tail, code, ctx),
+        None => code.push(Instr::Const(LinslExpr::List(LinslList::default()))),
+    };
+    code[jump] = Instr::Jump(code.len());
+}
+
+/// Append code for a do loop. The initial values are pushed in order, and the test, the result
+/// forms, the body and the steps are compiled to code of their own, which is run in the frame of
+/// each round.
+fn compile_do(forms: &[LinslExpr], tail: bool, code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let pos = ctx.pos;
+    let DoForms { vars, test, result, body } = match do_forms(forms) {
+        Ok(split) => split,
+        Err(msg) => return code.push(fail(ctx, msg)),
+    };
+
+    for (_, init, _) in &vars {
+        compile(init, false, code, ctx);
+    }
+    let result = match result {
+        [] => Chunk {
+            code: vec![Instr::Const(LinslExpr::List(LinslList::default())), Instr::Return],
+            pos: ctx.pos,
+            params: None,
+        },
+        result => compile_body(result, ctx),
+    };
+    let do_loop = DoLoop {
+        vars: vars.iter().map(|(name, ..)| (*name).clone()).collect(),
+        steps: vars.iter()
+            .map(|(_, _, step)| step.map(|step| Arc::new(compile_chunk(step, None, ctx))))
+            .collect(),
+        test: Arc::new(compile_chunk(test, None, ctx)),
+        result: Arc::new(result),
+        body: (!body.is_empty()).then(|| Arc::new(compile_body(body, ctx))),
+    };
+    code.push(Instr::Do(Arc::new(do_loop), tail, pos));
+}
+
+/// Append code for a while, which jumps back to the test after running the body, until the test is
+/// false. None of the forms are in tail position, since the loop goes on after them.
+fn compile_while(forms: &[LinslExpr], code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let Some((test_form, body)) = forms.split_first() else {
+        return code.push(fail(ctx, "while must have a test form".to_string()));
+    };
+
+    let start = code.len();
+    compile(test_form, false, code, ctx);
+    let jump_if_false = code.len();
+    code.push(Instr::JumpIfFalse(0));
+    for form in body {
+        compile(form, false, code, ctx);
+        code.push(Instr::Pop);
+    }
+    code.push(Instr::Jump(start));
+    code[jump_if_false] = Instr::JumpIfFalse(code.len());
+    code.push(Instr::Const(LinslExpr::List(vec![].into())));
+}
+
+/// Append code creating a closure or macro. Its body is compiled right away, so that it is ready
+/// whenever it is called.
+fn compile_lambda(kind: &Symbol, forms: &[LinslExpr], code: &mut Vec<Instr>, ctx: &mut LinslCtx) {
+    let (params_form, body_form, doc) = match get_params_and_body(forms) {
+        Ok(parts) => parts,
+        Err(msg) => return code.push(fail(ctx, msg)),
+    };
+
+    let (params, body) = (Arc::new(params_form), Arc::new(body_form));
+    body_chunk(&params, &body, ctx);
+    code.push(if kind == "lambda" {
+        Instr::MakeClosure(params, body, doc)
+    } else {
+        Instr::MakeMacro(params, body, doc)
+    });
+}
+
+/// Bind the parameters of a closure or macro, whose body has been compiled to `chunk`.
+fn bind_params(
+    chunk: &Chunk,
+    params: &LinslExpr,
+    args: &[LinslExpr],
+    env: &LinslEnv
+) -> Result<LinslEnv, LinslErr> {
+    match &chunk.params {
+        Some(symbs) => bind_symbols(symbs, args, env),
+        // The parameters are malformed, and so binding them will report why.
+        None => bind(params, args, env),
+    }
+}
+
+/// Start running `next`, keeping the current frame to return to.
+fn push_frame(
+    frames: &mut Vec<Frame>,
+    frame: &mut Frame,
+    next: Frame,
+    ctx: &mut LinslCtx
+) -> Result<(), LinslErr> {
+    // The calls in progress are limited just as the nesting of evaluations is when walking the
+    // expressions, even though the machine itself does not use the stack for them.
+    if frames.len() + 1 >= ctx.max_depth {
+        return Err(LinslErr::RecursionLimit(frames.len() + 1, next.chunk.pos));
+    };
+    frames.push(mem::replace(frame, next));
+    ctx.stats.max_depth = ctx.stats.max_depth.max(frames.len());
+    Ok(())
+}
+
+/// Start running `chunk` in `env`. If `tail` is set it replaces the current frame, and otherwise
+/// it returns to it.
+fn enter(
+    frames: &mut Vec<Frame>,
+    frame: &mut Frame,
+    chunk: Arc<Chunk>,
+    env: LinslEnv,
+    tail: bool,
+    ctx: &mut LinslCtx
+) -> Result<(), LinslErr> {
+    if tail {
+        frame.chunk = chunk;
+        frame.pc = 0;
+        frame.env = env;
+        Ok(())
+    } else {
+        push_frame(frames, frame, Frame { chunk, pc: 0, env, expand: None }, ctx)
+    }
+}
+
+/// Return `val` from the current frame. If it is the last one, the value is instead given back.
+fn return_value(
+    val: LinslExpr,
+    stack: &mut Vec<LinslExpr>,
+    frames: &mut Vec<Frame>,
+    frame: &mut Frame,
+    ctx: &mut LinslCtx
+) -> Option<LinslExpr> {
+    match frame.expand.take() {
+        // If a macro body returns, the value is its expansion, which is then evaluated in place of
+        // the macro application.
+        Some(env) => {
+            let chunk = Arc::new(compile_chunk(&val, None, ctx));
+            *frame = Frame { chunk, pc: 0, env, expand: None };
+            None
+        },
+        None => match frames.pop() {
+            Some(caller) => {
+                *frame = caller;
+                stack.push(val);
+                None
+            },
+            None => Some(val),
+        },
+    }
+}
+
+/// Call a closure with arguments which have already been evaluated, running its body until it
+/// returns.
+pub(crate) fn apply_closure(
+    params: &Arc<LinslExpr>,
+    body: &Arc<LinslExpr>,
+    closure_env: &LinslEnv,
+    args: &[LinslExpr],
+    ctx: &mut LinslCtx
+) -> LinslRes {
+    let chunk = body_chunk(params, body, ctx);
+    let env = bind_params(&chunk, params, args, closure_env)?;
+    run(chunk, &env, ctx)
+}
+
+/// Run code until it returns. The position kept in the context is given back afterwards, since
+/// whatever is run from a primitive is run in the middle of the application of the primitive.
+fn run(chunk: Arc<Chunk>, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let outer_pos = ctx.pos;
+    let res = run_frames(chunk, env, ctx);
+    ctx.pos = outer_pos;
+    res
+}
+
+/// Run code until it returns, keeping the calls in progress in frames of its own.
+fn run_frames(chunk: Arc<Chunk>, env: &LinslEnv, ctx: &mut LinslCtx) -> LinslRes {
+    let mut stack: Vec<LinslExpr> = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut frame = Frame { chunk, pc: 0, env: env.clone(), expand: None };
+
+    loop {
+        // The context keeps the position of the code being run, for the errors of whatever is
+        // called from it.
+        ctx.pos = frame.chunk.pos;
+        let pc = frame.pc;
+        frame.pc += 1;
+        match &frame.chunk.code[pc] {
+            Instr::Const(val) => stack.push(val.clone()),
+            Instr::Load(s) => {
+                let val = env_get(s, &frame.env).ok_or_else(|| undefined_symbol(s, ctx))?;
+                stack.push(val);
+            },
+            Instr::Define(name, pos) => {
+                ctx.pos = *pos;
+                let val = pop(&mut stack)?;
+                stack.push(define(name, val, &frame.env, ctx)?);
+            },
+            Instr::DefConst(name, pos) => {
+                ctx.pos = *pos;
+                let val = pop(&mut stack)?;
+                stack.push(defconst(name, val, &frame.env, ctx)?);
+            },
+            Instr::DefineValues(names, pos) => {
+                ctx.pos = *pos;
+                let val = pop(&mut stack)?;
+                stack.push(define_values(names, val, &frame.env, ctx)?);
+            },
+            Instr::DefineRecord(forms) => stack.push(evaluate_define_record(forms, &frame.env)?),
+            Instr::DefineTest(forms, pos) => {
+                ctx.pos = *pos;
+                stack.push(evaluate_define_test(forms, &frame.env, ctx)?)
+            },
+            Instr::Module(forms) => stack.push(evaluate_module(forms, &frame.env, ctx)?),
+            Instr::Import(forms) => stack.push(evaluate_import(forms, &frame.env, ctx)?),
+            Instr::Require => {
+                let name = pop(&mut stack)?;
+                stack.push(require(&name, &frame.env, ctx)?);
+            },
+            Instr::SaveImage => {
+                let path = pop(&mut stack)?;
+                stack.push(save_image(&path, &frame.env, ctx)?);
+            },
+            Instr::JumpIfFalse(target) => {
+                if !is_truthy(&pop(&mut stack)?) {
+                    frame.pc = *target;
+                };
+            },
+            Instr::Jump(target) => {
+                ctx.burn_fuel(frame.chunk.pos)?;
+                frame.pc = *target
+            },
+            Instr::MakeClosure(params, body, doc) => {
+                let env = frame.env.clone();
+                stack.push(LinslExpr::Closure(params.clone(), body.clone(), env, None, doc.clone()))
+            },
+            Instr::MakeCaseLambda(clauses) => stack.push(make_case_lambda(clauses, &frame.env)),
+            Instr::MakeMacro(params, body, doc) => stack.push(
+                LinslExpr::Macro(params.clone(), body.clone(), doc.clone())
+            ),
+            Instr::MakePromise(expr) => stack.push(
+                LinslExpr::Promise(LinslPromise::new(expr.clone(), frame.env.clone()))
+            ),
+            Instr::Force => {
+                let val = pop(&mut stack)?;
+                stack.push(force(val, ctx)?);
+            },
+            Instr::Doc => {
+                let val = pop(&mut stack)?;
+                stack.push(doc(val, &frame.env)?);
+            },
+            Instr::Help(with_arg) => {
+                let arg = if *with_arg { Some(pop(&mut stack)?) } else { None };
+                stack.push(help(arg, &frame.env)?);
+            },
+            Instr::Bound => {
+                let name = pop(&mut stack)?;
+                stack.push(is_bound(name, &frame.env)?);
+            },
+            Instr::Undefine => {
+                let name = pop(&mut stack)?;
+                stack.push(undefine(name, &frame.env)?);
+            },
+            Instr::RestorePrimitive => {
+                let name = pop(&mut stack)?;
+                stack.push(restore_primitive(name, &frame.env)?);
+            },
+            Instr::EnvSymbols(with_arg) => {
+                let arg = if *with_arg { Some(pop(&mut stack)?) } else { None };
+                stack.push(env_symbols(arg, &frame.env)?);
+            },
+            Instr::Stream(form, n) => {
+                let args = stack.split_off(stack.len() - n);
+                stack.push(apply_stream_form(*form, &args, &frame.env, ctx)?);
+            },
+            Instr::Parameterize(n, body) => {
+                let vals = stack.split_off(stack.len() - 2 * n);
+                let body = body.clone();
+                stack.push(parameterize(vals, ctx, |ctx| run(body, &frame.env, ctx))?);
+            },
+            Instr::Match(arms, tail, pos) => {
+                let (arms, tail, pos) = (arms.clone(), *tail, *pos);
+                let val = pop(&mut stack)?;
+                let patterns = arms.iter().map(|arm| &arm.pattern);
+                let (i, env) = choose_clause(patterns, &val, &frame.env, pos, |i, scope| {
+                    match &arms[i].guard {
+                        Some(guard) => {
+                            let guard = guard.clone();
+                            Ok(is_truthy(&nested(ctx, pos, |ctx| run(guard, scope, ctx))?))
+                        },
+                        None => Ok(true),
+                    }
+                })?;
+                enter(&mut frames, &mut frame, arms[i].body.clone(), env, tail, ctx)?;
+            },
+            Instr::LetValues(params, body, tail, pos) => {
+                let (body, tail) = (body.clone(), *tail);
+                let vals = stack.split_off(stack.len() - params.len());
+                let env = bind_all_values(params.iter(), &vals, &frame.env, *pos)?;
+                enter(&mut frames, &mut frame, body, env, tail, ctx)?;
+            },
+            Instr::Do(do_loop, tail, pos) => {
+                let (do_loop, tail, pos) = (do_loop.clone(), *tail, *pos);
+                let mut vals = stack.split_off(stack.len() - do_loop.vars.len());
+                let scope = loop {
+                    ctx.burn_fuel(pos)?;
+                    let scope = LinslEnv::new(&frame.env);
+                    for (name, val) in do_loop.vars.iter().zip(vals.drain(..)) {
+                        scope.insert(name.clone(), val);
+                    }
+                    let test = do_loop.test.clone();
+                    if is_truthy(&nested(ctx, pos, |ctx| run(test, &scope, ctx))?) {
+                        break scope;
+                    };
+                    if let Some(body) = &do_loop.body {
+                        let body = body.clone();
+                        nested(ctx, pos, |ctx| run(body, &scope, ctx))?;
+                    };
+                    // Every step is computed in this round's frame before the next one is made.
+                    for (name, step) in do_loop.vars.iter().zip(&do_loop.steps) {
+                        vals.push(match step {
+                            Some(step) => {
+                                let step = step.clone();
+                                nested(ctx, pos, |ctx| run(step, &scope, ctx))?
+                            },
+                            None => {
+                                env_get(name, &scope).ok_or_else(|| undefined_symbol(name, ctx))?
+                            },
+                        });
+                    }
+                };
+                enter(&mut frames, &mut frame, do_loop.result.clone(), scope, tail, ctx)?;
+            },
+            Instr::Pop => {
+                pop(&mut stack)?;
+            },
+            Instr::Apply { forms, after, tail, pos, .. } => match stack.last() {
+                Some(LinslExpr::Closure(..))
+                | Some(LinslExpr::CaseLambda(_))
+                | Some(LinslExpr::Primitive(_, _))
+                | Some(LinslExpr::CtxPrimitive(_, _))
+                | Some(LinslExpr::Escape(_))
+                | Some(LinslExpr::Parameter(_))
+                | Some(LinslExpr::RecordFn(_, _)) => (),
+                Some(LinslExpr::Macro(params, body, _)) => {
+                    let (params, body) = (params.clone(), body.clone());
+                    let (forms, after, tail) = (forms.clone(), *after, *tail);
+                    stack.pop();
+
+                    // The parameters are bound to the unevaluated forms, on top of the environment
+                    // the macro is applied in.
+                    let chunk = body_chunk(&params, &body, ctx);
+                    let macro_env = bind_params(&chunk, &params, &forms, &frame.env)
+                        .map_err(|err| {
+                            let head = LinslExpr::Macro(params.clone(), body.clone(), None);
+                            let (callee, pos) = call_site(&frame.chunk.code[pc]);
+                            name_callee(err, callee, &head, pos)
+                        })?;
+                    let next = Frame { chunk, pc: 0, env: macro_env, expand: Some(frame.env.clone()) };
+
+                    // A macro application in tail position replaces the current frame, unless the
+                    // frame is itself waiting to have its value expanded.
+                    if tail && frame.expand.is_none() {
+                        frame = next;
+                    } else {
+                        frame.pc = after;
+                        push_frame(&mut frames, &mut frame, next, ctx)?;
+                    }
+                },
+                head => return Err(
+                    LinslErr::SyntaxError(
+                        format!("Expected the head of list to be a primitive, found \'{}\'",
+                            head.map(|h| h.to_string()).unwrap_or_default()),
+                        *pos
+                    )
+                ),
+            },
+            Instr::Call(n, _, _) | Instr::TailCall(n, _, _) => {
+                let tail = matches!(frame.chunk.code[pc], Instr::TailCall(..));
+                let (callee, pos) = call_site(&frame.chunk.code[pc]);
+                ctx.burn_fuel(pos)?;
+                // Whatever the function calls in turn is called from here.
+                ctx.pos = pos;
+                let args = stack.split_off(stack.len() - n);
+                let mut f = pop(&mut stack)?;
+                // A case-lambda is applied as its clause accepting as many arguments as there are.
+                if let LinslExpr::CaseLambda(clauses) = &f {
+                    let clause = select_clause(clauses, *n)
+                        .map_err(|err| name_callee(err, callee, &f, pos))?;
+                    f = clause.clone();
+                };
+                let named = |err| name_callee(err, callee, &f, pos);
+                let val = match &f {
+                    LinslExpr::Primitive(_, f) => f(&args).map_err(named)?,
+                    LinslExpr::CtxPrimitive(_, f) => f(&args, ctx).map_err(named)?,
+                    LinslExpr::RecordFn(kind, op) => {
+                        apply_record_fn(kind, *op, &args).map_err(named)?
+                    },
+                    LinslExpr::Escape(escape) => apply_escape(escape, &args).map_err(named)?,
+                    LinslExpr::Parameter(param) => {
+                        apply_parameter(param, &args, ctx).map_err(named)?
+                    },
+                    LinslExpr::Closure(params, body, closure_env, _, _) => {
+                        // The parameters are bound in a new frame on top of the frame the closure
+                        // was created in, not the one it is called from.
+                        let chunk = body_chunk(params, body, ctx);
+                        let env = bind_params(&chunk, params, &args, closure_env).map_err(named)?;
+                        if tail {
+                            frame.chunk = chunk;
+                            frame.pc = 0;
+                            frame.env = env;
+                        } else {
+                            let next = Frame { chunk, pc: 0, env, expand: None };
+                            push_frame(&mut frames, &mut frame, next, ctx)?;
+                        }
+                        continue;
+                    },
+                    _ => return Err(
+                        LinslErr::InternalError("Applied something which is not callable.".to_string())
+                    ),
+                };
+
+                // Primitives (and record functions) are done right away, and give a value.
+                if !tail {
+                    stack.push(val);
+                } else if let Some(val) =
+                    return_value(val, &mut stack, &mut frames, &mut frame, ctx)
+                {
+                    return Ok(val);
+                }
+            },
+            Instr::Return => {
+                let val = pop(&mut stack)?;
+                if let Some(val) = return_value(val, &mut stack, &mut frames, &mut frame, ctx) {
+                    return Ok(val);
+                }
+            },
+            Instr::Fail(msg, pos) => return Err(LinslErr::SyntaxError(msg.clone(), *pos)),
+        }
+    }
+}
+
+/// The instruction failing with `msg`, at the position of the form being compiled.
+fn fail(ctx: &LinslCtx, msg: String) -> Instr {
+    Instr::Fail(msg, ctx.pos)
+}
+
+/// The symbol the function applied by an instruction was looked up by, if any, along with the
+/// position of the application.
+fn call_site(instr: &Instr) -> (Option<&Symbol>, Pos) {
+    match instr {
+        Instr::Apply { callee, pos, .. }
+        | Instr::Call(_, callee, pos)
+        | Instr::TailCall(_, callee, pos) => (callee.as_ref(), *pos),
+        _ => (None, (0, 0)),
+    }
+}
+
+fn pop(stack: &mut Vec<LinslExpr>) -> LinslRes {
+    stack.pop().ok_or(LinslErr::InternalError("The value stack is empty.".to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::datatypes::Engine;
+    use crate::interpreter::Interpreter;
+
+    fn run_on(engine: Engine, program: &[&str]) -> Vec<String> {
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.engine = engine;
+        program.iter().map(|code| match interpreter.eval_str(code) {
+            Ok(val) => val.to_string(),
+            Err(e) => e.to_string(),
+        }).collect()
+    }
+
+    #[test]
+    fn engines_agree() {
+        let program = [
+            "(define - (lambda (a b) (+ a (neg b))))",
+            "(define fib (lambda (n) (if (> 2 n) n (+ (fib (- n 1)) (fib (- n 2))))))",
+            "(fib 15)",
+            "(define rest (lambda (a b) b))",
+            "(rest 1 2 3 4)",
+            "(define swap (macro (a b) `(,b ,a)))",
+            "(swap 1 neg)",
+            "((lambda (x) (quote x y)) 1)",
+            "(define make-adder (lambda (n) (lambda (x) (+ x n))))",
+            "((make-adder 5) 3)",
+            "(eqt? car (lambda (x) x))",
+            // Malformed forms are only reported once evaluated.
+            "(define broken (lambda () (if #t)))",
+            "(broken)",
+            "(if 1 2 3)",
+            "(1 2)",
+            "()",
+            "(undefined 1)",
+            "(define 1 2)",
+            "(define a)",
+            "(lambda (x))",
+            "(quote)",
+            "((lambda (1) 1) 2)",
+            "((lambda (a b) a) 1)",
+            "(inv 0)",
+        ];
+
+        assert_eq!(run_on(Engine::TreeWalker, &program), run_on(Engine::Vm, &program));
+    }
+
+    #[test]
+    fn bodies_are_compiled_once() {
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.engine = Engine::Vm;
+        interpreter.eval_str("(define f (lambda (x) ((lambda (y) (+ x y)) 1)))").unwrap();
+        let compiled = interpreter.ctx.code.0.len();
+
+        for i in 0..100 {
+            assert_eq!((i + 1).to_string(), interpreter.eval_str(&format!("(f {})", i)).unwrap().to_string());
+        }
+        assert_eq!(compiled, interpreter.ctx.code.0.len());
+    }
+
+    #[test]
+    fn macros_in_tail_position_do_not_grow_frames() {
+        let mut interpreter = Interpreter::default();
+        interpreter.ctx.engine = Engine::Vm;
+        interpreter.eval_str("
+            (define my-if (macro (c a b) `(if ,c ,a ,b)))
+            (define loop (lambda (n) (my-if (= n 0) 'done (loop (+ n -1)))))
+        ").unwrap();
+
+        assert_eq!("done", interpreter.eval_str("(loop 10000)").unwrap().to_string());
+    }
+}
@@ -0,0 +1,267 @@
+//! Tests running the Linsl binary, as it is run from the command line.
+
+use std::env::temp_dir;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Run the binary with the given arguments, piping `stdin` into it.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_Linsl"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn interactive_after_files() {
+    let defs = temp_dir().join("linsl-test-interactive-defs.linsl");
+    fs::write(&defs, "(define sq (lambda (x) (* x x)))\n").unwrap();
+    let broken = temp_dir().join("linsl-test-interactive-broken.linsl");
+    fs::write(&broken, "(define y 2)\n(car 1)\n(define z 3)\n").unwrap();
+    let (defs, broken) = (defs.to_str().unwrap(), broken.to_str().unwrap());
+
+    // The definitions made by the file can be used at the prompt.
+    let output = run(&[defs, "-i"], "(sq 7)\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success());
+    assert!(stdout.starts_with("#<closure sq (x)>\n"), "{}", stdout);
+    assert!(stdout.contains("$1 = 49\n"), "{}", stdout);
+
+    // An error in a file is printed, and the REPL is started anyway.
+    let output = run(&["--interactive", defs, broken], "(sq y)\nz\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Starting the REPL"), "{}", stderr);
+    assert!(stdout.contains("4\n"), "{}", stdout);
+    assert!(stdout.contains("Undefined symbol 'z'"), "{}", stdout);
+
+    // Without the flag, the binary exits at the error.
+    let output = run(&[defs, broken], "(sq y)\n");
+    assert_eq!(Some(1), output.status.code());
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("4\n"));
+
+    fs::remove_file(defs).unwrap();
+    fs::remove_file(broken).unwrap();
+}
+
+#[test]
+fn lint_fixtures() {
+    // Every fixture in tests/lint comes with the warnings expected for it, one per line.
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/lint");
+    let mut fixtures: Vec<_> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "linsl"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty());
+    for fixture in fixtures {
+        let expected = fs::read_to_string(fixture.with_extension("expected")).unwrap();
+        let output = run(&["check", fixture.to_str().unwrap()], "");
+        assert!(output.status.success(), "{}", fixture.display());
+        assert_eq!(expected, String::from_utf8(output.stderr).unwrap(), "{}", fixture.display());
+        assert!(output.stdout.is_empty());
+    }
+
+    // Warnings only fail checking when they are denied.
+    let unused = format!("{}/unused.linsl", dir);
+    let output = run(&["check", "--deny-warnings", &unused], "");
+    assert_eq!(Some(1), output.status.code());
+    let clean = format!("{}/clean.linsl", dir);
+    assert!(run(&["check", "--deny-warnings", &clean], "").status.success());
+}
+
+#[test]
+fn test_mode() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/testing");
+    let passing = format!("{}/passing.linsl", dir);
+    let output = run(&["test", &passing], "");
+    assert!(output.status.success());
+    assert_eq!(
+        "test squares ... ok\ntest square-needs-a-number ... ok\n2 passed, 0 failed\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    // The values of the forms are not printed, only the report, and failures make it exit with an
+    // error.
+    let failing = format!("{}/failing.linsl", dir);
+    let output = run(&["test", &failing], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "test doubles ... FAILED (defined at (3, 0)): Assertion failed at (4, 2): Expected '4', \
+         found '6'\n\
+         test doubles-zero ... ok\n\
+         test double-needs-an-argument ... ok\n\
+         test double-accepts-anything ... FAILED (defined at (12, 0)): Type error at (0, 0): \
+         Expected a number, \
+         found '\"a\"'\n\
+         2 passed, 2 failed\n",
+        stdout
+    );
+    assert_eq!("2 test(s) failed\n", String::from_utf8(output.stderr).unwrap());
+    assert_eq!(Some(2), run(&["test"], "").status.code());
+
+    // Outside of test mode, the tests run when run-tests is applied.
+    let output = run(&[&passing], "(run-tests)\n");
+    assert!(output.status.success());
+    let output = run(&["-i", &passing], "(run-tests)\n");
+    assert!(String::from_utf8(output.stdout).unwrap().contains("2 passed, 0 failed\n$1 = #t\n"));
+}
+
+#[test]
+fn warning_policies() {
+    let script = temp_dir().join("linsl-test-warnings.linsl");
+    fs::write(&script, "(define x 1)\n(define x 2)\n").unwrap();
+    let script = script.to_str().unwrap();
+
+    let output = run(&[script], "");
+    assert!(output.status.success());
+    assert_eq!("Warning at (1, 0): Redefining 'x'\n", String::from_utf8(output.stderr).unwrap());
+    let output = run(&["--warnings=silent", script], "");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let output = run(&["--warnings=error", script], "");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("1\n", String::from_utf8(output.stdout).unwrap());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Denied warning"));
+    assert_eq!(Some(2), run(&["--warnings=loud", script], "").status.code());
+
+    fs::remove_file(script).unwrap();
+}
+
+#[test]
+fn subcommands() {
+    let output = run(&["-e", "(define x 2)", "--eval", "(* x 3)"], "");
+    assert!(output.status.success());
+    assert_eq!("2\n6\n", String::from_utf8(output.stdout).unwrap());
+    let output = run(&["repl"], "(+ 1 2)\n");
+    assert!(String::from_utf8(output.stdout).unwrap().contains("3\n"));
+
+    let file = temp_dir().join("linsl-test-fmt.linsl");
+    fs::write(&file, "(define f\n(lambda (x)\n      (* x x)))\n").unwrap();
+    let output = run(&["fmt", file.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert_eq!(
+        "(define f\n  (lambda (x)\n    (* x x)))\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+    fs::write(&file, "(define f\n").unwrap();
+    assert_eq!(Some(1), run(&["fmt", file.to_str().unwrap()], "").status.code());
+    fs::remove_file(file).unwrap();
+
+    let output = run(&["--help"], "");
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("Linsl, an interpreter"));
+    let output = run(&["-V"], "");
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("Linsl "));
+
+    // Unknown options and options conflicting with each other or the subcommand are usage errors.
+    for args in [&["--unknown"][..], &["check", "--vm", "a.linsl"], &["--debug", "--vm"]] {
+        let output = run(args, "");
+        assert_eq!(Some(2), output.status.code(), "{:?}", args);
+        assert!(String::from_utf8(output.stderr).unwrap().ends_with("Try 'Linsl --help' for more \
+                                                                     information\n"));
+    }
+}
+
+#[test]
+fn show_ast() {
+    // The forms are printed as they were parsed, i.e. with the quasiquote rewritten, before they
+    // are evaluated.
+    let output = run(&["--show-ast", "-e", "(define b 1) `(a ,b)"], "");
+    assert!(output.status.success());
+    assert_eq!("1\n(a 1)\n", String::from_utf8(output.stdout).unwrap());
+    assert_eq!(
+        ";; AST at (0, 0): (define b 1)\n\
+         ;; AST at (0, 13): (append (list (quote a)) (list b))\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+
+    // At the REPL, :ast prints the forms without evaluating them.
+    let output = run(&["repl"], ":ast `(a ,@b) (undefined)\nb\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(append (list (quote a)) b)\n(undefined)\n"), "{}", stdout);
+    assert!(stdout.contains("Undefined symbol 'b'"), "{}", stdout);
+}
+
+#[test]
+fn trace_eval() {
+    let output = run(&["--trace-eval", "--trace-width=5", "-e", "(car (list 1 2))"], "");
+    assert!(output.status.success());
+    assert_eq!("1\n", String::from_utf8(output.stdout).unwrap());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("-> (car ...\n  -> car\n"), "{}", stderr);
+    assert!(stderr.ends_with("  <- (1 2)\n<- 1\n"), "{}", stderr);
+}
+
+#[test]
+fn print_limits() {
+    let input = "(list 1 2 3 4 5)\n(set-print-length! 3)\n$1\n";
+    let output = run(&["repl", "--print-length=2"], input);
+    assert_eq!(
+        "$1 = (1 2 ... (3 more))\n$2 = 2\n$3 = (1 2 3 ... (2 more))\n",
+        String::from_utf8(output.stdout).unwrap().replace("Linsl> ", "")
+    );
+}
+
+#[test]
+fn keep_going() {
+    let file = temp_dir().join("linsl-test-keep-going.linsl");
+    fs::write(&file, "(define a 1)\n(car 1)\n(define c 3)\n").unwrap();
+    let path = file.to_str().unwrap();
+
+    // The form which fails is reported, and the ones around it are run.
+    let output = run(&["--keep-going", path], "");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("1\n3\n", String::from_utf8(output.stdout).unwrap());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(&format!("In the form at (1, 0) of {}: ", path)), "{}", stderr);
+    assert!(stderr.ends_with("1 form(s) failed\n"), "{}", stderr);
+
+    // Without it, nothing is run after the error.
+    let output = run(&[path], "");
+    assert_eq!("1\n", String::from_utf8(output.stdout).unwrap());
+
+    fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn chdir_and_require() {
+    let root = temp_dir().join(format!("linsl-test-chdir-{}", std::process::id()));
+    fs::create_dir_all(root.join("lib")).unwrap();
+    fs::create_dir_all(root.join("data")).unwrap();
+    fs::write(root.join("data/data.txt"), "from data").unwrap();
+    fs::write(root.join("lib/sibling.linsl"), "(define sibling \"from lib\")\n").unwrap();
+    fs::write(
+        root.join("lib/main.linsl"),
+        "(chdir \"data\")\n(require 'sibling)\n(list sibling (read-file \"data.txt\"))\n\
+         (equal? (current-directory) (path-join (path-parent (current-directory)) \"data\"))\n",
+    ).unwrap();
+
+    // The script is given relative to the working directory it leaves: require still searches
+    // beside it, while read-file uses the new working directory.
+    let output = Command::new(env!("CARGO_BIN_EXE_Linsl"))
+        .arg(Path::new("lib").join("main.linsl"))
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(
+        "\"data\"\nsibling\n(\"from lib\" \"from data\")\n#t\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    // Changing to a directory which does not exist is an error which can be caught.
+    let output = run(&["-e", "(assert-error (lambda () (chdir \"/linsl-missing\")))"], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("\"Could not change to '/linsl-missing': "), "{}", stdout);
+
+    fs::remove_dir_all(root).unwrap();
+}
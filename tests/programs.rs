@@ -0,0 +1,18 @@
+//! Tests evaluating the programs in tests/programs on both engines.
+
+mod runner;
+
+use linsl::datatypes::Engine;
+
+#[test]
+fn programs() {
+    let programs = runner::programs();
+    assert!(!programs.is_empty());
+    let failures: Vec<String> = programs.iter()
+        .flat_map(|program| {
+            [Engine::TreeWalker, Engine::Vm].map(|engine| runner::check(program, engine))
+        })
+        .filter_map(Result::err)
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,79 @@
+//! The runner for the programs in tests/programs, which are evaluated from start to end and whose
+//! output is compared with the `.expected` file next to each.
+//!
+//! The output of a program is what running it as a file prints: the value of every top-level form
+//! on a line of its own, preceded by the warnings given while evaluating it. If a form fails, the
+//! error is the last line. Setting the `LINSL_BLESS` environment variable writes the output to the
+//! `.expected` files instead of comparing it, e.g. after adding a program.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use linsl::datatypes::{Engine, LinslErr};
+use linsl::interpreter::Interpreter;
+use linsl::parsing::{parse, read_file, Tokenizer};
+
+/// The programs in tests/programs, in order.
+pub fn programs() -> Vec<PathBuf> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs");
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "linsl"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// Evaluate a program with a fresh interpreter using `engine`, and return its output.
+pub fn run(path: &Path, engine: Engine) -> String {
+    let mut interpreter = Interpreter::default();
+    interpreter.ctx.engine = engine;
+    let mut output = String::new();
+    if let Err(e) = evaluate_forms(path, &mut interpreter, &mut output) {
+        output.push_str(&format!("{}\n", e));
+    };
+    output
+}
+
+/// Evaluate every form of a program in turn, adding what it prints to `output`.
+fn evaluate_forms(
+    path: &Path,
+    interpreter: &mut Interpreter,
+    output: &mut String
+) -> Result<(), LinslErr> {
+    let mut tokenizer = Tokenizer::new(vec![read_file(path.to_str().unwrap())?].into())?;
+    while tokenizer.peek().is_some() {
+        let expr = parse(&mut tokenizer)?;
+        interpreter.ctx.take_parse_warnings(&mut tokenizer)?;
+        let res = interpreter.evaluate(&expr);
+        for warning in interpreter.take_warnings() {
+            output.push_str(&format!("{}\n", warning));
+        }
+        output.push_str(&format!("{}\n", res?));
+    }
+    Ok(())
+}
+
+/// Compare the output of a program with its `.expected` file, or write the file if `LINSL_BLESS`
+/// is set. Returns a description of the difference if they are not the same.
+pub fn check(path: &Path, engine: Engine) -> Result<(), String> {
+    let output = run(path, engine);
+    let expected_path = path.with_extension("expected");
+    if env::var_os("LINSL_BLESS").is_some() {
+        fs::write(&expected_path, &output).unwrap();
+        return Ok(());
+    };
+    let expected = fs::read_to_string(&expected_path)
+        .map_err(|e| format!("{}: {}", expected_path.display(), e))?;
+    if output == expected {
+        Ok(())
+    } else {
+        Err(
+            format!(
+                "{} on {:?}\n--- expected\n{}--- found\n{}",
+                path.display(), engine, expected, output
+            )
+        )
+    }
+}